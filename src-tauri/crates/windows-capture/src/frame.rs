@@ -470,7 +470,11 @@ impl<'a> FrameBuffer<'a> {
     #[must_use]
     #[inline]
     pub const fn has_padding(&self) -> bool {
-        self.width * 4 != self.row_pitch
+        let multiplier = match self.color_format {
+            ColorFormat::Rgba16F => 8,
+            ColorFormat::Rgba8 | ColorFormat::Bgra8 => 4,
+        };
+        self.width * multiplier != self.row_pitch
     }
 
     /// Gets the raw pixel data, which may include padding.
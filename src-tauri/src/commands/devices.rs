@@ -1,9 +1,18 @@
 use crate::error::RecorderError;
+#[cfg(target_os = "linux")]
+use crate::recording::screen_capturer_x11::{
+    connect, desktop_positions, enumerate_outputs, enumerate_windows, X11Output,
+};
 use cpal::traits::{DeviceTrait, HostTrait};
 use serde::{Deserialize, Serialize};
+#[cfg(windows)]
 use windows::Win32::Foundation::RECT;
+#[cfg(windows)]
 use windows::Win32::Graphics::Gdi::{GetMonitorInfoW, MONITORINFOEXW};
+#[cfg(windows)]
 use windows_capture::monitor::Monitor;
+#[cfg(windows)]
+use windows_capture::window::Window;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DisplayInfo {
@@ -17,7 +26,6 @@ pub struct DisplayInfo {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[allow(dead_code)]
 pub struct WindowInfo {
     pub window_id: u64,
     pub title: String,
@@ -40,6 +48,7 @@ pub struct AudioDeviceInfo {
     pub is_input: bool,
 }
 
+#[cfg(windows)]
 fn monitor_rect(monitor: &Monitor) -> Result<RECT, RecorderError> {
     let mut info = MONITORINFOEXW::default();
     info.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
@@ -64,11 +73,17 @@ fn monitor_rect(monitor: &Monitor) -> Result<RECT, RecorderError> {
 /// List connected monitors.
 #[tauri::command]
 pub async fn get_displays() -> Result<Vec<DisplayInfo>, RecorderError> {
-    let monitors = Monitor::enumerate().map_err(|e| RecorderError::file_error(e.to_string()))?;
-    if monitors.is_empty() {
+    let displays = enumerate_displays()?;
+    if displays.is_empty() {
         return Err(RecorderError::device_not_found("Display"));
     }
+    Ok(displays)
+}
 
+/// Platform display list. `DisplayInfo::index` matches the index `ScreenCapturer::new` expects.
+#[cfg(windows)]
+pub(crate) fn enumerate_displays() -> Result<Vec<DisplayInfo>, RecorderError> {
+    let monitors = Monitor::enumerate().map_err(|e| RecorderError::file_error(e.to_string()))?;
     let primary = Monitor::primary().ok();
     let mut displays = Vec::with_capacity(monitors.len());
 
@@ -106,6 +121,73 @@ pub async fn get_displays() -> Result<Vec<DisplayInfo>, RecorderError> {
     Ok(displays)
 }
 
+/// RandR monitors (or X screens without RandR), in the same order `ScreenCapturer` uses.
+#[cfg(target_os = "linux")]
+pub(crate) fn enumerate_displays() -> Result<Vec<DisplayInfo>, RecorderError> {
+    let (conn, default_screen) = connect(None)?;
+    Ok(display_list(enumerate_outputs(&conn, default_screen)?))
+}
+
+/// `DisplayInfo` for each X11 output, indexed as `ScreenCapturer` expects.
+#[cfg(target_os = "linux")]
+pub(crate) fn display_list(outputs: Vec<X11Output>) -> Vec<DisplayInfo> {
+    let positions = desktop_positions(&outputs);
+    outputs
+        .into_iter()
+        .zip(positions)
+        .enumerate()
+        .map(|(i, (o, (x, y)))| DisplayInfo {
+            index: i as u32,
+            name: if o.primary {
+                format!("{} (Primary)", o.name)
+            } else {
+                o.name
+            },
+            width: o.width as u32,
+            height: o.height as u32,
+            x,
+            y,
+            is_primary: o.primary,
+        })
+        .collect()
+}
+
+/// List application windows that can be recorded on their own.
+///
+/// `window_id` is what `RecordingSettings::selected_window` expects (as a decimal string).
+#[tauri::command]
+pub async fn get_windows() -> Result<Vec<WindowInfo>, RecorderError> {
+    list_windows()
+}
+
+#[cfg(windows)]
+fn list_windows() -> Result<Vec<WindowInfo>, RecorderError> {
+    let windows = Window::enumerate().map_err(|e| RecorderError::device_not_found(format!("Window ({e})")))?;
+
+    Ok(windows
+        .into_iter()
+        .filter_map(|w| {
+            let title = w.title().ok().filter(|t| !t.trim().is_empty())?;
+            Some(WindowInfo {
+                window_id: w.as_raw_hwnd() as usize as u64,
+                title,
+            })
+        })
+        .collect())
+}
+
+#[cfg(target_os = "linux")]
+fn list_windows() -> Result<Vec<WindowInfo>, RecorderError> {
+    let (conn, _) = connect(None)?;
+    Ok(enumerate_windows(&conn)?
+        .into_iter()
+        .map(|w| WindowInfo {
+            window_id: w.id as u64,
+            title: w.title,
+        })
+        .collect())
+}
+
 /// List webcams.
 #[tauri::command]
 pub async fn get_cameras() -> Result<Vec<CameraInfo>, RecorderError> {
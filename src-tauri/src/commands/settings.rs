@@ -16,6 +16,7 @@ pub async fn update_settings(
     state: State<'_, Arc<AppState>>,
     settings: RecordingSettings,
 ) -> Result<(), RecorderError> {
+    settings.resolution.validate()?;
     state.update_settings(settings.clone());
 
     let mut config: AppConfig = match load_config() {
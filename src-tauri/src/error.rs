@@ -72,6 +72,15 @@ impl RecorderError {
         )
     }
 
+    /// Screen or device capture failed after the device was opened.
+    pub fn capture_failed(reason: impl Into<String>) -> Self {
+        Self::new(
+            "CAPTURE_FAILED",
+            "Capture failed.".to_string(),
+            Some(reason.into()),
+        )
+    }
+
     /// File system error (read/write/create).
     pub fn file_error(reason: impl Into<String>) -> Self {
         Self::new(
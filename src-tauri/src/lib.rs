@@ -1,10 +1,13 @@
 mod commands;
 mod error;
-mod recording;
-mod state;
+// Public for the benchmarks in `tests/`.
+pub mod recording;
+pub mod state;
 mod utils;
 
-use crate::commands::devices::{get_audio_inputs, get_cameras, get_displays, get_system_audio_devices};
+use crate::commands::devices::{
+    get_audio_inputs, get_cameras, get_displays, get_system_audio_devices, get_windows,
+};
 use crate::commands::files::{
     delete_recording, get_last_recording_info, open_recording_in_explorer, open_recordings_folder,
 };
@@ -55,6 +58,7 @@ pub fn run() {
             update_settings,
             // Devices
             get_displays,
+            get_windows,
             get_cameras,
             get_audio_inputs,
             get_system_audio_devices,
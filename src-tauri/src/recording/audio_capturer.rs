@@ -1,21 +1,11 @@
-#![allow(dead_code)]
-
 use crate::error::RecorderError;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{SampleFormat, StreamConfig};
+use cpal::{SampleFormat, StreamConfig, SupportedStreamConfig};
 use parking_lot::Mutex;
 use std::collections::VecDeque;
 use std::sync::Arc;
 
-#[derive(Debug, Clone)]
-pub struct AudioFrame {
-    pub data: Vec<f32>,
-    pub sample_rate: u32,
-    pub channels: u16,
-    pub timestamp: u64,
-}
-
-/// Placeholder microphone capture.
+/// Audio input capture: a microphone, or with `system_audio` whatever the system plays.
 ///
 /// Captures PCM samples via `cpal` stream callbacks into a ring buffer.
 pub struct MicrophoneCapture {
@@ -48,6 +38,65 @@ impl MicrophoneCapture {
             .default_input_config()
             .map_err(|e| RecorderError::device_not_found(format!("Microphone ({e})")))?;
 
+        Self::start(&device, default_config, "microphone")
+    }
+
+    /// Capture what `device_name` (an output device, as listed by `get_system_audio_devices`)
+    /// plays; the default output device when empty.
+    ///
+    /// On Windows this is a WASAPI loopback stream. Elsewhere it needs a monitor source,
+    /// such as PulseAudio's "Monitor of ..." inputs.
+    pub fn system_audio(device_name: Option<&str>) -> Result<Self, RecorderError> {
+        let host = cpal::default_host();
+        let name = device_name.map(str::trim).filter(|s| !s.is_empty());
+
+        #[cfg(windows)]
+        let (device, default_config) = {
+            // cpal opens an input stream on an output device as a loopback capture.
+            let device = match name {
+                Some(name) => host
+                    .output_devices()
+                    .map_err(|e| RecorderError::device_not_found(format!("System audio ({e})")))?
+                    .find(|d| d.name().is_ok_and(|n| n == name)),
+                None => None,
+            }
+            .or_else(|| host.default_output_device())
+            .ok_or_else(|| RecorderError::device_not_found("System audio"))?;
+            let config = device
+                .default_output_config()
+                .map_err(|e| RecorderError::device_not_found(format!("System audio ({e})")))?;
+            (device, config)
+        };
+
+        #[cfg(not(windows))]
+        let (device, default_config) = {
+            let monitors: Vec<(cpal::Device, String)> = host
+                .input_devices()
+                .map_err(|e| RecorderError::device_not_found(format!("System audio ({e})")))?
+                .filter_map(|d| d.name().ok().map(|n| (d, n)))
+                .filter(|(_, n)| n.to_lowercase().contains("monitor"))
+                .collect();
+            let wanted = name.map(|n| n.to_lowercase());
+            let device = monitors
+                .iter()
+                .find(|(_, n)| wanted.as_ref().is_some_and(|w| n.to_lowercase().contains(w.as_str())))
+                .or_else(|| monitors.first())
+                .map(|(d, _)| d.clone())
+                .ok_or_else(|| RecorderError::device_not_found("System audio (no monitor input found)"))?;
+            let config = device
+                .default_input_config()
+                .map_err(|e| RecorderError::device_not_found(format!("System audio ({e})")))?;
+            (device, config)
+        };
+
+        Self::start(&device, default_config, "system audio")
+    }
+
+    fn start(
+        device: &cpal::Device,
+        default_config: SupportedStreamConfig,
+        label: &'static str,
+    ) -> Result<Self, RecorderError> {
         let sample_format = default_config.sample_format();
         let cfg: StreamConfig = default_config.into();
 
@@ -59,8 +108,8 @@ impl MicrophoneCapture {
             .saturating_mul(channels as usize)
             .saturating_mul(10); // ~10s ring buffer
 
-        let err_fn = |err| {
-            eprintln!("RecordFlow: microphone stream error: {err}");
+        let err_fn = move |err| {
+            eprintln!("RecordFlow: {label} stream error: {err}");
         };
 
         let buffer_for_cb = buffer.clone();
@@ -116,7 +165,7 @@ impl MicrophoneCapture {
                 .map_err(|e| RecorderError::encoding_failed(e.to_string()))?,
             other => {
                 return Err(RecorderError::invalid_settings(format!(
-                    "Unsupported microphone sample format: {other:?}"
+                    "Unsupported {label} sample format: {other:?}"
                 )))
             }
         };
@@ -141,6 +190,11 @@ impl MicrophoneCapture {
         self.channels
     }
 
+    /// Throw away everything captured so far, e.g. audio that arrived while paused.
+    pub fn discard_buffered(&self) {
+        self.buffer.lock().clear();
+    }
+
     pub fn take_pcm_i16(&self, sample_count: usize) -> Vec<i16> {
         let mut q = self.buffer.lock();
         let mut out = Vec::with_capacity(sample_count);
@@ -161,47 +215,3 @@ impl MicrophoneCapture {
         out
     }
 }
-
-pub struct AudioMixer {
-    mic_volume: f32,
-    system_volume: f32,
-}
-
-impl AudioMixer {
-    pub fn new() -> Self {
-        Self {
-            mic_volume: 1.0,
-            system_volume: 1.0,
-        }
-    }
-
-    pub fn set_mic_volume(&mut self, volume: f32) {
-        self.mic_volume = volume.clamp(0.0, 1.0);
-    }
-
-    pub fn set_system_audio_volume(&mut self, volume: f32) {
-        self.system_volume = volume.clamp(0.0, 1.0);
-    }
-
-    pub fn mix(&self, mic_frame: &AudioFrame, system_frame: &AudioFrame) -> Result<AudioFrame, RecorderError> {
-        if mic_frame.sample_rate != system_frame.sample_rate {
-            return Err(RecorderError::invalid_settings("Sample rate mismatch"));
-        }
-
-        let len = mic_frame.data.len().max(system_frame.data.len());
-        let mut out = Vec::with_capacity(len);
-
-        for i in 0..len {
-            let a = mic_frame.data.get(i).copied().unwrap_or(0.0) * self.mic_volume;
-            let b = system_frame.data.get(i).copied().unwrap_or(0.0) * self.system_volume;
-            out.push((a + b).clamp(-1.0, 1.0));
-        }
-
-        Ok(AudioFrame {
-            data: out,
-            sample_rate: mic_frame.sample_rate,
-            channels: 2,
-            timestamp: mic_frame.timestamp,
-        })
-    }
-}
@@ -0,0 +1,449 @@
+use crate::error::RecorderError;
+use crate::recording::audio_sink::AudioSink;
+use crate::recording::mux::flac_metadata;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Samples per channel in each frame; 4096 is what the reference encoder uses at 16 bits.
+const BLOCK_SIZE: usize = 4096;
+
+/// Highest fixed predictor order FLAC defines.
+const MAX_ORDER: usize = 4;
+
+/// Rice partitions per subframe go up to `2^MAX_PARTITION_ORDER`.
+const MAX_PARTITION_ORDER: u32 = 8;
+
+/// Parameter 15 marks an escaped partition in 4-bit Rice coding.
+const MAX_RICE_PARAM: u32 = 14;
+
+const BITS_PER_SAMPLE: u32 = 16;
+
+/// Where STREAMINFO's body starts: after `fLaC` and the 4-byte block header.
+const STREAMINFO_OFFSET: u64 = 8;
+
+/// CRC-8, polynomial x^8 + x^2 + x + 1, over the frame header.
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |mut crc, &byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+/// CRC-16, polynomial x^16 + x^15 + x^2 + 1, over the whole frame.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |mut crc, &byte| {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+/// Big-endian bit packer.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u64, bits: u32) {
+        debug_assert!(bits <= 32);
+        if bits == 0 {
+            return;
+        }
+        self.acc = (self.acc << bits) | (value & ((1u64 << bits) - 1));
+        self.bits += bits;
+        while self.bits >= 8 {
+            self.bits -= 8;
+            self.bytes.push((self.acc >> self.bits) as u8);
+        }
+    }
+
+    fn put_signed(&mut self, value: i32, bits: u32) {
+        self.put(value as u32 as u64, bits);
+    }
+
+    fn put_rice(&mut self, value: i32, param: u32) {
+        // Fold the sign into the low bit so small magnitudes stay small.
+        let folded = ((value << 1) ^ (value >> 31)) as u32;
+        let mut quotient = folded >> param;
+        while quotient >= 32 {
+            self.put(0, 32);
+            quotient -= 32;
+        }
+        self.put(1, quotient + 1);
+        self.put(folded as u64, param);
+    }
+
+    /// Pad with zero bits to the next byte.
+    fn align(&mut self) {
+        if self.bits > 0 {
+            self.put(0, 8 - self.bits);
+        }
+    }
+}
+
+/// FLAC's UTF-8-like coding of the frame number.
+fn put_utf8(w: &mut BitWriter, value: u32) {
+    if value < 0x80 {
+        w.put(value as u64, 8);
+        return;
+    }
+    // Each continuation byte carries 6 bits; the lead byte what's left.
+    let len = (2..6).find(|&len| value >> (5 * len + 1) == 0).unwrap_or(6);
+    let lead = (0xFF00u32 >> len) as u8;
+    w.put((lead | (value >> (6 * (len - 1))) as u8) as u64, 8);
+    for i in (0..len - 1).rev() {
+        w.put((0x80 | ((value >> (6 * i)) & 0x3F)) as u64, 8);
+    }
+}
+
+/// Residual of the order-`order` fixed polynomial predictor, from sample `order` on.
+fn fixed_residual(samples: &[i32], order: usize) -> Vec<i32> {
+    let s = samples;
+    (order..s.len())
+        .map(|i| match order {
+            0 => s[i],
+            1 => s[i] - s[i - 1],
+            2 => s[i] - 2 * s[i - 1] + s[i - 2],
+            3 => s[i] - 3 * s[i - 1] + 3 * s[i - 2] - s[i - 3],
+            _ => s[i] - 4 * s[i - 1] + 6 * s[i - 2] - 4 * s[i - 3] + s[i - 4],
+        })
+        .collect()
+}
+
+/// Cheapest Rice parameter for `residual` and the bits it takes.
+fn rice_cost(residual: &[i32]) -> (u32, u64) {
+    let n = residual.len() as u64;
+    let sum: u64 = residual.iter().map(|&r| ((r << 1) ^ (r >> 31)) as u32 as u64).sum();
+    if n == 0 {
+        return (0, 0);
+    }
+    // The best parameter sits around log2 of the mean; check either side of it.
+    let guess = (sum / n).max(1).ilog2().min(MAX_RICE_PARAM);
+    (guess.saturating_sub(1)..=(guess + 1).min(MAX_RICE_PARAM))
+        .map(|k| {
+            let bits: u64 = residual
+                .iter()
+                .map(|&r| (((r << 1) ^ (r >> 31)) as u32 >> k) as u64 + 1 + k as u64)
+                .sum();
+            (k, bits)
+        })
+        .min_by_key(|&(_, bits)| bits)
+        .unwrap_or((0, 0))
+}
+
+/// Partition order and per-partition parameters that code `residual` (the residual of a
+/// `block_len` block after `order` warm-up samples) in the fewest bits.
+fn best_partitions(residual: &[i32], block_len: usize, order: usize) -> (u32, Vec<u32>, u64) {
+    let mut best: Option<(u32, Vec<u32>, u64)> = None;
+    for p in 0..=MAX_PARTITION_ORDER {
+        let parts = 1usize << p;
+        if !block_len.is_multiple_of(parts) || block_len / parts <= order {
+            break;
+        }
+        let part_len = block_len / parts;
+        let (mut params, mut bits, mut start) = (Vec::with_capacity(parts), 0u64, 0);
+        for i in 0..parts {
+            // The first partition is short by the warm-up samples.
+            let len = if i == 0 { part_len - order } else { part_len };
+            let (k, cost) = rice_cost(&residual[start..start + len]);
+            params.push(k);
+            bits += cost + 4;
+            start += len;
+        }
+        if best.as_ref().is_none_or(|b| bits < b.2) {
+            best = Some((p, params, bits));
+        }
+    }
+    best.unwrap_or((0, vec![0], u64::MAX))
+}
+
+/// Write one channel of a block as the smallest of a constant, fixed-predictor or verbatim
+/// subframe.
+fn write_subframe(w: &mut BitWriter, samples: &[i32]) {
+    if samples.iter().all(|&s| s == samples[0]) {
+        w.put(0b0000_0000, 8);
+        w.put_signed(samples[0], BITS_PER_SAMPLE);
+        return;
+    }
+
+    let verbatim_bits = samples.len() as u64 * BITS_PER_SAMPLE as u64;
+    let best = (0..=MAX_ORDER.min(samples.len() - 1))
+        .map(|order| {
+            let residual = fixed_residual(samples, order);
+            let (p, params, bits) = best_partitions(&residual, samples.len(), order);
+            (order, residual, p, params, bits + (order as u64 * BITS_PER_SAMPLE as u64) + 6)
+        })
+        .min_by_key(|c| c.4);
+
+    match best {
+        Some((order, residual, p, params, bits)) if bits < verbatim_bits => {
+            w.put(0b0001_0000 | (order as u64) << 1, 8);
+            for &s in &samples[..order] {
+                w.put_signed(s, BITS_PER_SAMPLE);
+            }
+            // Rice coding with 4-bit parameters, then the partition order.
+            w.put(0b00, 2);
+            w.put(p as u64, 4);
+            let part_len = samples.len() >> p;
+            let mut start = 0;
+            for (i, &k) in params.iter().enumerate() {
+                let len = if i == 0 { part_len - order } else { part_len };
+                w.put(k as u64, 4);
+                for &r in &residual[start..start + len] {
+                    w.put_rice(r, k);
+                }
+                start += len;
+            }
+        }
+        _ => {
+            w.put(0b0000_0010, 8);
+            for &s in samples {
+                w.put_signed(s, BITS_PER_SAMPLE);
+            }
+        }
+    }
+}
+
+/// Lossless 16-bit FLAC encoder: interleaved PCM in, fixed 4096-sample frames out, with
+/// fixed-predictor subframes. No libFLAC needed; frames come out somewhat larger than
+/// `flac -5` since LPC and stereo decorrelation are left out.
+///
+/// Produces frames only; `FlacWriter` puts them in a `.flac` file, muxers in MP4 or
+/// Matroska tracks.
+pub struct FlacEncoder {
+    sample_rate: u32,
+    channels: u16,
+    /// Interleaved samples not yet making up a full block.
+    pending: Vec<i16>,
+    frames_written: u32,
+    /// Per channel.
+    samples_written: u64,
+    min_frame_size: u32,
+    max_frame_size: u32,
+}
+
+impl FlacEncoder {
+    pub fn new(sample_rate: u32, channels: u16) -> Result<Self, RecorderError> {
+        if !(1..=8).contains(&channels) {
+            return Err(RecorderError::invalid_settings(format!(
+                "FLAC holds 1-8 channels, got {channels}"
+            )));
+        }
+        if !(1..=655_350).contains(&sample_rate) {
+            return Err(RecorderError::invalid_settings(format!(
+                "FLAC can't store a {sample_rate} Hz sample rate"
+            )));
+        }
+        Ok(Self {
+            sample_rate,
+            channels,
+            pending: Vec::with_capacity(BLOCK_SIZE * channels as usize),
+            frames_written: 0,
+            samples_written: 0,
+            min_frame_size: 0,
+            max_frame_size: 0,
+        })
+    }
+
+    /// The STREAMINFO block body for what has been encoded so far. Before the first frame
+    /// the sample count and frame sizes are zero, which decoders read as "unknown".
+    pub fn stream_info(&self) -> [u8; 34] {
+        let mut w = BitWriter::default();
+        w.put(BLOCK_SIZE as u64, 16);
+        w.put(BLOCK_SIZE as u64, 16);
+        w.put(self.min_frame_size as u64, 24);
+        w.put(self.max_frame_size as u64, 24);
+        w.put(self.sample_rate as u64, 20);
+        w.put(self.channels as u64 - 1, 3);
+        w.put(BITS_PER_SAMPLE as u64 - 1, 5);
+        w.put(self.samples_written >> 32, 4);
+        w.put(self.samples_written & 0xFFFF_FFFF, 32);
+        w.bytes.resize(34, 0);
+        let mut out = [0; 34];
+        out.copy_from_slice(&w.bytes);
+        out
+    }
+
+    /// Queue interleaved samples for the next frames.
+    pub fn push(&mut self, pcm: &[i16]) {
+        self.pending.extend_from_slice(pcm);
+    }
+
+    /// The next full frame and the samples per channel in it, or with `flush` whatever is
+    /// left as a shorter last frame.
+    pub fn next_frame(&mut self, flush: bool) -> Option<(Vec<u8>, u32)> {
+        let queued = self.pending.len() / self.channels as usize;
+        let len = if queued >= BLOCK_SIZE { BLOCK_SIZE } else if flush { queued } else { 0 };
+        (len > 0).then(|| (self.encode_frame(len), len as u32))
+    }
+
+    /// Encode the first `len` samples per channel of `pending` as one frame.
+    fn encode_frame(&mut self, len: usize) -> Vec<u8> {
+        let channels = self.channels as usize;
+        let mut w = BitWriter::default();
+
+        // Sync code, fixed block size.
+        w.put(0xFFF8, 16);
+        let size_code = if len == BLOCK_SIZE { 0b1100 } else { 0b0111 };
+        // Sample rate comes from STREAMINFO.
+        w.put(size_code, 4);
+        w.put(0b0000, 4);
+        // Independent channels, 16 bits per sample.
+        w.put(channels as u64 - 1, 4);
+        w.put(0b100, 3);
+        w.put(0, 1);
+        put_utf8(&mut w, self.frames_written);
+        if size_code == 0b0111 {
+            w.put(len as u64 - 1, 16);
+        }
+        let crc = crc8(&w.bytes);
+        w.put(crc as u64, 8);
+
+        let mut channel = Vec::with_capacity(len);
+        for c in 0..channels {
+            channel.clear();
+            channel.extend(self.pending[..len * channels].iter().skip(c).step_by(channels).map(|&s| s as i32));
+            write_subframe(&mut w, &channel);
+        }
+        w.align();
+        let crc = crc16(&w.bytes);
+        w.put(crc as u64, 16);
+
+        let size = w.bytes.len() as u32;
+        self.min_frame_size = if self.frames_written == 0 { size } else { self.min_frame_size.min(size) };
+        self.max_frame_size = self.max_frame_size.max(size);
+        self.frames_written += 1;
+        self.samples_written += len as u64;
+        self.pending.drain(..len * channels);
+        w.bytes
+    }
+}
+
+/// A `.flac` file written by `FlacEncoder`.
+///
+/// STREAMINFO is written up front and its sample count and frame sizes filled in on
+/// finalize. The MD5 signature stays zero, which decoders read as "not computed".
+pub struct FlacWriter {
+    /// `None` once finalized.
+    file: Option<BufWriter<File>>,
+    encoder: FlacEncoder,
+}
+
+impl FlacWriter {
+    pub fn new(path: &Path, sample_rate: u32, channels: u16) -> Result<Self, RecorderError> {
+        let encoder = FlacEncoder::new(sample_rate, channels)?;
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&[b"fLaC".as_slice(), &flac_metadata(&encoder.stream_info())].concat())?;
+        Ok(Self { file: Some(file), encoder })
+    }
+
+    /// Encode and write frames while there are any, down to a partial one with `flush`.
+    fn write_frames(&mut self, flush: bool) -> Result<(), RecorderError> {
+        let file = self
+            .file
+            .as_mut()
+            .ok_or_else(|| RecorderError::encoding_failed("FLAC writer already finalized"))?;
+        while let Some((frame, _)) = self.encoder.next_frame(flush) {
+            file.write_all(&frame)?;
+        }
+        Ok(())
+    }
+}
+
+impl AudioSink for FlacWriter {
+    fn write(&mut self, pcm: &[i16]) -> Result<(), RecorderError> {
+        if self.file.is_none() {
+            return Err(RecorderError::encoding_failed("FLAC writer already finalized"));
+        }
+        self.encoder.push(pcm);
+        self.write_frames(false)
+    }
+
+    fn finalize(&mut self) -> Result<(), RecorderError> {
+        if self.file.is_none() {
+            return Ok(());
+        }
+        self.write_frames(true)?;
+
+        let info = self.encoder.stream_info();
+        if let Some(mut file) = self.file.take() {
+            file.seek(SeekFrom::Start(STREAMINFO_OFFSET))?;
+            file.write_all(&info)?;
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for FlacWriter {
+    fn drop(&mut self) {
+        // Best-effort: write the last block and the sample count.
+        let _ = self.finalize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::test_util::temp_path;
+
+    /// Deterministic full-scale noise, so failures reproduce.
+    fn noise(len: usize, seed: u32) -> Vec<i16> {
+        let mut state = seed.wrapping_mul(747_796_405).wrapping_add(1);
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 16) as i16
+            })
+            .collect()
+    }
+
+    /// Write `pcm` in uneven chunks, then decode it with claxon and compare.
+    fn round_trip(name: &str, sample_rate: u32, channels: u16, pcm: &[i16]) {
+        let path = temp_path(name);
+        let mut writer = FlacWriter::new(&path, sample_rate, channels).unwrap();
+        for chunk in pcm.chunks(1234 * channels as usize) {
+            writer.write(chunk).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let mut reader = claxon::FlacReader::open(&path).unwrap();
+        let info = reader.streaminfo();
+        assert_eq!(info.sample_rate, sample_rate);
+        assert_eq!(info.channels, channels as u32);
+        assert_eq!(info.bits_per_sample, 16);
+        assert_eq!(info.samples, Some((pcm.len() / channels as usize) as u64));
+        let decoded: Vec<i16> = reader.samples().map(|s| s.unwrap() as i16).collect();
+        assert!(decoded == pcm, "{name} decodes differently");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn noise_round_trips() {
+        // Three full blocks and a partial one.
+        let frames = 3 * BLOCK_SIZE + 1000;
+        round_trip("noise.flac", 48_000, 2, &noise(frames * 2, 1));
+        round_trip("noise_mono.flac", 44_100, 1, &noise(frames, 2));
+    }
+
+    #[test]
+    fn constant_and_smooth_round_trip() {
+        let frames = 2 * BLOCK_SIZE + 17;
+        // Left sits at the negative limit, right is a sine: constant and fixed-predictor
+        // subframes in the same frames.
+        let pcm: Vec<i16> = (0..frames)
+            .flat_map(|i| [i16::MIN, ((i as f64 * 0.05).sin() * 20_000.0) as i16])
+            .collect();
+        round_trip("constant.flac", 48_000, 2, &pcm);
+        round_trip("silence.flac", 8_000, 1, &[0; 100]);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::error::RecorderError;
+use crate::recording::audio_sink::AudioSink;
+use std::path::Path;
+use windows::core::{HSTRING, PCWSTR};
+use windows::Win32::Media::MediaFoundation::*;
+use windows::Win32::System::Com::{CoInitializeEx, CoUninitialize, COINIT_MULTITHREADED};
+
+const HNS_PER_SEC: i64 = 10_000_000; // 100-ns units
+
+fn win_err(context: &str, e: windows::core::Error) -> RecorderError {
+    RecorderError::encoding_failed(format!("{context}: {e}"))
+}
+
+/// AAC-LC in an MPEG-4 audio file (`.m4a`) through the Media Foundation sink writer; the
+/// same encoder settings as the audio track of `VideoEncoder`.
+pub struct AacWriter {
+    writer: IMFSinkWriter,
+    stream: u32,
+    sample_rate: u32,
+    channels: u16,
+    /// Per channel.
+    written_frames: u64,
+
+    finished: bool,
+    com_inited: bool,
+    mf_started: bool,
+}
+
+impl AacWriter {
+    pub fn new(path: &Path, sample_rate: u32, channels: u16) -> Result<Self, RecorderError> {
+        let (sr, ch) = (sample_rate.max(1), channels.max(1));
+
+        unsafe {
+            CoInitializeEx(None, COINIT_MULTITHREADED)
+                .ok()
+                .map_err(|e| win_err("COM init failed", e))?;
+            MFStartup(MF_VERSION, MFSTARTUP_NOSOCKET).map_err(|e| win_err("MFStartup failed", e))?;
+        }
+
+        let url = HSTRING::from(path.as_os_str());
+        let url = PCWSTR(url.as_ptr());
+
+        let mut attrs: Option<IMFAttributes> = None;
+        unsafe {
+            MFCreateAttributes(&mut attrs, 1).map_err(|e| win_err("MFCreateAttributes failed", e))?;
+        }
+        let attrs = attrs.ok_or_else(|| RecorderError::encoding_failed("MFCreateAttributes returned null"))?;
+        // The sink writer doesn't know `.m4a`; ask for MPEG-4 explicitly.
+        unsafe {
+            attrs
+                .SetGUID(&MF_TRANSCODE_CONTAINERTYPE, &MFTranscodeContainerType_MPEG4)
+                .map_err(|e| win_err("SetGUID(container_type)", e))?;
+        }
+
+        let writer = unsafe {
+            MFCreateSinkWriterFromURL(url, None, Some(&attrs))
+                .map_err(|e| win_err("MFCreateSinkWriterFromURL failed", e))?
+        };
+
+        let audio_out = unsafe { MFCreateMediaType().map_err(|e| win_err("MFCreateMediaType(audio_out)", e))? };
+        unsafe {
+            audio_out
+                .SetGUID(&MF_MT_MAJOR_TYPE, &MFMediaType_Audio)
+                .map_err(|e| win_err("SetGUID(audio_out.major)", e))?;
+            audio_out
+                .SetGUID(&MF_MT_SUBTYPE, &MFAudioFormat_AAC)
+                .map_err(|e| win_err("SetGUID(audio_out.subtype)", e))?;
+            audio_out
+                .SetUINT32(&MF_MT_AUDIO_SAMPLES_PER_SECOND, sr)
+                .map_err(|e| win_err("SetUINT32(audio_out.sample_rate)", e))?;
+            audio_out
+                .SetUINT32(&MF_MT_AUDIO_NUM_CHANNELS, ch as u32)
+                .map_err(|e| win_err("SetUINT32(audio_out.channels)", e))?;
+            audio_out
+                .SetUINT32(&MF_MT_AUDIO_BITS_PER_SAMPLE, 16)
+                .map_err(|e| win_err("SetUINT32(audio_out.bits_per_sample)", e))?;
+
+            // ~128kbps mono / 192kbps stereo, like the video backend's audio track.
+            let aac_bps: u32 = if ch <= 1 { 128_000 } else { 192_000 };
+            audio_out
+                .SetUINT32(&MF_MT_AUDIO_AVG_BYTES_PER_SECOND, aac_bps / 8)
+                .map_err(|e| win_err("SetUINT32(audio_out.avg_bytes_per_sec)", e))?;
+            let _ = audio_out.SetUINT32(&MF_MT_AAC_PAYLOAD_TYPE, 0);
+            let _ = audio_out.SetUINT32(&MF_MT_AAC_AUDIO_PROFILE_LEVEL_INDICATION, 0x29);
+        }
+
+        let stream = unsafe { writer.AddStream(&audio_out).map_err(|e| win_err("AddStream(audio)", e))? };
+
+        let audio_in = unsafe { MFCreateMediaType().map_err(|e| win_err("MFCreateMediaType(audio_in)", e))? };
+        unsafe {
+            audio_in
+                .SetGUID(&MF_MT_MAJOR_TYPE, &MFMediaType_Audio)
+                .map_err(|e| win_err("SetGUID(audio_in.major)", e))?;
+            audio_in
+                .SetGUID(&MF_MT_SUBTYPE, &MFAudioFormat_PCM)
+                .map_err(|e| win_err("SetGUID(audio_in.subtype)", e))?;
+            audio_in
+                .SetUINT32(&MF_MT_AUDIO_SAMPLES_PER_SECOND, sr)
+                .map_err(|e| win_err("SetUINT32(audio_in.sample_rate)", e))?;
+            audio_in
+                .SetUINT32(&MF_MT_AUDIO_NUM_CHANNELS, ch as u32)
+                .map_err(|e| win_err("SetUINT32(audio_in.channels)", e))?;
+            audio_in
+                .SetUINT32(&MF_MT_AUDIO_BITS_PER_SAMPLE, 16)
+                .map_err(|e| win_err("SetUINT32(audio_in.bits_per_sample)", e))?;
+            let block_align = (ch as u32).saturating_mul(2);
+            audio_in
+                .SetUINT32(&MF_MT_AUDIO_BLOCK_ALIGNMENT, block_align)
+                .map_err(|e| win_err("SetUINT32(audio_in.block_align)", e))?;
+            audio_in
+                .SetUINT32(&MF_MT_AUDIO_AVG_BYTES_PER_SECOND, sr.saturating_mul(block_align))
+                .map_err(|e| win_err("SetUINT32(audio_in.avg_bytes_per_sec)", e))?;
+
+            writer
+                .SetInputMediaType(stream, &audio_in, None)
+                .map_err(|e| win_err("SetInputMediaType(audio)", e))?;
+            writer.BeginWriting().map_err(|e| win_err("BeginWriting", e))?;
+        }
+
+        Ok(Self {
+            writer,
+            stream,
+            sample_rate: sr,
+            channels: ch,
+            written_frames: 0,
+            finished: false,
+            com_inited: true,
+            mf_started: true,
+        })
+    }
+
+    fn shutdown(&mut self) {
+        if self.mf_started {
+            let _ = unsafe { MFShutdown() };
+        }
+        if self.com_inited {
+            unsafe { CoUninitialize() };
+        }
+        self.mf_started = false;
+        self.com_inited = false;
+    }
+}
+
+impl AudioSink for AacWriter {
+    fn write(&mut self, pcm: &[i16]) -> Result<(), RecorderError> {
+        if self.finished {
+            return Err(RecorderError::encoding_failed("AAC writer already finalized"));
+        }
+        let frames = (pcm.len() / self.channels as usize) as u64;
+        if frames == 0 {
+            return Ok(());
+        }
+        let bytes: Vec<u8> = pcm[..frames as usize * self.channels as usize]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+
+        let sample_time_hns = (self.written_frames as i64).saturating_mul(HNS_PER_SEC) / self.sample_rate as i64;
+        let sample_duration_hns = (frames as i64).saturating_mul(HNS_PER_SEC) / self.sample_rate as i64;
+        self.written_frames = self.written_frames.saturating_add(frames);
+
+        let buffer = unsafe {
+            MFCreateMemoryBuffer(bytes.len() as u32).map_err(|e| win_err("MFCreateMemoryBuffer(audio)", e))?
+        };
+
+        unsafe {
+            let mut ptr: *mut u8 = std::ptr::null_mut();
+            buffer
+                .Lock(&mut ptr, None, None)
+                .map_err(|e| win_err("IMFMediaBuffer::Lock(audio)", e))?;
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, bytes.len());
+            buffer
+                .Unlock()
+                .map_err(|e| win_err("IMFMediaBuffer::Unlock(audio)", e))?;
+            buffer
+                .SetCurrentLength(bytes.len() as u32)
+                .map_err(|e| win_err("IMFMediaBuffer::SetCurrentLength(audio)", e))?;
+
+            let sample = MFCreateSample().map_err(|e| win_err("MFCreateSample(audio)", e))?;
+            sample
+                .AddBuffer(&buffer)
+                .map_err(|e| win_err("IMFSample::AddBuffer(audio)", e))?;
+            sample
+                .SetSampleTime(sample_time_hns)
+                .map_err(|e| win_err("IMFSample::SetSampleTime(audio)", e))?;
+            sample
+                .SetSampleDuration(sample_duration_hns.max(1))
+                .map_err(|e| win_err("IMFSample::SetSampleDuration(audio)", e))?;
+
+            self.writer
+                .WriteSample(self.stream, &sample)
+                .map_err(|e| win_err("WriteSample(audio)", e))?;
+        }
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<(), RecorderError> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        let result = unsafe { self.writer.Finalize() }.map_err(|e| win_err("Finalize", e));
+        self.shutdown();
+        result
+    }
+}
+
+impl Drop for AacWriter {
+    fn drop(&mut self) {
+        // Best-effort cleanup.
+        let _ = self.finalize();
+    }
+}
@@ -0,0 +1,51 @@
+use crate::error::RecorderError;
+use crate::recording::audio_sink::AudioSink;
+use hound::{SampleFormat, WavSpec};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+fn wav_err(context: &str, e: hound::Error) -> RecorderError {
+    RecorderError::encoding_failed(format!("{context}: {e}"))
+}
+
+/// 16-bit PCM WAV.
+pub struct WavWriter {
+    /// `None` once finalized.
+    writer: Option<hound::WavWriter<BufWriter<File>>>,
+}
+
+impl WavWriter {
+    pub fn new(path: &Path, sample_rate: u32, channels: u16) -> Result<Self, RecorderError> {
+        let spec = WavSpec { channels, sample_rate, bits_per_sample: 16, sample_format: SampleFormat::Int };
+        let writer = hound::WavWriter::create(path, spec).map_err(|e| wav_err("WAV create failed", e))?;
+        Ok(Self { writer: Some(writer) })
+    }
+}
+
+impl AudioSink for WavWriter {
+    fn write(&mut self, pcm: &[i16]) -> Result<(), RecorderError> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| RecorderError::encoding_failed("WAV writer already finalized"))?;
+        for &sample in pcm {
+            writer.write_sample(sample).map_err(|e| wav_err("WAV write failed", e))?;
+        }
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<(), RecorderError> {
+        match self.writer.take() {
+            Some(writer) => writer.finalize().map_err(|e| wav_err("WAV finalize failed", e)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        // Best-effort: fix up the header sizes.
+        let _ = self.finalize();
+    }
+}
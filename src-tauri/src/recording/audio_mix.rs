@@ -0,0 +1,271 @@
+use crate::recording::audio_capturer::MicrophoneCapture;
+use std::time::Duration;
+
+/// Input frames kept from the previous pull, since interpolation can reach back that far.
+const HISTORY_FRAMES: usize = 2;
+
+/// Something `AudioMix` pulls interleaved 16-bit audio from.
+pub trait AudioInput {
+    fn sample_rate(&self) -> u32;
+
+    fn channels(&self) -> u16;
+
+    /// The next `sample_count` interleaved samples, padded with silence past what has been
+    /// captured.
+    fn take_pcm_i16(&self, sample_count: usize) -> Vec<i16>;
+
+    /// Drop everything captured so far.
+    fn discard_buffered(&self);
+}
+
+impl AudioInput for MicrophoneCapture {
+    fn sample_rate(&self) -> u32 {
+        MicrophoneCapture::sample_rate(self)
+    }
+
+    fn channels(&self) -> u16 {
+        MicrophoneCapture::channels(self)
+    }
+
+    fn take_pcm_i16(&self, sample_count: usize) -> Vec<i16> {
+        MicrophoneCapture::take_pcm_i16(self, sample_count)
+    }
+
+    fn discard_buffered(&self) {
+        MicrophoneCapture::discard_buffered(self)
+    }
+}
+
+/// One captured input, pulled at its own rate and mapped onto the mix format.
+struct MixInput {
+    capture: Box<dyn AudioInput>,
+    volume: f32,
+    /// Frames taken from the capture so far.
+    taken: u64,
+    /// The last `HISTORY_FRAMES` frames taken, interleaved.
+    history: Vec<i16>,
+}
+
+/// Mixes the microphone and system audio into one interleaved 16-bit stream.
+///
+/// Each input is pulled by recording time at its own sample rate, then resampled (linear)
+/// and channel-mapped onto the output format, so devices running at 44.1 and 48 kHz can be
+/// mixed. Inputs that run dry are padded with silence.
+pub struct AudioMix {
+    inputs: Vec<MixInput>,
+    sample_rate: u32,
+    channels: u16,
+    /// Frames produced so far.
+    written: u64,
+}
+
+impl AudioMix {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self { inputs: Vec::new(), sample_rate: sample_rate.max(1), channels: channels.max(1), written: 0 }
+    }
+
+    pub fn add_input(&mut self, capture: impl AudioInput + 'static, volume: f32) {
+        let history = vec![0; HISTORY_FRAMES * capture.channels().max(1) as usize];
+        let capture = Box::new(capture);
+        self.inputs.push(MixInput { capture, volume: volume.clamp(0.0, 1.0), taken: 0, history });
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Drop whatever the inputs captured since the last pull, e.g. while paused.
+    pub fn discard_buffered(&self) {
+        for input in &self.inputs {
+            input.capture.discard_buffered();
+        }
+    }
+
+    /// Mixed samples from where the last pull stopped up to recording time `elapsed`.
+    pub fn pull(&mut self, elapsed: Duration) -> Vec<i16> {
+        let target = (elapsed.as_nanos() * self.sample_rate as u128 / 1_000_000_000) as u64;
+        let frames = target.saturating_sub(self.written) as usize;
+        if frames == 0 {
+            return Vec::new();
+        }
+        let out_channels = self.channels as usize;
+        let mut mix = vec![0f32; frames * out_channels];
+
+        for input in &mut self.inputs {
+            let rate = input.capture.sample_rate().max(1) as u64;
+            let in_channels = input.capture.channels().max(1) as usize;
+            // Up to the frame after the last output position, the right-hand neighbour it
+            // interpolates with.
+            let in_target = (target - 1) * rate / self.sample_rate as u64 + 2;
+            let in_frames = in_target.saturating_sub(input.taken) as usize;
+            // `window` starts `HISTORY_FRAMES` before the first frame taken now.
+            let mut window = std::mem::take(&mut input.history);
+            window.extend(input.capture.take_pcm_i16(in_frames * in_channels));
+            let window_frames = window.len() / in_channels;
+            let first = input.taken as i64 - HISTORY_FRAMES as i64;
+            let sample = |frame: i64, c: usize| {
+                let i = (frame - first).clamp(0, window_frames as i64 - 1) as usize;
+                window[i * in_channels + c.min(in_channels - 1)] as f32
+            };
+
+            for (j, out) in mix.chunks_exact_mut(out_channels).enumerate() {
+                // Position of this output frame on the input's timeline.
+                let pos = (self.written + j as u64) as f64 * rate as f64 / self.sample_rate as f64;
+                let (frame, frac) = (pos.floor() as i64, pos.fract() as f32);
+                let at = |c: usize| {
+                    let (s0, s1) = (sample(frame, c), sample(frame + 1, c));
+                    s0 + (s1 - s0) * frac
+                };
+                if in_channels == out_channels {
+                    for (c, o) in out.iter_mut().enumerate() {
+                        *o += at(c) * input.volume;
+                    }
+                } else if out_channels == 1 {
+                    out[0] += (0..in_channels).map(&at).sum::<f32>() / in_channels as f32 * input.volume;
+                } else if in_channels == 1 {
+                    let s = at(0) * input.volume;
+                    out.iter_mut().for_each(|o| *o += s);
+                } else {
+                    // Keep the channels both layouts share; extra output channels stay silent.
+                    for (c, o) in out.iter_mut().enumerate().take(in_channels) {
+                        *o += at(c) * input.volume;
+                    }
+                }
+            }
+
+            input.history = window[window.len() - HISTORY_FRAMES * in_channels..].to_vec();
+            input.taken += in_frames as u64;
+        }
+
+        self.written = target;
+        mix.into_iter()
+            .map(|s| s.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+
+    /// Canned input: plays `samples` once, then silence.
+    struct Canned {
+        sample_rate: u32,
+        channels: u16,
+        samples: RefCell<VecDeque<i16>>,
+    }
+
+    impl Canned {
+        fn new(sample_rate: u32, channels: u16, samples: impl IntoIterator<Item = i16>) -> Self {
+            Self { sample_rate, channels, samples: RefCell::new(samples.into_iter().collect()) }
+        }
+    }
+
+    impl AudioInput for Canned {
+        fn sample_rate(&self) -> u32 {
+            self.sample_rate
+        }
+
+        fn channels(&self) -> u16 {
+            self.channels
+        }
+
+        fn take_pcm_i16(&self, sample_count: usize) -> Vec<i16> {
+            let mut samples = self.samples.borrow_mut();
+            (0..sample_count).map(|_| samples.pop_front().unwrap_or(0)).collect()
+        }
+
+        fn discard_buffered(&self) {
+            self.samples.borrow_mut().clear();
+        }
+    }
+
+    fn sine(rate: u32, hz: f64, frames: usize) -> impl Iterator<Item = i16> {
+        (0..frames).map(move |i| ((i as f64 * hz / rate as f64 * std::f64::consts::TAU).sin() * 10_000.0) as i16)
+    }
+
+    /// Pull up to `until` in steps of `step`.
+    fn pull_in_steps(mix: &mut AudioMix, step: Duration, until: Duration) -> Vec<i16> {
+        let mut out = Vec::new();
+        let mut elapsed = Duration::ZERO;
+        while elapsed < until {
+            elapsed = (elapsed + step).min(until);
+            out.extend(mix.pull(elapsed));
+        }
+        out
+    }
+
+    #[test]
+    fn same_format_passes_through() {
+        let ramp: Vec<i16> = (0..4800).map(|i| i as i16 - 2400).collect();
+        let mut mix = AudioMix::new(48_000, 1);
+        mix.add_input(Canned::new(48_000, 1, ramp.clone()), 1.0);
+        assert_eq!(pull_in_steps(&mut mix, Duration::from_millis(7), Duration::from_millis(100)), ramp);
+    }
+
+    #[test]
+    fn resamples_44_1_to_48_khz() {
+        let mut mix = AudioMix::new(48_000, 1);
+        mix.add_input(Canned::new(44_100, 1, sine(44_100, 1000.0, 44_200)), 1.0);
+        let out = pull_in_steps(&mut mix, Duration::from_millis(10), Duration::from_secs(1));
+        assert_eq!(out.len(), 48_000);
+        // Linear interpolation of a 1 kHz tone stays within a few LSB of the ideal one.
+        let worst = out.iter().zip(sine(48_000, 1000.0, 48_000)).map(|(&a, b)| a.abs_diff(b)).max().unwrap();
+        assert!(worst <= 40, "off by up to {worst}");
+    }
+
+    #[test]
+    fn history_bridges_pulls() {
+        // Pulling in odd steps has to give the same samples as pulling once: frames needed
+        // for interpolation across a pull boundary come from the history.
+        let output = |step| {
+            let mut mix = AudioMix::new(48_000, 2);
+            mix.add_input(Canned::new(44_100, 2, sine(44_100, 440.0, 2 * 4_500)), 1.0);
+            pull_in_steps(&mut mix, step, Duration::from_millis(100))
+        };
+        assert_eq!(output(Duration::from_millis(3)), output(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn channels_are_mapped() {
+        let one_frame = |mix_channels, input: Canned| {
+            let mut mix = AudioMix::new(48_000, mix_channels);
+            mix.add_input(input, 1.0);
+            let out = mix.pull(Duration::from_millis(1));
+            assert_eq!(out.len(), 48 * mix_channels as usize);
+            out[..mix_channels as usize].to_vec()
+        };
+        let stereo = || Canned::new(48_000, 2, [1000, 3000].repeat(48));
+
+        // Mono goes to every channel, stereo into mono is averaged, shared channels are kept.
+        assert_eq!(one_frame(2, Canned::new(48_000, 1, [1000; 48])), [1000, 1000]);
+        assert_eq!(one_frame(1, stereo()), [2000]);
+        assert_eq!(one_frame(2, stereo()), [1000, 3000]);
+        assert_eq!(one_frame(4, stereo()), [1000, 3000, 0, 0]);
+    }
+
+    #[test]
+    fn inputs_are_mixed_at_their_volume() {
+        let mut mix = AudioMix::new(48_000, 1);
+        mix.add_input(Canned::new(48_000, 1, [20_000; 96]), 0.5);
+        mix.add_input(Canned::new(48_000, 1, [4_000; 48].into_iter().chain([30_000; 48])), 1.0);
+        let out = mix.pull(Duration::from_millis(2));
+        // 10000 + 4000, then clipped instead of wrapping around.
+        assert_eq!(out, [[14_000; 48], [i16::MAX; 48]].concat());
+    }
+
+    #[test]
+    fn dry_inputs_are_padded_with_silence() {
+        let mut mix = AudioMix::new(48_000, 1);
+        mix.add_input(Canned::new(48_000, 1, [500; 100]), 1.0);
+        let out = mix.pull(Duration::from_millis(10));
+        assert_eq!(out, [[500; 100].as_slice(), &[0; 380]].concat());
+        assert_eq!(mix.pull(Duration::from_millis(20)), [0; 480]);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::error::RecorderError;
+use crate::recording::audio_encoder_flac::FlacWriter;
+#[cfg(windows)]
+use crate::recording::audio_encoder_mf::AacWriter;
+use crate::recording::audio_encoder_wav::WavWriter;
+use crate::state::app_state::AudioFormat;
+use std::path::Path;
+
+/// Destination of an audio-only recording.
+pub trait AudioSink {
+    /// Interleaved 16-bit samples in the format the sink was opened with.
+    fn write(&mut self, pcm: &[i16]) -> Result<(), RecorderError>;
+
+    /// Flush everything and close the file. Safe to call twice.
+    fn finalize(&mut self) -> Result<(), RecorderError>;
+}
+
+/// Sample rate and channel count `format` will be written with, as close to the captured
+/// `sample_rate` and `channels` as the format allows.
+pub fn sink_format(format: AudioFormat, sample_rate: u32, channels: u16) -> (u32, u16) {
+    match format {
+        AudioFormat::Wav => (sample_rate, channels),
+        AudioFormat::Flac => (sample_rate, channels.clamp(1, 8)),
+        // Media Foundation's AAC encoder only takes 44.1/48 kHz mono or stereo.
+        AudioFormat::M4a => {
+            let rate = if sample_rate.is_multiple_of(11_025) { 44_100 } else { 48_000 };
+            (rate, channels.clamp(1, 2))
+        }
+    }
+}
+
+/// Open a `format` file at `path`. `sample_rate` and `channels` should come from
+/// `sink_format`.
+pub fn open_audio_sink(
+    path: &Path,
+    format: AudioFormat,
+    sample_rate: u32,
+    channels: u16,
+) -> Result<Box<dyn AudioSink>, RecorderError> {
+    let sink: Box<dyn AudioSink> = match format {
+        AudioFormat::Wav => Box::new(WavWriter::new(path, sample_rate, channels)?),
+        AudioFormat::Flac => Box::new(FlacWriter::new(path, sample_rate, channels)?),
+        #[cfg(windows)]
+        AudioFormat::M4a => Box::new(AacWriter::new(path, sample_rate, channels)?),
+        #[cfg(not(windows))]
+        AudioFormat::M4a => {
+            return Err(RecorderError::invalid_settings(
+                "M4A recording needs Media Foundation's AAC encoder, which is Windows only",
+            ))
+        }
+    };
+    Ok(sink)
+}
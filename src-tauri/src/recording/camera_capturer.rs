@@ -1,5 +1,5 @@
 use crate::error::RecorderError;
-use crate::recording::screen_capturer::Frame;
+use crate::recording::frame_source::{Frame, FrameSource};
 use nokhwa::pixel_format::RgbFormat;
 use nokhwa::utils::{ApiBackend, CameraFormat, CameraIndex, FrameFormat, RequestedFormat, RequestedFormatType, Resolution};
 use nokhwa::Camera;
@@ -50,8 +50,10 @@ impl CameraCapturer {
             frame_number: 0,
         })
     }
+}
 
-    pub fn capture_frame(&mut self) -> Result<Frame, RecorderError> {
+impl FrameSource for CameraCapturer {
+    fn capture_frame(&mut self) -> Result<Frame, RecorderError> {
         self.frame_number += 1;
 
         let buffer = self
@@ -77,7 +79,7 @@ impl CameraCapturer {
         }
 
         Ok(Frame {
-            data: bgra,
+            data: bgra.into(),
             width,
             height,
             timestamp: 0,
@@ -85,7 +87,12 @@ impl CameraCapturer {
         })
     }
 
-    pub fn stop(&mut self) {
+    fn dimensions(&self) -> (u32, u32) {
+        let res = self.camera.resolution();
+        (res.width(), res.height())
+    }
+
+    fn stop(&mut self) {
         let _ = self.camera.stop_stream();
     }
 }
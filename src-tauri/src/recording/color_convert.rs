@@ -0,0 +1,557 @@
+use crate::error::RecorderError;
+use crate::recording::frame_pool::{FrameBuffer, FramePool};
+use crate::state::app_state::{ColorMatrix, ColorRange, RecordingSettings};
+
+/// Fractional bits of the fixed-point coefficients. The largest one (BT.709 green, full
+/// range) still fits an `i16` for `_mm_madd_epi16`.
+const PRECISION: u32 = 15;
+const ONE: f64 = (1 << PRECISION) as f64;
+const ROUND: i32 = 1 << (PRECISION - 1);
+/// Chroma works on the sum of a 2x2 block, hence two extra bits.
+const CHROMA_SHIFT: u32 = PRECISION + 2;
+const CHROMA_BIAS: i32 = (128 << CHROMA_SHIFT) + (1 << (CHROMA_SHIFT - 1));
+
+/// Memory layout of 4:2:0 output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YuvLayout {
+    /// Y plane followed by one plane of interleaved U/V pairs; what Media Foundation and
+    /// most hardware encoders take.
+    Nv12,
+    /// Y, U and V planes one after another.
+    I420,
+}
+
+impl YuvLayout {
+    /// Bytes in one `width` x `height` frame.
+    pub fn frame_len(self, width: u32, height: u32) -> usize {
+        let (cw, ch) = chroma_size(width as usize, height as usize);
+        width as usize * height as usize + 2 * cw * ch
+    }
+}
+
+fn chroma_size(width: usize, height: usize) -> (usize, usize) {
+    (width.div_ceil(2), height.div_ceil(2))
+}
+
+/// YUV matrix and range of an encoded stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorSpace {
+    pub matrix: ColorMatrix,
+    pub range: ColorRange,
+}
+
+impl ColorSpace {
+    pub fn from_settings(settings: &RecordingSettings) -> Self {
+        Self {
+            matrix: settings.color_matrix,
+            range: settings.color_range,
+        }
+    }
+
+    /// `colour_primaries` code point (ISO/IEC 23091-2), as used by `colr` and the H.264 VUI.
+    pub fn primaries_code(&self) -> u16 {
+        match self.matrix {
+            ColorMatrix::Bt601 => 6,
+            ColorMatrix::Bt709 => 1,
+        }
+    }
+
+    /// `transfer_characteristics` code point.
+    pub fn transfer_code(&self) -> u16 {
+        match self.matrix {
+            ColorMatrix::Bt601 => 6,
+            ColorMatrix::Bt709 => 1,
+        }
+    }
+
+    /// `matrix_coefficients` code point.
+    pub fn matrix_code(&self) -> u16 {
+        match self.matrix {
+            ColorMatrix::Bt601 => 6,
+            ColorMatrix::Bt709 => 1,
+        }
+    }
+
+    pub fn full_range(&self) -> bool {
+        self.range == ColorRange::Full
+    }
+
+    /// `nclx` colour box for an MP4 visual sample entry.
+    pub fn colr_box(&self) -> [u8; 19] {
+        let mut colr = [0u8; 19];
+        colr[0..4].copy_from_slice(&19u32.to_be_bytes());
+        colr[4..8].copy_from_slice(b"colr");
+        colr[8..12].copy_from_slice(b"nclx");
+        colr[12..14].copy_from_slice(&self.primaries_code().to_be_bytes());
+        colr[14..16].copy_from_slice(&self.transfer_code().to_be_bytes());
+        colr[16..18].copy_from_slice(&self.matrix_code().to_be_bytes());
+        colr[18] = if self.full_range() { 0x80 } else { 0 };
+        colr
+    }
+
+    fn coefficients(&self) -> Coefficients {
+        let (kr, kb) = match self.matrix {
+            ColorMatrix::Bt601 => (0.299, 0.114),
+            ColorMatrix::Bt709 => (0.2126, 0.0722),
+        };
+        let (luma_scale, chroma_scale, y_offset) = match self.range {
+            ColorRange::Limited => (219.0 / 255.0, 224.0 / 255.0, 16),
+            ColorRange::Full => (1.0, 1.0, 0),
+        };
+        let fixed = |v: f64| (v * ONE).round() as i32;
+
+        // Green takes the rounding error so white stays white and greys get exactly 128
+        // chroma.
+        let luma_r = fixed(kr * luma_scale);
+        let luma_b = fixed(kb * luma_scale);
+        let luma_g = fixed(luma_scale) - luma_r - luma_b;
+
+        let half = fixed(0.5 * chroma_scale);
+        let u_r = fixed(-kr / (2.0 * (1.0 - kb)) * chroma_scale);
+        let v_b = fixed(-kb / (2.0 * (1.0 - kr)) * chroma_scale);
+
+        Coefficients {
+            y: [luma_b, luma_g, luma_r],
+            u: [half, -half - u_r, u_r],
+            v: [v_b, -half - v_b, half],
+            y_offset,
+        }
+    }
+}
+
+/// Fixed-point weights in BGRA order.
+#[derive(Debug, Clone, Copy)]
+struct Coefficients {
+    y: [i32; 3],
+    u: [i32; 3],
+    v: [i32; 3],
+    y_offset: i32,
+}
+
+impl Coefficients {
+    fn y_bias(&self) -> i32 {
+        (self.y_offset << PRECISION) + ROUND
+    }
+
+    fn luma(&self, px: [i32; 3]) -> u8 {
+        let acc = self.y[0] * px[0] + self.y[1] * px[1] + self.y[2] * px[2] + self.y_bias();
+        (acc >> PRECISION).clamp(0, 255) as u8
+    }
+
+    /// `sum` holds the B, G, R totals of a 2x2 block.
+    fn chroma(weights: &[i32; 3], sum: [i32; 3]) -> u8 {
+        let acc = weights[0] * sum[0] + weights[1] * sum[1] + weights[2] * sum[2] + CHROMA_BIAS;
+        (acc >> CHROMA_SHIFT).clamp(0, 255) as u8
+    }
+}
+
+/// Destination of one row of chroma samples.
+enum ChromaRow<'a> {
+    Interleaved(&'a mut [u8]),
+    Planar(&'a mut [u8], &'a mut [u8]),
+}
+
+impl ChromaRow<'_> {
+    fn put(&mut self, i: usize, u: u8, v: u8) {
+        match self {
+            ChromaRow::Interleaved(uv) => {
+                uv[i * 2] = u;
+                uv[i * 2 + 1] = v;
+            }
+            ChromaRow::Planar(us, vs) => {
+                us[i] = u;
+                vs[i] = v;
+            }
+        }
+    }
+}
+
+/// Converts composited BGRA frames into the 4:2:0 YUV the encoders consume, so the matrix
+/// and range are ours rather than whatever the encoder defaults to.
+///
+/// Chroma is the average of each 2x2 block; odd edges repeat the last row or column.
+pub struct ColorConverter {
+    space: ColorSpace,
+    coeffs: Coefficients,
+}
+
+impl ColorConverter {
+    pub fn new(space: ColorSpace) -> Self {
+        Self {
+            space,
+            coeffs: space.coefficients(),
+        }
+    }
+
+    pub fn space(&self) -> ColorSpace {
+        self.space
+    }
+
+    /// Convert into a buffer from the shared `FramePool`.
+    pub fn convert(
+        &self,
+        bgra: &[u8],
+        width: u32,
+        height: u32,
+        layout: YuvLayout,
+    ) -> Result<FrameBuffer, RecorderError> {
+        let mut out = FramePool::shared().take(layout.frame_len(width, height));
+        self.convert_into(bgra, width, height, layout, &mut out)?;
+        Ok(out)
+    }
+
+    /// Convert a tightly packed BGRA frame into `dst`, which must be exactly
+    /// `layout.frame_len(width, height)` bytes.
+    pub fn convert_into(
+        &self,
+        bgra: &[u8],
+        width: u32,
+        height: u32,
+        layout: YuvLayout,
+        dst: &mut [u8],
+    ) -> Result<(), RecorderError> {
+        let (w, h) = (width as usize, height as usize);
+        if bgra.len() != w * h * 4 || dst.len() != layout.frame_len(width, height) {
+            return Err(RecorderError::encoding_failed(format!(
+                "Color conversion size mismatch: {} BGRA bytes into {} for {width}x{height}",
+                bgra.len(),
+                dst.len()
+            )));
+        }
+        if w == 0 || h == 0 {
+            return Ok(());
+        }
+
+        let (cw, ch) = chroma_size(w, h);
+        let (luma, chroma) = dst.split_at_mut(w * h);
+        let mut luma_rows = luma.chunks_exact_mut(w);
+        let stride = w * 4;
+
+        for cy in 0..ch {
+            let top = &bgra[cy * 2 * stride..][..stride];
+            let bottom = &bgra[(cy * 2 + 1).min(h - 1) * stride..][..stride];
+
+            let y_top = luma_rows.next().expect("a luma row for every source row");
+            let y_bottom = if cy * 2 + 1 < h { luma_rows.next() } else { None };
+            let mut chroma_row = match layout {
+                YuvLayout::Nv12 => ChromaRow::Interleaved(&mut chroma[cy * cw * 2..][..cw * 2]),
+                YuvLayout::I420 => {
+                    let (u, v) = chroma.split_at_mut(cw * ch);
+                    ChromaRow::Planar(&mut u[cy * cw..][..cw], &mut v[cy * cw..][..cw])
+                }
+            };
+            convert_rows(&self.coeffs, top, bottom, y_top, y_bottom, &mut chroma_row);
+        }
+        Ok(())
+    }
+}
+
+/// Convert a pair of source rows: two luma rows (one for a trailing odd row) and the chroma
+/// row they share.
+fn convert_rows(
+    c: &Coefficients,
+    top: &[u8],
+    bottom: &[u8],
+    y_top: &mut [u8],
+    y_bottom: Option<&mut [u8]>,
+    chroma: &mut ChromaRow,
+) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        // SAFETY: SSE2 is part of the x86_64 baseline; both source rows hold `y_top.len()`
+        // BGRA pixels and `chroma` holds half as many samples, rounded up.
+        unsafe { sse2::convert_rows(c, top, bottom, y_top, y_bottom, chroma) }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    convert_rows_scalar(c, top, bottom, y_top, y_bottom, chroma, 0)
+}
+
+/// Pixels from `from` (even) onwards; the SIMD path uses it for the row tail.
+fn convert_rows_scalar(
+    c: &Coefficients,
+    top: &[u8],
+    bottom: &[u8],
+    y_top: &mut [u8],
+    mut y_bottom: Option<&mut [u8]>,
+    chroma: &mut ChromaRow,
+    from: usize,
+) {
+    let w = y_top.len();
+    let px = |row: &[u8], x: usize| [row[x * 4] as i32, row[x * 4 + 1] as i32, row[x * 4 + 2] as i32];
+
+    for x in (from..w).step_by(2) {
+        let x1 = (x + 1).min(w - 1);
+        let block = [px(top, x), px(top, x1), px(bottom, x), px(bottom, x1)];
+
+        y_top[x] = c.luma(block[0]);
+        y_top[x1] = c.luma(block[1]);
+        if let Some(yb) = y_bottom.as_deref_mut() {
+            yb[x] = c.luma(block[2]);
+            yb[x1] = c.luma(block[3]);
+        }
+
+        let sum = [0, 1, 2].map(|ch| block.iter().map(|p| p[ch]).sum::<i32>());
+        chroma.put(x / 2, Coefficients::chroma(&c.u, sum), Coefficients::chroma(&c.v, sum));
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod sse2 {
+    use super::{convert_rows_scalar, ChromaRow, Coefficients, CHROMA_BIAS, CHROMA_SHIFT, PRECISION};
+    use std::arch::x86_64::*;
+
+    /// Two 16-bit weights repeated across the register, matching `[a0, b0, a1, b1, ...]`.
+    #[inline(always)]
+    unsafe fn weight_pair(a: i32, b: i32) -> __m128i {
+        _mm_set1_epi32(((b as i16 as u16 as u32) << 16 | a as i16 as u16 as u32) as i32)
+    }
+
+    /// Weights for one output component: `br` multiplies the `[b, r]` lanes, `g` the
+    /// `[g, a]` lanes (alpha weighted by zero).
+    struct Weights {
+        br: __m128i,
+        g: __m128i,
+    }
+
+    impl Weights {
+        #[inline(always)]
+        unsafe fn new(w: &[i32; 3]) -> Self {
+            Self {
+                br: weight_pair(w[0], w[2]),
+                g: weight_pair(w[1], 0),
+            }
+        }
+
+        /// 32-bit weighted sums of four pixels split into `[b, r]` and `[g, a]` lanes.
+        #[inline(always)]
+        unsafe fn apply(&self, br: __m128i, ga: __m128i) -> __m128i {
+            _mm_add_epi32(_mm_madd_epi16(br, self.br), _mm_madd_epi16(ga, self.g))
+        }
+    }
+
+    /// Four BGRA pixels as 16-bit `[b, r]` and `[g, a]` lanes.
+    #[inline(always)]
+    unsafe fn split(p: *const u8) -> (__m128i, __m128i) {
+        let px = _mm_loadu_si128(p as *const __m128i);
+        (
+            _mm_and_si128(px, _mm_set1_epi16(0x00ff)),
+            _mm_srli_epi16(px, 8),
+        )
+    }
+
+    /// Luma of eight pixels from two `split` halves, stored as eight bytes.
+    #[inline(always)]
+    unsafe fn store_luma(y: &Weights, bias: __m128i, a: (__m128i, __m128i), b: (__m128i, __m128i), dst: *mut u8) {
+        let lo = _mm_srai_epi32(_mm_add_epi32(y.apply(a.0, a.1), bias), PRECISION as i32);
+        let hi = _mm_srai_epi32(_mm_add_epi32(y.apply(b.0, b.1), bias), PRECISION as i32);
+        let packed = _mm_packs_epi32(lo, hi);
+        _mm_storel_epi64(dst as *mut __m128i, _mm_packus_epi16(packed, packed));
+    }
+
+    /// Add horizontally neighbouring pixels: 32-bit lanes 0 and 2 end up with the sums.
+    #[inline(always)]
+    unsafe fn pair_sum(v: __m128i) -> __m128i {
+        _mm_add_epi16(v, _mm_shuffle_epi32(v, 0b10_11_00_01))
+    }
+
+    pub(super) unsafe fn convert_rows(
+        c: &Coefficients,
+        top: &[u8],
+        bottom: &[u8],
+        y_top: &mut [u8],
+        mut y_bottom: Option<&mut [u8]>,
+        chroma: &mut ChromaRow,
+    ) {
+        let w = y_top.len();
+        debug_assert!(top.len() >= w * 4 && bottom.len() >= w * 4);
+        let chunks = w / 8;
+
+        let y = Weights::new(&c.y);
+        let u = Weights::new(&c.u);
+        let v = Weights::new(&c.v);
+        let y_bias = _mm_set1_epi32(c.y_bias());
+        let chroma_bias = _mm_set1_epi32(CHROMA_BIAS);
+
+        for i in 0..chunks {
+            let t0 = split(top.as_ptr().add(i * 32));
+            let t1 = split(top.as_ptr().add(i * 32 + 16));
+            let b0 = split(bottom.as_ptr().add(i * 32));
+            let b1 = split(bottom.as_ptr().add(i * 32 + 16));
+
+            store_luma(&y, y_bias, t0, t1, y_top.as_mut_ptr().add(i * 8));
+            if let Some(yb) = y_bottom.as_deref_mut() {
+                store_luma(&y, y_bias, b0, b1, yb.as_mut_ptr().add(i * 8));
+            }
+
+            // 2x2 sums fit comfortably in 16 bits (4 * 255).
+            let br0 = pair_sum(_mm_add_epi16(t0.0, b0.0));
+            let ga0 = pair_sum(_mm_add_epi16(t0.1, b0.1));
+            let br1 = pair_sum(_mm_add_epi16(t1.0, b1.0));
+            let ga1 = pair_sum(_mm_add_epi16(t1.1, b1.1));
+
+            let component = |w: &Weights| {
+                let first = _mm_shuffle_epi32(w.apply(br0, ga0), 0b00_00_10_00);
+                let second = _mm_shuffle_epi32(w.apply(br1, ga1), 0b00_00_10_00);
+                let sums = _mm_unpacklo_epi64(first, second);
+                _mm_srai_epi32(_mm_add_epi32(sums, chroma_bias), CHROMA_SHIFT as i32)
+            };
+            let (us, vs) = (component(&u), component(&v));
+
+            match chroma {
+                ChromaRow::Interleaved(uv) => {
+                    let lo = _mm_unpacklo_epi32(us, vs);
+                    let hi = _mm_unpackhi_epi32(us, vs);
+                    let packed = _mm_packs_epi32(lo, hi);
+                    _mm_storel_epi64(uv.as_mut_ptr().add(i * 8) as *mut __m128i, _mm_packus_epi16(packed, packed));
+                }
+                ChromaRow::Planar(u_row, v_row) => {
+                    let packed = _mm_packs_epi32(us, vs);
+                    let bytes = _mm_packus_epi16(packed, packed);
+                    (u_row.as_mut_ptr().add(i * 4) as *mut i32).write_unaligned(_mm_cvtsi128_si32(bytes));
+                    (v_row.as_mut_ptr().add(i * 4) as *mut i32)
+                        .write_unaligned(_mm_cvtsi128_si32(_mm_srli_si128(bytes, 4)));
+                }
+            }
+        }
+
+        convert_rows_scalar(c, top, bottom, y_top, y_bottom, chroma, chunks * 8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPACES: [ColorSpace; 4] = [
+        ColorSpace { matrix: ColorMatrix::Bt601, range: ColorRange::Limited },
+        ColorSpace { matrix: ColorMatrix::Bt601, range: ColorRange::Full },
+        ColorSpace { matrix: ColorMatrix::Bt709, range: ColorRange::Limited },
+        ColorSpace { matrix: ColorMatrix::Bt709, range: ColorRange::Full },
+    ];
+
+    fn space(matrix: ColorMatrix, range: ColorRange) -> ColorSpace {
+        ColorSpace { matrix, range }
+    }
+
+    /// Deterministic noise, so failures reproduce.
+    fn noise(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(747_796_405).wrapping_add(1);
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    fn convert(space: ColorSpace, bgra: &[u8], width: u32, height: u32, layout: YuvLayout) -> Vec<u8> {
+        ColorConverter::new(space).convert(bgra, width, height, layout).unwrap().to_vec()
+    }
+
+    /// Y, U and V of a frame filled with one BGRA pixel.
+    fn solid(space: ColorSpace, bgra: [u8; 4]) -> (u8, u8, u8) {
+        let yuv = convert(space, &bgra.repeat(4 * 4), 4, 4, YuvLayout::I420);
+        let (luma, chroma) = yuv.split_at(16);
+        assert!(luma.iter().all(|&y| y == luma[0]));
+        (luma[0], chroma[0], chroma[4])
+    }
+
+    /// `ColorConverter::convert_into` with the scalar kernel on every row.
+    fn convert_scalar(space: ColorSpace, bgra: &[u8], width: u32, height: u32, layout: YuvLayout) -> Vec<u8> {
+        let c = space.coefficients();
+        let (w, h) = (width as usize, height as usize);
+        let (cw, ch) = chroma_size(w, h);
+        let mut out = vec![0; layout.frame_len(width, height)];
+        let (luma, chroma) = out.split_at_mut(w * h);
+        let mut luma_rows = luma.chunks_exact_mut(w);
+        for cy in 0..ch {
+            let top = &bgra[cy * 2 * w * 4..][..w * 4];
+            let bottom = &bgra[(cy * 2 + 1).min(h - 1) * w * 4..][..w * 4];
+            let y_top = luma_rows.next().unwrap();
+            let y_bottom = luma_rows.next();
+            let mut chroma_row = match layout {
+                YuvLayout::Nv12 => ChromaRow::Interleaved(&mut chroma[cy * cw * 2..][..cw * 2]),
+                YuvLayout::I420 => {
+                    let (u, v) = chroma.split_at_mut(cw * ch);
+                    ChromaRow::Planar(&mut u[cy * cw..][..cw], &mut v[cy * cw..][..cw])
+                }
+            };
+            convert_rows_scalar(&c, top, bottom, y_top, y_bottom, &mut chroma_row, 0);
+        }
+        out
+    }
+
+    #[test]
+    fn simd_matches_scalar_on_ragged_sizes() {
+        let sizes = [(1, 1), (2, 2), (3, 5), (7, 2), (8, 8), (9, 3), (15, 7), (17, 1), (33, 4), (641, 361)];
+        for (seed, &(w, h)) in sizes.iter().enumerate() {
+            let bgra = noise(w as usize * h as usize * 4, seed as u32);
+            for space in SPACES {
+                for layout in [YuvLayout::Nv12, YuvLayout::I420] {
+                    assert_eq!(
+                        convert(space, &bgra, w, h, layout),
+                        convert_scalar(space, &bgra, w, h, layout),
+                        "{space:?} {layout:?} {w}x{h}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn odd_edges_repeat_the_last_pixel() {
+        // 3x3: the right column and bottom row form half-empty chroma blocks.
+        let mut bgra = [0u8, 0, 0, 255].repeat(9);
+        for i in [2, 5, 6, 7, 8] {
+            bgra[i * 4..i * 4 + 3].copy_from_slice(&[255, 255, 255]);
+        }
+        let space = space(ColorMatrix::Bt709, ColorRange::Full);
+        let yuv = convert(space, &bgra, 3, 3, YuvLayout::Nv12);
+        assert_eq!(yuv.len(), 9 + 2 * 2 * 2);
+        assert_eq!(&yuv[..9], [0, 0, 255, 0, 0, 255, 255, 255, 255]);
+        // Every block is grey in chroma, whatever mix of black and white it holds.
+        assert!(yuv[9..].iter().all(|&c| c == 128), "{:?}", &yuv[9..]);
+    }
+
+    #[test]
+    fn neutral_levels() {
+        for matrix in [ColorMatrix::Bt601, ColorMatrix::Bt709] {
+            let limited = space(matrix, ColorRange::Limited);
+            assert_eq!(solid(limited, [0, 0, 0, 255]), (16, 128, 128));
+            assert_eq!(solid(limited, [255, 255, 255, 255]), (235, 128, 128));
+            assert_eq!(solid(limited, [128, 128, 128, 255]), (126, 128, 128));
+
+            let full = space(matrix, ColorRange::Full);
+            assert_eq!(solid(full, [0, 0, 0, 255]), (0, 128, 128));
+            assert_eq!(solid(full, [255, 255, 255, 255]), (255, 128, 128));
+            assert_eq!(solid(full, [128, 128, 128, 255]), (128, 128, 128));
+        }
+    }
+
+    #[test]
+    fn pure_red_depends_on_the_matrix() {
+        let red = [0, 0, 255, 255];
+        assert_eq!(solid(space(ColorMatrix::Bt601, ColorRange::Limited), red), (81, 90, 240));
+        assert_eq!(solid(space(ColorMatrix::Bt709, ColorRange::Limited), red), (63, 102, 240));
+        assert_eq!(solid(space(ColorMatrix::Bt601, ColorRange::Full), red), (76, 85, 255));
+        assert_eq!(solid(space(ColorMatrix::Bt709, ColorRange::Full), red), (54, 99, 255));
+    }
+
+    #[test]
+    fn colr_box_bytes() {
+        let header = [0, 0, 0, 19, b'c', b'o', b'l', b'r', b'n', b'c', b'l', b'x'];
+        let bt709 = space(ColorMatrix::Bt709, ColorRange::Limited).colr_box();
+        assert_eq!(bt709[..12], header);
+        assert_eq!(bt709[12..], [0, 1, 0, 1, 0, 1, 0]);
+        let bt601 = space(ColorMatrix::Bt601, ColorRange::Full).colr_box();
+        assert_eq!(bt601[12..], [0, 6, 0, 6, 0, 6, 0x80]);
+    }
+
+    #[test]
+    fn mismatched_buffers_are_rejected() {
+        let converter = ColorConverter::new(SPACES[0]);
+        let mut dst = vec![0; YuvLayout::Nv12.frame_len(4, 4)];
+        assert!(converter.convert_into(&[0; 4 * 4 * 4 - 1], 4, 4, YuvLayout::Nv12, &mut dst).is_err());
+        assert!(converter.convert_into(&[0; 4 * 4 * 4], 4, 4, YuvLayout::Nv12, &mut dst[1..]).is_err());
+    }
+}
@@ -1,27 +1,93 @@
 #![allow(dead_code)]
 
 use crate::error::RecorderError;
+use crate::recording::cursor::{CursorFrame, CursorStyle, MouseButton, CLICK_RING_MS};
+use crate::recording::frame_pool::FrameBuffer;
+use crate::recording::frame_source::{ContentRect, Frame};
+use crate::recording::scaler::Scaler;
 use crate::state::app_state::{CameraPosition, CameraSize};
 
+// Classic arrow pointer, hotspot at the top-left. 'B' outline, 'W' fill, ' ' transparent.
+const ARROW: [&[u8; 12]; 19] = [
+    b"B           ",
+    b"BB          ",
+    b"BWB         ",
+    b"BWWB        ",
+    b"BWWWB       ",
+    b"BWWWWB      ",
+    b"BWWWWWB     ",
+    b"BWWWWWWB    ",
+    b"BWWWWWWWB   ",
+    b"BWWWWWWWWB  ",
+    b"BWWWWWWWWWB ",
+    b"BWWWWWWBBBBB",
+    b"BWWWBWWB    ",
+    b"BWWBBWWB    ",
+    b"BWB  BWWB   ",
+    b"BB   BWWB   ",
+    b"B     BWWB  ",
+    b"      BWWB  ",
+    b"       BB   ",
+];
+
+/// Camera picture resized for the overlay.
+///
+/// The camera usually runs slower than the screen, so the resized frame is kept and reused
+/// until the camera delivers a new `frame_number` or the overlay size changes.
+pub struct CameraOverlay {
+    scaler: Scaler,
+    cached: Option<ScaledCamera>,
+}
+
+struct ScaledCamera {
+    frame_number: u64,
+    width: u32,
+    height: u32,
+    data: FrameBuffer,
+}
+
+impl CameraOverlay {
+    pub fn new(scaler: Scaler) -> Self {
+        Self { scaler, cached: None }
+    }
+
+    fn scaled(&mut self, camera: &Frame, width: u32, height: u32) -> &[u8] {
+        let cached = match self.cached.take() {
+            Some(c) if (c.frame_number, c.width, c.height) == (camera.frame_number, width, height) => c,
+            _ => ScaledCamera {
+                frame_number: camera.frame_number,
+                width,
+                height,
+                data: self.scaler.scale(&camera.data, camera.width, camera.height, width, height),
+            },
+        };
+        &self.cached.insert(cached).data
+    }
+}
+
 pub struct FrameCompositor;
 
 impl FrameCompositor {
+    /// Resize `camera` through `overlay` and paste it into a corner of `screen`, or of the
+    /// visible part of `content` when the picture doesn't fill the frame.
+    #[allow(clippy::too_many_arguments)]
     pub fn overlay_bgra(
         screen: &mut [u8],
         screen_width: u32,
         screen_height: u32,
-        camera: &[u8],
-        camera_width: u32,
-        camera_height: u32,
+        camera: &Frame,
         position: CameraPosition,
         size: CameraSize,
+        content: Option<ContentRect>,
+        overlay: &mut CameraOverlay,
     ) -> Result<(), RecorderError> {
+        let (camera_width, camera_height) = (camera.width, camera.height);
         if screen_width == 0
             || screen_height == 0
             || camera_width == 0
             || camera_height == 0
             || screen.len() < (screen_width as usize * screen_height as usize * 4)
-            || camera.len() < (camera_width as usize * camera_height as usize * 4)
+            || camera.data.len() < (camera_width as usize * camera_height as usize * 4)
         {
             return Err(RecorderError::invalid_settings("Invalid frame dimensions"));
         }
@@ -32,43 +98,312 @@ impl FrameCompositor {
             CameraSize::Large => 35,
         };
 
-        let target_w = ((screen_width as u64 * pct as u64) / 100).max(1) as u32;
+        let bounds = content
+            .map(|c| c.visible(screen_width, screen_height))
+            .filter(|c| c.width > 0 && c.height > 0)
+            .unwrap_or(ContentRect {
+                x: 0,
+                y: 0,
+                width: screen_width,
+                height: screen_height,
+            });
+        let (bounds_w, bounds_h) = (bounds.width, bounds.height);
+
+        let target_w = ((bounds_w as u64 * pct as u64) / 100).max(1) as u32;
         let target_h = ((target_w as u64 * camera_height as u64) / camera_width as u64).max(1) as u32;
 
-        let target_h = target_h.min(screen_height);
-        let target_w = target_w.min(screen_width);
+        let target_h = target_h.min(bounds_h);
+        let target_w = target_w.min(bounds_w);
 
         let margin = 16u32;
         let (mut x0, mut y0) = match position {
             CameraPosition::TopLeft => (margin, margin),
-            CameraPosition::TopRight => (screen_width.saturating_sub(target_w + margin), margin),
-            CameraPosition::BottomLeft => (margin, screen_height.saturating_sub(target_h + margin)),
+            CameraPosition::TopRight => (bounds_w.saturating_sub(target_w + margin), margin),
+            CameraPosition::BottomLeft => (margin, bounds_h.saturating_sub(target_h + margin)),
             CameraPosition::BottomRight => (
-                screen_width.saturating_sub(target_w + margin),
-                screen_height.saturating_sub(target_h + margin),
+                bounds_w.saturating_sub(target_w + margin),
+                bounds_h.saturating_sub(target_h + margin),
             ),
         };
 
-        if x0 + target_w > screen_width {
+        if x0 + target_w > bounds_w {
             x0 = 0;
         }
-        if y0 + target_h > screen_height {
+        if y0 + target_h > bounds_h {
             y0 = 0;
         }
+        let x0 = x0 + bounds.x as u32;
+        let y0 = y0 + bounds.y as u32;
+
+        // Resize, then overlay (no alpha blending; simple overwrite).
+        let scaled = overlay.scaled(camera, target_w, target_h);
+        let row = target_w as usize * 4;
+        for dy in 0..target_h as usize {
+            let d_idx = ((y0 as usize + dy) * screen_width as usize + x0 as usize) * 4;
+            screen[d_idx..d_idx + row].copy_from_slice(&scaled[dy * row..(dy + 1) * row]);
+        }
+
+        Ok(())
+    }
+
+    /// Draw click rings and the pointer described by `cursor` onto a BGRA frame.
+    pub fn draw_cursor(
+        screen: &mut [u8],
+        screen_width: u32,
+        screen_height: u32,
+        cursor: &CursorFrame,
+        style: &CursorStyle,
+    ) -> Result<(), RecorderError> {
+        if screen_width == 0
+            || screen_height == 0
+            || screen.len() < (screen_width as usize * screen_height as usize * 4)
+        {
+            return Err(RecorderError::invalid_settings("Invalid frame dimensions"));
+        }
+
+        if style.click_rings {
+            for click in &cursor.clicks {
+                let t = (click.age_ms as f32 / CLICK_RING_MS as f32).min(1.0);
+                let color = match click.button {
+                    MouseButton::Left => style.left_click_color,
+                    MouseButton::Right => style.right_click_color,
+                };
+                let radius = (8.0 + 22.0 * t) * style.scale;
+                let thickness = (3.0 * style.scale).max(1.0);
+                let alpha = (1.0 - t) * 0.9;
+                draw_ring(screen, screen_width, screen_height, click.x, click.y, radius, thickness, color, alpha);
+            }
+        }
 
-        // Nearest-neighbor resize + overlay (no alpha blending; simple overwrite).
-        for dy in 0..target_h {
-            let sy = (dy as u64 * camera_height as u64) / target_h as u64;
-            for dx in 0..target_w {
-                let sx = (dx as u64 * camera_width as u64) / target_w as u64;
+        let idle = style.idle_timeout_ms.is_some_and(|limit| cursor.idle_ms >= limit);
+        let Some((hx, hy)) = cursor.position else {
+            return Ok(());
+        };
+        if idle {
+            return Ok(());
+        }
 
-                let s_idx = ((sy as u32 * camera_width + sx as u32) * 4) as usize;
-                let d_idx = (((y0 + dy) * screen_width + (x0 + dx)) * 4) as usize;
+        let w = (ARROW[0].len() as f32 * style.scale).round().max(1.0) as i64;
+        let h = (ARROW.len() as f32 * style.scale).round().max(1.0) as i64;
+        let (x0, y0) = (hx.round() as i64, hy.round() as i64);
 
-                screen[d_idx..d_idx + 4].copy_from_slice(&camera[s_idx..s_idx + 4]);
+        for dy in 0..h {
+            let y = y0 + dy;
+            if y < 0 || y >= screen_height as i64 {
+                continue;
+            }
+            let row = ARROW[((dy as f32 / style.scale) as usize).min(ARROW.len() - 1)];
+            for dx in 0..w {
+                let x = x0 + dx;
+                if x < 0 || x >= screen_width as i64 {
+                    continue;
+                }
+                let px = match row[((dx as f32 / style.scale) as usize).min(row.len() - 1)] {
+                    b'B' => [0, 0, 0, 255],
+                    b'W' => [255, 255, 255, 255],
+                    _ => continue,
+                };
+                let i = ((y as usize * screen_width as usize) + x as usize) * 4;
+                screen[i..i + 4].copy_from_slice(&px);
             }
         }
 
         Ok(())
     }
 }
+
+/// Alpha-blend an annulus of outer radius `radius` centred on (`cx`, `cy`).
+#[allow(clippy::too_many_arguments)]
+fn draw_ring(
+    screen: &mut [u8],
+    width: u32,
+    height: u32,
+    cx: f32,
+    cy: f32,
+    radius: f32,
+    thickness: f32,
+    color: [u8; 3],
+    alpha: f32,
+) {
+    let inner = (radius - thickness).max(0.0);
+    let x0 = (cx - radius).floor().max(0.0) as u32;
+    let y0 = (cy - radius).floor().max(0.0) as u32;
+    let x1 = ((cx + radius).ceil().max(0.0) as u32).min(width);
+    let y1 = ((cy + radius).ceil().max(0.0) as u32).min(height);
+
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let d = ((x as f32 + 0.5 - cx).powi(2) + (y as f32 + 0.5 - cy).powi(2)).sqrt();
+            // One pixel of anti-aliasing on both edges.
+            let coverage = (radius - d).clamp(0.0, 1.0) * (d - inner).clamp(0.0, 1.0);
+            let a = alpha * coverage;
+            if a <= 0.0 {
+                continue;
+            }
+            let i = (y as usize * width as usize + x as usize) * 4;
+            for c in 0..3 {
+                let dst = screen[i + c] as f32;
+                screen[i + c] = (dst + (color[c] as f32 - dst) * a).round() as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::cursor::ClickRing;
+    use crate::recording::scaler::ScaleOptions;
+    use crate::state::app_state::{CursorSettings, ScaleFilter};
+
+    const GREY: [u8; 4] = [128, 128, 128, 255];
+
+    fn style(settings: CursorSettings) -> CursorStyle {
+        CursorStyle::from_settings(&settings).unwrap()
+    }
+
+    fn draw(width: u32, height: u32, cursor: &CursorFrame, style: &CursorStyle) -> Vec<u8> {
+        let mut screen = GREY.repeat(width as usize * height as usize);
+        FrameCompositor::draw_cursor(&mut screen, width, height, cursor, style).unwrap();
+        screen
+    }
+
+    fn pixel(screen: &[u8], width: u32, x: u32, y: u32) -> [u8; 4] {
+        let i = (y * width + x) as usize * 4;
+        screen[i..i + 4].try_into().unwrap()
+    }
+
+    fn click(x: f32, y: f32, button: MouseButton, age_ms: u64) -> CursorFrame {
+        CursorFrame {
+            position: None,
+            idle_ms: 0,
+            clicks: vec![ClickRing { x, y, button, age_ms }],
+        }
+    }
+
+    #[test]
+    fn arrow_is_scaled_from_the_rounded_hotspot() {
+        let style = style(CursorSettings { scale: 2.0, ..Default::default() });
+        let cursor = CursorFrame { position: Some((10.4, 20.6)), ..Default::default() };
+        let screen = draw(64, 64, &cursor, &style);
+
+        let black = [0, 0, 0, 255];
+        let white = [255, 255, 255, 255];
+        assert_eq!(pixel(&screen, 64, 10, 21), black);
+        assert_eq!(pixel(&screen, 64, 11, 22), black);
+        assert_eq!(pixel(&screen, 64, 9, 21), GREY);
+        assert_eq!(pixel(&screen, 64, 10, 20), GREY);
+        // Third row, second column of the 12x19 shape, each cell 2x2.
+        assert_eq!(pixel(&screen, 64, 12, 25), white);
+        assert_eq!(pixel(&screen, 64, 13, 26), white);
+        // Transparent cells leave the frame alone; the shape ends 24x38 px from the hotspot.
+        assert_eq!(pixel(&screen, 64, 10 + 23, 21), GREY);
+        assert_eq!(pixel(&screen, 64, 10 + 23, 21 + 22), black);
+        assert_eq!(pixel(&screen, 64, 10 + 15, 21 + 37), black);
+        assert_eq!(pixel(&screen, 64, 10 + 15, 21 + 38), GREY);
+    }
+
+    #[test]
+    fn arrow_is_clipped_at_the_frame_edge() {
+        let cursor = CursorFrame { position: Some((-3.0, 14.0)), ..Default::default() };
+        let screen = draw(8, 20, &cursor, &style(CursorSettings::default()));
+        // Only columns 3.. of the shape are left: transparent in the first row, outline in
+        // the fourth, fill in the fifth.
+        assert_eq!(pixel(&screen, 8, 0, 14), GREY);
+        assert_eq!(pixel(&screen, 8, 0, 17), [0, 0, 0, 255]);
+        assert_eq!(pixel(&screen, 8, 0, 18), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn pointer_is_hidden_when_idle_or_unseen() {
+        let hiding = style(CursorSettings { hide_when_idle: true, idle_timeout_ms: 1000, ..Default::default() });
+        let untouched = GREY.repeat(32 * 32);
+
+        let idle = CursorFrame { position: Some((4.0, 4.0)), idle_ms: 1000, clicks: Vec::new() };
+        assert_eq!(draw(32, 32, &idle, &hiding), untouched);
+        let active = CursorFrame { idle_ms: 999, ..idle.clone() };
+        assert_ne!(draw(32, 32, &active, &hiding), untouched);
+        // Without hide_when_idle the idle time doesn't matter.
+        assert_ne!(draw(32, 32, &idle, &style(CursorSettings::default())), untouched);
+
+        assert_eq!(draw(32, 32, &CursorFrame::default(), &hiding), untouched);
+    }
+
+    #[test]
+    fn click_rings_use_the_button_color_and_fade_out() {
+        let style = style(CursorSettings::default());
+        let close = |a: [u8; 4], b: [u8; 4]| a.iter().zip(b).all(|(&a, b)| a.abs_diff(b) <= 1);
+
+        // A fresh ring has an 8 px radius, 3 px thick, at 90% opacity.
+        let screen = draw(100, 100, &click(50.0, 50.0, MouseButton::Left, 0), &style);
+        let tint = |c: u8| (128.0 + (c as f32 - 128.0) * 0.9).round() as u8;
+        // #FFC107 in BGR.
+        assert!(close(pixel(&screen, 100, 56, 50), [tint(0x07), tint(0xC1), tint(0xFF), 255]));
+        assert_eq!(pixel(&screen, 100, 50, 50), GREY);
+        assert_eq!(pixel(&screen, 100, 60, 50), GREY);
+
+        let screen = draw(100, 100, &click(50.0, 50.0, MouseButton::Right, 0), &style);
+        // #2196F3 in BGR.
+        assert!(close(pixel(&screen, 100, 56, 50), [tint(0xF3), tint(0x96), tint(0x21), 255]));
+
+        let untouched = GREY.repeat(100 * 100);
+        assert_eq!(draw(100, 100, &click(50.0, 50.0, MouseButton::Left, CLICK_RING_MS), &style), untouched);
+        let no_rings = CursorStyle { click_rings: false, ..style.clone() };
+        assert_eq!(draw(100, 100, &click(50.0, 50.0, MouseButton::Left, 0), &no_rings), untouched);
+    }
+
+    #[test]
+    fn click_rings_are_clipped_at_the_frame_edge() {
+        let style = style(CursorSettings { scale: 8.0, ..Default::default() });
+        for (x, y) in [(0.0, 0.0), (19.5, 19.5), (-100.0, -100.0), (500.0, 10.0)] {
+            draw(20, 20, &click(x, y, MouseButton::Left, 100), &style);
+        }
+    }
+
+    #[test]
+    fn draw_cursor_rejects_short_buffers() {
+        let mut screen = vec![0; 10];
+        let cursor = CursorFrame::default();
+        let style = style(CursorSettings::default());
+        assert!(FrameCompositor::draw_cursor(&mut screen, 4, 4, &cursor, &style).is_err());
+    }
+
+    fn camera(frame_number: u64, value: u8) -> Frame {
+        Frame { data: vec![value; 64 * 48 * 4].into(), width: 64, height: 48, timestamp: 0, frame_number }
+    }
+
+    /// Overlay `camera` onto a black 200x100 screen and return the top-left pixel of the
+    /// camera picture.
+    fn overlay(camera: &Frame, overlay: &mut CameraOverlay) -> u8 {
+        let mut screen = vec![0u8; 200 * 100 * 4];
+        FrameCompositor::overlay_bgra(
+            &mut screen,
+            200,
+            100,
+            camera,
+            CameraPosition::TopLeft,
+            CameraSize::Medium,
+            None,
+            overlay,
+        )
+        .unwrap();
+        screen[(16 * 200 + 16) * 4]
+    }
+
+    #[test]
+    fn camera_is_scaled_once_per_camera_frame() {
+        let mut cache = CameraOverlay::new(Scaler::new(ScaleOptions::stretch(ScaleFilter::Nearest)));
+        assert_eq!(overlay(&camera(1, 10), &mut cache), 10);
+        // Same camera frame number: the cached picture is reused.
+        assert_eq!(overlay(&camera(1, 20), &mut cache), 10);
+        assert_eq!(overlay(&camera(2, 30), &mut cache), 30);
+    }
+
+    #[test]
+    fn camera_is_rescaled_when_the_overlay_size_changes() {
+        let mut cache = CameraOverlay::new(Scaler::new(ScaleOptions::stretch(ScaleFilter::Nearest)));
+        let frame = camera(1, 10);
+        assert_eq!(cache.scaled(&frame, 50, 37).len(), 50 * 37 * 4);
+        assert_eq!(cache.scaled(&frame, 30, 22).len(), 30 * 22 * 4);
+    }
+}
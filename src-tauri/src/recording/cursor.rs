@@ -0,0 +1,514 @@
+use crate::error::RecorderError;
+use crate::recording::frame_source::{CaptureArea, ContentRect};
+use crate::state::app_state::CursorSettings;
+use crate::utils::color::parse_hex_color;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How long a click ring stays on screen.
+pub(crate) const CLICK_RING_MS: u64 = 450;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorEventKind {
+    Move,
+    Press(MouseButton),
+}
+
+/// One pointer sample. `timestamp` is in milliseconds since the stream's epoch.
+#[derive(Debug, Clone, Copy)]
+pub struct CursorEvent {
+    pub timestamp: u64,
+    pub x: i32,
+    pub y: i32,
+    pub kind: CursorEventKind,
+}
+
+/// Source of pointer movement and clicks.
+///
+/// Coordinates are desktop pixels for streams that follow the real pointer, or frame
+/// pixels for streams paired with a source that has no `CaptureArea`.
+pub trait CursorStream {
+    /// Events that happened since the previous call, oldest first.
+    fn poll(&mut self) -> Vec<CursorEvent>;
+}
+
+/// Colors and sizes used by `FrameCompositor::draw_cursor`, parsed from `CursorSettings`.
+#[derive(Debug, Clone)]
+pub struct CursorStyle {
+    pub scale: f32,
+    pub click_rings: bool,
+    pub left_click_color: [u8; 3],
+    pub right_click_color: [u8; 3],
+    /// Hide the pointer after this long without movement; `None` keeps it visible.
+    pub idle_timeout_ms: Option<u64>,
+}
+
+impl CursorStyle {
+    pub fn from_settings(settings: &CursorSettings) -> Result<Self, RecorderError> {
+        if !settings.scale.is_finite() || settings.scale <= 0.0 || settings.scale > 8.0 {
+            return Err(RecorderError::invalid_settings("Cursor scale must be between 0 and 8"));
+        }
+
+        Ok(Self {
+            scale: settings.scale,
+            click_rings: settings.click_rings,
+            left_click_color: parse_hex_color(&settings.left_click_color)?,
+            right_click_color: parse_hex_color(&settings.right_click_color)?,
+            idle_timeout_ms: settings
+                .hide_when_idle
+                .then_some(settings.idle_timeout_ms as u64),
+        })
+    }
+}
+
+/// A click being animated, in frame pixels.
+#[derive(Debug, Clone, Copy)]
+pub struct ClickRing {
+    pub x: f32,
+    pub y: f32,
+    pub button: MouseButton,
+    pub age_ms: u64,
+}
+
+/// Everything the compositor needs to draw the pointer onto one frame.
+#[derive(Debug, Clone, Default)]
+pub struct CursorFrame {
+    /// Hotspot in frame pixels; `None` if the pointer hasn't been seen yet.
+    pub position: Option<(f32, f32)>,
+    pub idle_ms: u64,
+    pub clicks: Vec<ClickRing>,
+}
+
+struct Click {
+    x: i32,
+    y: i32,
+    button: MouseButton,
+    at: u64,
+}
+
+/// Folds a `CursorStream` into the pointer state for each recorded frame.
+pub struct CursorTracker {
+    stream: Box<dyn CursorStream>,
+    style: CursorStyle,
+    epoch: Instant,
+    position: Option<(i32, i32)>,
+    last_activity: u64,
+    clicks: Vec<Click>,
+}
+
+impl CursorTracker {
+    /// `epoch` must be the instant the stream's timestamps are relative to.
+    pub fn new(stream: Box<dyn CursorStream>, style: CursorStyle, epoch: Instant) -> Self {
+        Self {
+            stream,
+            style,
+            epoch,
+            position: None,
+            last_activity: 0,
+            clicks: Vec::new(),
+        }
+    }
+
+    pub fn style(&self) -> &CursorStyle {
+        &self.style
+    }
+
+    /// Drain the stream and map the pointer into a `frame_w` x `frame_h` frame showing `area`
+    /// inside `content` (the whole frame if `None`).
+    pub fn frame_state(
+        &mut self,
+        area: Option<CaptureArea>,
+        content: Option<ContentRect>,
+        frame_w: u32,
+        frame_h: u32,
+    ) -> CursorFrame {
+        for event in self.stream.poll() {
+            if self.position != Some((event.x, event.y)) || event.kind != CursorEventKind::Move {
+                self.last_activity = event.timestamp;
+            }
+            self.position = Some((event.x, event.y));
+            if let CursorEventKind::Press(button) = event.kind {
+                self.clicks.push(Click {
+                    x: event.x,
+                    y: event.y,
+                    button,
+                    at: event.timestamp,
+                });
+            }
+        }
+
+        let now = self.epoch.elapsed().as_millis() as u64;
+        self.clicks.retain(|c| now.saturating_sub(c.at) < CLICK_RING_MS);
+
+        let content = content.unwrap_or(ContentRect {
+            x: 0,
+            y: 0,
+            width: frame_w,
+            height: frame_h,
+        });
+        let map = |x: i32, y: i32| -> (f32, f32) {
+            match area {
+                Some(a) if a.width > 0 && a.height > 0 => (
+                    content.x as f32 + (x - a.x) as f32 * content.width as f32 / a.width as f32,
+                    content.y as f32 + (y - a.y) as f32 * content.height as f32 / a.height as f32,
+                ),
+                _ => (x as f32, y as f32),
+            }
+        };
+
+        CursorFrame {
+            position: self.position.map(|(x, y)| map(x, y)),
+            idle_ms: now.saturating_sub(self.last_activity),
+            clicks: self
+                .clicks
+                .iter()
+                .map(|c| {
+                    let (x, y) = map(c.x, c.y);
+                    ClickRing {
+                        x,
+                        y,
+                        button: c.button,
+                        age_ms: now.saturating_sub(c.at),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Follows the real pointer by polling it on a background thread, so clicks shorter than
+/// a frame interval still show up.
+pub struct SystemCursorStream {
+    events: Arc<Mutex<Vec<CursorEvent>>>,
+    stop_flag: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SystemCursorStream {
+    pub fn start(epoch: Instant) -> Result<Self, RecorderError> {
+        let mut reader = platform::PointerReader::open()?;
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let stop_flag = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let events = events.clone();
+            let stop_flag = stop_flag.clone();
+            std::thread::spawn(move || {
+                let mut last: Option<(i32, i32, bool, bool)> = None;
+                while !stop_flag.load(Ordering::SeqCst) {
+                    if let Some((x, y, left, right)) = reader.read() {
+                        let timestamp = epoch.elapsed().as_millis() as u64;
+                        let (was_left, was_right) = last.map(|l| (l.2, l.3)).unwrap_or((false, false));
+                        let mut out = events.lock();
+                        if last.map(|l| (l.0, l.1)) != Some((x, y)) {
+                            out.push(CursorEvent { timestamp, x, y, kind: CursorEventKind::Move });
+                        }
+                        if left && !was_left {
+                            out.push(CursorEvent {
+                                timestamp,
+                                x,
+                                y,
+                                kind: CursorEventKind::Press(MouseButton::Left),
+                            });
+                        }
+                        if right && !was_right {
+                            out.push(CursorEvent {
+                                timestamp,
+                                x,
+                                y,
+                                kind: CursorEventKind::Press(MouseButton::Right),
+                            });
+                        }
+                        last = Some((x, y, left, right));
+                    }
+                    std::thread::sleep(Duration::from_millis(8));
+                }
+            })
+        };
+
+        Ok(Self {
+            events,
+            stop_flag,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl CursorStream for SystemCursorStream {
+    fn poll(&mut self) -> Vec<CursorEvent> {
+        std::mem::take(&mut *self.events.lock())
+    }
+}
+
+impl Drop for SystemCursorStream {
+    fn drop(&mut self) {
+        self.stop_flag.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Scripted pointer for synthetic sources: traces a figure eight across a `width` x `height`
+/// frame, left-clicking every 2 s and right-clicking every 5 s. Position depends only on time.
+pub struct SyntheticCursorStream {
+    width: u32,
+    height: u32,
+    epoch: Instant,
+    last_poll: Option<u64>,
+}
+
+impl SyntheticCursorStream {
+    pub fn new(width: u32, height: u32, epoch: Instant) -> Self {
+        Self {
+            width,
+            height,
+            epoch,
+            last_poll: None,
+        }
+    }
+
+    fn position_at(&self, t: u64) -> (i32, i32) {
+        let phase = t as f32 / 4000.0 * std::f32::consts::TAU;
+        let (w, h) = (self.width as f32, self.height as f32);
+        let x = w / 2.0 + phase.sin() * w * 0.35;
+        let y = h / 2.0 + (phase * 2.0).sin() * h * 0.25;
+        (x as i32, y as i32)
+    }
+}
+
+impl CursorStream for SyntheticCursorStream {
+    fn poll(&mut self) -> Vec<CursorEvent> {
+        let now = self.epoch.elapsed().as_millis() as u64;
+        let since = self.last_poll.map(|t| t + 1).unwrap_or(0);
+        self.last_poll = Some(now);
+
+        let mut out = Vec::new();
+        for (period, button) in [(2000u64, MouseButton::Left), (5000, MouseButton::Right)] {
+            let mut t = since.div_ceil(period).max(1) * period;
+            while t <= now {
+                let (x, y) = self.position_at(t);
+                out.push(CursorEvent { timestamp: t, x, y, kind: CursorEventKind::Press(button) });
+                t += period;
+            }
+        }
+        out.sort_by_key(|e| e.timestamp);
+
+        let (x, y) = self.position_at(now);
+        out.push(CursorEvent { timestamp: now, x, y, kind: CursorEventKind::Move });
+        out
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use crate::error::RecorderError;
+    use windows::Win32::Foundation::POINT;
+    use windows::Win32::UI::Input::KeyboardAndMouse::{GetAsyncKeyState, VK_LBUTTON, VK_RBUTTON};
+    use windows::Win32::UI::WindowsAndMessaging::GetCursorPos;
+
+    pub struct PointerReader;
+
+    impl PointerReader {
+        pub fn open() -> Result<Self, RecorderError> {
+            Ok(Self)
+        }
+
+        /// Pointer position in desktop coordinates plus left/right button state.
+        pub fn read(&mut self) -> Option<(i32, i32, bool, bool)> {
+            let mut point = POINT::default();
+            unsafe {
+                GetCursorPos(&mut point).ok()?;
+                let left = GetAsyncKeyState(VK_LBUTTON.0 as i32) < 0;
+                let right = GetAsyncKeyState(VK_RBUTTON.0 as i32) < 0;
+                Some((point.x, point.y, left, right))
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use crate::error::RecorderError;
+    use crate::recording::screen_capturer_x11::connect;
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{ConnectionExt as _, KeyButMask, Window};
+    use x11rb::rust_connection::RustConnection;
+
+    pub struct PointerReader {
+        conn: RustConnection,
+        root: Window,
+    }
+
+    impl PointerReader {
+        pub fn open() -> Result<Self, RecorderError> {
+            let (conn, default_screen) = connect(None)?;
+            let root = conn.setup().roots[default_screen].root;
+            Ok(Self { conn, root })
+        }
+
+        /// Pointer position in root-window coordinates plus left/right button state.
+        pub fn read(&mut self) -> Option<(i32, i32, bool, bool)> {
+            let reply = self.conn.query_pointer(self.root).ok()?.reply().ok()?;
+            if !reply.same_screen {
+                return None;
+            }
+            Some((
+                reply.root_x as i32,
+                reply.root_y as i32,
+                reply.mask.contains(KeyButMask::BUTTON1),
+                reply.mask.contains(KeyButMask::BUTTON3),
+            ))
+        }
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+mod platform {
+    use crate::error::RecorderError;
+
+    pub enum PointerReader {}
+
+    impl PointerReader {
+        pub fn open() -> Result<Self, RecorderError> {
+            Err(RecorderError::device_not_found("Pointer"))
+        }
+
+        pub fn read(&mut self) -> Option<(i32, i32, bool, bool)> {
+            match *self {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out one batch of events per poll.
+    struct Script(Vec<Vec<CursorEvent>>);
+
+    impl CursorStream for Script {
+        fn poll(&mut self) -> Vec<CursorEvent> {
+            if self.0.is_empty() {
+                Vec::new()
+            } else {
+                self.0.remove(0)
+            }
+        }
+    }
+
+    fn event(timestamp: u64, x: i32, y: i32, kind: CursorEventKind) -> CursorEvent {
+        CursorEvent { timestamp, x, y, kind }
+    }
+
+    fn style() -> CursorStyle {
+        CursorStyle::from_settings(&CursorSettings::default()).unwrap()
+    }
+
+    /// A tracker whose clock reads 10 s.
+    fn tracker(batches: Vec<Vec<CursorEvent>>) -> CursorTracker {
+        CursorTracker::new(Box::new(Script(batches)), style(), Instant::now() - Duration::from_secs(10))
+    }
+
+    #[test]
+    fn desktop_position_maps_into_the_content_rect() {
+        // Second monitor, letterboxed into a 1280x1000 frame.
+        let area = CaptureArea { x: 1920, y: 0, width: 1920, height: 1080 };
+        let content = ContentRect { x: 0, y: 140, width: 1280, height: 720 };
+        let mut cursor = tracker(vec![vec![event(9000, 1920 + 960, 540, CursorEventKind::Move)]]);
+
+        let state = cursor.frame_state(Some(area), Some(content), 1280, 1000);
+        assert_eq!(state.position, Some((640.0, 500.0)));
+    }
+
+    #[test]
+    fn frame_coordinates_pass_through_without_an_area() {
+        let mut cursor = tracker(vec![vec![event(9000, 33, 44, CursorEventKind::Move)]]);
+        assert_eq!(cursor.frame_state(None, None, 100, 100).position, Some((33.0, 44.0)));
+
+        let mut unseen = tracker(Vec::new());
+        assert_eq!(unseen.frame_state(None, None, 100, 100).position, None);
+    }
+
+    #[test]
+    fn only_movement_and_clicks_count_as_activity() {
+        let mut cursor = tracker(vec![
+            vec![event(8000, 10, 10, CursorEventKind::Move)],
+            vec![event(9500, 10, 10, CursorEventKind::Move)],
+            vec![event(9800, 10, 10, CursorEventKind::Press(MouseButton::Left))],
+        ]);
+
+        let idle = cursor.frame_state(None, None, 100, 100).idle_ms;
+        assert!((2000..2500).contains(&idle), "{idle}");
+        // Same position again: still idle since 8 s.
+        let idle = cursor.frame_state(None, None, 100, 100).idle_ms;
+        assert!((2000..2500).contains(&idle), "{idle}");
+        let idle = cursor.frame_state(None, None, 100, 100).idle_ms;
+        assert!(idle < 500, "{idle}");
+    }
+
+    #[test]
+    fn click_rings_expire() {
+        let now = 10_000;
+        let mut cursor = tracker(vec![vec![
+            event(now - CLICK_RING_MS - 50, 1, 1, CursorEventKind::Press(MouseButton::Left)),
+            event(now - 100, 2, 3, CursorEventKind::Press(MouseButton::Right)),
+        ]]);
+
+        let clicks = cursor.frame_state(None, None, 100, 100).clicks;
+        assert_eq!(clicks.len(), 1);
+        assert_eq!((clicks[0].x, clicks[0].y, clicks[0].button), (2.0, 3.0, MouseButton::Right));
+        assert!((100..CLICK_RING_MS).contains(&clicks[0].age_ms));
+
+        std::thread::sleep(Duration::from_millis(CLICK_RING_MS));
+        assert!(cursor.frame_state(None, None, 100, 100).clicks.is_empty());
+    }
+
+    #[test]
+    fn synthetic_stream_clicks_on_schedule_inside_the_frame() {
+        let mut stream = SyntheticCursorStream::new(640, 360, Instant::now() - Duration::from_millis(10_500));
+
+        let events = stream.poll();
+        let presses: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e.kind {
+                CursorEventKind::Press(button) => Some((e.timestamp, button)),
+                CursorEventKind::Move => None,
+            })
+            .collect();
+        let (left, right) = (MouseButton::Left, MouseButton::Right);
+        assert_eq!(
+            presses,
+            [(2000, left), (4000, left), (5000, right), (6000, left), (8000, left), (10_000, left), (10_000, right)]
+        );
+        assert_eq!(events.last().unwrap().kind, CursorEventKind::Move);
+        assert!(events.iter().all(|e| (0..640).contains(&e.x) && (0..360).contains(&e.y)));
+
+        // Nothing is replayed on the next poll.
+        let events = stream.poll();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, CursorEventKind::Move);
+    }
+
+    #[test]
+    fn style_rejects_bad_scale_and_colors() {
+        for scale in [0.0, -1.0, 8.5, f32::NAN] {
+            assert!(CursorStyle::from_settings(&CursorSettings { scale, ..Default::default() }).is_err());
+        }
+        let bad_color = CursorSettings { left_click_color: "yellow".into(), ..Default::default() };
+        assert!(CursorStyle::from_settings(&bad_color).is_err());
+
+        assert_eq!(style().idle_timeout_ms, None);
+        let hiding = CursorSettings { hide_when_idle: true, idle_timeout_ms: 1500, ..Default::default() };
+        assert_eq!(CursorStyle::from_settings(&hiding).unwrap().idle_timeout_ms, Some(1500));
+    }
+}
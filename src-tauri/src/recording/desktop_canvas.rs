@@ -0,0 +1,264 @@
+use crate::commands::devices::{enumerate_displays, DisplayInfo};
+use crate::error::RecorderError;
+use crate::recording::frame_source::{CaptureArea, ContentRect, Frame, FrameSource};
+use crate::recording::scaler::{ScaleOptions, Scaler};
+use crate::recording::screen_capturer::ScreenCapturer;
+use crate::recording::tone_map::ToneMapper;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Opaque black, used for parts of the bounding box no monitor covers.
+const GAP_FILL: [u8; 4] = [0, 0, 0, 255];
+
+struct Tile {
+    capturer: Box<dyn FrameSource>,
+    /// Offset of the monitor inside the canvas.
+    x: u32,
+    y: u32,
+}
+
+/// Every connected monitor laid out as on the virtual desktop.
+///
+/// Each display is captured at its native size and copied into a canvas covering the
+/// bounding box of all `DisplayInfo` rectangles. Areas between or beside monitors of
+/// different sizes are filled with black, and the finished canvas is scaled to the output size.
+pub struct DesktopCanvasSource {
+    tiles: Vec<Tile>,
+    area: CaptureArea,
+    canvas: Vec<u8>,
+    canvas_w: u32,
+    canvas_h: u32,
+    target_w: u32,
+    target_h: u32,
+    scaler: Scaler,
+    started_at: Instant,
+    frame_number: u64,
+}
+
+impl DesktopCanvasSource {
+    pub fn new(
+        width: u32,
+        height: u32,
+        system_cursor: bool,
+        scale: ScaleOptions,
+        tone_map: Option<Arc<ToneMapper>>,
+    ) -> Result<Self, RecorderError> {
+        Self::from_displays(enumerate_displays()?, width, height, scale, |d| {
+            let capturer =
+                ScreenCapturer::new(d.index, None, d.width, d.height, system_cursor, scale, tone_map.clone())?;
+            Ok(Box::new(capturer))
+        })
+    }
+
+    /// Lay `displays` out on the canvas, capturing each through `open`.
+    fn from_displays(
+        displays: Vec<DisplayInfo>,
+        width: u32,
+        height: u32,
+        scale: ScaleOptions,
+        mut open: impl FnMut(&DisplayInfo) -> Result<Box<dyn FrameSource>, RecorderError>,
+    ) -> Result<Self, RecorderError> {
+        if width == 0 || height == 0 {
+            return Err(RecorderError::invalid_settings("Invalid target resolution"));
+        }
+
+        let displays: Vec<DisplayInfo> = displays
+            .into_iter()
+            .filter(|d| d.width > 0 && d.height > 0)
+            .collect();
+        if displays.is_empty() {
+            return Err(RecorderError::device_not_found("Display"));
+        }
+
+        let (left, top, canvas_w, canvas_h) = bounding_box(&displays);
+
+        let mut tiles = Vec::with_capacity(displays.len());
+        for d in &displays {
+            tiles.push(Tile {
+                capturer: open(d)?,
+                x: (d.x as i64 - left) as u32,
+                y: (d.y as i64 - top) as u32,
+            });
+        }
+
+        let mut canvas = vec![0u8; canvas_w as usize * canvas_h as usize * 4];
+        for px in canvas.chunks_exact_mut(4) {
+            px.copy_from_slice(&GAP_FILL);
+        }
+
+        Ok(Self {
+            tiles,
+            area: CaptureArea {
+                x: left as i32,
+                y: top as i32,
+                width: canvas_w,
+                height: canvas_h,
+            },
+            canvas,
+            canvas_w,
+            canvas_h,
+            target_w: width,
+            target_h: height,
+            scaler: Scaler::new(scale),
+            started_at: Instant::now(),
+            frame_number: 0,
+        })
+    }
+
+    fn blit(&mut self, frame: &Frame, x: u32, y: u32) {
+        let w = frame.width.min(self.canvas_w.saturating_sub(x)) as usize;
+        let h = frame.height.min(self.canvas_h.saturating_sub(y)) as usize;
+        let canvas_stride = self.canvas_w as usize * 4;
+        let src_stride = frame.width as usize * 4;
+
+        for row in 0..h {
+            let s = row * src_stride;
+            let d = (y as usize + row) * canvas_stride + x as usize * 4;
+            self.canvas[d..d + w * 4].copy_from_slice(&frame.data[s..s + w * 4]);
+        }
+    }
+}
+
+impl FrameSource for DesktopCanvasSource {
+    fn capture_frame(&mut self) -> Result<Frame, RecorderError> {
+        self.frame_number += 1;
+
+        let mut grabbed = Vec::with_capacity(self.tiles.len());
+        for tile in self.tiles.iter_mut() {
+            grabbed.push((tile.capturer.capture_frame()?, tile.x, tile.y));
+        }
+        for (frame, x, y) in &grabbed {
+            self.blit(frame, *x, *y);
+        }
+
+        let data = self.scaler.scale(
+            &self.canvas,
+            self.canvas_w,
+            self.canvas_h,
+            self.target_w,
+            self.target_h,
+        );
+
+        Ok(Frame {
+            data: data.into(),
+            width: self.target_w,
+            height: self.target_h,
+            timestamp: self.started_at.elapsed().as_millis() as u64,
+            frame_number: self.frame_number,
+        })
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.target_w, self.target_h)
+    }
+
+    fn stop(&mut self) {
+        for tile in self.tiles.iter_mut() {
+            tile.capturer.stop();
+        }
+    }
+
+    fn capture_area(&self) -> Option<CaptureArea> {
+        Some(self.area)
+    }
+
+    fn content_rect(&self) -> Option<ContentRect> {
+        Some(self.scaler.content_rect(self.canvas_w, self.canvas_h, self.target_w, self.target_h))
+    }
+}
+
+/// Left, top, width and height of the smallest rectangle containing every display.
+pub(crate) fn bounding_box(displays: &[DisplayInfo]) -> (i64, i64, u32, u32) {
+    let left = displays.iter().map(|d| d.x as i64).min().unwrap_or(0);
+    let top = displays.iter().map(|d| d.y as i64).min().unwrap_or(0);
+    let right = displays
+        .iter()
+        .map(|d| d.x as i64 + d.width as i64)
+        .max()
+        .unwrap_or(0);
+    let bottom = displays
+        .iter()
+        .map(|d| d.y as i64 + d.height as i64)
+        .max()
+        .unwrap_or(0);
+
+    (left, top, (right - left).max(1) as u32, (bottom - top).max(1) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::test_util::SolidSource;
+    use crate::state::app_state::ScaleFilter;
+
+    fn display(index: u32, x: i32, y: i32, width: u32, height: u32) -> DisplayInfo {
+        DisplayInfo { index, name: format!("Display {index}"), width, height, x, y, is_primary: index == 0 }
+    }
+
+    /// Display `i` shows `COLORS[i]`.
+    const COLORS: [[u8; 4]; 3] = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+
+    /// A canvas at its native size, so pixels can be checked exactly.
+    fn canvas(displays: Vec<DisplayInfo>) -> DesktopCanvasSource {
+        let (_, _, w, h) = bounding_box(&displays);
+        let scale = ScaleOptions::stretch(ScaleFilter::Nearest);
+        DesktopCanvasSource::from_displays(displays, w, h, scale, |d| {
+            Ok(Box::new(SolidSource::new(d.width, d.height, COLORS[d.index as usize])))
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn bounding_box_covers_negative_and_gapped_displays() {
+        assert_eq!(bounding_box(&[display(0, 0, 0, 1920, 1080)]), (0, 0, 1920, 1080));
+        // A monitor left of and above the primary.
+        let left = [display(0, 0, 0, 1920, 1080), display(1, -1280, -200, 1280, 1024)];
+        assert_eq!(bounding_box(&left), (-1280, -200, 3200, 1280));
+        // Two monitors with a gap between them.
+        let gapped = [display(0, 0, 0, 800, 600), display(1, 1000, 100, 800, 600)];
+        assert_eq!(bounding_box(&gapped), (0, 0, 1800, 700));
+        assert_eq!(bounding_box(&[]), (0, 0, 1, 1));
+    }
+
+    #[test]
+    fn tiles_land_at_their_desktop_offsets() {
+        let mut source = canvas(vec![
+            display(0, 0, 0, 40, 30),
+            display(1, -20, -10, 20, 10),
+            display(2, 50, 20, 10, 20),
+        ]);
+        assert_eq!(source.capture_area(), Some(CaptureArea { x: -20, y: -10, width: 80, height: 50 }));
+
+        let frame = source.capture_frame().unwrap();
+        assert_eq!((frame.width, frame.height), (80, 50));
+        // Desktop coordinates to canvas pixels.
+        let px = |x: i32, y: i32| frame.data[(((y + 10) * 80 + x + 20) * 4) as usize..][..4].to_vec();
+        assert_eq!(px(0, 0), COLORS[0]);
+        assert_eq!(px(39, 29), COLORS[0]);
+        assert_eq!(px(-20, -10), COLORS[1]);
+        assert_eq!(px(-1, -1), COLORS[1]);
+        assert_eq!(px(50, 20), COLORS[2]);
+        assert_eq!(px(59, 39), COLORS[2]);
+        // Uncovered: left of the primary below the second display, the gap between the
+        // primary and the third display, and above the third.
+        assert_eq!(px(-1, 0), GAP_FILL);
+        assert_eq!(px(45, 25), GAP_FILL);
+        assert_eq!(px(55, 0), GAP_FILL);
+        assert_eq!(px(59, 19), GAP_FILL);
+    }
+
+    #[test]
+    fn empty_displays_are_left_out() {
+        let mut source = canvas(vec![display(0, 0, 0, 16, 16), display(1, 16, 0, 0, 0)]);
+        assert_eq!(source.capture_frame().unwrap().width, 16);
+
+        let none = DesktopCanvasSource::from_displays(
+            vec![display(0, 0, 0, 0, 0)],
+            16,
+            16,
+            ScaleOptions::stretch(ScaleFilter::Nearest),
+            |_| unreachable!(),
+        );
+        assert_eq!(none.err().unwrap().code, "DEVICE_NOT_FOUND");
+    }
+}
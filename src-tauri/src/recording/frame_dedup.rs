@@ -0,0 +1,84 @@
+use crate::recording::frame_pool::FrameData;
+use crate::recording::frame_source::Frame;
+
+const SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+const MUL: u64 = 0xFF51_AFD7_ED55_8CCD;
+
+/// 64-bit content hash of a BGRA buffer. Not cryptographic, just cheap enough to run on every
+/// frame (one multiply per 8 bytes) with a negligible chance of two different frames colliding.
+pub fn frame_hash(data: &[u8]) -> u64 {
+    let mut h = SEED ^ data.len() as u64;
+    let mut words = data.chunks_exact(8);
+    for w in &mut words {
+        let v = u64::from_le_bytes([w[0], w[1], w[2], w[3], w[4], w[5], w[6], w[7]]);
+        h = (h ^ v).wrapping_mul(MUL).rotate_left(29);
+    }
+    for &b in words.remainder() {
+        h = (h ^ b as u64).wrapping_mul(MUL).rotate_left(29);
+    }
+
+    // Final avalanche so nearby inputs don't produce nearby hashes.
+    h ^= h >> 33;
+    h = h.wrapping_mul(MUL);
+    h ^ (h >> 33)
+}
+
+/// Spots frames identical to the one before, so the encoder can stretch the previous
+/// sample instead of encoding the same picture again.
+///
+/// The hash only rules frames out; on a match the pixels are compared with the previous
+/// frame, which is kept (shared, not copied) for that.
+#[derive(Default)]
+pub struct DuplicateFrameDetector {
+    last: Option<LastFrame>,
+}
+
+struct LastFrame {
+    width: u32,
+    height: u32,
+    hash: u64,
+    data: FrameData,
+}
+
+impl DuplicateFrameDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if `frame` has the same size and content as the previous frame passed in.
+    pub fn is_duplicate(&mut self, frame: &Frame) -> bool {
+        let hash = frame_hash(&frame.data);
+        let duplicate = self.last.as_ref().is_some_and(|last| {
+            (last.width, last.height, last.hash) == (frame.width, frame.height, hash) && last.data[..] == frame.data[..]
+        });
+        self.last = Some(LastFrame { width: frame.width, height: frame.height, hash, data: frame.data.clone() });
+        duplicate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(data: Vec<u8>) -> Frame {
+        Frame { data: data.into(), width: 2, height: 1, timestamp: 0, frame_number: 0 }
+    }
+
+    #[test]
+    fn detects_repeated_frames() {
+        let mut dedup = DuplicateFrameDetector::new();
+        assert!(!dedup.is_duplicate(&frame(vec![1; 8])));
+        assert!(dedup.is_duplicate(&frame(vec![1; 8])));
+        assert!(!dedup.is_duplicate(&frame(vec![2; 8])));
+        assert!(!dedup.is_duplicate(&Frame { width: 1, height: 2, ..frame(vec![2; 8]) }));
+    }
+
+    #[test]
+    fn hash_collisions_are_not_duplicates() {
+        let mut dedup = DuplicateFrameDetector::new();
+        dedup.is_duplicate(&frame(vec![1; 8]));
+        // Forge a colliding hash for different pixels.
+        dedup.last.as_mut().unwrap().hash = frame_hash(&[2; 8]);
+        assert!(!dedup.is_duplicate(&frame(vec![2; 8])));
+    }
+}
@@ -0,0 +1,248 @@
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// Idle buffers kept around per pool. Enough for every queue slot of the pipeline plus the
+/// frames each stage is holding, without hoarding memory after a resolution change.
+const MAX_IDLE_BUFFERS: usize = 16;
+
+/// Allocation counters of a `FramePool`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FramePoolStats {
+    /// Buffers that had to be freshly allocated.
+    pub allocations: u64,
+    pub allocated_bytes: u64,
+    /// Requests served from a recycled buffer.
+    pub reuses: u64,
+}
+
+impl FramePoolStats {
+    /// Counters accumulated since `earlier` was taken.
+    pub fn since(&self, earlier: &FramePoolStats) -> FramePoolStats {
+        FramePoolStats {
+            allocations: self.allocations.saturating_sub(earlier.allocations),
+            allocated_bytes: self.allocated_bytes.saturating_sub(earlier.allocated_bytes),
+            reuses: self.reuses.saturating_sub(earlier.reuses),
+        }
+    }
+}
+
+#[derive(Default)]
+struct PoolInner {
+    idle: Mutex<Vec<Vec<u8>>>,
+    allocations: AtomicU64,
+    allocated_bytes: AtomicU64,
+    reuses: AtomicU64,
+}
+
+impl PoolInner {
+    fn recycle(&self, buf: Vec<u8>) {
+        if buf.capacity() == 0 {
+            return;
+        }
+        let mut idle = self.idle.lock();
+        if idle.len() < MAX_IDLE_BUFFERS {
+            idle.push(buf);
+        }
+    }
+}
+
+/// Recycles frame-sized byte buffers so steady-state capture doesn't allocate.
+///
+/// Buffers come back automatically when the last `FrameBuffer` holding them is dropped.
+#[derive(Clone, Default)]
+pub struct FramePool {
+    inner: Arc<PoolInner>,
+}
+
+impl FramePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process-wide pool shared by the capture sources and the compositor.
+    pub fn shared() -> &'static FramePool {
+        static SHARED: OnceLock<FramePool> = OnceLock::new();
+        SHARED.get_or_init(FramePool::new)
+    }
+
+    /// A buffer of exactly `len` bytes. Contents are unspecified when it is recycled, so
+    /// callers are expected to overwrite all of it.
+    pub fn take(&self, len: usize) -> FrameBuffer {
+        let recycled = {
+            let mut idle = self.inner.idle.lock();
+            idle.iter()
+                .position(|b| b.len() == len)
+                .or_else(|| idle.iter().position(|b| b.capacity() >= len))
+                .map(|i| idle.swap_remove(i))
+        };
+
+        let data = match recycled {
+            Some(mut buf) => {
+                self.inner.reuses.fetch_add(1, Ordering::Relaxed);
+                if buf.len() != len {
+                    buf.resize(len, 0);
+                }
+                buf
+            }
+            None => {
+                self.inner.allocations.fetch_add(1, Ordering::Relaxed);
+                self.inner.allocated_bytes.fetch_add(len as u64, Ordering::Relaxed);
+                vec![0u8; len]
+            }
+        };
+
+        FrameBuffer {
+            data,
+            pool: self.inner.clone(),
+        }
+    }
+
+    /// A pooled copy of `src`.
+    pub fn copy_of(&self, src: &[u8]) -> FrameBuffer {
+        let mut buf = self.take(src.len());
+        buf.copy_from_slice(src);
+        buf
+    }
+
+    pub fn stats(&self) -> FramePoolStats {
+        FramePoolStats {
+            allocations: self.inner.allocations.load(Ordering::Relaxed),
+            allocated_bytes: self.inner.allocated_bytes.load(Ordering::Relaxed),
+            reuses: self.inner.reuses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Byte buffer on loan from a `FramePool`; returned to it on drop.
+pub struct FrameBuffer {
+    data: Vec<u8>,
+    pool: Arc<PoolInner>,
+}
+
+impl From<Vec<u8>> for FrameBuffer {
+    /// Wrap a buffer allocated elsewhere (decoders, device APIs). It joins the shared pool
+    /// once dropped.
+    fn from(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            pool: FramePool::shared().inner.clone(),
+        }
+    }
+}
+
+impl Clone for FrameBuffer {
+    fn clone(&self) -> Self {
+        FramePool {
+            inner: self.pool.clone(),
+        }
+        .copy_of(&self.data)
+    }
+}
+
+impl Deref for FrameBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl DerefMut for FrameBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+impl Drop for FrameBuffer {
+    fn drop(&mut self) {
+        self.pool.recycle(std::mem::take(&mut self.data));
+    }
+}
+
+/// Reference-counted frame pixels.
+///
+/// Cloning a `Frame` only bumps the count, so a capturer can hand out its latest picture
+/// while keeping it for the next request. Writing through `DerefMut` copies the pixels into
+/// a fresh pooled buffer first if anyone else still holds them.
+#[derive(Clone)]
+pub struct FrameData(Arc<FrameBuffer>);
+
+impl std::fmt::Debug for FrameData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FrameData({} bytes)", self.0.len())
+    }
+}
+
+impl From<FrameBuffer> for FrameData {
+    fn from(buf: FrameBuffer) -> Self {
+        Self(Arc::new(buf))
+    }
+}
+
+impl From<Vec<u8>> for FrameData {
+    fn from(data: Vec<u8>) -> Self {
+        Self(Arc::new(FrameBuffer::from(data)))
+    }
+}
+
+impl Deref for FrameData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl DerefMut for FrameData {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut Arc::make_mut(&mut self.0)[..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropped_buffers_are_reused() {
+        let pool = FramePool::new();
+        let a = pool.take(1000);
+        let b = pool.copy_of(&[7; 1000]);
+        drop((a, b));
+        assert_eq!(pool.stats(), FramePoolStats { allocations: 2, allocated_bytes: 2000, reuses: 0 });
+
+        // Shared frames go back once the last reference is gone.
+        let shared = FrameData::from(pool.take(1000));
+        let copy = shared.clone();
+        drop(shared);
+        let smaller = pool.take(500);
+        assert_eq!(smaller.len(), 500);
+        drop((copy, smaller));
+
+        let before = pool.stats();
+        for _ in 0..100 {
+            let _frame = pool.take(1000);
+        }
+        assert_eq!(pool.stats().since(&before), FramePoolStats { allocations: 0, allocated_bytes: 0, reuses: 100 });
+    }
+
+    #[test]
+    fn writing_to_a_shared_frame_copies_it() {
+        let pool = FramePool::new();
+        let mut frame = FrameData::from(pool.copy_of(&[1; 16]));
+        let kept = frame.clone();
+        frame[0] = 2;
+        assert_eq!((frame[0], kept[0]), (2, 1));
+    }
+
+    #[test]
+    fn idle_buffers_are_capped() {
+        let pool = FramePool::new();
+        let buffers: Vec<_> = (0..MAX_IDLE_BUFFERS + 4).map(|_| pool.take(64)).collect();
+        drop(buffers);
+        assert_eq!(pool.inner.idle.lock().len(), MAX_IDLE_BUFFERS);
+    }
+}
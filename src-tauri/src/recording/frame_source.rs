@@ -0,0 +1,76 @@
+use crate::error::RecorderError;
+use crate::recording::frame_pool::FrameData;
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// BGRA pixels. Cheap to clone; see `FrameData`.
+    pub data: FrameData,
+    pub width: u32,
+    pub height: u32,
+    pub timestamp: u64,
+    pub frame_number: u64,
+}
+
+/// Part of the virtual desktop a source is capturing, in desktop pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureArea {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Where the source picture sits inside an output frame, in frame pixels.
+///
+/// Smaller than the frame when `FitMode::Fit` adds bars; larger than the frame, with a
+/// negative offset, when `FitMode::Fill` crops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ContentRect {
+    /// The part of the rectangle inside a `frame_w` x `frame_h` frame.
+    pub fn visible(&self, frame_w: u32, frame_h: u32) -> ContentRect {
+        let x0 = self.x.clamp(0, frame_w as i32);
+        let y0 = self.y.clamp(0, frame_h as i32);
+        let x1 = (self.x as i64 + self.width as i64).clamp(x0 as i64, frame_w as i64) as i32;
+        let y1 = (self.y as i64 + self.height as i64).clamp(y0 as i64, frame_h as i64) as i32;
+        ContentRect {
+            x: x0,
+            y: y0,
+            width: (x1 - x0) as u32,
+            height: (y1 - y0) as u32,
+        }
+    }
+}
+
+/// Anything that can feed BGRA frames into the recording pipeline.
+///
+/// Implemented by the real screen/camera capturers as well as synthetic sources, so the
+/// worker loop in `RecordingManager` doesn't care where pixels come from.
+pub trait FrameSource {
+    /// Produce the next frame. `Frame::timestamp` is in milliseconds since the source started.
+    fn capture_frame(&mut self) -> Result<Frame, RecorderError>;
+
+    /// Size of the frames returned by `capture_frame`.
+    fn dimensions(&self) -> (u32, u32);
+
+    /// Release the underlying device. Safe to call more than once.
+    fn stop(&mut self);
+
+    /// Desktop area behind the most recent frame, used to map pointer positions into it.
+    /// `None` for sources that aren't a view of the desktop.
+    fn capture_area(&self) -> Option<CaptureArea> {
+        None
+    }
+
+    /// Where the captured picture sits in the most recent frame. `None` when it fills the
+    /// whole frame.
+    fn content_rect(&self) -> Option<ContentRect> {
+        None
+    }
+}
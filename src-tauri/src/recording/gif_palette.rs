@@ -0,0 +1,268 @@
+use crate::state::app_state::GifQuantizer;
+use color_quant::NeuQuant;
+
+/// GIF palettes hold at most this many colors.
+pub const MAX_COLORS: usize = 256;
+
+/// Pixels looked at when building a palette; larger inputs are sampled evenly.
+const MAX_SAMPLES: usize = 1 << 18;
+
+/// NeuQuant sampling factor: 1 learns from every sample, 30 from a 30th. 10 is the usual
+/// trade-off.
+const NEUQUANT_SAMPLE_FACTOR: i32 = 10;
+
+/// Nearest-color cache slot nobody has asked for yet.
+const UNSET: u16 = u16::MAX;
+
+/// RGB palette for one GIF color table, with the lookups to map BGRA pixels onto it.
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+    /// Nearest palette index per 15-bit RGB color, filled in on first use.
+    nearest: Vec<u16>,
+}
+
+impl Palette {
+    /// Build a palette for the BGRA pixels in `frames`.
+    pub fn build<'a>(quantizer: GifQuantizer, frames: impl Iterator<Item = &'a [u8]> + Clone) -> Self {
+        let total: usize = frames.clone().map(|f| f.len() / 4).sum();
+        let step = total.div_ceil(MAX_SAMPLES).max(1);
+        let samples: Vec<[u8; 3]> = frames
+            .flat_map(|f| f.chunks_exact(4).step_by(step))
+            .map(|px| [px[2], px[1], px[0]])
+            .collect();
+
+        let colors = match quantizer {
+            GifQuantizer::MedianCut => median_cut(samples),
+            GifQuantizer::NeuQuant => neuquant(&samples),
+        };
+        Self::from_colors(colors)
+    }
+
+    fn from_colors(mut colors: Vec<[u8; 3]>) -> Self {
+        if colors.is_empty() {
+            colors.push([0, 0, 0]);
+        }
+        Self { colors, nearest: vec![UNSET; 1 << 15] }
+    }
+
+    /// Flat RGB table for the GIF encoder.
+    pub fn rgb(&self) -> Vec<u8> {
+        self.colors.iter().flatten().copied().collect()
+    }
+
+    fn index_of(&mut self, rgb: [i32; 3]) -> u8 {
+        let [r, g, b] = rgb.map(|c| c.clamp(0, 255) as usize);
+        let key = (r >> 3) << 10 | (g >> 3) << 5 | b >> 3;
+        if self.nearest[key] == UNSET {
+            // Match against the middle of the 5-bit cell the color falls in.
+            let probe = [(r & !7) as i32 + 4, (g & !7) as i32 + 4, (b & !7) as i32 + 4];
+            let best = self
+                .colors
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, c)| {
+                    let d = |i: usize| (c[i] as i32 - probe[i]).pow(2);
+                    // Weighted towards green, which the eye is most sensitive to.
+                    2 * d(0) + 4 * d(1) + 3 * d(2)
+                })
+                .map_or(0, |(i, _)| i);
+            self.nearest[key] = best as u16;
+        }
+        self.nearest[key] as u8
+    }
+
+    /// Palette indices for a `width` x `height` BGRA image, optionally with Floyd-Steinberg
+    /// dithering to hide banding in gradients.
+    pub fn map(&mut self, bgra: &[u8], width: usize, height: usize, dither: bool) -> Vec<u8> {
+        let mut indices = Vec::with_capacity(width * height);
+        if !dither {
+            for px in bgra.chunks_exact(4).take(width * height) {
+                indices.push(self.index_of([px[2] as i32, px[1] as i32, px[0] as i32]));
+            }
+            return indices;
+        }
+
+        // Error carried into the current and the next row, with a pixel of slack either side.
+        let mut current = vec![[0i32; 3]; width + 2];
+        let mut next = vec![[0i32; 3]; width + 2];
+        for y in 0..height {
+            for x in 0..width {
+                let px = &bgra[(y * width + x) * 4..][..4];
+                let err = current[x + 1];
+                let want = [
+                    px[2] as i32 + err[0] / 16,
+                    px[1] as i32 + err[1] / 16,
+                    px[0] as i32 + err[2] / 16,
+                ];
+                let index = self.index_of(want);
+                indices.push(index);
+
+                let got = self.colors[index as usize];
+                for c in 0..3 {
+                    let e = want[c].clamp(0, 255) - got[c] as i32;
+                    current[x + 2][c] += e * 7;
+                    next[x][c] += e * 3;
+                    next[x + 1][c] += e * 5;
+                    next[x + 2][c] += e;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.fill([0; 3]);
+        }
+        indices
+    }
+}
+
+/// Heckbert's median cut: split the box with the widest channel at its median until there
+/// are enough boxes, then take each box's average.
+fn median_cut(mut samples: Vec<[u8; 3]>) -> Vec<[u8; 3]> {
+    if samples.is_empty() {
+        return Vec::new();
+    }
+
+    fn widest(pixels: &[[u8; 3]]) -> (usize, u8) {
+        (0..3)
+            .map(|c| {
+                let (lo, hi) = pixels.iter().fold((255, 0), |(lo, hi), p| (p[c].min(lo), p[c].max(hi)));
+                (c, hi - lo)
+            })
+            .max_by_key(|&(_, range)| range)
+            .unwrap_or((0, 0))
+    }
+
+    // Boxes are ranges of `samples`, which gets partially sorted in place.
+    let mut boxes = vec![(0, samples.len())];
+    while boxes.len() < MAX_COLORS {
+        // Split where it buys the most: wide boxes with many pixels in them.
+        let Some((i, channel)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, &(start, end))| end - start > 1)
+            .map(|(i, &(start, end))| {
+                let (channel, range) = widest(&samples[start..end]);
+                (i, channel, range as usize * (end - start))
+            })
+            .filter(|&(_, _, score)| score > 0)
+            .max_by_key(|&(_, _, score)| score)
+            .map(|(i, channel, _)| (i, channel))
+        else {
+            break;
+        };
+
+        let (start, end) = boxes[i];
+        let mid = (end - start) / 2;
+        samples[start..end].select_nth_unstable_by_key(mid, |p| p[channel]);
+        boxes[i] = (start, start + mid);
+        boxes.push((start + mid, end));
+    }
+
+    boxes
+        .into_iter()
+        .map(|(start, end)| {
+            let sum = samples[start..end]
+                .iter()
+                .fold([0u64; 3], |acc, p| [acc[0] + p[0] as u64, acc[1] + p[1] as u64, acc[2] + p[2] as u64]);
+            let n = (end - start) as u64;
+            sum.map(|s| ((s + n / 2) / n) as u8)
+        })
+        .collect()
+}
+
+/// Kohonen network quantizer; slower than median cut but kinder to smooth gradients.
+fn neuquant(samples: &[[u8; 3]]) -> Vec<[u8; 3]> {
+    if samples.is_empty() {
+        return Vec::new();
+    }
+    // The network learns from a fixed share of its input, too few steps for a small image
+    // such as the changed area of a frame with its own palette; repeat those.
+    let repeat = MAX_SAMPLES.div_ceil(samples.len());
+    let rgba: Vec<u8> = samples
+        .iter()
+        .cycle()
+        .take(samples.len() * repeat)
+        .flat_map(|&[r, g, b]| [r, g, b, 255])
+        .collect();
+    let colors = MAX_COLORS.min(samples.len());
+    NeuQuant::new(NEUQUANT_SAMPLE_FACTOR, colors, &rgba)
+        .color_map_rgb()
+        .chunks_exact(3)
+        .map(|c| [c[0], c[1], c[2]])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `colors` as a row of BGRA pixels.
+    fn bgra(colors: &[[u8; 3]]) -> Vec<u8> {
+        colors.iter().flat_map(|&[r, g, b]| [b, g, r, 255]).collect()
+    }
+
+    /// 300 different colors spread over the whole cube.
+    fn spread() -> Vec<[u8; 3]> {
+        (0..300u32).map(|i| [(i % 7 * 42) as u8, (i / 7 % 7 * 42) as u8, (i / 49 * 42) as u8]).collect()
+    }
+
+    /// Mean and largest per-channel difference between `colors` and what `indices` pick.
+    fn error(palette: &Palette, colors: &[[u8; 3]], indices: &[u8]) -> (f64, u8) {
+        let diffs: Vec<u8> = colors
+            .iter()
+            .zip(indices)
+            .flat_map(|(c, &i)| (0..3).map(move |ch| (c, i, ch)))
+            .map(|(c, i, ch)| c[ch].abs_diff(palette.colors[i as usize][ch]))
+            .collect();
+        let mean = diffs.iter().map(|&d| d as f64).sum::<f64>() / diffs.len() as f64;
+        (mean, diffs.into_iter().max().unwrap_or(0))
+    }
+
+    #[test]
+    fn too_many_colors_fit_in_a_table() {
+        let colors = spread();
+        let image = bgra(&colors);
+        for quantizer in [GifQuantizer::MedianCut, GifQuantizer::NeuQuant] {
+            let mut palette = Palette::build(quantizer, std::iter::once(image.as_slice()));
+            assert!(palette.colors.len() <= MAX_COLORS, "{quantizer:?}: {} colors", palette.colors.len());
+            assert_eq!(palette.rgb().len(), palette.colors.len() * 3);
+            let indices = palette.map(&image, colors.len(), 1, false);
+            let (mean, max) = error(&palette, &colors, &indices);
+            assert!(mean < 8.0 && max <= 48, "{quantizer:?}: mean error {mean:.2}, largest {max}");
+        }
+    }
+
+    #[test]
+    fn few_colors_come_through_exactly() {
+        let colors = [[255, 0, 0], [0, 128, 0], [16, 32, 240], [255, 255, 255]].repeat(10);
+        let image = bgra(&colors);
+        let mut palette = Palette::build(GifQuantizer::MedianCut, std::iter::once(image.as_slice()));
+        assert_eq!(palette.colors.len(), 4);
+        let indices = palette.map(&image, colors.len(), 1, false);
+        assert_eq!(error(&palette, &colors, &indices), (0.0, 0));
+    }
+
+    #[test]
+    fn empty_input_still_has_a_color() {
+        for quantizer in [GifQuantizer::MedianCut, GifQuantizer::NeuQuant] {
+            assert_eq!(Palette::build(quantizer, std::iter::empty()).rgb(), [0, 0, 0]);
+        }
+    }
+
+    #[test]
+    fn dithering_keeps_the_average() {
+        // Grey 100 between entries 85 and 170: plain mapping rounds every pixel down,
+        // dithering mixes the two so the area averages out close to 100.
+        let mut palette = Palette::from_colors([0, 85, 170, 255].map(|v| [v; 3]).to_vec());
+        let (w, h) = (32, 32);
+        let image = bgra(&vec![[100; 3]; w * h]);
+        let average = |indices: &[u8], palette: &Palette| {
+            indices.iter().map(|&i| palette.colors[i as usize][0] as f64).sum::<f64>() / indices.len() as f64
+        };
+
+        let plain = palette.map(&image, w, h, false);
+        assert!(plain.iter().all(|&i| i == 1));
+        let dithered = palette.map(&image, w, h, true);
+        assert!(dithered.iter().all(|&i| i == 1 || i == 2), "only the neighbouring entries are used");
+        let mean = average(&dithered, &palette);
+        assert!((mean - 100.0).abs() < 2.0, "dithered average {mean:.2}");
+    }
+}
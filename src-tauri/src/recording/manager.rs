@@ -1,11 +1,28 @@
+use crate::commands::devices::{enumerate_displays, DisplayInfo};
 use crate::error::RecorderError;
 use crate::recording::status::RecordingStatus;
 use crate::recording::audio_capturer::MicrophoneCapture;
+use crate::recording::audio_mix::AudioMix;
+use crate::recording::audio_sink::{open_audio_sink, sink_format, AudioSink};
 use crate::recording::camera_capturer::CameraCapturer;
-use crate::recording::compositor::FrameCompositor;
+use crate::recording::color_convert::ColorSpace;
+use crate::recording::compositor::{CameraOverlay, FrameCompositor};
+use crate::recording::cursor::{CursorStyle, CursorTracker, SyntheticCursorStream, SystemCursorStream};
+use crate::recording::desktop_canvas::{bounding_box, DesktopCanvasSource};
+use crate::recording::frame_dedup::DuplicateFrameDetector;
+use crate::recording::frame_pool::{FramePool, FramePoolStats};
+use crate::recording::frame_source::{CaptureArea, ContentRect, Frame, FrameSource};
+use crate::recording::mux::MuxOptions;
+use crate::recording::pipeline::{LatestSlot, PipelineMetrics, StageQueue};
+use crate::recording::replay_source::ReplaySource;
+use crate::recording::scaler::{ScaleOptions, Scaler};
 use crate::recording::screen_capturer::ScreenCapturer;
-use crate::recording::video_encoder::VideoEncoder;
-use crate::state::app_state::AppState;
+use crate::recording::test_pattern::TestPatternSource;
+use crate::recording::tone_map::ToneMapper;
+use crate::recording::video_sink::{SinkConfig, SinkRegistry, VideoSink};
+use crate::state::app_state::{
+    AppState, CaptureRegion, CaptureSource, CursorMode, RecordingSettings, Resolution,
+};
 use crate::state::history::{SessionStatus, TimerSession};
 use crate::utils::config::get_default_recordings_path;
 use chrono::{Local, Utc};
@@ -13,7 +30,7 @@ use parking_lot::Mutex;
 use std::fs;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc;
 use std::thread::JoinHandle;
 use std::time::Duration;
@@ -21,6 +38,29 @@ use std::time::Instant;
 use tauri::{AppHandle, Emitter};
 use uuid::Uuid;
 
+/// Longest a static picture is stretched before it is encoded again, so seeking into a long
+/// unchanged stretch still lands near a real frame.
+const MAX_FRAME_HOLD: Duration = Duration::from_secs(1);
+
+/// How often an audio-only recording pulls from the devices.
+const AUDIO_PULL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Audio-only recordings read this far behind the clock, so the device callbacks have
+/// delivered the samples by the time they are pulled.
+const AUDIO_LATENCY: Duration = Duration::from_millis(100);
+
+/// A screen frame travelling through the capture -> composite -> encode stages.
+struct StagedFrame {
+    frame: Frame,
+    /// Desktop area the frame shows, for mapping the cursor.
+    area: Option<CaptureArea>,
+    /// Where that area landed in the frame after fitting it to the output size.
+    content: Option<ContentRect>,
+    /// Recording time of the frame, paused time excluded.
+    elapsed: Duration,
+    captured_at: Instant,
+}
+
 #[derive(Clone)]
 pub struct RecordingManager {
     pub(crate) state: Arc<AppState>,
@@ -29,9 +69,19 @@ pub struct RecordingManager {
     paused_at: Arc<Mutex<Option<Instant>>>,
     paused_total: Arc<Mutex<Duration>>,
     session_id: Arc<Mutex<Option<String>>>,
+    session_region: Arc<Mutex<Option<CaptureRegion>>>,
     last_session: Arc<Mutex<Option<TimerSession>>>,
     stop_flag: Arc<AtomicBool>,
     pause_flag: Arc<AtomicBool>,
+    /// Duplicate frames folded into the previous sample during the current session.
+    skipped_frames: Arc<AtomicU64>,
+    pipeline_metrics: Arc<PipelineMetrics>,
+    /// Shared pool counters when the session started; status reports the difference.
+    pool_baseline: Arc<Mutex<FramePoolStats>>,
+    /// Problems the current recording worked around, shown to the user.
+    warnings: Arc<Mutex<Vec<String>>>,
+    /// Encoder backends `settings.video_backend` picks from.
+    sinks: Arc<SinkRegistry>,
     worker: Arc<Mutex<Option<JoinHandle<Result<(), RecorderError>>>>>,
     tick_task: Arc<Mutex<Option<tauri::async_runtime::JoinHandle<()>>>>,
 }
@@ -45,9 +95,15 @@ impl RecordingManager {
             paused_at: Arc::new(Mutex::new(None)),
             paused_total: Arc::new(Mutex::new(Duration::from_secs(0))),
             session_id: Arc::new(Mutex::new(None)),
+            session_region: Arc::new(Mutex::new(None)),
             last_session: Arc::new(Mutex::new(None)),
             stop_flag: Arc::new(AtomicBool::new(false)),
             pause_flag: Arc::new(AtomicBool::new(false)),
+            skipped_frames: Arc::new(AtomicU64::new(0)),
+            pipeline_metrics: Arc::new(PipelineMetrics::default()),
+            pool_baseline: Arc::new(Mutex::new(FramePoolStats::default())),
+            warnings: Arc::new(Mutex::new(Vec::new())),
+            sinks: Arc::new(SinkRegistry::builtin()),
             worker: Arc::new(Mutex::new(None)),
             tick_task: Arc::new(Mutex::new(None)),
         }
@@ -59,6 +115,10 @@ impl RecordingManager {
             is_paused: *self.state.is_paused.lock(),
             output_file: self.state.output_file.lock().clone(),
             elapsed_seconds: self.elapsed_seconds(),
+            skipped_frames: self.skipped_frames.load(Ordering::Relaxed),
+            pipeline: self.pipeline_metrics.snapshot(),
+            frame_pool: FramePool::shared().stats().since(&self.pool_baseline.lock()),
+            warnings: self.warnings.lock().clone(),
         }
     }
 
@@ -93,8 +153,9 @@ impl RecordingManager {
         self.last_session.lock().take()
     }
 
-    fn begin_session(&self) {
+    fn begin_session(&self, region: Option<CaptureRegion>) {
         *self.session_id.lock() = Some(Uuid::new_v4().to_string());
+        *self.session_region.lock() = region;
         *self.started_wall.lock() = Some(Utc::now());
         *self.started_at.lock() = Some(Instant::now());
         *self.paused_at.lock() = None;
@@ -107,6 +168,8 @@ impl RecordingManager {
         let ended = Utc::now().to_rfc3339();
         let duration_seconds = self.elapsed_seconds();
         let output_file = self.state.output_file.lock().clone();
+        let capture_region = *self.session_region.lock();
+        let skipped_frames = self.skipped_frames.load(Ordering::Relaxed);
 
         Some(TimerSession {
             id,
@@ -115,149 +178,624 @@ impl RecordingManager {
             duration_seconds,
             status,
             output_file,
+            capture_region,
+            skipped_frames,
         })
     }
 
-    fn build_output_path() -> Result<PathBuf, RecorderError> {
+    fn build_output_path(extension: &str) -> Result<PathBuf, RecorderError> {
         let dir = get_default_recordings_path()?;
         let ts = Local::now().format("%Y%m%d_%H%M%S").to_string();
-        Ok(dir.join(format!("recording_{ts}.mp4")))
+        Ok(dir.join(format!("recording_{ts}.{extension}")))
+    }
+
+    fn open_screen_source(
+        settings: &RecordingSettings,
+        width: u32,
+        height: u32,
+        fps: u32,
+    ) -> Result<Box<dyn FrameSource>, RecorderError> {
+        let system_cursor = settings.cursor.mode == CursorMode::System;
+        let scale = ScaleOptions::from_settings(settings)?;
+        let tone_map = if settings.hdr.enabled {
+            Some(Arc::new(ToneMapper::from_settings(&settings.hdr)?))
+        } else {
+            None
+        };
+        let source: Box<dyn FrameSource> = match settings.capture_source {
+            CaptureSource::Display => Box::new(ScreenCapturer::new(
+                settings.selected_display,
+                settings.capture_region,
+                width,
+                height,
+                system_cursor,
+                scale,
+                tone_map,
+            )?),
+            CaptureSource::AllDisplays => Box::new(DesktopCanvasSource::new(
+                width,
+                height,
+                system_cursor,
+                scale,
+                tone_map,
+            )?),
+            CaptureSource::Window => Box::new(ScreenCapturer::for_window(
+                Self::selected_window_id(settings)?,
+                width,
+                height,
+                system_cursor,
+                scale,
+                tone_map,
+            )?),
+            CaptureSource::TestPattern => Box::new(TestPatternSource::new(width, height, fps)?),
+            CaptureSource::Replay => {
+                let replay = settings
+                    .replay
+                    .as_ref()
+                    .ok_or_else(|| RecorderError::invalid_settings("No replay clip selected"))?;
+                Box::new(ReplaySource::new(replay, Some((width, height)), scale)?)
+            }
+        };
+        Ok(source)
+    }
+
+    fn selected_window_id(settings: &RecordingSettings) -> Result<u64, RecorderError> {
+        settings
+            .selected_window
+            .as_deref()
+            .and_then(|w| w.trim().parse::<u64>().ok())
+            .ok_or_else(|| RecorderError::invalid_settings("No window selected"))
+    }
+
+    /// Pixel size of the recording for `settings.resolution`.
+    fn output_size(settings: &RecordingSettings) -> Result<(u32, u32), RecorderError> {
+        settings.resolution.dimensions(|| Self::native_size(settings))
+    }
+
+    /// Size of the captured picture before any scaling, used by `Resolution::Native`.
+    fn native_size(settings: &RecordingSettings) -> Result<(u32, u32), RecorderError> {
+        match settings.capture_source {
+            CaptureSource::Display => {
+                if let Some(region) = Self::resolve_capture_region(settings)? {
+                    return Ok((region.width, region.height));
+                }
+                enumerate_displays()?
+                    .iter()
+                    .find(|d| d.index == settings.selected_display)
+                    .map(|d| (d.width, d.height))
+                    .ok_or_else(|| RecorderError::device_not_found("Display"))
+            }
+            CaptureSource::AllDisplays => {
+                let displays: Vec<_> = enumerate_displays()?
+                    .into_iter()
+                    .filter(|d| d.width > 0 && d.height > 0)
+                    .collect();
+                if displays.is_empty() {
+                    return Err(RecorderError::device_not_found("Display"));
+                }
+                let (_, _, width, height) = bounding_box(&displays);
+                Ok((width, height))
+            }
+            // Only the backend knows how big a window is, so open it once and ask.
+            CaptureSource::Window => {
+                let min = Resolution::MIN_DIMENSION;
+                let mut probe = ScreenCapturer::for_window(
+                    Self::selected_window_id(settings)?,
+                    min,
+                    min,
+                    false,
+                    ScaleOptions::from_settings(settings)?,
+                    None,
+                )?;
+                let size = probe.source_dimensions();
+                probe.stop();
+                Ok(size)
+            }
+            // A test pattern has no size of its own.
+            CaptureSource::TestPattern => Ok((1920, 1080)),
+            CaptureSource::Replay => {
+                let replay = settings
+                    .replay
+                    .as_ref()
+                    .ok_or_else(|| RecorderError::invalid_settings("No replay clip selected"))?;
+                let probe = ReplaySource::new(replay, None, ScaleOptions::from_settings(settings)?)?;
+                Ok(probe.dimensions())
+            }
+        }
+    }
+
+    fn open_camera_source(settings: &RecordingSettings) -> Result<Box<dyn FrameSource>, RecorderError> {
+        let source: Box<dyn FrameSource> = match &settings.camera_replay {
+            Some(replay) => Box::new(ReplaySource::new(
+                replay,
+                None,
+                ScaleOptions::stretch(settings.scale_filter),
+            )?),
+            None => Box::new(CameraCapturer::new(settings.selected_camera.clone())?),
+        };
+        Ok(source)
+    }
+
+    /// Pointer overlay for `CursorMode::Overlay`. Synthetic sources get a scripted pointer since
+    /// the real one has no meaning on them.
+    fn open_cursor_tracker(
+        settings: &RecordingSettings,
+        width: u32,
+        height: u32,
+    ) -> Result<Option<CursorTracker>, RecorderError> {
+        if settings.cursor.mode != CursorMode::Overlay {
+            return Ok(None);
+        }
+
+        let style = CursorStyle::from_settings(&settings.cursor)?;
+        let epoch = Instant::now();
+        let tracker = match settings.capture_source {
+            CaptureSource::TestPattern | CaptureSource::Replay => CursorTracker::new(
+                Box::new(SyntheticCursorStream::new(width, height, epoch)),
+                style,
+                epoch,
+            ),
+            _ => match SystemCursorStream::start(epoch) {
+                Ok(stream) => CursorTracker::new(Box::new(stream), style, epoch),
+                Err(e) => {
+                    eprintln!("RecordFlow: pointer tracking failed, continuing without cursor: {e}");
+                    return Ok(None);
+                }
+            },
+        };
+        Ok(Some(tracker))
+    }
+
+    /// Region actually applied for these settings, checked against the display's bounds.
+    fn resolve_capture_region(settings: &RecordingSettings) -> Result<Option<CaptureRegion>, RecorderError> {
+        Self::capture_region_on(settings, enumerate_displays)
+    }
+
+    /// `resolve_capture_region` against the displays `list_displays` returns. They are only
+    /// listed when a region is set.
+    fn capture_region_on(
+        settings: &RecordingSettings,
+        list_displays: impl FnOnce() -> Result<Vec<DisplayInfo>, RecorderError>,
+    ) -> Result<Option<CaptureRegion>, RecorderError> {
+        let region = match (&settings.capture_source, settings.capture_region) {
+            (CaptureSource::Display, Some(region)) => region,
+            _ => return Ok(None),
+        };
+
+        let displays = list_displays()?;
+        let display = displays
+            .iter()
+            .find(|d| d.index == settings.selected_display)
+            .ok_or_else(|| RecorderError::device_not_found("Display"))?;
+        region.validate_within(display.width, display.height)?;
+        Ok(Some(region))
+    }
+
+    /// Pull screen frames at `fps` and queue them for compositing. Owns the pause clock, so
+    /// `StagedFrame::elapsed` already excludes paused time.
+    #[allow(clippy::too_many_arguments)]
+    fn capture_stage(
+        settings: &RecordingSettings,
+        width: u32,
+        height: u32,
+        fps: u32,
+        stop_flag: &AtomicBool,
+        pause_flag: &AtomicBool,
+        output: &StageQueue<StagedFrame>,
+        metrics: &PipelineMetrics,
+        init_tx: mpsc::Sender<Result<(), RecorderError>>,
+    ) -> Result<(), RecorderError> {
+        let mut source = match Self::open_screen_source(settings, width, height, fps) {
+            Ok(source) => source,
+            Err(e) => {
+                let _ = init_tx.send(Err(e.clone()));
+                return Err(e);
+            }
+        };
+        let _ = init_tx.send(Ok(()));
+
+        let frame_time = Duration::from_secs_f64(1.0 / fps as f64);
+        let started_clock = Instant::now();
+        let mut paused_total = Duration::from_secs(0);
+        let mut pause_started: Option<Instant> = None;
+        let mut next_tick = Instant::now();
+
+        let result = loop {
+            if stop_flag.load(Ordering::SeqCst) {
+                break Ok(());
+            }
+
+            if pause_flag.load(Ordering::SeqCst) {
+                if pause_started.is_none() {
+                    pause_started = Some(Instant::now());
+                }
+                std::thread::sleep(Duration::from_millis(25));
+                continue;
+            }
+
+            if let Some(p) = pause_started.take() {
+                paused_total += p.elapsed();
+                next_tick = Instant::now();
+            }
+
+            let now = Instant::now();
+            if now < next_tick {
+                std::thread::sleep(next_tick - now);
+            }
+            // Keep a fixed cadence, but don't burst to catch up after a long stall.
+            next_tick = (next_tick + frame_time).max(Instant::now());
+
+            let captured_at = Instant::now();
+            let elapsed = captured_at.duration_since(started_clock).saturating_sub(paused_total);
+            let frame = match source.capture_frame() {
+                Ok(frame) => frame,
+                Err(e) => break Err(e),
+            };
+            metrics.capture.record(captured_at.elapsed());
+
+            let staged = StagedFrame {
+                frame,
+                area: source.capture_area(),
+                content: source.content_rect(),
+                elapsed,
+                captured_at,
+            };
+            if !output.push(staged) {
+                break Ok(());
+            }
+        };
+
+        source.stop();
+        result
+    }
+
+    /// Keep the newest camera frame in `slot`. Runs at its own pace so a slow device only
+    /// makes the overlay lag, never the recording.
+    fn camera_stage(
+        settings: &RecordingSettings,
+        fps: u32,
+        done: &AtomicBool,
+        slot: &LatestSlot<Frame>,
+        metrics: &PipelineMetrics,
+    ) {
+        let mut camera = match Self::open_camera_source(settings) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("RecordFlow: camera init failed, continuing without camera: {e}");
+                return;
+            }
+        };
+
+        let frame_time = Duration::from_secs_f64(1.0 / fps as f64);
+        while !done.load(Ordering::SeqCst) {
+            let tick = Instant::now();
+            match camera.capture_frame() {
+                Ok(frame) => {
+                    metrics.camera.record(tick.elapsed());
+                    slot.publish(frame);
+                }
+                Err(e) => {
+                    eprintln!("RecordFlow: camera capture failed, continuing without camera: {e}");
+                    break;
+                }
+            }
+
+            let elapsed = tick.elapsed();
+            if elapsed < frame_time {
+                std::thread::sleep(frame_time - elapsed);
+            }
+        }
+
+        camera.stop();
+    }
+
+    /// Draw the cursor and camera overlay onto each captured frame.
+    #[allow(clippy::too_many_arguments)]
+    fn composite_stage(
+        settings: &RecordingSettings,
+        width: u32,
+        height: u32,
+        input: &StageQueue<StagedFrame>,
+        output: &StageQueue<StagedFrame>,
+        camera_slot: &LatestSlot<Frame>,
+        metrics: &PipelineMetrics,
+        init_tx: mpsc::Sender<Result<(), RecorderError>>,
+    ) -> Result<(), RecorderError> {
+        let mut cursor = match Self::open_cursor_tracker(settings, width, height) {
+            Ok(cursor) => cursor,
+            Err(e) => {
+                let _ = init_tx.send(Err(e.clone()));
+                return Err(e);
+            }
+        };
+        let _ = init_tx.send(Ok(()));
+        let mut camera_overlay = CameraOverlay::new(Scaler::new(ScaleOptions::stretch(settings.scale_filter)));
+
+        while let Some(mut staged) = input.pop() {
+            let tick = Instant::now();
+            let camera = camera_slot.latest();
+            Self::composite_frame(settings, &mut staged, camera.as_deref(), cursor.as_mut(), &mut camera_overlay)?;
+
+            metrics.composite.record(tick.elapsed());
+            if !output.push(staged) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Paste the camera picture into one frame, then draw the pointer on top so the camera
+    /// never hides it.
+    fn composite_frame(
+        settings: &RecordingSettings,
+        staged: &mut StagedFrame,
+        camera: Option<&Frame>,
+        cursor: Option<&mut CursorTracker>,
+        camera_overlay: &mut CameraOverlay,
+    ) -> Result<(), RecorderError> {
+        let frame = &mut staged.frame;
+
+        if let Some(cam_frame) = camera {
+            FrameCompositor::overlay_bgra(
+                &mut frame.data,
+                frame.width,
+                frame.height,
+                cam_frame,
+                settings.camera_position.clone(),
+                settings.camera_size.clone(),
+                staged.content,
+                camera_overlay,
+            )?;
+        }
+
+        if let Some(cursor) = cursor {
+            let state = cursor.frame_state(staged.area, staged.content, frame.width, frame.height);
+            FrameCompositor::draw_cursor(&mut frame.data, frame.width, frame.height, &state, cursor.style())?;
+        }
+        Ok(())
+    }
+
+    /// Encode composited frames until the queue closes, folding duplicates into the previous
+    /// sample and interleaving microphone audio.
+    fn encode_stage(
+        settings: &RecordingSettings,
+        sink: &mut dyn VideoSink,
+        mic: Option<&MicrophoneCapture>,
+        input: &StageQueue<StagedFrame>,
+        metrics: &PipelineMetrics,
+        skipped_frames: &AtomicU64,
+        warnings: &Mutex<Vec<String>>,
+    ) -> Result<(), RecorderError> {
+        let variable_frame_rate = settings.variable_frame_rate && sink.capabilities().variable_frame_rate;
+        let mut dedup = variable_frame_rate.then(DuplicateFrameDetector::new);
+        let mut last_encoded = Duration::from_secs(0);
+
+        while let Some(staged) = input.pop() {
+            let tick = Instant::now();
+            let elapsed_recording = staged.elapsed;
+
+            let duplicate = dedup.as_mut().is_some_and(|d| d.is_duplicate(&staged.frame))
+                && elapsed_recording.saturating_sub(last_encoded) < MAX_FRAME_HOLD;
+
+            let audio_pcm = mic.map(|mic| {
+                let sample_count = sink.audio_samples_needed(elapsed_recording);
+                mic.take_pcm_bytes_le(sample_count, settings.mic_volume)
+            });
+
+            if duplicate {
+                sink.hold_video(elapsed_recording);
+                skipped_frames.fetch_add(1, Ordering::Relaxed);
+            } else {
+                sink.write_video(&staged.frame.data, elapsed_recording)?;
+                last_encoded = elapsed_recording;
+            }
+            if let Some(audio_pcm) = audio_pcm {
+                sink.write_audio(&audio_pcm)?;
+            }
+            warnings.lock().extend(sink.take_warnings());
+
+            metrics.encode.record(tick.elapsed());
+            metrics.end_to_end.record(staged.captured_at.elapsed());
+        }
+
+        Ok(())
     }
 
     pub async fn start_recording(&self) -> Result<String, RecorderError> {
         let settings = self.state.get_settings();
-        if !settings.screen_enabled {
-            return Err(RecorderError::invalid_settings(
-                "Screen recording is disabled (camera/audio-only recording not implemented yet)",
-            ));
-        }
 
         // Prevent double-start without stopping.
         if self.worker.lock().is_some() {
             return Err(RecorderError::already_recording());
         }
 
-        let path = Self::build_output_path()?;
+        if !settings.screen_enabled {
+            return self.start_audio_recording(&settings);
+        }
+
+        let capture_region = Self::resolve_capture_region(&settings)?;
+
+        let path = Self::build_output_path(settings.container.extension())?;
 
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
         }
 
         let output_path = path.to_string_lossy().to_string();
-        let output_path_for_thread = output_path.clone();
 
         self.stop_flag.store(false, Ordering::SeqCst);
         self.pause_flag.store(false, Ordering::SeqCst);
+        self.skipped_frames.store(0, Ordering::Relaxed);
+        self.pipeline_metrics.reset();
+        *self.pool_baseline.lock() = FramePool::shared().stats();
+        self.warnings.lock().clear();
 
         let stop_flag = self.stop_flag.clone();
         let pause_flag = self.pause_flag.clone();
+        let skipped_frames = self.skipped_frames.clone();
+        let metrics = self.pipeline_metrics.clone();
         let state = self.state.clone();
+        let sinks = self.sinks.clone();
+        let warnings = self.warnings.clone();
 
         let (ready_tx, ready_rx) = mpsc::channel::<Result<(), RecorderError>>();
 
         let handle = std::thread::spawn(move || -> Result<(), RecorderError> {
             let run = (|| -> Result<(), RecorderError> {
                 let settings = state.get_settings();
-                let (w, h) = match settings.resolution {
-                    crate::state::app_state::Resolution::P720 => (1280, 720),
-                    crate::state::app_state::Resolution::P1080 => (1920, 1080),
-                };
+                let (w, h) = Self::output_size(&settings)?;
 
                 let fps = settings.fps.max(1);
 
-                let mic = if settings.mic_enabled {
+                let mut mic = if settings.mic_enabled {
                     match MicrophoneCapture::new(Some(settings.microphone_device.as_str())) {
                         Ok(m) => Some(m),
                         Err(e) => {
                             eprintln!("RecordFlow: microphone init failed, continuing without mic: {e}");
+                            warnings
+                                .lock()
+                                .push(format!("Recording without the microphone: {}", e.details.unwrap_or(e.message)));
                             None
                         }
                     }
                 } else {
                     None
                 };
-                let audio_cfg = mic.as_ref().map(|m| (m.sample_rate(), m.channels()));
-
-                let mut capturer = ScreenCapturer::new(settings.selected_display, w, h)?;
-                let mut encoder = VideoEncoder::new(
-                    &output_path_for_thread,
-                    w,
-                    h,
-                    fps,
-                    settings.bitrate.max(1),
-                    audio_cfg,
+
+                let (backend, mut sink) = sinks.open(
+                    &settings,
+                    &SinkConfig {
+                        path,
+                        width: w,
+                        height: h,
+                        fps,
+                        bitrate_kbps: settings.bitrate.max(1),
+                        keyframe_interval: settings.keyframe_interval_secs.saturating_mul(fps),
+                        color: ColorSpace::from_settings(&settings),
+                        container: settings.container,
+                        mux: MuxOptions::from_settings(&settings),
+                        av1: settings.av1.clone(),
+                        gif: settings.gif.clone(),
+                        audio: mic.as_ref().map(|m| (m.sample_rate(), m.channels())),
+                    },
                 )?;
+                if mic.is_some() && !sink.capabilities().audio {
+                    eprintln!("RecordFlow: {} can't record audio, continuing without mic", backend.name);
+                    warnings
+                        .lock()
+                        .push(format!("{} records video only, so the microphone is not recorded", backend.name));
+                    mic = None;
+                }
 
-                let mut camera = if settings.camera_enabled {
-                    match CameraCapturer::new(settings.selected_camera.clone()) {
-                        Ok(c) => Some(c),
-                        Err(e) => {
-                            eprintln!("RecordFlow: camera init failed, continuing without camera: {e}");
-                            None
-                        }
-                    }
-                } else {
-                    None
-                };
+                let depth = settings.pipeline.queue_depth.max(1) as usize;
+                let captured = StageQueue::new(depth, settings.pipeline.capture_queue, metrics.clone(), |m| {
+                    &m.composite
+                });
+                let composited = StageQueue::new(depth, settings.pipeline.encode_queue, metrics.clone(), |m| {
+                    &m.encode
+                });
+                let camera_slot = LatestSlot::default();
+                let pipeline_done = AtomicBool::new(false);
 
-                let _ = ready_tx.send(Ok(()));
+                let (settings, metrics) = (&settings, &*metrics);
+                let (captured, composited, camera_slot) = (&captured, &composited, &camera_slot);
+                let (stop_flag, pause_flag, pipeline_done) = (&*stop_flag, &*pause_flag, &pipeline_done);
 
-                let frame_time = Duration::from_secs_f64(1.0 / fps as f64);
-                let started_clock = Instant::now();
-                let mut paused_total = Duration::from_secs(0);
-                let mut pause_started: Option<Instant> = None;
-                while !stop_flag.load(Ordering::SeqCst) {
-                    if pause_flag.load(Ordering::SeqCst) {
-                        if pause_started.is_none() {
-                            pause_started = Some(Instant::now());
+                std::thread::scope(|s| -> Result<(), RecorderError> {
+                    let (init_tx, init_rx) = mpsc::channel::<Result<(), RecorderError>>();
+
+                    let capture = s.spawn({
+                        let init_tx = init_tx.clone();
+                        move || {
+                            let r = Self::capture_stage(
+                                settings, w, h, fps, stop_flag, pause_flag, captured, metrics, init_tx,
+                            );
+                            if r.is_err() {
+                                stop_flag.store(true, Ordering::SeqCst);
+                            }
+                            captured.close();
+                            r
                         }
-                        std::thread::sleep(Duration::from_millis(25));
-                        continue;
-                    }
+                    });
 
-                    if let Some(p) = pause_started.take() {
-                        paused_total += p.elapsed();
-                    }
+                    let composite = s.spawn({
+                        let init_tx = init_tx.clone();
+                        move || {
+                            let r = Self::composite_stage(
+                                settings, w, h, captured, composited, camera_slot, metrics, init_tx,
+                            );
+                            if r.is_err() {
+                                stop_flag.store(true, Ordering::SeqCst);
+                            }
+                            captured.close();
+                            composited.close();
+                            r
+                        }
+                    });
+                    drop(init_tx);
 
-                    let tick = Instant::now();
-                    let elapsed_recording = tick.duration_since(started_clock).saturating_sub(paused_total);
-                    let mut frame = capturer.capture_frame()?;
-
-                    if let Some(cam) = camera.as_mut() {
-                        let cam_frame = cam.capture_frame()?;
-                        FrameCompositor::overlay_bgra(
-                            &mut frame.data,
-                            frame.width,
-                            frame.height,
-                            &cam_frame.data,
-                            cam_frame.width,
-                            cam_frame.height,
-                            settings.camera_position.clone(),
-                            settings.camera_size.clone(),
-                        )?;
+                    if settings.camera_enabled {
+                        s.spawn(move || Self::camera_stage(settings, fps, pipeline_done, camera_slot, metrics));
                     }
 
-                    if let Some(mic) = mic.as_ref() {
-                        let sample_count = encoder
-                            .audio_samples_needed_for_elapsed(elapsed_recording)
-                            .unwrap_or(0);
-                        let audio_pcm = mic.take_pcm_bytes_le(sample_count, settings.mic_volume);
-                        encoder.encode_frame_with_audio(&frame.data, elapsed_recording, &audio_pcm)?;
-                    } else {
-                        encoder.encode_frame(&frame.data, elapsed_recording)?;
+                    // Both the screen source and the compositor have to come up before we
+                    // report the recording as started.
+                    let mut init_error = None;
+                    for _ in 0..2 {
+                        match init_rx.recv() {
+                            Ok(Ok(())) => {}
+                            Ok(Err(e)) => {
+                                init_error = Some(e);
+                                break;
+                            }
+                            Err(_) => break,
+                        }
+                    }
+                    if let Some(e) = init_error {
+                        stop_flag.store(true, Ordering::SeqCst);
+                        captured.close();
+                        composited.close();
+                        pipeline_done.store(true, Ordering::SeqCst);
+                        return Err(e);
                     }
 
-                    let elapsed = tick.elapsed();
-                    if elapsed < frame_time {
-                        std::thread::sleep(frame_time - elapsed);
+                    let _ = ready_tx.send(Ok(()));
+
+                    let encoded = Self::encode_stage(
+                        settings,
+                        sink.as_mut(),
+                        mic.as_ref(),
+                        composited,
+                        metrics,
+                        &skipped_frames,
+                        &warnings,
+                    );
+                    if encoded.is_err() {
+                        stop_flag.store(true, Ordering::SeqCst);
+                        composited.close();
+                        captured.close();
                     }
-                }
+                    pipeline_done.store(true, Ordering::SeqCst);
+
+                    let capture_result = capture
+                        .join()
+                        .unwrap_or_else(|_| Err(RecorderError::encoding_failed("Capture thread panicked")));
+                    let composite_result = composite
+                        .join()
+                        .unwrap_or_else(|_| Err(RecorderError::encoding_failed("Composite thread panicked")));
+
+                    encoded?;
+                    sink.finalize()?;
+                    warnings.lock().extend(sink.take_warnings());
+                    capture_result?;
+                    composite_result
+                })?;
 
-                capturer.stop();
-                if let Some(cam) = camera.as_mut() {
-                    cam.stop();
+                let skipped = skipped_frames.load(Ordering::Relaxed);
+                if skipped > 0 {
+                    eprintln!("RecordFlow: skipped {skipped} duplicate frames");
                 }
-                encoder.finalize()?;
                 Ok(())
             })();
 
@@ -272,10 +810,167 @@ impl RecordingManager {
             run
         });
 
+        self.await_startup(handle, ready_rx, output_path, capture_region)
+    }
+
+    /// Record the microphone and/or system audio on their own, into `settings.audio_format`.
+    /// Pausing, stopping and history work as for video sessions.
+    fn start_audio_recording(&self, settings: &RecordingSettings) -> Result<String, RecorderError> {
+        if settings.camera_enabled {
+            return Err(RecorderError::invalid_settings(
+                "Camera-only recording is not implemented yet; turn off the camera for an audio-only recording",
+            ));
+        }
+        if !settings.mic_enabled && !settings.system_audio_enabled {
+            return Err(RecorderError::invalid_settings(
+                "Nothing to record: enable the screen, the microphone or system audio",
+            ));
+        }
+
+        let path = Self::build_output_path(settings.audio_format.extension())?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let output_path = path.to_string_lossy().to_string();
+
+        self.stop_flag.store(false, Ordering::SeqCst);
+        self.pause_flag.store(false, Ordering::SeqCst);
+        self.skipped_frames.store(0, Ordering::Relaxed);
+        self.pipeline_metrics.reset();
+        *self.pool_baseline.lock() = FramePool::shared().stats();
+        self.warnings.lock().clear();
+
+        let stop_flag = self.stop_flag.clone();
+        let pause_flag = self.pause_flag.clone();
+        let state = self.state.clone();
+
+        let (ready_tx, ready_rx) = mpsc::channel::<Result<(), RecorderError>>();
+
+        let handle = std::thread::spawn(move || -> Result<(), RecorderError> {
+            let run = (|| -> Result<(), RecorderError> {
+                let settings = state.get_settings();
+                let mut mix = Self::open_audio_mix(&settings)?;
+                let mut sink = open_audio_sink(&path, settings.audio_format, mix.sample_rate(), mix.channels())?;
+
+                let _ = ready_tx.send(Ok(()));
+
+                let recorded = Self::audio_stage(&mut mix, sink.as_mut(), &stop_flag, &pause_flag);
+                let finalized = sink.finalize();
+                recorded?;
+                finalized
+            })();
+
+            if let Err(e) = &run {
+                let _ = ready_tx.send(Err(e.clone()));
+                eprintln!("RecordFlow: recording worker failed: {e}");
+                stop_flag.store(true, Ordering::SeqCst);
+                *state.is_recording.lock() = false;
+                *state.is_paused.lock() = false;
+            }
+
+            run
+        });
+
+        self.await_startup(handle, ready_rx, output_path, None)
+    }
+
+    /// The enabled audio inputs, mixed into a format `settings.audio_format` can store. An
+    /// input that fails to open is left out as long as another one works.
+    fn open_audio_mix(settings: &RecordingSettings) -> Result<AudioMix, RecorderError> {
+        let mut inputs = Vec::new();
+        let mut init_error = None;
+
+        if settings.mic_enabled {
+            match MicrophoneCapture::new(Some(settings.microphone_device.as_str())) {
+                Ok(mic) => inputs.push((mic, settings.mic_volume)),
+                Err(e) => {
+                    eprintln!("RecordFlow: microphone init failed: {e}");
+                    init_error = Some(e);
+                }
+            }
+        }
+        if settings.system_audio_enabled {
+            match MicrophoneCapture::system_audio(Some(settings.system_audio_device.as_str())) {
+                Ok(system) => inputs.push((system, settings.system_audio_volume)),
+                Err(e) => {
+                    eprintln!("RecordFlow: system audio init failed: {e}");
+                    init_error = Some(e);
+                }
+            }
+        }
+
+        // Mix at the first input's rate, with as many channels as the widest one.
+        let Some((first, _)) = inputs.first() else {
+            return Err(init_error.unwrap_or_else(|| RecorderError::device_not_found("Audio input")));
+        };
+        let channels = inputs.iter().map(|(c, _)| c.channels()).max().unwrap_or(1);
+        let (sample_rate, channels) = sink_format(settings.audio_format, first.sample_rate(), channels);
+
+        let mut mix = AudioMix::new(sample_rate, channels);
+        for (capture, volume) in inputs {
+            mix.add_input(capture, volume);
+        }
+        Ok(mix)
+    }
+
+    /// Write mixed audio to `sink` until stopped. Owns the pause clock like `capture_stage`;
+    /// whatever the devices pick up while paused is thrown away.
+    fn audio_stage(
+        mix: &mut AudioMix,
+        sink: &mut dyn AudioSink,
+        stop_flag: &AtomicBool,
+        pause_flag: &AtomicBool,
+    ) -> Result<(), RecorderError> {
+        let started_clock = Instant::now();
+        let mut paused_total = Duration::from_secs(0);
+        let mut pause_started: Option<Instant> = None;
+        mix.discard_buffered();
+
+        loop {
+            let stopping = stop_flag.load(Ordering::SeqCst);
+
+            if !stopping && pause_flag.load(Ordering::SeqCst) {
+                if pause_started.is_none() {
+                    // Catch up to the pause before the devices' buffers are dropped.
+                    let elapsed = started_clock.elapsed().saturating_sub(paused_total);
+                    sink.write(&mix.pull(elapsed))?;
+                    pause_started = Some(Instant::now());
+                }
+                mix.discard_buffered();
+                std::thread::sleep(Duration::from_millis(25));
+                continue;
+            }
+
+            if let Some(p) = pause_started.take() {
+                paused_total += p.elapsed();
+                mix.discard_buffered();
+            }
+
+            let elapsed = started_clock.elapsed().saturating_sub(paused_total);
+            if stopping {
+                sink.write(&mix.pull(elapsed))?;
+                return Ok(());
+            }
+
+            sink.write(&mix.pull(elapsed.saturating_sub(AUDIO_LATENCY)))?;
+            std::thread::sleep(AUDIO_PULL_INTERVAL);
+        }
+    }
+
+    /// Wait for the worker to report that recording started, then mark the session running.
+    fn await_startup(
+        &self,
+        handle: JoinHandle<Result<(), RecorderError>>,
+        ready_rx: mpsc::Receiver<Result<(), RecorderError>>,
+        output_path: String,
+        capture_region: Option<CaptureRegion>,
+    ) -> Result<String, RecorderError> {
         match ready_rx.recv_timeout(Duration::from_secs(3)) {
             Ok(Ok(())) => {
                 self.state.start_recording(output_path.clone())?;
-                self.begin_session();
+                self.begin_session(capture_region);
                 *self.worker.lock() = Some(handle);
                 Ok(output_path)
             }
@@ -296,7 +991,7 @@ impl RecordingManager {
                     }
                 } else {
                     self.state.start_recording(output_path.clone())?;
-                    self.begin_session();
+                    self.begin_session(capture_region);
                     *self.worker.lock() = Some(handle);
                     Ok(output_path)
                 }
@@ -339,6 +1034,7 @@ impl RecordingManager {
         *self.started_at.lock() = None;
         *self.started_wall.lock() = None;
         *self.session_id.lock() = None;
+        *self.session_region.lock() = None;
         *self.paused_at.lock() = None;
         *self.paused_total.lock() = Duration::from_secs(0);
 
@@ -382,3 +1078,207 @@ impl RecordingManager {
             .as_secs()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::color_convert::{ColorConverter, YuvLayout};
+    use crate::recording::cursor::{CursorEvent, CursorEventKind, CursorStream};
+    use crate::recording::video_sink::SinkCapabilities;
+    use crate::state::app_state::{CameraPosition, CursorSettings, ScaleFilter};
+
+    fn display(index: u32, width: u32, height: u32) -> DisplayInfo {
+        DisplayInfo { index, name: format!("Display {index}"), width, height, x: 0, y: 0, is_primary: index == 0 }
+    }
+
+    fn region_settings(selected_display: u32, region: CaptureRegion) -> RecordingSettings {
+        RecordingSettings {
+            capture_source: CaptureSource::Display,
+            selected_display,
+            capture_region: Some(region),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn capture_region_is_checked_against_the_selected_display() {
+        let displays = || Ok(vec![display(0, 1920, 1080), display(1, 1280, 1024)]);
+        let region = CaptureRegion { x: 1280, y: 0, width: 640, height: 1080 };
+
+        let on_first = RecordingManager::capture_region_on(&region_settings(0, region), displays);
+        assert_eq!(on_first.unwrap(), Some(region));
+        let on_second = RecordingManager::capture_region_on(&region_settings(1, region), displays);
+        assert_eq!(on_second.unwrap_err().code, "INVALID_SETTINGS");
+        let missing = RecordingManager::capture_region_on(&region_settings(2, region), displays);
+        assert_eq!(missing.unwrap_err().code, "DEVICE_NOT_FOUND");
+    }
+
+    #[test]
+    fn capture_region_only_applies_to_a_display() {
+        let region = CaptureRegion { x: 0, y: 0, width: 64, height: 64 };
+        let no_displays = || -> Result<Vec<DisplayInfo>, RecorderError> { panic!("displays listed") };
+        for source in [CaptureSource::AllDisplays, CaptureSource::Window, CaptureSource::TestPattern] {
+            let settings = RecordingSettings { capture_source: source, ..region_settings(0, region) };
+            assert_eq!(RecordingManager::capture_region_on(&settings, no_displays).unwrap(), None);
+        }
+        let settings = RecordingSettings { capture_region: None, ..region_settings(0, region) };
+        assert_eq!(RecordingManager::capture_region_on(&settings, no_displays).unwrap(), None);
+    }
+
+    /// Replays a fixed list of pointer events on the first poll.
+    struct Script(Vec<CursorEvent>);
+
+    impl CursorStream for Script {
+        fn poll(&mut self) -> Vec<CursorEvent> {
+            std::mem::take(&mut self.0)
+        }
+    }
+
+    fn solid_frame(width: u32, height: u32, value: u8) -> Frame {
+        let data = vec![value; width as usize * height as usize * 4].into();
+        Frame { data, width, height, timestamp: 0, frame_number: 1 }
+    }
+
+    #[test]
+    fn pointer_is_drawn_over_the_camera() {
+        let settings = RecordingSettings { camera_position: CameraPosition::TopLeft, ..Default::default() };
+        let mut staged = StagedFrame {
+            frame: solid_frame(200, 100, 0),
+            area: None,
+            content: None,
+            elapsed: Duration::ZERO,
+            captured_at: Instant::now(),
+        };
+        let camera = solid_frame(64, 48, 200);
+        // The camera covers 16..66 x 16..53; put the pointer's tip inside it.
+        let style = CursorStyle::from_settings(&CursorSettings { mode: CursorMode::Overlay, ..Default::default() });
+        let pointer = Script(vec![CursorEvent { timestamp: 0, x: 30, y: 30, kind: CursorEventKind::Move }]);
+        let mut cursor = CursorTracker::new(Box::new(pointer), style.unwrap(), Instant::now());
+        let mut overlay = CameraOverlay::new(Scaler::new(ScaleOptions::stretch(ScaleFilter::Nearest)));
+
+        RecordingManager::composite_frame(&settings, &mut staged, Some(&camera), Some(&mut cursor), &mut overlay)
+            .unwrap();
+        let px = |x: usize, y: usize| staged.frame.data[(y * 200 + x) * 4..][..4].to_vec();
+        assert_eq!(px(20, 20), [200; 4]);
+        // Outline at the tip, fill inside the arrow.
+        assert_eq!(px(30, 30), [0, 0, 0, 255]);
+        assert_eq!(px(31, 33), [255, 255, 255, 255]);
+    }
+
+    /// Does the color conversion every encoder starts with, then throws the picture away.
+    struct NullSink {
+        converter: ColorConverter,
+        nv12: Vec<u8>,
+        width: u32,
+        height: u32,
+        /// Recording time of each written frame.
+        written: Vec<Duration>,
+    }
+
+    impl VideoSink for NullSink {
+        fn capabilities(&self) -> SinkCapabilities {
+            SinkCapabilities { audio: false, variable_frame_rate: false }
+        }
+
+        fn audio_samples_needed(&self, _elapsed: Duration) -> usize {
+            0
+        }
+
+        fn write_video(&mut self, bgra: &[u8], elapsed: Duration) -> Result<(), RecorderError> {
+            self.converter
+                .convert_into(bgra, self.width, self.height, YuvLayout::Nv12, &mut self.nv12)?;
+            std::hint::black_box(&self.nv12);
+            self.written.push(elapsed);
+            Ok(())
+        }
+
+        fn write_audio(&mut self, _pcm_i16le: &[u8]) -> Result<(), RecorderError> {
+            Ok(())
+        }
+
+        fn finalize(&mut self) -> Result<(), RecorderError> {
+            Ok(())
+        }
+    }
+
+    /// The CPU-only goal: 1080p60 from the test pattern through the real capture, composite
+    /// (camera and cursor overlay) and encode stages, each on its own thread as in a recording.
+    /// Run with `cargo test --release -- --ignored --nocapture sustains_1080p60`.
+    #[test]
+    #[ignore = "throughput measurement; run in release mode"]
+    fn sustains_1080p60_on_the_cpu() {
+        const RUN: Duration = Duration::from_secs(5);
+        let settings = RecordingSettings {
+            capture_source: CaptureSource::TestPattern,
+            resolution: Resolution::P1080,
+            fps: 60,
+            cursor: CursorSettings { mode: CursorMode::Overlay, ..Default::default() },
+            ..Default::default()
+        };
+        let (width, height) = RecordingManager::output_size(&settings).unwrap();
+        let metrics = Arc::new(PipelineMetrics::default());
+        let depth = settings.pipeline.queue_depth as usize;
+        let captured = StageQueue::new(depth, settings.pipeline.capture_queue, metrics.clone(), |m| &m.composite);
+        let composited = StageQueue::new(depth, settings.pipeline.encode_queue, metrics.clone(), |m| &m.encode);
+        let camera_slot = LatestSlot::default();
+        let (stop_flag, pause_flag) = (AtomicBool::new(false), AtomicBool::new(false));
+        let mut sink = NullSink {
+            converter: ColorConverter::new(ColorSpace::from_settings(&settings)),
+            nv12: vec![0; YuvLayout::Nv12.frame_len(width, height)],
+            width,
+            height,
+            written: Vec::new(),
+        };
+
+        std::thread::scope(|s| {
+            let (init_tx, _init_rx) = mpsc::channel();
+            let init = init_tx.clone();
+            let (settings, metrics) = (&settings, &*metrics);
+            let (captured, composited, camera_slot) = (&captured, &composited, &camera_slot);
+            let stop_flag = &stop_flag;
+            s.spawn(move || {
+                let r = RecordingManager::capture_stage(
+                    settings, width, height, 60, stop_flag, &pause_flag, captured, metrics, init,
+                );
+                captured.close();
+                r.unwrap();
+            });
+            s.spawn(move || {
+                let r = RecordingManager::composite_stage(
+                    settings, width, height, captured, composited, camera_slot, metrics, init_tx,
+                );
+                composited.close();
+                r.unwrap();
+            });
+            // Stands in for a 30 fps webcam.
+            s.spawn(move || {
+                let mut camera = TestPatternSource::new(640, 480, 30).unwrap();
+                while !stop_flag.load(Ordering::SeqCst) {
+                    camera_slot.publish(camera.capture_frame().unwrap());
+                    std::thread::sleep(Duration::from_millis(33));
+                }
+            });
+            s.spawn(move || {
+                std::thread::sleep(RUN);
+                stop_flag.store(true, Ordering::SeqCst);
+            });
+
+            let skipped = AtomicU64::new(0);
+            let warnings = Mutex::new(Vec::new());
+            RecordingManager::encode_stage(settings, &mut sink, None, composited, metrics, &skipped, &warnings)
+                .unwrap();
+        });
+
+        for stats in metrics.snapshot() {
+            println!(
+                "{:>10}: avg {:.2} ms, max {:.2} ms, dropped {}",
+                stats.stage, stats.avg_ms, stats.max_ms, stats.dropped
+            );
+        }
+        let span = *sink.written.last().unwrap() - sink.written[0];
+        let fps = (sink.written.len() - 1) as f64 / span.as_secs_f64();
+        println!("1080p pipeline: {fps:.1} fps over {} frames", sink.written.len());
+        assert!(fps >= 59.0, "pipeline ran at {fps:.1} fps");
+        assert!(metrics.snapshot().iter().all(|stats| stats.dropped == 0), "frames were dropped");
+    }
+}
@@ -0,0 +1,672 @@
+use crate::error::RecorderError;
+use crate::recording::mux::{avc_decoder_config, flac_metadata, AudioCodec, Muxer, Sample, TrackConfig, VideoCodec};
+use crate::state::app_state::Container;
+use std::io::{Seek, SeekFrom, Write};
+
+const EBML: u32 = 0x1A45_DFA3;
+const EBML_VERSION: u32 = 0x4286;
+const EBML_READ_VERSION: u32 = 0x42F7;
+const EBML_MAX_ID_LENGTH: u32 = 0x42F2;
+const EBML_MAX_SIZE_LENGTH: u32 = 0x42F3;
+const DOC_TYPE: u32 = 0x4282;
+const DOC_TYPE_VERSION: u32 = 0x4287;
+const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+const SEGMENT: u32 = 0x1853_8067;
+const SEEK_HEAD: u32 = 0x114D_9B74;
+const SEEK: u32 = 0x4DBB;
+const SEEK_ID: u32 = 0x53AB;
+const SEEK_POSITION: u32 = 0x53AC;
+const INFO: u32 = 0x1549_A966;
+const TIMESTAMP_SCALE: u32 = 0x2A_D7B1;
+const MUXING_APP: u32 = 0x4D80;
+const WRITING_APP: u32 = 0x5741;
+const DURATION: u32 = 0x4489;
+const TRACKS: u32 = 0x1654_AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const TRACK_UID: u32 = 0x73C5;
+const TRACK_TYPE: u32 = 0x83;
+const FLAG_LACING: u32 = 0x9C;
+const LANGUAGE: u32 = 0x22_B59C;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const VIDEO: u32 = 0xE0;
+const PIXEL_WIDTH: u32 = 0xB0;
+const PIXEL_HEIGHT: u32 = 0xBA;
+const COLOUR: u32 = 0x55B0;
+const MATRIX_COEFFICIENTS: u32 = 0x55B1;
+const RANGE: u32 = 0x55B9;
+const TRANSFER_CHARACTERISTICS: u32 = 0x55BA;
+const PRIMARIES: u32 = 0x55BB;
+const AUDIO: u32 = 0xE1;
+const SAMPLING_FREQUENCY: u32 = 0xB5;
+const CHANNELS: u32 = 0x9F;
+const CLUSTER: u32 = 0x1F43_B675;
+const TIMESTAMP: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+const CUES: u32 = 0x1C53_BB6B;
+const CUE_POINT: u32 = 0xBB;
+const CUE_TIME: u32 = 0xB3;
+const CUE_TRACK_POSITIONS: u32 = 0xB7;
+const CUE_TRACK: u32 = 0xF7;
+const CUE_CLUSTER_POSITION: u32 = 0xF1;
+const VOID: u32 = 0xEC;
+
+/// Block timestamps are in milliseconds.
+const TIMESTAMP_SCALE_NS: u64 = 1_000_000;
+/// Start a new cluster at the first keyframe after this long.
+const CLUSTER_MS: u64 = 5_000;
+/// Bytes kept free after the EBML header for the `SeekHead` written by `finish`.
+const SEEK_HEAD_RESERVE: usize = 96;
+/// Size field of an element whose size isn't known yet.
+const UNKNOWN_SIZE: u64 = 0x01FF_FFFF_FFFF_FFFF;
+
+fn mux_err(context: &str) -> RecorderError {
+    RecorderError::encoding_failed(format!("Matroska muxer: {context}"))
+}
+
+fn put_id(out: &mut Vec<u8>, id: u32) {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count();
+    out.extend_from_slice(&bytes[skip..]);
+}
+
+/// EBML variable-length size in the fewest bytes. All ones is reserved for "unknown".
+fn put_size(out: &mut Vec<u8>, len: u64) {
+    let width = (1..=8).find(|&n| len < (1u64 << (7 * n)) - 1).unwrap_or(8);
+    let marked = len | 1u64 << (7 * width);
+    out.extend_from_slice(&marked.to_be_bytes()[8 - width..]);
+}
+
+fn element(out: &mut Vec<u8>, id: u32, body: impl FnOnce(&mut Vec<u8>)) {
+    let mut b = Vec::new();
+    body(&mut b);
+    put_id(out, id);
+    put_size(out, b.len() as u64);
+    out.extend_from_slice(&b);
+}
+
+fn uint(out: &mut Vec<u8>, id: u32, value: u64) {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count().min(7);
+    element(out, id, |b| b.extend_from_slice(&bytes[skip..]));
+}
+
+fn float(out: &mut Vec<u8>, id: u32, value: f64) {
+    element(out, id, |b| b.extend_from_slice(&value.to_be_bytes()));
+}
+
+fn string(out: &mut Vec<u8>, id: u32, value: &str) {
+    element(out, id, |b| b.extend_from_slice(value.as_bytes()));
+}
+
+fn binary(out: &mut Vec<u8>, id: u32, value: &[u8]) {
+    element(out, id, |b| b.extend_from_slice(value));
+}
+
+/// `Void` element exactly `len` bytes long, `len >= 2`.
+fn void(out: &mut Vec<u8>, len: usize) {
+    put_id(out, VOID);
+    // One size byte up to 126, eight beyond; pad accordingly.
+    let header = if len - 1 <= 127 { 2 } else { 9 };
+    if header == 2 {
+        put_size(out, (len - 2) as u64);
+    } else {
+        out.push(0x01);
+        out.extend_from_slice(&((len - 9) as u64).to_be_bytes()[1..]);
+    }
+    out.resize(out.len() + len - header, 0);
+}
+
+struct MkvTrack {
+    config: TrackConfig,
+    /// Decode time of the next sample, in the track's timescale.
+    next_time: u64,
+}
+
+/// Blocks waiting to go out as one cluster.
+struct Cluster {
+    timestamp: u64,
+    blocks: Vec<u8>,
+    /// Starts with a keyframe of the first track, so it gets a cue point.
+    cued: bool,
+}
+
+/// Writes Matroska (`.mkv`) or WebM files from already encoded elementary streams.
+///
+/// Samples are gathered into clusters of about `CLUSTER_MS`, each started at a keyframe of
+/// the first track and written out whole. The segment is written with an unknown size, so a
+/// file cut off mid-recording still plays up to the last complete cluster. `finish` adds
+/// cue points for seeking, the duration, the real segment size and a `SeekHead`.
+pub struct MkvMuxer<W: Write + Seek> {
+    out: W,
+    webm: bool,
+    tracks: Vec<MkvTrack>,
+    tracks_written: bool,
+    cluster: Option<Cluster>,
+    /// `(timestamp, cluster position)` per cue point.
+    cues: Vec<(u64, u64)>,
+
+    /// Start of the segment's payload; positions inside it are relative to this.
+    segment_start: u64,
+    info_position: u64,
+    tracks_position: u64,
+    /// Absolute position of the `Duration` float, patched by `finish`.
+    duration_at: u64,
+    /// Latest block end seen, in milliseconds.
+    end_ms: u64,
+    position: u64,
+}
+
+impl<W: Write + Seek> MkvMuxer<W> {
+    pub fn new(mut out: W, container: Container) -> Result<Self, RecorderError> {
+        let webm = match container {
+            Container::Mkv => false,
+            Container::Webm => true,
+            Container::Mp4 | Container::Y4m | Container::Gif => {
+                return Err(mux_err(&format!("{} is not a Matroska container", container.extension())))
+            }
+        };
+
+        let mut head = Vec::new();
+        element(&mut head, EBML, |b| {
+            uint(b, EBML_VERSION, 1);
+            uint(b, EBML_READ_VERSION, 1);
+            uint(b, EBML_MAX_ID_LENGTH, 4);
+            uint(b, EBML_MAX_SIZE_LENGTH, 8);
+            string(b, DOC_TYPE, if webm { "webm" } else { "matroska" });
+            uint(b, DOC_TYPE_VERSION, 4);
+            uint(b, DOC_TYPE_READ_VERSION, 2);
+        });
+        put_id(&mut head, SEGMENT);
+        head.extend_from_slice(&UNKNOWN_SIZE.to_be_bytes());
+        let segment_start = head.len() as u64;
+
+        void(&mut head, SEEK_HEAD_RESERVE);
+        let info_position = head.len() as u64 - segment_start;
+        element(&mut head, INFO, |b| {
+            uint(b, TIMESTAMP_SCALE, TIMESTAMP_SCALE_NS);
+            string(b, MUXING_APP, "RecordFlow");
+            string(b, WRITING_APP, "RecordFlow");
+            // Last, so its payload ends the element.
+            float(b, DURATION, 0.0);
+        });
+        let duration_at = head.len() as u64 - 8;
+
+        out.write_all(&head)?;
+        Ok(Self {
+            out,
+            webm,
+            tracks: Vec::new(),
+            tracks_written: false,
+            cluster: None,
+            cues: Vec::new(),
+            segment_start,
+            info_position,
+            tracks_position: 0,
+            duration_at,
+            end_ms: 0,
+            position: head.len() as u64,
+        })
+    }
+
+    pub fn add_track(&mut self, config: TrackConfig) -> Result<u32, RecorderError> {
+        if config.timescale() == 0 {
+            return Err(mux_err("track timescale must be above zero"));
+        }
+        if self.tracks_written {
+            return Err(mux_err("tracks can't be added after the first cluster"));
+        }
+        if self.webm && !webm_compatible(&config) {
+            return Err(RecorderError::invalid_settings(format!(
+                "WebM can't hold {}; pick MKV or MP4",
+                codec_id(&config)
+            )));
+        }
+        if self.tracks.len() >= 126 {
+            return Err(mux_err("too many tracks"));
+        }
+        self.tracks.push(MkvTrack { config, next_time: 0 });
+        Ok(self.tracks.len() as u32)
+    }
+
+    pub fn write_sample(&mut self, track_id: u32, sample: Sample) -> Result<(), RecorderError> {
+        let index = track_id
+            .checked_sub(1)
+            .map(|i| i as usize)
+            .filter(|&i| i < self.tracks.len())
+            .ok_or_else(|| mux_err(&format!("no track {track_id}")))?;
+
+        let track = &mut self.tracks[index];
+        let timescale = track.config.timescale() as i128;
+        let pts = (track.next_time as i128 + sample.composition_offset as i128).max(0);
+        let timestamp = (pts * 1000 / timescale) as u64;
+        track.next_time += sample.duration as u64;
+        self.end_ms = self.end_ms.max(((pts + sample.duration as i128) * 1000 / timescale) as u64);
+
+        let keyframe_of_lead = index == 0 && sample.is_sync;
+        if let Some(cluster) = &self.cluster {
+            let relative = timestamp as i64 - cluster.timestamp as i64;
+            let long_enough = relative >= CLUSTER_MS as i64;
+            if (keyframe_of_lead && long_enough) || !(i16::MIN as i64..=i16::MAX as i64).contains(&relative) {
+                self.flush_cluster()?;
+            }
+        }
+        let cluster = self.cluster.get_or_insert_with(|| Cluster {
+            timestamp,
+            blocks: Vec::new(),
+            cued: keyframe_of_lead,
+        });
+
+        let relative = (timestamp as i64 - cluster.timestamp as i64) as i16;
+        put_id(&mut cluster.blocks, SIMPLE_BLOCK);
+        put_size(&mut cluster.blocks, 4 + sample.data.len() as u64);
+        cluster.blocks.push(0x80 | track_id as u8);
+        cluster.blocks.extend_from_slice(&relative.to_be_bytes());
+        cluster.blocks.push(if sample.is_sync { 0x80 } else { 0 });
+        cluster.blocks.extend_from_slice(sample.data);
+        Ok(())
+    }
+
+    /// Write the collected cluster, preceded by `Tracks` the first time.
+    fn flush_cluster(&mut self) -> Result<(), RecorderError> {
+        let mut out = Vec::new();
+        if !self.tracks_written {
+            self.tracks_written = true;
+            self.tracks_position = self.position - self.segment_start;
+            write_tracks(&mut out, &self.tracks);
+        }
+
+        if let Some(cluster) = self.cluster.take() {
+            let cluster_position = self.position + out.len() as u64 - self.segment_start;
+            if cluster.cued {
+                self.cues.push((cluster.timestamp, cluster_position));
+            }
+            element(&mut out, CLUSTER, |b| {
+                uint(b, TIMESTAMP, cluster.timestamp);
+                b.extend_from_slice(&cluster.blocks);
+            });
+        }
+
+        self.out.write_all(&out)?;
+        self.out.flush()?;
+        self.position += out.len() as u64;
+        Ok(())
+    }
+
+    /// Write cues and fill in everything that was left open, then return the output.
+    pub fn finish(mut self) -> Result<W, RecorderError> {
+        self.flush_cluster()?;
+
+        let cues_position = self.position - self.segment_start;
+        let mut cues = Vec::new();
+        if !self.cues.is_empty() {
+            element(&mut cues, CUES, |b| {
+                for &(time, cluster_position) in &self.cues {
+                    element(b, CUE_POINT, |b| {
+                        uint(b, CUE_TIME, time);
+                        element(b, CUE_TRACK_POSITIONS, |b| {
+                            uint(b, CUE_TRACK, 1);
+                            uint(b, CUE_CLUSTER_POSITION, cluster_position);
+                        });
+                    });
+                }
+            });
+        }
+        self.out.write_all(&cues)?;
+        self.position += cues.len() as u64;
+
+        let mut seek_head = Vec::new();
+        element(&mut seek_head, SEEK_HEAD, |b| {
+            let mut seek = |id: u32, position: u64| {
+                element(b, SEEK, |b| {
+                    element(b, SEEK_ID, |b| put_id(b, id));
+                    uint(b, SEEK_POSITION, position);
+                });
+            };
+            seek(INFO, self.info_position);
+            seek(TRACKS, self.tracks_position);
+            if !cues.is_empty() {
+                seek(CUES, cues_position);
+            }
+        });
+        let padding = SEEK_HEAD_RESERVE - seek_head.len();
+        void(&mut seek_head, padding);
+        self.out.seek(SeekFrom::Start(self.segment_start))?;
+        self.out.write_all(&seek_head)?;
+
+        self.out.seek(SeekFrom::Start(self.duration_at))?;
+        self.out.write_all(&(self.end_ms as f64).to_be_bytes())?;
+
+        let segment_size = self.position - self.segment_start;
+        self.out.seek(SeekFrom::Start(self.segment_start - 8))?;
+        self.out.write_all(&(segment_size | 1 << 56).to_be_bytes())?;
+
+        self.out.seek(SeekFrom::Start(self.position))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+impl<W: Write + Seek> Muxer for MkvMuxer<W> {
+    fn add_track(&mut self, config: TrackConfig) -> Result<u32, RecorderError> {
+        MkvMuxer::add_track(self, config)
+    }
+
+    fn write_sample(&mut self, track_id: u32, sample: Sample) -> Result<(), RecorderError> {
+        MkvMuxer::write_sample(self, track_id, sample)
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), RecorderError> {
+        MkvMuxer::finish(*self).map(drop)
+    }
+}
+
+fn codec_id(config: &TrackConfig) -> &'static str {
+    match config {
+        TrackConfig::Video { codec: VideoCodec::Avc { .. }, .. } => "V_MPEG4/ISO/AVC",
+        TrackConfig::Video { codec: VideoCodec::Av1 { .. }, .. } => "V_AV1",
+        TrackConfig::Audio { codec: AudioCodec::Aac { .. }, .. } => "A_AAC",
+        TrackConfig::Audio { codec: AudioCodec::Flac { .. }, .. } => "A_FLAC",
+    }
+}
+
+/// WebM only allows VP8/VP9/AV1 video and Vorbis/Opus audio.
+fn webm_compatible(config: &TrackConfig) -> bool {
+    matches!(codec_id(config), "V_VP8" | "V_VP9" | "V_AV1" | "A_VORBIS" | "A_OPUS")
+}
+
+fn write_tracks(out: &mut Vec<u8>, tracks: &[MkvTrack]) {
+    element(out, TRACKS, |b| {
+        for (i, track) in tracks.iter().enumerate() {
+            let number = i as u64 + 1;
+            element(b, TRACK_ENTRY, |b| {
+                uint(b, TRACK_NUMBER, number);
+                uint(b, TRACK_UID, number);
+                uint(b, FLAG_LACING, 0);
+                string(b, LANGUAGE, "und");
+                string(b, CODEC_ID, codec_id(&track.config));
+                match &track.config {
+                    TrackConfig::Video { codec, width, height, color, .. } => {
+                        uint(b, TRACK_TYPE, 1);
+                        match codec {
+                            VideoCodec::Avc { sps, pps } => binary(b, CODEC_PRIVATE, &avc_decoder_config(sps, pps)),
+                            VideoCodec::Av1 { config } => binary(b, CODEC_PRIVATE, config),
+                        }
+                        element(b, VIDEO, |b| {
+                            uint(b, PIXEL_WIDTH, *width as u64);
+                            uint(b, PIXEL_HEIGHT, *height as u64);
+                            if let Some(color) = color {
+                                element(b, COLOUR, |b| {
+                                    uint(b, MATRIX_COEFFICIENTS, color.matrix_code() as u64);
+                                    // 1 = broadcast range, 2 = full.
+                                    uint(b, RANGE, if color.full_range() { 2 } else { 1 });
+                                    uint(b, TRANSFER_CHARACTERISTICS, color.transfer_code() as u64);
+                                    uint(b, PRIMARIES, color.primaries_code() as u64);
+                                });
+                            }
+                        });
+                    }
+                    TrackConfig::Audio { codec, sample_rate, channels } => {
+                        uint(b, TRACK_TYPE, 2);
+                        match codec {
+                            AudioCodec::Aac { config } => binary(b, CODEC_PRIVATE, config),
+                            AudioCodec::Flac { stream_info } => {
+                                binary(b, CODEC_PRIVATE, &[b"fLaC".as_slice(), &flac_metadata(stream_info)].concat())
+                            }
+                        }
+                        element(b, AUDIO, |b| {
+                            float(b, SAMPLING_FREQUENCY, *sample_rate as f64);
+                            uint(b, CHANNELS, *channels as u64);
+                        });
+                    }
+                }
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::color_convert::ColorSpace;
+    use crate::state::app_state::{ColorMatrix, ColorRange};
+    use std::io::Cursor;
+
+    const SPS: [u8; 4] = [0x67, 0x42, 0xc0, 0x1e];
+    const PPS: [u8; 4] = [0x68, 0xce, 0x3c, 0x80];
+    const COLOR: ColorSpace = ColorSpace { matrix: ColorMatrix::Bt709, range: ColorRange::Full };
+
+    /// An element read back: its id, where it and its body start in the file, and the body.
+    struct Element<'a> {
+        id: u32,
+        start: usize,
+        body_start: usize,
+        body: &'a [u8],
+    }
+
+    impl<'a> Element<'a> {
+        fn children(&self) -> Vec<Element<'a>> {
+            elements(self.body, self.body_start)
+        }
+
+        fn child(&self, id: u32) -> Option<Element<'a>> {
+            self.children().into_iter().find(|e| e.id == id)
+        }
+
+        fn uint(&self) -> u64 {
+            self.body.iter().fold(0, |v, &b| v << 8 | b as u64)
+        }
+    }
+
+    /// The complete elements back to back in `data`, which sits `start` bytes into the file.
+    /// An unknown size runs to the end of `data`; an element cut short ends the list.
+    fn elements(data: &[u8], start: usize) -> Vec<Element<'_>> {
+        let width = |first: u8| first.leading_zeros() as usize + 1;
+        let mut found = Vec::new();
+        let mut pos = 0;
+        while let Some(&first) = data.get(pos) {
+            let id_len = width(first);
+            let Some(&size_first) = data.get(pos + id_len) else { break };
+            let size_len = width(size_first);
+            let body_start = pos + id_len + size_len;
+            let Some(header) = data.get(pos..body_start) else { break };
+            let id = header[..id_len].iter().fold(0u32, |v, &b| v << 8 | b as u32);
+            let all_ones = (1u64 << (7 * size_len)) - 1;
+            let size = header[id_len..].iter().fold(0u64, |v, &b| v << 8 | b as u64) & all_ones;
+            let end = if size == all_ones { data.len() } else { body_start + size as usize };
+            if end > data.len() {
+                break;
+            }
+            let body = &data[body_start..end];
+            found.push(Element { id, start: start + pos, body_start: start + body_start, body });
+            pos = end;
+        }
+        found
+    }
+
+    fn video_track() -> TrackConfig {
+        TrackConfig::Video {
+            codec: VideoCodec::Avc { sps: SPS.to_vec(), pps: PPS.to_vec() },
+            width: 320,
+            height: 240,
+            timescale: 90_000,
+            color: Some(COLOR),
+        }
+    }
+
+    fn audio_track() -> TrackConfig {
+        TrackConfig::Audio { codec: AudioCodec::Flac { stream_info: vec![7; 34] }, sample_rate: 48_000, channels: 2 }
+    }
+
+    fn sample_data(track: u32, index: u32) -> Vec<u8> {
+        let mut data = format!("track {track} sample {index};").into_bytes();
+        data.resize(100 + (index * 37 % 400) as usize, index as u8);
+        data
+    }
+
+    /// `seconds` of 30 fps video with a keyframe every two seconds, and 48 kHz audio in
+    /// 1024-sample frames keeping up with it. Returns the unfinished muxer and the time the
+    /// last sample ends, in milliseconds.
+    fn feed(container: Container, seconds: u32) -> (MkvMuxer<Cursor<Vec<u8>>>, u64) {
+        let mut muxer = MkvMuxer::new(Cursor::new(Vec::new()), container).unwrap();
+        assert_eq!(muxer.add_track(video_track()).unwrap(), 1);
+        assert_eq!(muxer.add_track(audio_track()).unwrap(), 2);
+        let mut audio_frames = 0u64;
+        for i in 0..seconds * 30 {
+            let data = sample_data(1, i);
+            let sample = Sample { data: &data, duration: 3000, composition_offset: 0, is_sync: i % 60 == 0 };
+            muxer.write_sample(1, sample).unwrap();
+            while audio_frames * 1024 * 90_000 < (i as u64 + 1) * 3000 * 48_000 {
+                let data = sample_data(2, audio_frames as u32);
+                let sample = Sample { data: &data, duration: 1024, composition_offset: 0, is_sync: true };
+                muxer.write_sample(2, sample).unwrap();
+                audio_frames += 1;
+            }
+        }
+        let end_ms = (seconds as u64 * 1000).max(audio_frames * 1024 * 1000 / 48_000);
+        (muxer, end_ms)
+    }
+
+    /// Track number, keyframe flag and payload of a `SimpleBlock`.
+    fn block(element: &Element) -> (u8, bool, Vec<u8>) {
+        (element.body[0] & 0x7f, element.body[3] & 0x80 != 0, element.body[4..].to_vec())
+    }
+
+    #[test]
+    fn segment_and_seek_head() {
+        let (muxer, _) = feed(Container::Mkv, 11);
+        let file = muxer.finish().unwrap().into_inner();
+        let top = elements(&file, 0);
+        assert_eq!(top.iter().map(|e| e.id).collect::<Vec<_>>(), [EBML, SEGMENT]);
+
+        // The size was patched in: no longer unknown, and it covers everything that follows.
+        let segment = &top[1];
+        let size = u64::from_be_bytes(file[segment.body_start - 8..segment.body_start].try_into().unwrap());
+        assert_eq!(size, (file.len() - segment.body_start) as u64 | 1 << 56);
+
+        let children = segment.children();
+        let last = children.last().unwrap();
+        assert_eq!((last.id, last.body_start + last.body.len()), (CUES, file.len()));
+        let seek_head = &children[0];
+        assert_eq!(seek_head.id, SEEK_HEAD);
+        let targets: Vec<_> = seek_head
+            .children()
+            .iter()
+            .map(|seek| (seek.child(SEEK_ID).unwrap().uint() as u32, seek.child(SEEK_POSITION).unwrap().uint()))
+            .collect();
+        assert_eq!(targets.iter().map(|t| t.0).collect::<Vec<_>>(), [INFO, TRACKS, CUES]);
+        for (id, position) in targets {
+            let target = children.iter().find(|e| e.start == segment.body_start + position as usize);
+            assert_eq!(target.map(|e| e.id), Some(id), "seek entry for {id:#x}");
+        }
+    }
+
+    #[test]
+    fn cue_points_lead_to_keyframes() {
+        let (muxer, _) = feed(Container::Mkv, 23);
+        let file = muxer.finish().unwrap().into_inner();
+        let segment = elements(&file, 0).remove(1);
+        let children = segment.children();
+        let clusters: Vec<_> = children.iter().filter(|e| e.id == CLUSTER).collect();
+        // A new cluster at the first keyframe five seconds in: 0, 6, 12 and 18 s.
+        let starts: Vec<_> = clusters.iter().map(|c| c.child(TIMESTAMP).unwrap().uint()).collect();
+        assert_eq!(starts, [0, 6000, 12_000, 18_000]);
+
+        let cues = segment.child(CUES).unwrap().children();
+        assert_eq!(cues.len(), clusters.len());
+        for (cue, start) in cues.iter().zip(starts) {
+            assert_eq!(cue.id, CUE_POINT);
+            assert_eq!(cue.child(CUE_TIME).unwrap().uint(), start);
+            let positions = cue.child(CUE_TRACK_POSITIONS).unwrap();
+            assert_eq!(positions.child(CUE_TRACK).unwrap().uint(), 1);
+            let position = positions.child(CUE_CLUSTER_POSITION).unwrap().uint() as usize;
+            let cluster = clusters.iter().find(|c| c.start == segment.body_start + position).unwrap();
+            assert_eq!(cluster.child(TIMESTAMP).unwrap().uint(), start);
+            let (track, keyframe, _) = block(&cluster.child(SIMPLE_BLOCK).unwrap());
+            assert!(track == 1 && keyframe, "cluster at {start} ms opens with track {track}, keyframe {keyframe}");
+        }
+    }
+
+    #[test]
+    fn track_entries_and_duration() {
+        let (muxer, end_ms) = feed(Container::Mkv, 11);
+        let file = muxer.finish().unwrap().into_inner();
+        let segment = elements(&file, 0).remove(1);
+
+        let entries = segment.child(TRACKS).unwrap().children();
+        let [video, audio] = &entries[..] else { panic!("expected two track entries") };
+        assert_eq!(video.child(CODEC_ID).unwrap().body, b"V_MPEG4/ISO/AVC");
+        assert_eq!(video.child(CODEC_PRIVATE).unwrap().body, avc_decoder_config(&SPS, &PPS));
+        let picture = video.child(VIDEO).unwrap();
+        assert_eq!(picture.child(PIXEL_WIDTH).unwrap().uint(), 320);
+        assert_eq!(picture.child(PIXEL_HEIGHT).unwrap().uint(), 240);
+        // BT.709 everywhere, full range.
+        let colour = picture.child(COLOUR).unwrap();
+        let codes: Vec<_> = [MATRIX_COEFFICIENTS, RANGE, TRANSFER_CHARACTERISTICS, PRIMARIES]
+            .into_iter()
+            .map(|id| colour.child(id).unwrap().uint())
+            .collect();
+        assert_eq!(codes, [1, 2, 1, 1]);
+
+        assert_eq!(audio.child(CODEC_ID).unwrap().body, b"A_FLAC");
+        let private = audio.child(CODEC_PRIVATE).unwrap().body;
+        assert_eq!(private, [b"fLaC".as_slice(), &[0x80, 0, 0, 34], &[7; 34]].concat());
+        let sound = audio.child(AUDIO).unwrap();
+        assert_eq!(f64::from_be_bytes(sound.child(SAMPLING_FREQUENCY).unwrap().body.try_into().unwrap()), 48_000.0);
+        assert_eq!(sound.child(CHANNELS).unwrap().uint(), 2);
+
+        let duration = segment.child(INFO).unwrap().child(DURATION).unwrap();
+        assert_eq!(f64::from_be_bytes(duration.body.try_into().unwrap()), end_ms as f64);
+    }
+
+    #[test]
+    fn clusters_survive_a_crash() {
+        // Never finished: the cluster from 18 s on is still being collected.
+        let (muxer, _) = feed(Container::Mkv, 23);
+        let file = muxer.out.get_ref().clone();
+        let segment = elements(&file, 0).remove(1);
+        assert_eq!(&file[segment.body_start - 8..segment.body_start], UNKNOWN_SIZE.to_be_bytes());
+        let last = segment.children().into_iter().last().unwrap();
+        assert_eq!((last.id, last.body_start + last.body.len()), (CLUSTER, file.len()));
+
+        // Cut inside the last cluster: the two before it read back whole.
+        let cut = &file[..last.body_start + last.body.len() / 2];
+        let segment = elements(cut, 0).remove(1);
+        let children = segment.children();
+        let ids: Vec<_> = children.iter().map(|e| e.id).collect();
+        assert_eq!(ids, [VOID, INFO, TRACKS, CLUSTER, CLUSTER]);
+        let video: Vec<_> = children
+            .iter()
+            .filter(|e| e.id == CLUSTER)
+            .flat_map(|c| c.children())
+            .filter(|e| e.id == SIMPLE_BLOCK)
+            .map(|e| block(&e))
+            .filter(|b| b.0 == 1)
+            .collect();
+        assert_eq!(video.len(), 12 * 30);
+        for (i, (_, keyframe, data)) in video.into_iter().enumerate() {
+            assert_eq!(keyframe, i % 60 == 0);
+            assert!(data == sample_data(1, i as u32), "video frame {i}");
+        }
+    }
+
+    #[test]
+    fn webm_takes_av1_but_not_h264() {
+        let mut muxer = MkvMuxer::new(Cursor::new(Vec::new()), Container::Webm).unwrap();
+        let e = muxer.add_track(video_track()).unwrap_err();
+        assert_eq!(e.code, "INVALID_SETTINGS");
+        let av1 = TrackConfig::Video {
+            codec: VideoCodec::Av1 { config: vec![0x81, 0, 0x0c, 0] },
+            width: 320,
+            height: 240,
+            timescale: 90_000,
+            color: None,
+        };
+        assert_eq!(muxer.add_track(av1).unwrap(), 1);
+        assert!(muxer.add_track(audio_track()).is_err(), "FLAC isn't WebM either");
+        let file = muxer.finish().unwrap().into_inner();
+        let ebml = elements(&file, 0).remove(0);
+        assert_eq!(ebml.child(DOC_TYPE).unwrap().body, b"webm");
+    }
+}
@@ -1,8 +1,41 @@
 pub mod audio_capturer;
+pub mod audio_encoder_flac;
+#[cfg(windows)]
+pub mod audio_encoder_mf;
+pub mod audio_encoder_wav;
+pub mod audio_mix;
+pub mod audio_sink;
 pub mod camera_capturer;
+pub mod color_convert;
 pub mod compositor;
+pub mod cursor;
+pub mod desktop_canvas;
+pub mod frame_dedup;
+pub mod frame_pool;
+pub mod frame_source;
+pub mod gif_palette;
 pub mod manager;
+pub mod mkv_mux;
+pub mod mp4_mux;
+pub mod mux;
+pub mod pipeline;
+pub mod replay_source;
+pub mod scaler;
 pub mod screen_capturer;
+#[cfg(windows)]
+pub mod screen_capturer_wgc;
+#[cfg(target_os = "linux")]
+pub mod screen_capturer_x11;
 pub mod status;
+pub mod test_pattern;
+#[cfg(test)]
+mod test_util;
+pub mod tone_map;
 pub mod video_encoder;
+pub mod video_encoder_gif;
+#[cfg(windows)]
 pub mod video_encoder_mf;
+pub mod video_encoder_openh264;
+pub mod video_encoder_rav1e;
+pub mod video_encoder_y4m;
+pub mod video_sink;
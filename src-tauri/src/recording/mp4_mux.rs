@@ -0,0 +1,1182 @@
+use crate::error::RecorderError;
+use crate::recording::mux::{
+    avc_decoder_config, flac_metadata, AudioCodec, MuxOptions, Muxer, Sample, TrackConfig, VideoCodec,
+};
+use std::fs::{File, OpenOptions};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+/// Timescale of the movie header; track durations are converted to it.
+const MOVIE_TIMESCALE: u32 = 1000;
+/// Size of the `mdat` header; always the 64-bit form so recordings can pass 4 GiB.
+const MDAT_HEADER_LEN: u64 = 16;
+/// Chunk size used when moving `mdat` to make room for `moov`.
+const SHIFT_CHUNK: usize = 1 << 20;
+/// ISO-639-2 "und", packed as three 5-bit letters.
+const LANGUAGE_UND: u16 = 0x55C4;
+/// Identity transform for `mvhd`/`tkhd`.
+const UNITY_MATRIX: [u32; 9] = [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000];
+
+fn mux_err(context: &str) -> RecorderError {
+    RecorderError::encoding_failed(format!("MP4 muxer: {context}"))
+}
+
+/// Where `Mp4Muxer` writes: seekable and readable, since faststart moves media around, and
+/// able to stand in a second output for defragmenting, so a failure halfway through leaves
+/// the fragmented recording as it was.
+pub trait Mp4Output: Read + Write + Seek + Sized {
+    /// A new, empty output to write the defragmented file into.
+    fn sibling(&mut self) -> std::io::Result<Self>;
+
+    /// Put the complete `sibling` in place of this output and return it.
+    fn replace_with(self, sibling: Self) -> std::io::Result<Self>;
+
+    /// Throw away a `sibling` that won't be used.
+    fn discard(self) {}
+}
+
+/// An MP4 file on disk. Its sibling is a `.defrag` file next to it, renamed over the
+/// original once complete.
+pub struct Mp4File {
+    file: File,
+    path: PathBuf,
+}
+
+impl Mp4File {
+    /// `file` has to be open for reading and writing.
+    pub fn new(file: File, path: PathBuf) -> Self {
+        Self { file, path }
+    }
+}
+
+impl Read for Mp4File {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Write for Mp4File {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for Mp4File {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl Mp4Output for Mp4File {
+    fn sibling(&mut self) -> std::io::Result<Self> {
+        let mut name = self.path.clone().into_os_string();
+        name.push(".defrag");
+        let path = PathBuf::from(name);
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path)?;
+        Ok(Self { file, path })
+    }
+
+    fn replace_with(self, sibling: Self) -> std::io::Result<Self> {
+        // Windows won't rename over a file that is still open.
+        drop(self.file);
+        let renamed = sibling.file.sync_all().and_then(|()| std::fs::rename(&sibling.path, &self.path));
+        if let Err(e) = renamed {
+            sibling.discard();
+            return Err(e);
+        }
+        Ok(Self { file: sibling.file, path: self.path })
+    }
+
+    fn discard(self) {
+        drop(self.file);
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl Mp4Output for Cursor<Vec<u8>> {
+    fn sibling(&mut self) -> std::io::Result<Self> {
+        Ok(Cursor::new(Vec::new()))
+    }
+
+    fn replace_with(self, sibling: Self) -> std::io::Result<Self> {
+        Ok(sibling)
+    }
+}
+
+/// Sample table of a track, kept in memory until `moov` is written.
+struct Track {
+    config: TrackConfig,
+    sizes: Vec<u32>,
+    /// File offset of each sample; every sample is its own chunk.
+    offsets: Vec<u64>,
+    /// Run-length `(count, delta)` pairs for `stts`.
+    durations: Vec<(u32, u32)>,
+    /// Run-length `(count, offset)` pairs for `ctts`.
+    composition_offsets: Vec<(u32, i32)>,
+    /// 1-based numbers of the sync samples.
+    sync_samples: Vec<u32>,
+    total_duration: u64,
+    /// Samples of the fragment being collected, in fragmented mode.
+    fragment: Fragment,
+}
+
+/// The part of a track that goes into the next `moof`/`mdat` pair.
+#[derive(Default)]
+struct Fragment {
+    data: Vec<u8>,
+    /// `(size, duration, composition offset, is_sync)` per sample.
+    samples: Vec<(u32, u32, i32, bool)>,
+    /// Decode time of the first sample.
+    start: u64,
+    duration: u64,
+}
+
+impl Track {
+    fn new(config: TrackConfig) -> Self {
+        Self {
+            config,
+            sizes: Vec::new(),
+            offsets: Vec::new(),
+            durations: Vec::new(),
+            composition_offsets: Vec::new(),
+            sync_samples: Vec::new(),
+            total_duration: 0,
+            fragment: Fragment::default(),
+        }
+    }
+
+    /// Add `sample` to the sample table. Its offset is pushed separately, once known.
+    fn record(&mut self, sample: &Sample) {
+        self.sizes.push(sample.data.len() as u32);
+        push_run(&mut self.durations, sample.duration);
+        push_run(&mut self.composition_offsets, sample.composition_offset);
+        if sample.is_sync {
+            self.sync_samples.push(self.sizes.len() as u32);
+        }
+        self.total_duration += sample.duration as u64;
+    }
+}
+
+fn push_run<T: PartialEq + Copy>(runs: &mut Vec<(u32, T)>, value: T) {
+    match runs.last_mut() {
+        Some((count, last)) if *last == value => *count += 1,
+        _ => runs.push((1, value)),
+    }
+}
+
+/// Bookkeeping for fragmented output.
+struct Fragmenting {
+    /// Length of a fragment in the timescale of the first track, which decides the cuts.
+    target: u64,
+    /// `moof` sequence number of the next fragment.
+    sequence: u32,
+    /// Whether the initial, sample-less `moov` is out.
+    header_written: bool,
+    /// `(offset, length)` of each fragment's media, for `defragment`.
+    payloads: Vec<(u64, u64)>,
+}
+
+/// Writes ISO-BMFF (`.mp4`) files from already encoded elementary streams.
+///
+/// Regular files: samples go straight into `mdat` as they arrive while their sizes, offsets
+/// and timing are collected for the `moov` written by `finish`, after the media or, with
+/// `faststart`, moved in front of it by shifting the media in place.
+///
+/// Fragmented files: an empty `moov` goes out with the first fragment, then a `moof`/`mdat`
+/// pair every `fragment_duration` or so, cut at a sync sample of the first track where
+/// possible. Everything up to the last complete fragment stays playable if the recording
+/// never reaches `finish`. With `defragment`, `finish` compacts the media and writes a
+/// regular `moov` into a sibling output that replaces the fragmented one once complete, so
+/// the result is a plain MP4.
+pub struct Mp4Muxer<W: Mp4Output> {
+    out: W,
+    tracks: Vec<Track>,
+    options: MuxOptions,
+    fragmenting: Option<Fragmenting>,
+    /// Start of the `mdat` of a regular file.
+    mdat_start: u64,
+    /// Write position, the end of the file so far.
+    position: u64,
+}
+
+impl<W: Mp4Output> Mp4Muxer<W> {
+    pub fn new(mut out: W, options: MuxOptions) -> Result<Self, RecorderError> {
+        let mut head = Vec::new();
+        write_box(&mut head, b"ftyp", |b| {
+            b.extend_from_slice(b"isom");
+            put_u32(b, 512);
+            for brand in [b"isom", b"iso2", b"iso6", b"avc1", b"av01", b"mp41"] {
+                b.extend_from_slice(brand);
+            }
+        });
+        let mdat_start = head.len() as u64;
+
+        let fragmenting = options.fragment_duration.map(|d| Fragmenting {
+            target: d.as_millis() as u64,
+            sequence: 1,
+            header_written: false,
+            payloads: Vec::new(),
+        });
+        if fragmenting.is_none() {
+            // Size 1 means the real size follows as a u64, patched in by `finish`.
+            put_u32(&mut head, 1);
+            head.extend_from_slice(b"mdat");
+            put_u64(&mut head, 0);
+        }
+
+        out.write_all(&head)?;
+        Ok(Self { out, tracks: Vec::new(), options, fragmenting, mdat_start, position: head.len() as u64 })
+    }
+
+    /// Add a track and return its id for `write_sample`. Fragmented files need every track
+    /// before the first fragment is cut.
+    pub fn add_track(&mut self, config: TrackConfig) -> Result<u32, RecorderError> {
+        if config.timescale() == 0 {
+            return Err(mux_err("track timescale must be above zero"));
+        }
+        if self.fragmenting.as_ref().is_some_and(|f| f.header_written) {
+            return Err(mux_err("tracks can't be added after the first fragment"));
+        }
+        let first = self.tracks.is_empty();
+        if let (true, Some(fragmenting)) = (first, self.fragmenting.as_mut()) {
+            // `target` was in milliseconds until the first track fixed the timescale.
+            fragmenting.target = scale(fragmenting.target, 1000, config.timescale()).max(1);
+        }
+        self.tracks.push(Track::new(config));
+        Ok(self.tracks.len() as u32)
+    }
+
+    pub fn write_sample(&mut self, track_id: u32, sample: Sample) -> Result<(), RecorderError> {
+        let index = track_id
+            .checked_sub(1)
+            .map(|i| i as usize)
+            .filter(|&i| i < self.tracks.len())
+            .ok_or_else(|| mux_err(&format!("no track {track_id}")))?;
+        if sample.data.len() > u32::MAX as usize {
+            return Err(mux_err("sample larger than 4 GiB"));
+        }
+
+        let Some(target) = self.fragmenting.as_ref().map(|f| f.target) else {
+            self.out.write_all(sample.data)?;
+            let track = &mut self.tracks[index];
+            track.offsets.push(self.position);
+            track.record(&sample);
+            self.position += sample.data.len() as u64;
+            return Ok(());
+        };
+
+        if index == 0 {
+            let collected = self.tracks[0].fragment.duration;
+            if collected >= target && (sample.is_sync || collected >= 2 * target) {
+                self.flush_fragment()?;
+            }
+        }
+        let track = &mut self.tracks[index];
+        if track.fragment.samples.is_empty() {
+            track.fragment.start = track.total_duration;
+        }
+        track.fragment.data.extend_from_slice(sample.data);
+        track.fragment.samples.push((
+            sample.data.len() as u32,
+            sample.duration,
+            sample.composition_offset,
+            sample.is_sync,
+        ));
+        track.fragment.duration += sample.duration as u64;
+        track.record(&sample);
+        Ok(())
+    }
+
+    /// Close the file and return the output.
+    pub fn finish(mut self) -> Result<W, RecorderError> {
+        if self.fragmenting.is_none() {
+            self.write_index()?;
+            return Ok(self.out);
+        }
+
+        self.flush_fragment()?;
+        if !self.options.defragment {
+            self.out.flush()?;
+            return Ok(self.out);
+        }
+        let compacted = self.out.sibling()?;
+        let mut fragmented = std::mem::replace(&mut self.out, compacted);
+        if let Err(e) = self.compact(&mut fragmented).and_then(|()| self.write_index()) {
+            std::mem::replace(&mut self.out, fragmented).discard();
+            return Err(e);
+        }
+        Ok(fragmented.replace_with(self.out)?)
+    }
+
+    /// Write out the collected fragment, preceded by the initial `moov` the first time.
+    fn flush_fragment(&mut self) -> Result<(), RecorderError> {
+        let Some(fragmenting) = self.fragmenting.as_mut() else {
+            return Ok(());
+        };
+        let mut out = Vec::new();
+        if !fragmenting.header_written {
+            fragmenting.header_written = true;
+            let empty: Vec<Track> = self.tracks.iter().map(|t| Track::new(t.config.clone())).collect();
+            out = build_moov(&empty, 0, true);
+        }
+        if self.tracks.iter().all(|t| t.fragment.samples.is_empty()) {
+            self.out.write_all(&out)?;
+            self.position += out.len() as u64;
+            return Ok(());
+        }
+
+        write_moof(&mut out, fragmenting.sequence, &self.tracks);
+        fragmenting.sequence += 1;
+        let payload: usize = self.tracks.iter().map(|t| t.fragment.data.len()).sum();
+        put_u32(&mut out, (payload + 8) as u32);
+        out.extend_from_slice(b"mdat");
+
+        let payload_start = self.position + out.len() as u64;
+        fragmenting.payloads.push((payload_start, payload as u64));
+        let mut offset = payload_start;
+        for track in &mut self.tracks {
+            for &(size, ..) in &track.fragment.samples {
+                track.offsets.push(offset);
+                offset += size as u64;
+            }
+            out.extend_from_slice(&track.fragment.data);
+            track.fragment = Fragment::default();
+        }
+
+        self.out.write_all(&out)?;
+        self.out.flush()?;
+        self.position += out.len() as u64;
+        Ok(())
+    }
+
+    /// Copy `ftyp` and the media of all fragments of `fragmented` into one `mdat` in the
+    /// output, fixing up the sample offsets.
+    fn compact(&mut self, fragmented: &mut W) -> Result<(), RecorderError> {
+        let payloads = self.fragmenting.take().map(|f| f.payloads).unwrap_or_default();
+        let mut buf = vec![0u8; SHIFT_CHUNK];
+        fragmented.seek(SeekFrom::Start(0))?;
+        fragmented.read_exact(&mut buf[..self.mdat_start as usize])?;
+        self.out.write_all(&buf[..self.mdat_start as usize])?;
+        // Placeholder, sized by `write_index`.
+        put_mdat_header(&mut self.out, 0)?;
+
+        // (old start, old end, new start) per fragment.
+        let mut moved = Vec::with_capacity(payloads.len());
+        let mut dst = self.mdat_start + MDAT_HEADER_LEN;
+        for (start, len) in payloads {
+            fragmented.seek(SeekFrom::Start(start))?;
+            let mut done = 0;
+            while done < len {
+                let n = (len - done).min(SHIFT_CHUNK as u64) as usize;
+                fragmented.read_exact(&mut buf[..n])?;
+                self.out.write_all(&buf[..n])?;
+                done += n as u64;
+            }
+            moved.push((start, start + len, dst));
+            dst += len;
+        }
+
+        for track in &mut self.tracks {
+            let mut fragment = moved.iter();
+            let mut current = fragment.next();
+            for offset in &mut track.offsets {
+                while let Some(&(_, end, _)) = current {
+                    if *offset < end {
+                        break;
+                    }
+                    current = fragment.next();
+                }
+                if let Some(&(start, _, new_start)) = current {
+                    *offset = *offset - start + new_start;
+                }
+            }
+        }
+
+        self.position = dst;
+        Ok(())
+    }
+
+    /// Finish a regular file: size `mdat` and write `moov`.
+    fn write_index(&mut self) -> Result<(), RecorderError> {
+        let mut moov_after = None;
+        if self.options.faststart {
+            // Offsets move by the size of `moov`, which can itself grow when they no longer
+            // fit 32 bits; settle on a size that matches its own shift.
+            let mut shift = 0;
+            let moov = loop {
+                let moov = build_moov(&self.tracks, shift, false);
+                if moov.len() as u64 == shift {
+                    break moov;
+                }
+                shift = moov.len() as u64;
+            };
+            self.shift_media(shift)?;
+            self.out.seek(SeekFrom::Start(self.mdat_start))?;
+            self.out.write_all(&moov)?;
+            self.mdat_start += shift;
+            self.position += shift;
+        } else {
+            moov_after = Some(build_moov(&self.tracks, 0, false));
+        }
+
+        self.out.seek(SeekFrom::Start(self.mdat_start))?;
+        put_mdat_header(&mut self.out, self.position - self.mdat_start)?;
+        if let Some(moov) = moov_after {
+            self.out.seek(SeekFrom::Start(self.position))?;
+            self.out.write_all(&moov)?;
+            self.position += moov.len() as u64;
+        }
+        self.out.seek(SeekFrom::Start(self.position))?;
+        self.out.flush()?;
+        Ok(())
+    }
+
+    /// Move `mdat` (header included) `by` bytes towards the end, back to front.
+    fn shift_media(&mut self, by: u64) -> Result<(), RecorderError> {
+        let mut buf = vec![0u8; SHIFT_CHUNK];
+        let mut pos = self.position;
+        while pos > self.mdat_start {
+            let len = (pos - self.mdat_start).min(SHIFT_CHUNK as u64) as usize;
+            pos -= len as u64;
+            self.out.seek(SeekFrom::Start(pos))?;
+            self.out.read_exact(&mut buf[..len])?;
+            self.out.seek(SeekFrom::Start(pos + by))?;
+            self.out.write_all(&buf[..len])?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Mp4Output> Muxer for Mp4Muxer<W> {
+    fn add_track(&mut self, config: TrackConfig) -> Result<u32, RecorderError> {
+        Mp4Muxer::add_track(self, config)
+    }
+
+    fn write_sample(&mut self, track_id: u32, sample: Sample) -> Result<(), RecorderError> {
+        Mp4Muxer::write_sample(self, track_id, sample)
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), RecorderError> {
+        Mp4Muxer::finish(*self).map(drop)
+    }
+}
+
+fn put_mdat_header(out: &mut impl Write, len: u64) -> Result<(), RecorderError> {
+    out.write_all(&1u32.to_be_bytes())?;
+    out.write_all(b"mdat")?;
+    out.write_all(&len.to_be_bytes())?;
+    Ok(())
+}
+
+/// `moov` for `tracks`, with chunk offsets moved by `shift`. A `fragmented` header gets
+/// `mvex` and leaves durations to the fragments.
+fn build_moov(tracks: &[Track], shift: u64, fragmented: bool) -> Vec<u8> {
+    let movie_duration = tracks
+        .iter()
+        .map(|t| scale(t.total_duration, t.config.timescale(), MOVIE_TIMESCALE))
+        .max()
+        .unwrap_or(0);
+
+    let mut moov = Vec::new();
+    write_box(&mut moov, b"moov", |b| {
+        write_mvhd(b, movie_duration, tracks.len() as u32 + 1);
+        for (i, track) in tracks.iter().enumerate() {
+            write_trak(b, i as u32 + 1, track, shift);
+        }
+        if fragmented {
+            write_box(b, b"mvex", |b| {
+                for i in 0..tracks.len() as u32 {
+                    write_full_box(b, b"trex", 0, 0, |b| {
+                        put_u32(b, i + 1);
+                        put_u32(b, 1); // sample description index
+                        put_zeros(b, 12); // default duration, size, flags
+                    });
+                }
+            });
+        }
+    });
+    moov
+}
+
+/// Sample flags for `trun`: sync samples depend on nothing, the rest are non-sync.
+fn sample_flags(is_sync: bool) -> u32 {
+    if is_sync {
+        0x0200_0000
+    } else {
+        0x0101_0000
+    }
+}
+
+/// `moof` for the collected fragments of `tracks`, whose media follows in one `mdat` in
+/// track order.
+fn write_moof(out: &mut Vec<u8>, sequence: u32, tracks: &[Track]) {
+    // `trun` data offsets count from the start of `moof`, whose size doesn't depend on their
+    // values: lay it out once to measure, then for real.
+    let mut probe = Vec::new();
+    write_moof_with(&mut probe, sequence, tracks, 0);
+    write_moof_with(out, sequence, tracks, probe.len() as u32 + 8);
+}
+
+fn write_moof_with(out: &mut Vec<u8>, sequence: u32, tracks: &[Track], first_data_offset: u32) {
+    write_box(out, b"moof", |b| {
+        write_full_box(b, b"mfhd", 0, 0, |b| put_u32(b, sequence));
+        let mut data_offset = first_data_offset;
+        for (i, track) in tracks.iter().enumerate() {
+            let fragment = &track.fragment;
+            if fragment.samples.is_empty() {
+                continue;
+            }
+            write_box(b, b"traf", |b| {
+                // Flag 0x020000: offsets are relative to this `moof`.
+                write_full_box(b, b"tfhd", 0, 0x02_0000, |b| put_u32(b, i as u32 + 1));
+                write_full_box(b, b"tfdt", 1, 0, |b| put_u64(b, fragment.start));
+
+                let reordered = fragment.samples.iter().any(|s| s.2 != 0);
+                // Data offset, then per-sample duration, size, flags and maybe composition offset.
+                let flags = 0x0001 | 0x0100 | 0x0200 | 0x0400 | if reordered { 0x0800 } else { 0 };
+                write_full_box(b, b"trun", 1, flags, |b| {
+                    put_u32(b, fragment.samples.len() as u32);
+                    put_u32(b, data_offset);
+                    for &(size, duration, composition_offset, is_sync) in &fragment.samples {
+                        put_u32(b, duration);
+                        put_u32(b, size);
+                        put_u32(b, sample_flags(is_sync));
+                        if reordered {
+                            b.extend_from_slice(&composition_offset.to_be_bytes());
+                        }
+                    }
+                });
+            });
+            data_offset += fragment.data.len() as u32;
+        }
+    });
+}
+
+fn scale(value: u64, from: u32, to: u32) -> u64 {
+    (value as u128 * to as u128 / from as u128) as u64
+}
+
+fn put_u16(b: &mut Vec<u8>, v: u16) {
+    b.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_u32(b: &mut Vec<u8>, v: u32) {
+    b.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_u64(b: &mut Vec<u8>, v: u64) {
+    b.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_zeros(b: &mut Vec<u8>, n: usize) {
+    b.resize(b.len() + n, 0);
+}
+
+fn write_box(out: &mut Vec<u8>, kind: &[u8; 4], body: impl FnOnce(&mut Vec<u8>)) {
+    let start = out.len();
+    put_u32(out, 0);
+    out.extend_from_slice(kind);
+    body(out);
+    let len = (out.len() - start) as u32;
+    out[start..start + 4].copy_from_slice(&len.to_be_bytes());
+}
+
+fn write_full_box(out: &mut Vec<u8>, kind: &[u8; 4], version: u8, flags: u32, body: impl FnOnce(&mut Vec<u8>)) {
+    write_box(out, kind, |b| {
+        put_u32(b, (version as u32) << 24 | (flags & 0x00ff_ffff));
+        body(b);
+    });
+}
+
+/// Creation and modification time (left at zero), timescale and duration as `mvhd` and
+/// `mdhd` lay them out; version 1 widens them to 64 bits.
+fn put_times(b: &mut Vec<u8>, version: u8, timescale: u32, duration: u64) {
+    if version == 1 {
+        put_u64(b, 0);
+        put_u64(b, 0);
+        put_u32(b, timescale);
+        put_u64(b, duration);
+    } else {
+        put_u32(b, 0);
+        put_u32(b, 0);
+        put_u32(b, timescale);
+        put_u32(b, duration as u32);
+    }
+}
+
+fn version_for(duration: u64) -> u8 {
+    u8::from(duration > u32::MAX as u64)
+}
+
+fn put_matrix(b: &mut Vec<u8>) {
+    for v in UNITY_MATRIX {
+        put_u32(b, v);
+    }
+}
+
+fn write_mvhd(out: &mut Vec<u8>, duration: u64, next_track_id: u32) {
+    let version = version_for(duration);
+    write_full_box(out, b"mvhd", version, 0, |b| {
+        put_times(b, version, MOVIE_TIMESCALE, duration);
+        put_u32(b, 0x0001_0000); // rate 1.0
+        put_u16(b, 0x0100); // volume 1.0
+        put_zeros(b, 10);
+        put_matrix(b);
+        put_zeros(b, 24);
+        put_u32(b, next_track_id);
+    });
+}
+
+fn write_trak(out: &mut Vec<u8>, track_id: u32, track: &Track, shift: u64) {
+    let timescale = track.config.timescale();
+    write_box(out, b"trak", |b| {
+        write_tkhd(b, track_id, track, scale(track.total_duration, timescale, MOVIE_TIMESCALE));
+        write_box(b, b"mdia", |b| {
+            let version = version_for(track.total_duration);
+            write_full_box(b, b"mdhd", version, 0, |b| {
+                put_times(b, version, timescale, track.total_duration);
+                put_u16(b, LANGUAGE_UND);
+                put_u16(b, 0);
+            });
+            let (handler, name): (&[u8; 4], &[u8]) = match track.config {
+                TrackConfig::Video { .. } => (b"vide", b"VideoHandler\0"),
+                TrackConfig::Audio { .. } => (b"soun", b"SoundHandler\0"),
+            };
+            write_full_box(b, b"hdlr", 0, 0, |b| {
+                put_u32(b, 0);
+                b.extend_from_slice(handler);
+                put_zeros(b, 12);
+                b.extend_from_slice(name);
+            });
+            write_box(b, b"minf", |b| {
+                match track.config {
+                    TrackConfig::Video { .. } => write_full_box(b, b"vmhd", 0, 1, |b| put_zeros(b, 8)),
+                    TrackConfig::Audio { .. } => write_full_box(b, b"smhd", 0, 0, |b| put_zeros(b, 4)),
+                }
+                write_box(b, b"dinf", |b| {
+                    write_full_box(b, b"dref", 0, 0, |b| {
+                        put_u32(b, 1);
+                        // Flag 1: the media is in this file.
+                        write_full_box(b, b"url ", 0, 1, |_| {});
+                    });
+                });
+                write_stbl(b, track, shift);
+            });
+        });
+    });
+}
+
+fn write_tkhd(out: &mut Vec<u8>, track_id: u32, track: &Track, duration: u64) {
+    let version = version_for(duration);
+    // Flags: enabled, in movie.
+    write_full_box(out, b"tkhd", version, 0x3, |b| {
+        if version == 1 {
+            put_zeros(b, 16);
+            put_u32(b, track_id);
+            put_u32(b, 0);
+            put_u64(b, duration);
+        } else {
+            put_zeros(b, 8);
+            put_u32(b, track_id);
+            put_u32(b, 0);
+            put_u32(b, duration as u32);
+        }
+        put_zeros(b, 8);
+        put_u16(b, 0); // layer
+        put_u16(b, 0); // alternate group
+        match track.config {
+            TrackConfig::Audio { .. } => put_u16(b, 0x0100),
+            TrackConfig::Video { .. } => put_u16(b, 0),
+        }
+        put_u16(b, 0);
+        put_matrix(b);
+        match track.config {
+            TrackConfig::Video { width, height, .. } => {
+                put_u32(b, width << 16);
+                put_u32(b, height << 16);
+            }
+            TrackConfig::Audio { .. } => put_zeros(b, 8),
+        }
+    });
+}
+
+fn write_stbl(out: &mut Vec<u8>, track: &Track, shift: u64) {
+    write_box(out, b"stbl", |b| {
+        write_full_box(b, b"stsd", 0, 0, |b| {
+            put_u32(b, 1);
+            write_sample_entry(b, &track.config);
+        });
+
+        write_full_box(b, b"stts", 0, 0, |b| {
+            put_u32(b, track.durations.len() as u32);
+            for &(count, delta) in &track.durations {
+                put_u32(b, count);
+                put_u32(b, delta);
+            }
+        });
+
+        if track.composition_offsets.iter().any(|&(_, offset)| offset != 0) {
+            // Version 1 allows negative offsets.
+            let version = u8::from(track.composition_offsets.iter().any(|&(_, offset)| offset < 0));
+            write_full_box(b, b"ctts", version, 0, |b| {
+                put_u32(b, track.composition_offsets.len() as u32);
+                for &(count, offset) in &track.composition_offsets {
+                    put_u32(b, count);
+                    b.extend_from_slice(&offset.to_be_bytes());
+                }
+            });
+        }
+
+        // Without `stss` every sample counts as a sync sample.
+        if track.sync_samples.len() != track.sizes.len() {
+            write_full_box(b, b"stss", 0, 0, |b| {
+                put_u32(b, track.sync_samples.len() as u32);
+                for &n in &track.sync_samples {
+                    put_u32(b, n);
+                }
+            });
+        }
+
+        write_full_box(b, b"stsc", 0, 0, |b| {
+            // One sample per chunk throughout.
+            put_u32(b, 1);
+            put_u32(b, 1);
+            put_u32(b, 1);
+            put_u32(b, 1);
+        });
+
+        write_full_box(b, b"stsz", 0, 0, |b| {
+            put_u32(b, 0);
+            put_u32(b, track.sizes.len() as u32);
+            for &size in &track.sizes {
+                put_u32(b, size);
+            }
+        });
+
+        let offsets = track.offsets.iter().map(|&o| o + shift);
+        if track.offsets.last().is_some_and(|&o| o + shift > u32::MAX as u64) {
+            write_full_box(b, b"co64", 0, 0, |b| {
+                put_u32(b, track.offsets.len() as u32);
+                offsets.for_each(|o| put_u64(b, o));
+            });
+        } else {
+            write_full_box(b, b"stco", 0, 0, |b| {
+                put_u32(b, track.offsets.len() as u32);
+                offsets.for_each(|o| put_u32(b, o as u32));
+            });
+        }
+    });
+}
+
+fn write_sample_entry(out: &mut Vec<u8>, config: &TrackConfig) {
+    match config {
+        TrackConfig::Video { codec: VideoCodec::Avc { sps, pps }, width, height, color, .. } => {
+            write_box(out, b"avc1", |b| {
+                write_visual_entry_fields(b, *width, *height);
+                write_box(b, b"avcC", |b| b.extend_from_slice(&avc_decoder_config(sps, pps)));
+                if let Some(color) = color {
+                    b.extend_from_slice(&color.colr_box());
+                }
+            });
+        }
+        TrackConfig::Video { codec: VideoCodec::Av1 { config }, width, height, color, .. } => {
+            write_box(out, b"av01", |b| {
+                write_visual_entry_fields(b, *width, *height);
+                write_box(b, b"av1C", |b| b.extend_from_slice(config));
+                if let Some(color) = color {
+                    b.extend_from_slice(&color.colr_box());
+                }
+            });
+        }
+        TrackConfig::Audio { codec: AudioCodec::Aac { config }, sample_rate, channels } => {
+            write_box(out, b"mp4a", |b| {
+                write_audio_entry_fields(b, *sample_rate, *channels);
+                write_esds(b, config);
+            });
+        }
+        TrackConfig::Audio { codec: AudioCodec::Flac { stream_info }, sample_rate, channels } => {
+            write_box(out, b"fLaC", |b| {
+                write_audio_entry_fields(b, *sample_rate, *channels);
+                write_full_box(b, b"dfLa", 0, 0, |b| b.extend_from_slice(&flac_metadata(stream_info)));
+            });
+        }
+    }
+}
+
+/// Fields every audio sample entry starts with. Rates past 16 bits are left to the codec
+/// configuration.
+fn write_audio_entry_fields(b: &mut Vec<u8>, sample_rate: u32, channels: u16) {
+    put_zeros(b, 6);
+    put_u16(b, 1); // data_reference_index
+    put_zeros(b, 8);
+    put_u16(b, channels);
+    put_u16(b, 16); // sample size
+    put_zeros(b, 4);
+    put_u32(b, if sample_rate > 0xffff { 0 } else { sample_rate << 16 });
+}
+
+/// Fields every visual sample entry starts with.
+fn write_visual_entry_fields(b: &mut Vec<u8>, width: u32, height: u32) {
+    put_zeros(b, 6);
+    put_u16(b, 1); // data_reference_index
+    put_zeros(b, 16);
+    put_u16(b, width as u16);
+    put_u16(b, height as u16);
+    put_u32(b, 0x0048_0000); // 72 dpi
+    put_u32(b, 0x0048_0000);
+    put_u32(b, 0);
+    put_u16(b, 1); // frame count
+    put_zeros(b, 32); // compressor name
+    put_u16(b, 0x0018); // depth
+    put_u16(b, 0xffff); // pre_defined = -1
+}
+
+/// MPEG-4 descriptor with its variable-length size.
+fn write_descriptor(out: &mut Vec<u8>, tag: u8, body: &[u8]) {
+    out.push(tag);
+    let len = body.len() as u32;
+    for shift in [21, 14, 7] {
+        if len >> shift != 0 {
+            out.push(0x80 | (len >> shift) as u8 & 0x7f);
+        }
+    }
+    out.push(len as u8 & 0x7f);
+    out.extend_from_slice(body);
+}
+
+fn write_esds(out: &mut Vec<u8>, audio_specific_config: &[u8]) {
+    let mut decoder_config = vec![
+        0x40, // Audio ISO/IEC 14496-3
+        0x15, // audio stream
+    ];
+    put_zeros(&mut decoder_config, 3); // buffer size
+    put_u32(&mut decoder_config, 0); // max bitrate
+    put_u32(&mut decoder_config, 0); // average bitrate
+    write_descriptor(&mut decoder_config, 0x05, audio_specific_config);
+
+    let mut es = Vec::new();
+    put_u16(&mut es, 0); // ES_ID
+    es.push(0); // flags
+    write_descriptor(&mut es, 0x04, &decoder_config);
+    write_descriptor(&mut es, 0x06, &[0x02]); // SL config: predefined MP4
+
+    write_full_box(out, b"esds", 0, 0, |b| write_descriptor(b, 0x03, &es));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::audio_encoder_flac::FlacEncoder;
+    use crate::recording::test_util::{decode_flac_track, mp4_boxes, mp4_tracks, temp_path};
+    use std::time::Duration;
+
+    struct Written {
+        track: u32,
+        data: Vec<u8>,
+        duration: u32,
+        is_sync: bool,
+    }
+
+    /// Four seconds of 30 fps video, keyframe every second and every tenth frame held longer,
+    /// interleaved with 48 kHz AAC frames. Sizes vary and every sample's bytes are unique.
+    fn samples() -> Vec<Written> {
+        let mut video: Vec<_> = (0..120u32)
+            .map(|i| Written {
+                track: 1,
+                data: sample_data(1, i, 200 + i * 37 % 900),
+                duration: if i % 10 == 9 { 4500 } else { 3000 },
+                is_sync: i % 30 == 0,
+            })
+            .collect();
+        let video_end: u64 = video.iter().map(|s| s.duration as u64).sum();
+        let audio_frames = (video_end * 48_000 / 90_000 / 1024) as u32;
+        let mut audio: Vec<_> = (0..audio_frames)
+            .map(|i| Written { track: 2, data: sample_data(2, i, 100 + i * 13 % 300), duration: 1024, is_sync: true })
+            .collect();
+
+        // Merge by decode time.
+        let mut merged = Vec::new();
+        let (mut video_time, mut audio_time) = (0u64, 0u64);
+        video.reverse();
+        audio.reverse();
+        while !video.is_empty() || !audio.is_empty() {
+            let audio_first = video.is_empty() || (!audio.is_empty() && audio_time * 90_000 < video_time * 48_000);
+            let sample = if audio_first { audio.pop().unwrap() } else { video.pop().unwrap() };
+            match sample.track {
+                1 => video_time += sample.duration as u64,
+                _ => audio_time += sample.duration as u64,
+            }
+            merged.push(sample);
+        }
+        merged
+    }
+
+    fn sample_data(track: u32, index: u32, len: u32) -> Vec<u8> {
+        let mut data = format!("track {track} sample {index};").into_bytes();
+        data.resize(len as usize, (index * 7 + track) as u8);
+        data
+    }
+
+    fn mux(options: MuxOptions, written: &[Written]) -> Vec<u8> {
+        feed(Cursor::new(Vec::new()), options, written).finish().unwrap().into_inner()
+    }
+
+    /// A muxer with the video and audio tracks of `samples`, fed `written` but not finished.
+    fn feed<W: Mp4Output>(out: W, options: MuxOptions, written: &[Written]) -> Mp4Muxer<W> {
+        let mut muxer = Mp4Muxer::new(out, options).unwrap();
+        let video = TrackConfig::Video {
+            codec: VideoCodec::Avc { sps: vec![0x67, 0x42, 0xc0, 0x1e], pps: vec![0x68, 0xce, 0x3c, 0x80] },
+            width: 320,
+            height: 240,
+            timescale: 90_000,
+            color: None,
+        };
+        let aac = AudioCodec::Aac { config: vec![0x11, 0x90] };
+        let audio = TrackConfig::Audio { codec: aac, sample_rate: 48_000, channels: 2 };
+        assert_eq!(muxer.add_track(video).unwrap(), 1);
+        assert_eq!(muxer.add_track(audio).unwrap(), 2);
+        for s in written {
+            let sample = Sample { data: &s.data, duration: s.duration, composition_offset: 0, is_sync: s.is_sync };
+            muxer.write_sample(s.track, sample).unwrap();
+        }
+        muxer
+    }
+
+    fn top_level(file: &[u8]) -> Vec<String> {
+        mp4_boxes(file, 0).iter().map(|b| String::from_utf8_lossy(&b.kind).into_owned()).collect()
+    }
+
+    /// Read every sample back through the index and compare it with what was written.
+    fn assert_samples(file: &[u8], written: &[Written]) {
+        let tracks = mp4_tracks(file);
+        assert_eq!(tracks.len(), 2);
+        for (track, id) in tracks.iter().zip(1..) {
+            let expected: Vec<_> = written.iter().filter(|s| s.track == id).collect();
+            assert_eq!(track.samples.len(), expected.len(), "track {id}");
+            for (i, (got, want)) in track.samples.iter().zip(&expected).enumerate() {
+                assert!(got == &want.data, "track {id} sample {i} at offset {} has the wrong bytes", track.offsets[i]);
+            }
+            assert_eq!(track.durations, expected.iter().map(|s| s.duration).collect::<Vec<_>>(), "track {id}");
+            assert_eq!(track.sync, expected.iter().map(|s| s.is_sync).collect::<Vec<_>>(), "track {id}");
+        }
+        assert_eq!((&tracks[0].codec, tracks[0].timescale), (b"avc1", 90_000));
+        assert_eq!((&tracks[1].codec, tracks[1].timescale), (b"mp4a", 48_000));
+    }
+
+    fn options(faststart: bool, fragmented: bool, defragment: bool) -> MuxOptions {
+        MuxOptions { faststart, fragment_duration: fragmented.then(|| Duration::from_secs(1)), defragment }
+    }
+
+    #[test]
+    fn plain() {
+        let written = samples();
+        let file = mux(options(false, false, false), &written);
+        assert_eq!(top_level(&file), ["ftyp", "mdat", "moov"]);
+        assert_samples(&file, &written);
+    }
+
+    #[test]
+    fn faststart() {
+        let written = samples();
+        let file = mux(options(true, false, false), &written);
+        assert_eq!(top_level(&file), ["ftyp", "moov", "mdat"]);
+        assert_samples(&file, &written);
+        assert_eq!(file.len(), mux(options(false, false, false), &written).len());
+    }
+
+    #[test]
+    fn fragmented() {
+        let written = samples();
+        let file = mux(options(false, true, false), &written);
+        let top = top_level(&file);
+        assert_eq!(top[..2], ["ftyp", "moov"]);
+        let fragments = &top[2..];
+        assert_eq!(fragments.len(), 8, "{top:?}");
+        assert!(fragments.chunks(2).all(|pair| pair == ["moof", "mdat"]), "{top:?}");
+        assert_samples(&file, &written);
+
+        // The empty header announces fragments; each fragment opens on a keyframe.
+        let boxes = mp4_boxes(&file, 0);
+        assert!(boxes[1].child(b"mvex").is_some());
+        for moof in boxes.iter().filter(|b| &b.kind == b"moof") {
+            let traf = moof.child(b"traf").unwrap();
+            let trun = traf.child(b"trun").unwrap().body;
+            // Version and flags, sample count, data offset, then duration, size and flags.
+            assert_eq!(u32::from_be_bytes(trun[20..24].try_into().unwrap()), sample_flags(true));
+        }
+    }
+
+    /// Length of the complete boxes at the start of `file`, a `moof` only together with its
+    /// `mdat`: what a player gets out of a file cut short.
+    fn complete_prefix(file: &[u8]) -> usize {
+        let box_len = |pos: usize| {
+            let header = file.get(pos..pos + 8)?;
+            let len = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+            (len >= 8 && pos + len <= file.len()).then_some((&header[4..], len))
+        };
+        let mut pos = 0;
+        while let Some((kind, mut len)) = box_len(pos) {
+            if kind == b"moof" {
+                let Some((_, mdat_len)) = box_len(pos + len) else { break };
+                len += mdat_len;
+            }
+            pos += len;
+        }
+        pos
+    }
+
+    #[test]
+    fn fragments_survive_a_crash() {
+        let written = samples();
+        // Never finished: the fourth second is still being collected.
+        let muxer = feed(Cursor::new(Vec::new()), options(false, true, false), &written);
+        let file = muxer.out.get_ref().clone();
+        let boxes = mp4_boxes(&file, 0);
+        let (moof, mdat) = (&boxes[boxes.len() - 2], &boxes[boxes.len() - 1]);
+        assert_eq!((&moof.kind, &mdat.kind), (b"moof", b"mdat"));
+
+        // Cut inside the last `moof`, inside its `mdat`, and right after it.
+        let cuts = [(moof.start + 20, 60), (mdat.body_start + mdat.body.len() / 2, 60), (file.len(), 90)];
+        for (cut, frames) in cuts {
+            let file = &file[..cut];
+            let complete = complete_prefix(file);
+            assert_eq!(complete, if frames == 90 { cut } else { moof.start }, "cut at {cut}");
+
+            // Whole seconds of video, and the audio that went with them, read back intact.
+            let tracks = mp4_tracks(&file[..complete]);
+            assert_eq!(tracks[0].samples.len(), frames, "cut at {cut}");
+            for (track, id) in tracks.iter().zip(1..) {
+                let expected = written.iter().filter(|s| s.track == id).map(|s| &s.data);
+                assert!(track.samples.iter().zip(expected).all(|(got, want)| got == want), "cut at {cut}");
+            }
+            assert!(!tracks[1].samples.is_empty());
+        }
+    }
+
+    #[test]
+    fn defragmenting_replaces_the_file() {
+        let written = samples();
+        let path = temp_path("defragment.mp4");
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+        let mut muxer = feed(Mp4File::new(file, path.clone()), options(true, true, true), &written);
+        let sibling = muxer.out.sibling().unwrap();
+        let sibling_path = sibling.path.clone();
+        sibling.discard();
+        assert!(!sibling_path.exists());
+
+        // Until `finish` the fragmented file is what's on disk.
+        assert_eq!(top_level(&std::fs::read(&path).unwrap())[..2], ["ftyp", "moov"]);
+        muxer.finish().unwrap();
+        let file = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(top_level(&file), ["ftyp", "moov", "mdat"]);
+        assert_samples(&file, &written);
+        assert!(!sibling_path.exists());
+    }
+
+    #[test]
+    fn defragmented() {
+        let written = samples();
+        for faststart in [false, true] {
+            let file = mux(options(faststart, true, true), &written);
+            let expected = if faststart { ["ftyp", "moov", "mdat"] } else { ["ftyp", "mdat", "moov"] };
+            assert_eq!(top_level(&file), expected);
+            assert_samples(&file, &written);
+            // Nothing of the fragmented layout is left over, inside `mdat` or after `moov`.
+            let mdat = mp4_boxes(&file, 0).into_iter().find(|b| &b.kind == b"mdat").unwrap();
+            assert_eq!(mdat.body.len(), written.iter().map(|s| s.data.len()).sum::<usize>());
+        }
+    }
+
+    /// `ctts` of a track whose samples carry `offsets`, as `(version, [(count, offset)])`.
+    fn ctts_of(offsets: &[i32]) -> Option<(u8, Vec<(u32, i32)>)> {
+        let mut track = Track::new(TrackConfig::Video {
+            codec: VideoCodec::Avc { sps: vec![0x67, 0x42, 0xc0, 0x1e], pps: vec![0x68, 0xce, 0x3c, 0x80] },
+            width: 320,
+            height: 240,
+            timescale: 90_000,
+            color: None,
+        });
+        for (i, &composition_offset) in offsets.iter().enumerate() {
+            track.record(&Sample { data: &[0; 10], duration: 3000, composition_offset, is_sync: i == 0 });
+            track.offsets.push(100 + 10 * i as u64);
+        }
+        let mut out = Vec::new();
+        write_stbl(&mut out, &track, 0);
+        let ctts = mp4_boxes(&out, 0)[0].child(b"ctts")?.body;
+        let entries = (0..u32::from_be_bytes(ctts[4..8].try_into().unwrap()) as usize)
+            .map(|i| {
+                let entry = &ctts[8 + 8 * i..16 + 8 * i];
+                (u32::from_be_bytes(entry[..4].try_into().unwrap()), i32::from_be_bytes(entry[4..].try_into().unwrap()))
+            })
+            .collect();
+        Some((ctts[0], entries))
+    }
+
+    #[test]
+    fn composition_offsets() {
+        // In order: no `ctts` at all.
+        assert_eq!(ctts_of(&[0, 0, 0]), None);
+
+        // I P B B P B B, decoded as I P B B ...: B frames shown before the P they follow,
+        // offsets all positive so version 0 does.
+        let delayed = [3000, 9000, 0, 0, 9000, 0, 0];
+        let runs = vec![(1, 3000), (1, 9000), (2, 0), (1, 9000), (2, 0)];
+        assert_eq!(ctts_of(&delayed), Some((0, runs)));
+
+        // The same stream with the delay taken out: the B frames get negative offsets, which
+        // need version 1.
+        let shifted = [0, 6000, -3000, -3000, 6000, -3000, -3000];
+        let runs = vec![(1, 0), (1, 6000), (2, -3000), (1, 6000), (2, -3000)];
+        assert_eq!(ctts_of(&shifted), Some((1, runs)));
+    }
+
+    #[test]
+    fn flac_track_decodes() {
+        // Two full 4096-sample blocks and a short last one.
+        let pcm: Vec<i16> = (0..10_000).flat_map(|i| [(i * 37 % 2000) as i16, -(i % 500) as i16]).collect();
+        let mut encoder = FlacEncoder::new(44_100, 2).unwrap();
+        let mut muxer = Mp4Muxer::new(Cursor::new(Vec::new()), options(false, false, false)).unwrap();
+        let flac = AudioCodec::Flac { stream_info: encoder.stream_info().to_vec() };
+        let track = muxer.add_track(TrackConfig::Audio { codec: flac, sample_rate: 44_100, channels: 2 }).unwrap();
+        encoder.push(&pcm);
+        while let Some((frame, len)) = encoder.next_frame(true) {
+            let sample = Sample { data: &frame, duration: len, composition_offset: 0, is_sync: true };
+            muxer.write_sample(track, sample).unwrap();
+        }
+        let file = muxer.finish().unwrap().into_inner();
+
+        let [track] = &mp4_tracks(&file)[..] else { panic!("expected one track") };
+        assert_eq!(track.timescale, 44_100);
+        assert_eq!(track.durations, [4096, 4096, 1808]);
+        assert!(decode_flac_track(track) == pcm, "FLAC track decodes differently");
+    }
+
+    #[test]
+    fn large_offsets_switch_to_co64() {
+        let mut track = Track::new(TrackConfig::Audio {
+            codec: AudioCodec::Aac { config: vec![0x11, 0x90] },
+            sample_rate: 48_000,
+            channels: 2,
+        });
+        for (i, offset) in [100u64, 300].into_iter().enumerate() {
+            track.record(&Sample { data: &[0; 200], duration: 1024, composition_offset: 0, is_sync: i == 0 });
+            track.offsets.push(offset);
+        }
+        let mut out = Vec::new();
+        let shift = 5 << 30;
+        write_stbl(&mut out, &track, shift);
+        let stbl = &mp4_boxes(&out, 0)[0];
+        assert!(stbl.child(b"stco").is_none());
+        let co64 = stbl.child(b"co64").unwrap().body;
+        assert_eq!(u32::from_be_bytes(co64[4..8].try_into().unwrap()), 2);
+        assert_eq!(u64::from_be_bytes(co64[8..16].try_into().unwrap()), 100 + shift);
+        assert_eq!(u64::from_be_bytes(co64[16..24].try_into().unwrap()), 300 + shift);
+        let stss = stbl.child(b"stss").unwrap().body;
+        assert_eq!(&stss[4..], [0, 0, 0, 1, 0, 0, 0, 1]);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::error::RecorderError;
+use crate::recording::color_convert::ColorSpace;
+use crate::recording::mkv_mux::MkvMuxer;
+use crate::recording::mp4_mux::{Mp4File, Mp4Muxer};
+use crate::state::app_state::{Container, RecordingSettings};
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub enum VideoCodec {
+    /// H.264; samples are NAL units with 4-byte big-endian length prefixes.
+    Avc { sps: Vec<u8>, pps: Vec<u8> },
+    /// AV1; `config` is the `AV1CodecConfigurationRecord` with the sequence header OBU.
+    /// Samples are temporal units without the temporal delimiter.
+    Av1 { config: Vec<u8> },
+}
+
+#[derive(Debug, Clone)]
+pub enum AudioCodec {
+    /// AAC with its AudioSpecificConfig; samples are raw access units.
+    Aac { config: Vec<u8> },
+    /// FLAC with the body of its STREAMINFO block; samples are FLAC frames.
+    Flac { stream_info: Vec<u8> },
+}
+
+#[derive(Debug, Clone)]
+pub enum TrackConfig {
+    Video {
+        codec: VideoCodec,
+        width: u32,
+        height: u32,
+        timescale: u32,
+        /// Signalled in the container when set.
+        color: Option<ColorSpace>,
+    },
+    /// Audio tracks use the sample rate as their timescale.
+    Audio { codec: AudioCodec, sample_rate: u32, channels: u16 },
+}
+
+impl TrackConfig {
+    pub fn timescale(&self) -> u32 {
+        match self {
+            TrackConfig::Video { timescale, .. } => *timescale,
+            TrackConfig::Audio { sample_rate, .. } => *sample_rate,
+        }
+    }
+}
+
+/// One coded frame. Decode times follow from the durations of the samples before it.
+#[derive(Debug, Clone, Copy)]
+pub struct Sample<'a> {
+    pub data: &'a [u8],
+    /// In the track's timescale.
+    pub duration: u32,
+    /// Presentation time minus decode time, for streams with reordered frames.
+    pub composition_offset: i32,
+    pub is_sync: bool,
+}
+
+/// MP4 layout choices; Matroska has no equivalent and ignores them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MuxOptions {
+    /// Put `moov` in front of `mdat` in a regular (or defragmented) file.
+    pub faststart: bool,
+    /// Write `moof`/`mdat` fragments of about this length behind an empty `moov` instead of
+    /// indexing everything at the end, so a crash only loses the fragment in progress.
+    pub fragment_duration: Option<Duration>,
+    /// Rewrite a fragmented file as a regular one in `finish`.
+    pub defragment: bool,
+}
+
+impl MuxOptions {
+    pub fn from_settings(settings: &RecordingSettings) -> Self {
+        Self {
+            faststart: settings.faststart,
+            fragment_duration: (settings.fragment_interval_secs > 0)
+                .then(|| Duration::from_secs(settings.fragment_interval_secs as u64)),
+            defragment: settings.defragment_on_stop,
+        }
+    }
+}
+
+/// Container writer fed with encoded samples, so encoders don't care which container the
+/// user picked.
+pub trait Muxer {
+    /// Add a track and return its id for `write_sample`. Tracks have to be added before
+    /// the first samples are flushed to disk.
+    fn add_track(&mut self, config: TrackConfig) -> Result<u32, RecorderError>;
+
+    fn write_sample(&mut self, track_id: u32, sample: Sample) -> Result<(), RecorderError>;
+
+    /// Write the index and close the file.
+    fn finish(self: Box<Self>) -> Result<(), RecorderError>;
+}
+
+/// Create `path` and a muxer for `container` writing into it.
+pub fn open_muxer(path: &Path, container: Container, options: MuxOptions) -> Result<Box<dyn Muxer>, RecorderError> {
+    let matroska = match container {
+        Container::Mp4 => false,
+        Container::Mkv | Container::Webm => true,
+        Container::Y4m | Container::Gif => {
+            return Err(RecorderError::invalid_settings(format!(
+                "{} files are written by their own backend, not a muxer",
+                container.extension().to_uppercase()
+            )));
+        }
+    };
+    // Read access too: faststart and defragmenting read media back.
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    Ok(if matroska {
+        Box::new(MkvMuxer::new(file, container)?)
+    } else {
+        Box::new(Mp4Muxer::new(Mp4File::new(file, path.to_path_buf()), options)?)
+    })
+}
+
+/// FLAC metadata as MP4's `dfLa` and Matroska's `CodecPrivate` carry it: STREAMINFO behind
+/// a block header flagging it as the last block.
+pub fn flac_metadata(stream_info: &[u8]) -> Vec<u8> {
+    let len = stream_info.len() as u32;
+    let mut metadata = vec![0x80, (len >> 16) as u8, (len >> 8) as u8, len as u8];
+    metadata.extend_from_slice(stream_info);
+    metadata
+}
+
+/// `AVCDecoderConfigurationRecord` for one SPS and PPS: the body of MP4's `avcC` box and
+/// Matroska's `CodecPrivate` for H.264.
+pub fn avc_decoder_config(sps: &[u8], pps: &[u8]) -> Vec<u8> {
+    let mut config = vec![1]; // configurationVersion
+    // Profile, compatibility and level, copied from the SPS.
+    config.extend_from_slice(sps.get(1..4).unwrap_or(&[0x42, 0, 0x1f]));
+    config.push(0xff); // 4-byte NAL lengths
+    config.push(0xe1); // one SPS
+    config.extend_from_slice(&(sps.len() as u16).to_be_bytes());
+    config.extend_from_slice(sps);
+    config.push(1); // one PPS
+    config.extend_from_slice(&(pps.len() as u16).to_be_bytes());
+    config.extend_from_slice(pps);
+    config
+}
@@ -0,0 +1,300 @@
+use crate::state::app_state::QueuePolicy;
+use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Point-in-time numbers for one pipeline stage, as reported in `RecordingStatus`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageStats {
+    pub stage: String,
+    pub frames: u64,
+    /// Items thrown away by the stage's input queue under a drop policy.
+    pub dropped: u64,
+    /// Items waiting in the stage's input queue.
+    pub queued: u64,
+    pub avg_ms: f64,
+    pub max_ms: f64,
+    pub last_ms: f64,
+}
+
+/// Lock-free counters for one stage. Latency is the time the stage spends on a single item.
+#[derive(Default)]
+pub struct StageMetrics {
+    frames: AtomicU64,
+    dropped: AtomicU64,
+    queued: AtomicU64,
+    total_us: AtomicU64,
+    max_us: AtomicU64,
+    last_us: AtomicU64,
+}
+
+impl StageMetrics {
+    pub fn record(&self, latency: Duration) {
+        let us = latency.as_micros().min(u64::MAX as u128) as u64;
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        self.total_us.fetch_add(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+        self.last_us.store(us, Ordering::Relaxed);
+    }
+
+    fn reset(&self) {
+        for counter in [
+            &self.frames,
+            &self.dropped,
+            &self.queued,
+            &self.total_us,
+            &self.max_us,
+            &self.last_us,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn snapshot(&self, stage: &str) -> StageStats {
+        let frames = self.frames.load(Ordering::Relaxed);
+        let total_us = self.total_us.load(Ordering::Relaxed);
+        StageStats {
+            stage: stage.to_string(),
+            frames,
+            dropped: self.dropped.load(Ordering::Relaxed),
+            queued: self.queued.load(Ordering::Relaxed),
+            avg_ms: if frames == 0 { 0.0 } else { total_us as f64 / frames as f64 / 1000.0 },
+            max_ms: self.max_us.load(Ordering::Relaxed) as f64 / 1000.0,
+            last_ms: self.last_us.load(Ordering::Relaxed) as f64 / 1000.0,
+        }
+    }
+}
+
+/// Metrics for every stage of the recording pipeline. `end_to_end` runs from the moment a
+/// screen frame is captured until the encoder has accepted it.
+#[derive(Default)]
+pub struct PipelineMetrics {
+    pub capture: StageMetrics,
+    pub camera: StageMetrics,
+    pub composite: StageMetrics,
+    pub encode: StageMetrics,
+    pub end_to_end: StageMetrics,
+}
+
+impl PipelineMetrics {
+    pub fn reset(&self) {
+        for stage in self.stages() {
+            stage.1.reset();
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<StageStats> {
+        self.stages()
+            .into_iter()
+            .map(|(name, metrics)| metrics.snapshot(name))
+            .collect()
+    }
+
+    fn stages(&self) -> [(&'static str, &StageMetrics); 5] {
+        [
+            ("capture", &self.capture),
+            ("camera", &self.camera),
+            ("composite", &self.composite),
+            ("encode", &self.encode),
+            ("end_to_end", &self.end_to_end),
+        ]
+    }
+}
+
+struct QueueState<T> {
+    items: VecDeque<T>,
+    closed: bool,
+}
+
+/// Bounded hand-off between two stages.
+///
+/// What happens when the queue is full is up to the `QueuePolicy`: `Block` applies
+/// backpressure to the producer, the drop policies keep the producer running and count the
+/// loss against the consuming stage's metrics. Closing the queue from either end wakes both
+/// sides, which is how a failing stage shuts down its neighbours.
+pub struct StageQueue<T> {
+    state: Mutex<QueueState<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+    policy: QueuePolicy,
+    metrics: Arc<PipelineMetrics>,
+    consumer: fn(&PipelineMetrics) -> &StageMetrics,
+}
+
+impl<T> StageQueue<T> {
+    /// `consumer` picks the metrics of the stage that pops from this queue.
+    pub fn new(
+        capacity: usize,
+        policy: QueuePolicy,
+        metrics: Arc<PipelineMetrics>,
+        consumer: fn(&PipelineMetrics) -> &StageMetrics,
+    ) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                items: VecDeque::with_capacity(capacity.max(1)),
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity: capacity.max(1),
+            policy,
+            metrics,
+            consumer,
+        }
+    }
+
+    fn stage_metrics(&self) -> &StageMetrics {
+        (self.consumer)(&self.metrics)
+    }
+
+    /// Hand `item` to the next stage. Returns `false` once the queue has been closed.
+    pub fn push(&self, item: T) -> bool {
+        let mut state = self.state.lock();
+        if state.closed {
+            return false;
+        }
+
+        if state.items.len() >= self.capacity {
+            match self.policy {
+                QueuePolicy::Block => {
+                    while state.items.len() >= self.capacity && !state.closed {
+                        self.not_full.wait(&mut state);
+                    }
+                    if state.closed {
+                        return false;
+                    }
+                }
+                QueuePolicy::DropOldest => {
+                    state.items.pop_front();
+                    self.stage_metrics().dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                QueuePolicy::DropNewest => {
+                    self.stage_metrics().dropped.fetch_add(1, Ordering::Relaxed);
+                    return true;
+                }
+            }
+        }
+
+        state.items.push_back(item);
+        self.stage_metrics()
+            .queued
+            .store(state.items.len() as u64, Ordering::Relaxed);
+        self.not_empty.notify_one();
+        true
+    }
+
+    /// Wait for the next item. Returns `None` once the queue is closed and drained.
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self.state.lock();
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                self.stage_metrics()
+                    .queued
+                    .store(state.items.len() as u64, Ordering::Relaxed);
+                self.not_full.notify_one();
+                return Some(item);
+            }
+            if state.closed {
+                return None;
+            }
+            self.not_empty.wait(&mut state);
+        }
+    }
+
+    pub fn close(&self) {
+        self.state.lock().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+}
+
+/// Single-value mailbox where the newest value always wins. Used for the camera so a slow
+/// or stalled device never holds up the screen frames.
+pub struct LatestSlot<T> {
+    value: Mutex<Option<Arc<T>>>,
+}
+
+impl<T> Default for LatestSlot<T> {
+    fn default() -> Self {
+        Self {
+            value: Mutex::new(None),
+        }
+    }
+}
+
+impl<T> LatestSlot<T> {
+    pub fn publish(&self, value: T) {
+        *self.value.lock() = Some(Arc::new(value));
+    }
+
+    pub fn latest(&self) -> Option<Arc<T>> {
+        self.value.lock().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(capacity: usize, policy: QueuePolicy) -> StageQueue<u32> {
+        StageQueue::new(capacity, policy, Arc::default(), |m| &m.encode)
+    }
+
+    fn drain(queue: &StageQueue<u32>) -> Vec<u32> {
+        queue.close();
+        std::iter::from_fn(|| queue.pop()).collect()
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_newest_items() {
+        let q = queue(2, QueuePolicy::DropOldest);
+        assert!((1..=4).all(|i| q.push(i)));
+        assert_eq!(q.metrics.encode.dropped.load(Ordering::Relaxed), 2);
+        assert_eq!(q.metrics.encode.queued.load(Ordering::Relaxed), 2);
+        assert_eq!(drain(&q), [3, 4]);
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_oldest_items() {
+        let q = queue(2, QueuePolicy::DropNewest);
+        assert!((1..=4).all(|i| q.push(i)));
+        assert_eq!(q.metrics.encode.dropped.load(Ordering::Relaxed), 2);
+        assert_eq!(drain(&q), [1, 2]);
+    }
+
+    #[test]
+    fn block_waits_for_the_consumer() {
+        let q = queue(1, QueuePolicy::Block);
+        std::thread::scope(|s| {
+            let producer = s.spawn(|| (1..=3).all(|i| q.push(i)));
+            let mut popped = Vec::new();
+            while popped.len() < 3 {
+                // Never more than the capacity waiting.
+                assert!(q.metrics.encode.queued.load(Ordering::Relaxed) <= 1);
+                popped.extend(q.pop());
+            }
+            assert!(producer.join().unwrap());
+            assert_eq!(popped, [1, 2, 3]);
+        });
+        assert_eq!(q.metrics.encode.dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn close_wakes_a_blocked_producer_and_drains() {
+        let q = queue(1, QueuePolicy::Block);
+        assert!(q.push(1));
+        std::thread::scope(|s| {
+            let producer = s.spawn(|| q.push(2));
+            std::thread::sleep(Duration::from_millis(20));
+            q.close();
+            assert!(!producer.join().unwrap());
+        });
+        assert!(!q.push(3));
+        assert_eq!(q.pop(), Some(1));
+        assert_eq!(q.pop(), None);
+    }
+}
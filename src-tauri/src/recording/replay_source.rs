@@ -0,0 +1,759 @@
+use crate::error::RecorderError;
+use crate::recording::frame_pool::{FrameBuffer, FrameData, FramePool};
+use crate::recording::frame_source::{ContentRect, Frame, FrameSource};
+use crate::recording::scaler::{ScaleOptions, Scaler};
+use crate::state::app_state::{ColorMatrix, ReplaySettings};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Sequential access to a recorded clip, one BGRA frame at a time.
+trait ReplayReader {
+    /// Native frame size.
+    fn dimensions(&self) -> (u32, u32);
+
+    /// Native frame rate.
+    fn fps(&self) -> f64;
+
+    /// Decode the next frame, or `None` at the end of the clip.
+    fn next_frame(&mut self) -> Result<Option<FrameBuffer>, RecorderError>;
+
+    /// Advance past the next frame without decoding it. Returns `false` at the end of the clip.
+    fn skip_frame(&mut self) -> Result<bool, RecorderError>;
+
+    fn rewind(&mut self) -> Result<(), RecorderError>;
+}
+
+fn replay_err(path: &Path, e: impl std::fmt::Display) -> RecorderError {
+    RecorderError::file_error(format!("Replay source {}: {e}", path.to_string_lossy()))
+}
+
+/// Feeds frames from disk instead of a live device.
+///
+/// Understands a `.y4m` file, a directory of `.png` frames (sorted by file name) or anything
+/// else as a headerless BGRA dump whose size comes from `ReplaySettings`.
+///
+/// Without `realtime`, every `capture_frame` call returns the next frame of the clip, so
+/// runs are reproducible frame-for-frame. With `realtime`, the clip plays at its own frame
+/// rate against the wall clock and frames are skipped or repeated to match the caller.
+pub struct ReplaySource {
+    reader: Box<dyn ReplayReader>,
+    src_w: u32,
+    src_h: u32,
+    target_w: u32,
+    target_h: u32,
+    scaler: Scaler,
+    looping: bool,
+    realtime: bool,
+    /// Index of the next frame `reader` will yield.
+    position: u64,
+    current: Option<FrameData>,
+    ended: bool,
+    loop_started: Instant,
+    started_at: Instant,
+    frame_number: u64,
+}
+
+impl ReplaySource {
+    /// Open a clip. Frames are scaled to `target` as `scale` says, or kept at their native
+    /// size if `None`.
+    pub fn new(
+        settings: &ReplaySettings,
+        target: Option<(u32, u32)>,
+        scale: ScaleOptions,
+    ) -> Result<Self, RecorderError> {
+        let path = PathBuf::from(settings.path.trim());
+        if settings.path.trim().is_empty() {
+            return Err(RecorderError::invalid_settings("Replay source path is empty"));
+        }
+
+        let is_y4m = path
+            .extension()
+            .map(|e| e.eq_ignore_ascii_case("y4m"))
+            .unwrap_or(false);
+
+        let reader: Box<dyn ReplayReader> = if path.is_dir() {
+            Box::new(PngSequenceReader::open(&path, settings.fps, scale)?)
+        } else if is_y4m {
+            Box::new(Y4mReader::open(&path)?)
+        } else {
+            Box::new(RawBgraReader::open(&path, settings.width, settings.height, settings.fps)?)
+        };
+
+        let (src_w, src_h) = reader.dimensions();
+        let (target_w, target_h) = target.unwrap_or((src_w, src_h));
+        if target_w == 0 || target_h == 0 {
+            return Err(RecorderError::invalid_settings("Invalid target resolution"));
+        }
+
+        Ok(Self {
+            reader,
+            src_w,
+            src_h,
+            target_w,
+            target_h,
+            scaler: Scaler::new(scale),
+            looping: settings.looping,
+            realtime: settings.realtime,
+            position: 0,
+            current: None,
+            ended: false,
+            loop_started: Instant::now(),
+            started_at: Instant::now(),
+            frame_number: 0,
+        })
+    }
+
+    fn wanted_index(&self) -> u64 {
+        if self.realtime {
+            (self.loop_started.elapsed().as_secs_f64() * self.reader.fps()) as u64
+        } else {
+            self.position
+        }
+    }
+
+    /// Called when the reader runs dry. Returns `true` if playback restarted.
+    fn handle_end(&mut self) -> Result<bool, RecorderError> {
+        if !self.looping || self.position == 0 {
+            self.ended = true;
+            return Ok(false);
+        }
+
+        self.reader.rewind()?;
+        self.position = 0;
+        self.loop_started = Instant::now();
+        Ok(true)
+    }
+
+    fn advance(&mut self) -> Result<(), RecorderError> {
+        if self.ended {
+            return Ok(());
+        }
+
+        let mut wanted = self.wanted_index();
+        if self.current.is_some() && wanted < self.position {
+            // Still inside the current frame's display time.
+            return Ok(());
+        }
+
+        loop {
+            while self.position < wanted {
+                if !self.reader.skip_frame()? {
+                    break;
+                }
+                self.position += 1;
+            }
+
+            if self.position >= wanted {
+                if let Some(frame) = self.reader.next_frame()? {
+                    self.position += 1;
+                    let frame = if (self.src_w, self.src_h) == (self.target_w, self.target_h) {
+                        frame
+                    } else {
+                        self.scaler.scale(&frame, self.src_w, self.src_h, self.target_w, self.target_h)
+                    };
+                    self.current = Some(frame.into());
+                    return Ok(());
+                }
+            }
+
+            if !self.handle_end()? {
+                return Ok(());
+            }
+            wanted = self.wanted_index();
+        }
+    }
+}
+
+impl FrameSource for ReplaySource {
+    fn capture_frame(&mut self) -> Result<Frame, RecorderError> {
+        self.frame_number += 1;
+        self.advance()?;
+
+        let data = self
+            .current
+            .clone()
+            .ok_or_else(|| RecorderError::file_error("Replay source contains no frames"))?;
+
+        // Keeps counting across loops, unlike `position`.
+        let timestamp = if self.realtime {
+            self.started_at.elapsed().as_millis() as u64
+        } else {
+            ((self.frame_number - 1) as f64 * 1000.0 / self.reader.fps()) as u64
+        };
+
+        Ok(Frame {
+            data,
+            width: self.target_w,
+            height: self.target_h,
+            timestamp,
+            frame_number: self.frame_number,
+        })
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.target_w, self.target_h)
+    }
+
+    fn stop(&mut self) {}
+
+    fn content_rect(&self) -> Option<ContentRect> {
+        Some(self.scaler.content_rect(self.src_w, self.src_h, self.target_w, self.target_h))
+    }
+}
+
+/// Headerless BGRA frames back to back.
+struct RawBgraReader {
+    path: PathBuf,
+    file: BufReader<File>,
+    width: u32,
+    height: u32,
+    fps: f64,
+}
+
+impl RawBgraReader {
+    fn open(path: &Path, width: u32, height: u32, fps: u32) -> Result<Self, RecorderError> {
+        if width == 0 || height == 0 {
+            return Err(RecorderError::invalid_settings(
+                "Raw BGRA replay needs width and height",
+            ));
+        }
+
+        let file = File::open(path).map_err(|e| replay_err(path, e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: BufReader::new(file),
+            width,
+            height,
+            fps: fps.max(1) as f64,
+        })
+    }
+
+    fn frame_len(&self) -> usize {
+        self.width as usize * self.height as usize * 4
+    }
+}
+
+impl ReplayReader for RawBgraReader {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn fps(&self) -> f64 {
+        self.fps
+    }
+
+    fn next_frame(&mut self) -> Result<Option<FrameBuffer>, RecorderError> {
+        let mut buf = FramePool::shared().take(self.frame_len());
+        match self.file.read_exact(&mut buf) {
+            Ok(()) => Ok(Some(buf)),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(replay_err(&self.path, e)),
+        }
+    }
+
+    fn skip_frame(&mut self) -> Result<bool, RecorderError> {
+        let len = self.frame_len() as u64;
+        let pos = self.file.stream_position().map_err(|e| replay_err(&self.path, e))?;
+        let end = self.file.get_ref().metadata().map_err(|e| replay_err(&self.path, e))?.len();
+        if pos + len > end {
+            return Ok(false);
+        }
+        self.file
+            .seek_relative(len as i64)
+            .map_err(|e| replay_err(&self.path, e))?;
+        Ok(true)
+    }
+
+    fn rewind(&mut self) -> Result<(), RecorderError> {
+        self.file.rewind().map_err(|e| replay_err(&self.path, e))
+    }
+}
+
+/// A directory of PNG files, played in file name order.
+struct PngSequenceReader {
+    files: Vec<PathBuf>,
+    index: usize,
+    width: u32,
+    height: u32,
+    fps: f64,
+    /// Brings stray frames of a different size to the size of the first one.
+    scaler: Scaler,
+}
+
+impl PngSequenceReader {
+    fn open(dir: &Path, fps: u32, scale: ScaleOptions) -> Result<Self, RecorderError> {
+        let mut files: Vec<PathBuf> = fs::read_dir(dir)
+            .map_err(|e| replay_err(dir, e))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| {
+                p.extension()
+                    .map(|e| e.eq_ignore_ascii_case("png"))
+                    .unwrap_or(false)
+            })
+            .collect();
+        files.sort();
+
+        let first = files
+            .first()
+            .ok_or_else(|| replay_err(dir, "no .png files found"))?;
+        let (_, width, height) = decode_png(first)?;
+
+        Ok(Self {
+            files,
+            index: 0,
+            width,
+            height,
+            fps: fps.max(1) as f64,
+            scaler: Scaler::new(scale),
+        })
+    }
+}
+
+impl ReplayReader for PngSequenceReader {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn fps(&self) -> f64 {
+        self.fps
+    }
+
+    fn next_frame(&mut self) -> Result<Option<FrameBuffer>, RecorderError> {
+        let Some(path) = self.files.get(self.index) else {
+            return Ok(None);
+        };
+        self.index += 1;
+
+        let (bgra, w, h) = decode_png(path)?;
+        if (w, h) == (self.width, self.height) {
+            Ok(Some(FrameBuffer::from(bgra)))
+        } else {
+            Ok(Some(self.scaler.scale(&bgra, w, h, self.width, self.height)))
+        }
+    }
+
+    fn skip_frame(&mut self) -> Result<bool, RecorderError> {
+        if self.index >= self.files.len() {
+            return Ok(false);
+        }
+        self.index += 1;
+        Ok(true)
+    }
+
+    fn rewind(&mut self) -> Result<(), RecorderError> {
+        self.index = 0;
+        Ok(())
+    }
+}
+
+fn decode_png(path: &Path) -> Result<(Vec<u8>, u32, u32), RecorderError> {
+    let file = File::open(path).map_err(|e| replay_err(path, e))?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| replay_err(path, e))?;
+
+    let mut buf = vec![0u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(|e| replay_err(path, e))?;
+    let pixels = &buf[..info.buffer_size()];
+
+    let mut bgra = Vec::with_capacity(info.width as usize * info.height as usize * 4);
+    match info.color_type {
+        png::ColorType::Rgba => {
+            for px in pixels.chunks_exact(4) {
+                bgra.extend_from_slice(&[px[2], px[1], px[0], px[3]]);
+            }
+        }
+        png::ColorType::Rgb => {
+            for px in pixels.chunks_exact(3) {
+                bgra.extend_from_slice(&[px[2], px[1], px[0], 255]);
+            }
+        }
+        png::ColorType::GrayscaleAlpha => {
+            for px in pixels.chunks_exact(2) {
+                bgra.extend_from_slice(&[px[0], px[0], px[0], px[1]]);
+            }
+        }
+        png::ColorType::Grayscale => {
+            for &v in pixels {
+                bgra.extend_from_slice(&[v, v, v, 255]);
+            }
+        }
+        png::ColorType::Indexed => {
+            return Err(replay_err(path, "indexed PNG was not expanded"));
+        }
+    }
+
+    Ok((bgra, info.width, info.height))
+}
+
+/// 16.16 fixed-point weights turning 8-bit YUV back into RGB.
+#[derive(Debug, Clone, Copy)]
+struct YuvWeights {
+    y: i32,
+    y_offset: i32,
+    r_v: i32,
+    g_u: i32,
+    g_v: i32,
+    b_u: i32,
+}
+
+impl YuvWeights {
+    fn new(matrix: ColorMatrix, full_range: bool) -> Self {
+        let (kr, kb) = match matrix {
+            ColorMatrix::Bt601 => (0.299, 0.114),
+            ColorMatrix::Bt709 => (0.2126, 0.0722),
+        };
+        let kg = 1.0 - kr - kb;
+        let (luma_scale, chroma_scale, y_offset) = if full_range {
+            (1.0, 1.0, 0)
+        } else {
+            (255.0 / 219.0, 255.0 / 224.0, 16)
+        };
+        let fixed = |v: f64| (v * 65536.0).round() as i32;
+        Self {
+            y: fixed(luma_scale),
+            y_offset,
+            r_v: fixed(2.0 * (1.0 - kr) * chroma_scale),
+            g_u: fixed(-2.0 * (1.0 - kb) * kb / kg * chroma_scale),
+            g_v: fixed(-2.0 * (1.0 - kr) * kr / kg * chroma_scale),
+            b_u: fixed(2.0 * (1.0 - kb) * chroma_scale),
+        }
+    }
+}
+
+/// YUV4MPEG2 with 8-bit 4:2:0, 4:2:2, 4:4:4 or mono planes.
+///
+/// The matrix comes from an `XCOLORMATRIX=BT601|BT709` tag and the range from
+/// `XCOLORRANGE=LIMITED|FULL`; untagged files are taken as limited-range BT.601, which is
+/// what most tools write.
+struct Y4mReader {
+    path: PathBuf,
+    file: BufReader<File>,
+    data_start: u64,
+    width: u32,
+    height: u32,
+    fps: f64,
+    /// Horizontal/vertical chroma subsampling; `None` for mono.
+    chroma: Option<(u32, u32)>,
+    weights: YuvWeights,
+}
+
+impl Y4mReader {
+    fn open(path: &Path) -> Result<Self, RecorderError> {
+        let file = File::open(path).map_err(|e| replay_err(path, e))?;
+        let mut file = BufReader::new(file);
+
+        let mut header = String::new();
+        file.read_line(&mut header).map_err(|e| replay_err(path, e))?;
+        let mut tokens = header.split_ascii_whitespace();
+        if tokens.next() != Some("YUV4MPEG2") {
+            return Err(replay_err(path, "not a YUV4MPEG2 file"));
+        }
+
+        let mut width = 0u32;
+        let mut height = 0u32;
+        let mut fps = 25.0f64;
+        let mut chroma = Some((2, 2));
+        let mut full_range = false;
+        let mut matrix = ColorMatrix::Bt601;
+        for token in tokens {
+            let Some((tag, value)) = token.split_at_checked(1) else {
+                return Err(replay_err(path, "malformed Y4M header"));
+            };
+            match tag {
+                "W" => width = value.parse().unwrap_or(0),
+                "H" => height = value.parse().unwrap_or(0),
+                "F" => {
+                    if let Some((n, d)) = value.split_once(':') {
+                        let n: f64 = n.parse().unwrap_or(0.0);
+                        let d: f64 = d.parse().unwrap_or(0.0);
+                        if n > 0.0 && d > 0.0 {
+                            fps = n / d;
+                        }
+                    }
+                }
+                "C" => {
+                    chroma = match value {
+                        "420" | "420jpeg" | "420paldv" | "420mpeg2" => Some((2, 2)),
+                        "422" => Some((2, 1)),
+                        "444" => Some((1, 1)),
+                        "mono" => None,
+                        other => {
+                            return Err(replay_err(path, format!("unsupported Y4M colorspace C{other}")))
+                        }
+                    }
+                }
+                "X" => match value.split_once('=') {
+                    Some((key, v)) if key.eq_ignore_ascii_case("COLORRANGE") => {
+                        full_range = v.eq_ignore_ascii_case("FULL");
+                    }
+                    Some((key, v)) if key.eq_ignore_ascii_case("COLORMATRIX") => {
+                        matrix = match v.to_ascii_uppercase().as_str() {
+                            "BT601" => ColorMatrix::Bt601,
+                            "BT709" => ColorMatrix::Bt709,
+                            other => {
+                                return Err(replay_err(path, format!("unsupported Y4M color matrix {other}")))
+                            }
+                        };
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        if width == 0 || height == 0 {
+            return Err(replay_err(path, "missing Y4M frame size"));
+        }
+
+        let data_start = file.stream_position().map_err(|e| replay_err(path, e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            data_start,
+            width,
+            height,
+            fps,
+            chroma,
+            weights: YuvWeights::new(matrix, full_range),
+        })
+    }
+
+    fn chroma_size(&self) -> (usize, usize) {
+        match self.chroma {
+            Some((sx, sy)) => (self.width.div_ceil(sx) as usize, self.height.div_ceil(sy) as usize),
+            None => (0, 0),
+        }
+    }
+
+    fn frame_len(&self) -> usize {
+        let (cw, ch) = self.chroma_size();
+        self.width as usize * self.height as usize + 2 * cw * ch
+    }
+
+    /// Consume a `FRAME` header line. Returns `false` on a clean end of file.
+    fn read_frame_header(&mut self) -> Result<bool, RecorderError> {
+        let mut line = String::new();
+        let n = self
+            .file
+            .read_line(&mut line)
+            .map_err(|e| replay_err(&self.path, e))?;
+        if n == 0 {
+            return Ok(false);
+        }
+        if !line.starts_with("FRAME") {
+            return Err(replay_err(&self.path, "corrupt Y4M frame header"));
+        }
+        Ok(true)
+    }
+
+    fn yuv_to_bgra(&self, planes: &[u8]) -> FrameBuffer {
+        let w = self.width as usize;
+        let h = self.height as usize;
+        let (cw, ch) = self.chroma_size();
+        let (sx, sy) = self.chroma.map(|(x, y)| (x as usize, y as usize)).unwrap_or((1, 1));
+        let (y_plane, rest) = planes.split_at(w * h);
+        let (u_plane, v_plane) = rest.split_at(cw * ch);
+
+        let k = self.weights;
+
+        let mut out = FramePool::shared().take(w * h * 4);
+        for row in 0..h {
+            for col in 0..w {
+                let y = y_plane[row * w + col] as i32;
+                let (u, v) = if self.chroma.is_some() {
+                    let ci = (row / sy) * cw + col / sx;
+                    (u_plane[ci] as i32 - 128, v_plane[ci] as i32 - 128)
+                } else {
+                    (0, 0)
+                };
+
+                let c = (y - k.y_offset) * k.y;
+                let (r, g, b) = (k.r_v * v, k.g_u * u + k.g_v * v, k.b_u * u);
+
+                let d = (row * w + col) * 4;
+                out[d] = ((c + b + 32768) >> 16).clamp(0, 255) as u8;
+                out[d + 1] = ((c + g + 32768) >> 16).clamp(0, 255) as u8;
+                out[d + 2] = ((c + r + 32768) >> 16).clamp(0, 255) as u8;
+                out[d + 3] = 255;
+            }
+        }
+        out
+    }
+}
+
+impl ReplayReader for Y4mReader {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn fps(&self) -> f64 {
+        self.fps
+    }
+
+    fn next_frame(&mut self) -> Result<Option<FrameBuffer>, RecorderError> {
+        if !self.read_frame_header()? {
+            return Ok(None);
+        }
+
+        let mut planes = FramePool::shared().take(self.frame_len());
+        match self.file.read_exact(&mut planes) {
+            Ok(()) => Ok(Some(self.yuv_to_bgra(&planes))),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(replay_err(&self.path, e)),
+        }
+    }
+
+    fn skip_frame(&mut self) -> Result<bool, RecorderError> {
+        if !self.read_frame_header()? {
+            return Ok(false);
+        }
+        let len = self.frame_len() as i64;
+        self.file
+            .seek_relative(len)
+            .map_err(|e| replay_err(&self.path, e))?;
+        Ok(true)
+    }
+
+    fn rewind(&mut self) -> Result<(), RecorderError> {
+        self.file
+            .seek(SeekFrom::Start(self.data_start))
+            .map_err(|e| replay_err(&self.path, e))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::test_util::temp_path;
+    use crate::state::app_state::ScaleFilter;
+
+    fn open(path: &Path, width: u32, height: u32, looping: bool) -> ReplaySource {
+        let settings = ReplaySettings {
+            path: path.to_string_lossy().into_owned(),
+            width,
+            height,
+            fps: 10,
+            looping,
+            realtime: false,
+        };
+        ReplaySource::new(&settings, None, ScaleOptions::stretch(ScaleFilter::Nearest)).unwrap()
+    }
+
+    /// First pixel of each of the next `n` frames.
+    fn first_pixels(source: &mut ReplaySource, n: usize) -> Vec<[u8; 4]> {
+        (0..n)
+            .map(|_| source.capture_frame().unwrap().data[..4].try_into().unwrap())
+            .collect()
+    }
+
+    /// 4x2 4:4:4 clip of solid frames.
+    fn write_y4m(path: &Path, tags: &str, frames: &[[u8; 3]]) {
+        let mut data = format!("YUV4MPEG2 W4 H2 F10:1 Ip A1:1 C444 {tags}\n").into_bytes();
+        for yuv in frames {
+            data.extend_from_slice(b"FRAME\n");
+            for plane in yuv {
+                data.extend_from_slice(&[*plane; 8]);
+            }
+        }
+        fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn replays_y4m_and_loops() {
+        let path = temp_path("replay.y4m");
+        write_y4m(&path, "", &[[16, 128, 128], [126, 128, 128], [235, 128, 128]]);
+
+        let mut source = open(&path, 0, 0, true);
+        assert_eq!(source.dimensions(), (4, 2));
+        let grey = |v: u8| [v, v, v, 255];
+        assert_eq!(
+            first_pixels(&mut source, 7),
+            [grey(0), grey(128), grey(255), grey(0), grey(128), grey(255), grey(0)]
+        );
+        let frame = source.capture_frame().unwrap();
+        assert_eq!((frame.frame_number, frame.timestamp), (8, 700));
+        assert!(frame.data.chunks_exact(4).all(|px| px == grey(128)));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn y4m_honours_the_color_matrix_tag() {
+        // Pure red, limited range: BT.709 and BT.601 encodings.
+        let cases = [
+            ("XCOLORMATRIX=BT709 XCOLORRANGE=LIMITED", [63, 102, 240]),
+            ("XCOLORMATRIX=BT601", [81, 90, 240]),
+            ("", [81, 90, 240]),
+            ("XCOLORMATRIX=BT709 XCOLORRANGE=FULL", [54, 99, 255]),
+        ];
+        for (i, (tags, yuv)) in cases.into_iter().enumerate() {
+            let path = temp_path(&format!("matrix{i}.y4m"));
+            write_y4m(&path, tags, &[yuv]);
+            let [b, g, r, a] = first_pixels(&mut open(&path, 0, 0, false), 1)[0];
+            assert!(r >= 252 && g <= 3 && b <= 3 && a == 255, "{tags:?} decoded as {:?}", [b, g, r]);
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_bad_y4m_headers() {
+        let headers = ["YUV4MPEG2 W4 H2 \u{e9}4\n", "YUV4MPEG2 W4 H2 XCOLORMATRIX=BT2020\n", "YUV4MPEG2 H2\n"];
+        for (i, header) in headers.into_iter().enumerate() {
+            let path = temp_path(&format!("bad{i}.y4m"));
+            fs::write(&path, header).unwrap();
+            let settings = ReplaySettings { path: path.to_string_lossy().into_owned(), ..Default::default() };
+            let result = ReplaySource::new(&settings, None, ScaleOptions::stretch(ScaleFilter::Nearest));
+            assert!(result.is_err(), "{header:?} was accepted");
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn replays_a_png_directory_in_name_order() {
+        let dir = temp_path("replay-png");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        // RGBA, written out of order.
+        let frames = [("002.png", [0, 0, 255, 255]), ("000.png", [255, 0, 0, 255]), ("001.png", [0, 255, 0, 128])];
+        for (name, rgba) in frames {
+            let file = File::create(dir.join(name)).unwrap();
+            let mut encoder = png::Encoder::new(file, 3, 2);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.write_header().unwrap().write_image_data(&rgba.repeat(6)).unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "not a frame").unwrap();
+
+        let mut source = open(&dir, 0, 0, false);
+        assert_eq!(source.dimensions(), (3, 2));
+        let (red, green, blue) = ([0, 0, 255, 255], [0, 255, 0, 128], [255, 0, 0, 255]);
+        // Holds the last frame once the clip ends.
+        assert_eq!(first_pixels(&mut source, 5), [red, green, blue, blue, blue]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replays_raw_bgra() {
+        let path = temp_path("replay.bgra");
+        let mut data = Vec::new();
+        for v in [10u8, 20] {
+            data.extend((0..2 * 2).flat_map(|i| [v, i, 0, 255]));
+        }
+        // A truncated trailing frame is ignored.
+        data.extend_from_slice(&[1, 2, 3]);
+        fs::write(&path, data).unwrap();
+
+        let mut source = open(&path, 2, 2, true);
+        let (a, b) = ([10, 0, 0, 255], [20, 0, 0, 255]);
+        assert_eq!(first_pixels(&mut source, 5), [a, b, a, b, a]);
+        let frame = source.capture_frame().unwrap();
+        assert_eq!(&frame.data[12..16], [20, 3, 0, 255]);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
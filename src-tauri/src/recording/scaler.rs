@@ -0,0 +1,792 @@
+use crate::error::RecorderError;
+use crate::recording::frame_pool::{FrameBuffer, FramePool};
+use crate::recording::frame_source::ContentRect;
+use crate::state::app_state::{FitMode, RecordingSettings, ScaleFilter};
+use crate::utils::color::parse_hex_color;
+use std::ops::Range;
+
+/// Fractional bits of the fixed-point filter weights. 14 keeps every weight (Lanczos lobes
+/// included) inside an `i16`, which is what the SIMD multiply-add wants.
+const PRECISION: u32 = 14;
+const ONE: i32 = 1 << PRECISION;
+const ROUND: i32 = 1 << (PRECISION - 1);
+
+impl ScaleFilter {
+    /// Filter radius in source pixels at 1:1 scale.
+    fn support(self) -> f64 {
+        match self {
+            ScaleFilter::Nearest => 0.0,
+            ScaleFilter::Bilinear => 1.0,
+            ScaleFilter::Bicubic => 2.0,
+            ScaleFilter::Lanczos => 3.0,
+        }
+    }
+
+    fn weight(self, x: f64) -> f64 {
+        let x = x.abs();
+        match self {
+            ScaleFilter::Nearest => 0.0,
+            ScaleFilter::Bilinear => (1.0 - x).max(0.0),
+            // Catmull-Rom (a = -0.5): sharp without the ringing of larger `a`.
+            ScaleFilter::Bicubic => {
+                const A: f64 = -0.5;
+                if x < 1.0 {
+                    ((A + 2.0) * x - (A + 3.0)) * x * x + 1.0
+                } else if x < 2.0 {
+                    (((x - 5.0) * x + 8.0) * x - 4.0) * A
+                } else {
+                    0.0
+                }
+            }
+            ScaleFilter::Lanczos => {
+                if x < 3.0 {
+                    sinc(x) * sinc(x / 3.0)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let x = x * std::f64::consts::PI;
+        x.sin() / x
+    }
+}
+
+/// Fixed-point taps for every output pixel along one axis.
+struct Weights {
+    /// First source pixel and tap count per output pixel.
+    bounds: Vec<(usize, usize)>,
+    /// `taps` coefficients per output pixel, zero-padded; each row sums to `ONE`.
+    coeffs: Vec<i16>,
+    taps: usize,
+}
+
+impl Weights {
+    fn new(filter: ScaleFilter, in_size: u32, out_size: u32) -> Self {
+        let scale = in_size as f64 / out_size as f64;
+        // When shrinking, widen the filter so every source pixel contributes (no aliasing).
+        let filter_scale = scale.max(1.0);
+        let support = filter.support() * filter_scale;
+        let taps = (support.ceil() as usize * 2 + 1).min(in_size as usize);
+
+        let mut bounds = Vec::with_capacity(out_size as usize);
+        let mut coeffs = vec![0i16; out_size as usize * taps];
+        let mut row = Vec::with_capacity(taps);
+
+        for out in 0..out_size as usize {
+            let center = (out as f64 + 0.5) * scale;
+            let start = ((center - support + 0.5).floor().max(0.0) as usize).min(in_size as usize - 1);
+            let end = ((center + support + 0.5).floor() as usize).clamp(start + 1, in_size as usize);
+            let end = end.min(start + taps);
+
+            row.clear();
+            row.extend((start..end).map(|i| filter.weight((i as f64 - center + 0.5) / filter_scale)));
+            let total: f64 = row.iter().sum();
+            if total.abs() < f64::EPSILON {
+                // Degenerate window; fall back to the nearest pixel.
+                row.iter_mut().for_each(|w| *w = 0.0);
+                let nearest = (center as usize).clamp(start, end - 1);
+                row[nearest - start] = 1.0;
+            }
+            let total: f64 = row.iter().sum();
+
+            let dst = &mut coeffs[out * taps..out * taps + row.len()];
+            let mut sum = 0;
+            let mut peak = 0;
+            for (i, w) in row.iter().enumerate() {
+                dst[i] = (w / total * ONE as f64).round() as i16;
+                sum += dst[i] as i32;
+                if dst[i] > dst[peak] {
+                    peak = i;
+                }
+            }
+            // Rounding error goes to the largest tap so flat areas come out unchanged.
+            dst[peak] += (ONE - sum) as i16;
+
+            bounds.push((start, row.len()));
+        }
+
+        Self { bounds, coeffs, taps }
+    }
+
+    fn taps_for(&self, out: usize) -> (usize, &[i16]) {
+        let (start, len) = self.bounds[out];
+        (start, &self.coeffs[out * self.taps..out * self.taps + len])
+    }
+}
+
+/// Weights for one source size -> destination size pair.
+struct Plan {
+    sizes: (usize, usize, usize, usize),
+    horiz: Weights,
+    vert: Weights,
+}
+
+/// A `width` x `height` pixel window into a BGRA buffer with `stride` bytes per row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct View {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    stride: usize,
+}
+
+impl View {
+    fn full(width: u32, height: u32) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width: width as usize,
+            height: height as usize,
+            stride: width as usize * 4,
+        }
+    }
+
+    /// Byte range of row `y` of the window.
+    fn row(&self, y: usize) -> Range<usize> {
+        let start = (self.y + y) * self.stride + self.x * 4;
+        start..start + self.width * 4
+    }
+}
+
+/// Where a source lands in the output under a `FitMode`.
+struct Layout {
+    /// Part of the source that is shown.
+    crop: View,
+    /// Part of the output it is drawn into; the rest is bars.
+    out: View,
+    content: ContentRect,
+}
+
+impl FitMode {
+    fn layout(self, src_w: u32, src_h: u32, dst_w: u32, dst_h: u32) -> Layout {
+        let (sw, sh, dw, dh) = (src_w as u64, src_h as u64, dst_w as u64, dst_h as u64);
+        let full = ContentRect {
+            x: 0,
+            y: 0,
+            width: dst_w,
+            height: dst_h,
+        };
+        let mut crop = View::full(src_w, src_h);
+        let mut out = View::full(dst_w, dst_h);
+        let wider = sw * dh > dw * sh;
+
+        let content = match self {
+            FitMode::Stretch => full,
+            FitMode::Fit => {
+                if wider {
+                    out.height = ((dw * sh + sw / 2) / sw).clamp(1, dh) as usize;
+                    out.y = (dst_h as usize - out.height) / 2;
+                } else {
+                    out.width = ((dh * sw + sh / 2) / sh).clamp(1, dw) as usize;
+                    out.x = (dst_w as usize - out.width) / 2;
+                }
+                ContentRect {
+                    x: out.x as i32,
+                    y: out.y as i32,
+                    width: out.width as u32,
+                    height: out.height as u32,
+                }
+            }
+            FitMode::Fill => {
+                // The whole source scaled to cover the output, centred; only `crop` is visible.
+                if wider {
+                    crop.width = ((sh * dw + dh / 2) / dh).clamp(1, sw) as usize;
+                    crop.x = (src_w as usize - crop.width) / 2;
+                    ContentRect {
+                        x: -((crop.x as u64 * dh / sh) as i32),
+                        y: 0,
+                        width: (sw * dh / sh) as u32,
+                        height: dst_h,
+                    }
+                } else {
+                    crop.height = ((sw * dh + dw / 2) / dw).clamp(1, sh) as usize;
+                    crop.y = (src_h as usize - crop.height) / 2;
+                    ContentRect {
+                        x: 0,
+                        y: -((crop.y as u64 * dw / sw) as i32),
+                        width: dst_w,
+                        height: (sh * dw / sw) as u32,
+                    }
+                }
+            }
+        };
+
+        Layout { crop, out, content }
+    }
+}
+
+/// Filter, fit mode and bar color for resizing a source into an output frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScaleOptions {
+    pub filter: ScaleFilter,
+    pub fit: FitMode,
+    /// BGRA.
+    pub bar_color: [u8; 4],
+}
+
+impl ScaleOptions {
+    pub fn from_settings(settings: &RecordingSettings) -> Result<Self, RecorderError> {
+        let [b, g, r] = parse_hex_color(&settings.bar_color)?;
+        Ok(Self {
+            filter: settings.scale_filter,
+            fit: settings.fit_mode,
+            bar_color: [b, g, r, 255],
+        })
+    }
+
+    /// Plain resize to the exact output size, for callers that already kept the aspect ratio.
+    pub fn stretch(filter: ScaleFilter) -> Self {
+        Self {
+            filter,
+            fit: FitMode::Stretch,
+            bar_color: [0, 0, 0, 255],
+        }
+    }
+
+    /// Where a `src_w` x `src_h` source ends up inside a `dst_w` x `dst_h` output.
+    pub fn content_rect(&self, src_w: u32, src_h: u32, dst_w: u32, dst_h: u32) -> ContentRect {
+        if src_w == 0 || src_h == 0 || dst_w == 0 || dst_h == 0 {
+            return ContentRect {
+                x: 0,
+                y: 0,
+                width: dst_w,
+                height: dst_h,
+            };
+        }
+        self.fit.layout(src_w, src_h, dst_w, dst_h).content
+    }
+}
+
+/// Resizes BGRA frames with a separable filter: a horizontal pass into an intermediate
+/// buffer, then a vertical pass into the destination.
+///
+/// Coefficients are cached for the last size pair, so keep one `Scaler` per stream rather
+/// than one per frame. The inner loops use SSE2 on x86_64 and plain Rust elsewhere.
+pub struct Scaler {
+    options: ScaleOptions,
+    plan: Option<Plan>,
+    /// Horizontal pass output, reused across frames.
+    tmp: Vec<u8>,
+}
+
+impl Scaler {
+    pub fn new(options: ScaleOptions) -> Self {
+        Self {
+            options,
+            plan: None,
+            tmp: Vec::new(),
+        }
+    }
+
+    /// See `ScaleOptions::content_rect`.
+    pub fn content_rect(&self, src_w: u32, src_h: u32, dst_w: u32, dst_h: u32) -> ContentRect {
+        self.options.content_rect(src_w, src_h, dst_w, dst_h)
+    }
+
+    /// Scale into a buffer from the shared `FramePool`.
+    pub fn scale(&mut self, src: &[u8], src_w: u32, src_h: u32, dst_w: u32, dst_h: u32) -> FrameBuffer {
+        let mut out = FramePool::shared().take(dst_w as usize * dst_h as usize * 4);
+        self.scale_into(src, src_w, src_h, &mut out, dst_w, dst_h);
+        out
+    }
+
+    /// Scale into `dst`, which must hold exactly `dst_w * dst_h` BGRA pixels. Bars left by
+    /// `FitMode::Fit` are painted in the bar color.
+    pub fn scale_into(&mut self, src: &[u8], src_w: u32, src_h: u32, dst: &mut [u8], dst_w: u32, dst_h: u32) {
+        if src_w == 0 || src_h == 0 || dst_w == 0 || dst_h == 0 {
+            return;
+        }
+
+        let Layout { crop, out, .. } = self.options.fit.layout(src_w, src_h, dst_w, dst_h);
+        if out != View::full(dst_w, dst_h) {
+            fill_bars(dst, View::full(dst_w, dst_h), out, self.options.bar_color);
+        }
+        self.resample(src, crop, dst, out);
+    }
+
+    fn resample(&mut self, src: &[u8], crop: View, dst: &mut [u8], out: View) {
+        if (crop.width, crop.height) == (out.width, out.height) {
+            for y in 0..out.height {
+                dst[out.row(y)].copy_from_slice(&src[crop.row(y)]);
+            }
+            return;
+        }
+        if self.options.filter == ScaleFilter::Nearest {
+            scale_nearest(src, crop, dst, out);
+            return;
+        }
+
+        let sizes = (crop.width, crop.height, out.width, out.height);
+        if self.plan.as_ref().map(|p| p.sizes) != Some(sizes) {
+            self.plan = Some(Plan {
+                sizes,
+                horiz: Weights::new(self.options.filter, crop.width as u32, out.width as u32),
+                vert: Weights::new(self.options.filter, crop.height as u32, out.height as u32),
+            });
+        }
+        let Plan { horiz, vert, .. } = self.plan.as_ref().unwrap();
+
+        if crop.width == out.width {
+            vertical(src, crop, 0, dst, out, vert);
+            return;
+        }
+        if crop.height == out.height {
+            horizontal(src, crop, 0..crop.height, dst, out, horiz);
+            return;
+        }
+
+        // Only the source rows the vertical pass will read need a horizontal pass.
+        let first = vert.bounds[0].0;
+        let last = vert.bounds.iter().map(|(s, n)| s + n).max().unwrap_or(first);
+        let tmp_view = View {
+            x: 0,
+            y: 0,
+            width: out.width,
+            height: last - first,
+            stride: out.width * 4,
+        };
+        self.tmp.resize(tmp_view.height * tmp_view.stride, 0);
+        horizontal(src, crop, first..last, &mut self.tmp, tmp_view, horiz);
+        vertical(&self.tmp, tmp_view, first, dst, out, vert);
+    }
+}
+
+/// Paint everything in `frame` outside `content`.
+fn fill_bars(dst: &mut [u8], frame: View, content: View, color: [u8; 4]) {
+    let fill = |px: &mut [u8]| {
+        for p in px.chunks_exact_mut(4) {
+            p.copy_from_slice(&color);
+        }
+    };
+    for y in 0..frame.height {
+        let row = &mut dst[frame.row(y)];
+        if y < content.y || y >= content.y + content.height {
+            fill(row);
+        } else {
+            let (left, rest) = row.split_at_mut(content.x * 4);
+            fill(left);
+            fill(&mut rest[content.width * 4..]);
+        }
+    }
+}
+
+fn scale_nearest(src: &[u8], crop: View, dst: &mut [u8], out: View) {
+    for y in 0..out.height {
+        let src_row = &src[crop.row(y * crop.height / out.height)];
+        let dst_row = &mut dst[out.row(y)];
+        for (x, px) in dst_row.chunks_exact_mut(4).enumerate() {
+            let s = x * crop.width / out.width * 4;
+            px.copy_from_slice(&src_row[s..s + 4]);
+        }
+    }
+}
+
+/// Filter rows `first..` of `src` (the window rows the horizontal pass produced) down into
+/// `out`. Source row indices in `weights` are relative to the window, not to `first`.
+fn vertical(src: &[u8], view: View, first: usize, dst: &mut [u8], out: View, weights: &Weights) {
+    for y in 0..out.height {
+        let (start, coeffs) = weights.taps_for(y);
+        let base = view.row(start - first).start;
+        vertical_row(&src[base..], view.stride, coeffs, &mut dst[out.row(y)]);
+    }
+}
+
+/// Filter window rows `rows` of `src` horizontally into consecutive rows of `out`.
+fn horizontal(src: &[u8], view: View, rows: Range<usize>, dst: &mut [u8], out: View, weights: &Weights) {
+    for (i, y) in rows.enumerate() {
+        horizontal_row(&src[view.row(y)], &mut dst[out.row(i)], weights);
+    }
+}
+
+fn clamp_u8(acc: i32) -> u8 {
+    (acc >> PRECISION).clamp(0, 255) as u8
+}
+
+fn horizontal_row(src: &[u8], dst: &mut [u8], weights: &Weights) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        // SAFETY: SSE2 is part of the x86_64 baseline, and every tap window lies inside `src`
+        // because `Weights` was built for this row width.
+        unsafe { sse2::horizontal_row(src, dst, weights) }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    horizontal_row_scalar(src, dst, weights)
+}
+
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+fn horizontal_row_scalar(src: &[u8], dst: &mut [u8], weights: &Weights) {
+    for (x, px) in dst.chunks_exact_mut(4).enumerate() {
+        let (start, coeffs) = weights.taps_for(x);
+        let mut acc = [ROUND; 4];
+        for (k, &c) in coeffs.iter().enumerate() {
+            let s = (start + k) * 4;
+            for ch in 0..4 {
+                acc[ch] += src[s + ch] as i32 * c as i32;
+            }
+        }
+        for ch in 0..4 {
+            px[ch] = clamp_u8(acc[ch]);
+        }
+    }
+}
+
+/// `rows` starts at the first tap row; taps are `stride` bytes apart.
+fn vertical_row(rows: &[u8], stride: usize, coeffs: &[i16], dst: &mut [u8]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        // SAFETY: SSE2 is part of the x86_64 baseline; `vertical` hands in a slice holding
+        // every tap row up to `dst.len()` bytes in.
+        unsafe { sse2::vertical_row(rows, stride, coeffs, dst) }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    vertical_row_scalar(rows, stride, coeffs, dst, 0)
+}
+
+/// Output bytes from `from` onwards; the SIMD path uses it for the row tail.
+fn vertical_row_scalar(rows: &[u8], stride: usize, coeffs: &[i16], dst: &mut [u8], from: usize) {
+    for (i, out) in dst.iter_mut().enumerate().skip(from) {
+        let mut acc = ROUND;
+        for (k, &c) in coeffs.iter().enumerate() {
+            acc += rows[k * stride + i] as i32 * c as i32;
+        }
+        *out = clamp_u8(acc);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod sse2 {
+    use super::{vertical_row_scalar, Weights, PRECISION, ROUND};
+    use std::arch::x86_64::*;
+
+    /// Two 16-bit weights repeated across the register, matching pixels interleaved as
+    /// `[a0, b0, a1, b1, ...]` for `_mm_madd_epi16`.
+    #[inline(always)]
+    unsafe fn weight_pair(a: i16, b: i16) -> __m128i {
+        _mm_set1_epi32(((b as u16 as u32) << 16 | a as u16 as u32) as i32)
+    }
+
+    /// Shift four 32-bit channel sums back to 8 bits and store them as one BGRA pixel.
+    #[inline(always)]
+    unsafe fn store_pixel(acc: __m128i, dst: *mut u8) {
+        let v = _mm_srai_epi32(acc, PRECISION as i32);
+        let v = _mm_packs_epi32(v, v);
+        let v = _mm_packus_epi16(v, v);
+        (dst as *mut i32).write_unaligned(_mm_cvtsi128_si32(v));
+    }
+
+    pub(super) unsafe fn horizontal_row(src: &[u8], dst: &mut [u8], weights: &Weights) {
+        debug_assert!(weights.bounds.iter().all(|(s, n)| (s + n) * 4 <= src.len()));
+        let zero = _mm_setzero_si128();
+        let taps = weights.bounds.iter().zip(weights.coeffs.chunks_exact(weights.taps));
+        for ((&(start, len), coeffs), out) in taps.zip(dst.chunks_exact_mut(4)) {
+            let mut acc = _mm_set1_epi32(ROUND);
+            let mut p = src.as_ptr().add(start * 4);
+
+            let mut quads = coeffs[..len].chunks_exact(4);
+            for w in &mut quads {
+                // [b0 g0 r0 a0 b1 g1 r1 a1] -> [b0 b1 g0 g1 r0 r1 a0 a1], same for pixels 2, 3
+                let px = _mm_loadu_si128(p as *const __m128i);
+                let lo = _mm_unpacklo_epi8(px, zero);
+                let hi = _mm_unpackhi_epi8(px, zero);
+                let lo = _mm_unpacklo_epi16(lo, _mm_srli_si128(lo, 8));
+                let hi = _mm_unpacklo_epi16(hi, _mm_srli_si128(hi, 8));
+                acc = _mm_add_epi32(acc, _mm_madd_epi16(lo, weight_pair(w[0], w[1])));
+                acc = _mm_add_epi32(acc, _mm_madd_epi16(hi, weight_pair(w[2], w[3])));
+                p = p.add(16);
+            }
+            let mut pairs = quads.remainder().chunks_exact(2);
+            for w in &mut pairs {
+                let px = _mm_unpacklo_epi8(_mm_loadl_epi64(p as *const __m128i), zero);
+                let px = _mm_unpacklo_epi16(px, _mm_srli_si128(px, 8));
+                acc = _mm_add_epi32(acc, _mm_madd_epi16(px, weight_pair(w[0], w[1])));
+                p = p.add(8);
+            }
+            if let [w] = pairs.remainder() {
+                let px = _mm_cvtsi32_si128((p as *const i32).read_unaligned());
+                let px = _mm_unpacklo_epi16(_mm_unpacklo_epi8(px, zero), zero);
+                acc = _mm_add_epi32(acc, _mm_madd_epi16(px, weight_pair(*w, 0)));
+            }
+
+            store_pixel(acc, out.as_mut_ptr());
+        }
+    }
+
+    pub(super) unsafe fn vertical_row(rows: &[u8], stride: usize, coeffs: &[i16], dst: &mut [u8]) {
+        debug_assert!((coeffs.len() - 1) * stride + dst.len() <= rows.len());
+        let zero = _mm_setzero_si128();
+        let base = rows.as_ptr();
+        let chunks = dst.len() / 16;
+
+        for i in 0..chunks {
+            let mut acc = [_mm_set1_epi32(ROUND); 4];
+            let mut k = 0;
+            while k < coeffs.len() {
+                let a = _mm_loadu_si128(base.add(k * stride + i * 16) as *const __m128i);
+                // An odd tap count pairs the last row with zeros.
+                let (b, w) = if k + 1 < coeffs.len() {
+                    (
+                        _mm_loadu_si128(base.add((k + 1) * stride + i * 16) as *const __m128i),
+                        weight_pair(coeffs[k], coeffs[k + 1]),
+                    )
+                } else {
+                    (zero, weight_pair(coeffs[k], 0))
+                };
+
+                let a_lo = _mm_unpacklo_epi8(a, zero);
+                let a_hi = _mm_unpackhi_epi8(a, zero);
+                let b_lo = _mm_unpacklo_epi8(b, zero);
+                let b_hi = _mm_unpackhi_epi8(b, zero);
+                acc[0] = _mm_add_epi32(acc[0], _mm_madd_epi16(_mm_unpacklo_epi16(a_lo, b_lo), w));
+                acc[1] = _mm_add_epi32(acc[1], _mm_madd_epi16(_mm_unpackhi_epi16(a_lo, b_lo), w));
+                acc[2] = _mm_add_epi32(acc[2], _mm_madd_epi16(_mm_unpacklo_epi16(a_hi, b_hi), w));
+                acc[3] = _mm_add_epi32(acc[3], _mm_madd_epi16(_mm_unpackhi_epi16(a_hi, b_hi), w));
+                k += 2;
+            }
+
+            let shift = |v| _mm_srai_epi32(v, PRECISION as i32);
+            let lo = _mm_packs_epi32(shift(acc[0]), shift(acc[1]));
+            let hi = _mm_packs_epi32(shift(acc[2]), shift(acc[3]));
+            _mm_storeu_si128(dst.as_mut_ptr().add(i * 16) as *mut __m128i, _mm_packus_epi16(lo, hi));
+        }
+
+        vertical_row_scalar(rows, stride, coeffs, dst, chunks * 16);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::TAU;
+    use std::time::Instant;
+
+    const FILTERS: [ScaleFilter; 4] =
+        [ScaleFilter::Nearest, ScaleFilter::Bilinear, ScaleFilter::Bicubic, ScaleFilter::Lanczos];
+
+    /// Deterministic noise, so failures reproduce.
+    fn noise(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(747_796_405).wrapping_add(1);
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    /// A smooth pattern sampled at the centers of a `width` x `height` grid laid over a
+    /// `ref_w` x `ref_h` picture, so any grid sees the same image.
+    fn smooth(width: u32, height: u32, ref_w: u32, ref_h: u32) -> Vec<u8> {
+        let mut out = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let u = (x as f64 + 0.5) * ref_w as f64 / width as f64;
+                let v = (y as f64 + 0.5) * ref_h as f64 / height as f64;
+                for (c, period) in [(0, 23.0), (1, 17.0), (2, 29.0)] {
+                    let s = 128.0 + 70.0 * (TAU * u / period + c as f64).sin() * (TAU * v / (period + 6.0)).cos()
+                        + 40.0 * (TAU * (u + v) / 41.0).sin();
+                    out.push(s.round().clamp(0.0, 255.0) as u8);
+                }
+                out.push(255);
+            }
+        }
+        out
+    }
+
+    fn psnr(a: &[u8], b: &[u8]) -> f64 {
+        let mse = a.iter().zip(b).map(|(&x, &y)| (x as f64 - y as f64).powi(2)).sum::<f64>() / a.len() as f64;
+        10.0 * (255.0f64 * 255.0 / mse.max(1e-9)).log10()
+    }
+
+    fn scale(filter: ScaleFilter, src: &[u8], src_w: u32, src_h: u32, dst_w: u32, dst_h: u32) -> FrameBuffer {
+        Scaler::new(ScaleOptions::stretch(filter)).scale(src, src_w, src_h, dst_w, dst_h)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn sse2_matches_scalar() {
+        let sizes = [(640, 360), (360, 640), (100, 37), (37, 100), (9, 3), (1, 5), (5, 1)];
+        for filter in FILTERS.into_iter().skip(1) {
+            for (seed, &(in_size, out_size)) in sizes.iter().enumerate() {
+                let weights = Weights::new(filter, in_size, out_size);
+                let src = noise(in_size as usize * 4, seed as u32);
+                let (mut simd, mut scalar) = (vec![0; out_size as usize * 4], vec![0; out_size as usize * 4]);
+                unsafe { sse2::horizontal_row(&src, &mut simd, &weights) };
+                horizontal_row_scalar(&src, &mut scalar, &weights);
+                assert_eq!(simd, scalar, "horizontal {filter:?} {in_size}->{out_size}");
+
+                // Vertical: one output row of `width` bytes per tap window.
+                let width = 4 * 61;
+                let rows = noise(in_size as usize * width, seed as u32 + 100);
+                for out in 0..out_size as usize {
+                    let (start, coeffs) = weights.taps_for(out);
+                    let (mut simd, mut scalar) = (vec![0; width], vec![0; width]);
+                    unsafe { sse2::vertical_row(&rows[start * width..], width, coeffs, &mut simd) };
+                    vertical_row_scalar(&rows[start * width..], width, coeffs, &mut scalar, 0);
+                    assert_eq!(simd, scalar, "vertical {filter:?} {in_size}->{out_size} row {out}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn flat_frames_stay_flat() {
+        let src: Vec<u8> = [12, 34, 56, 255].repeat(97 * 53);
+        for filter in FILTERS {
+            for (w, h) in [(40, 20), (200, 120), (97, 11)] {
+                let out = scale(filter, &src, 97, 53, w, h);
+                assert!(out.chunks_exact(4).all(|px| px == [12, 34, 56, 255]), "{filter:?} to {w}x{h}");
+            }
+        }
+    }
+
+    #[test]
+    fn downscale_quality() {
+        // Downscales of a smooth picture against the picture rendered at the output size.
+        for ((src_w, src_h), (dst_w, dst_h)) in [((768, 432), (384, 216)), ((960, 540), (640, 360))] {
+            let src = smooth(src_w, src_h, dst_w, dst_h);
+            let reference = smooth(dst_w, dst_h, dst_w, dst_h);
+            let scores: Vec<f64> = FILTERS
+                .iter()
+                .map(|&f| psnr(&scale(f, &src, src_w, src_h, dst_w, dst_h), &reference))
+                .collect();
+            println!("{src_w}x{src_h} -> {dst_w}x{dst_h}: {scores:.1?} dB");
+            // Nearest, bilinear, bicubic, Lanczos.
+            for (score, min) in scores.iter().zip([30.0, 48.0, 50.0, 50.0]) {
+                assert!(*score >= min, "{scores:?}");
+            }
+            assert!(scores[2] > scores[1] && scores[3] > scores[1]);
+        }
+    }
+
+    #[test]
+    fn upscale_quality() {
+        let (src_w, src_h, dst_w, dst_h) = (160, 90, 400, 225);
+        let src = smooth(src_w, src_h, src_w, src_h);
+        let reference = smooth(dst_w, dst_h, src_w, src_h);
+        let scores: Vec<f64> = FILTERS
+            .iter()
+            .map(|&f| psnr(&scale(f, &src, src_w, src_h, dst_w, dst_h), &reference))
+            .collect();
+        println!("{src_w}x{src_h} -> {dst_w}x{dst_h}: {scores:.1?} dB");
+        for (score, min) in scores.iter().zip([30.0, 48.0, 50.0, 50.0]) {
+            assert!(*score >= min, "{scores:?}");
+        }
+    }
+
+    const BAR: [u8; 4] = [0, 0, 255, 255];
+
+    fn fit(mode: FitMode) -> ScaleOptions {
+        ScaleOptions { filter: ScaleFilter::Nearest, fit: mode, bar_color: BAR }
+    }
+
+    fn rect(x: i32, y: i32, width: u32, height: u32) -> ContentRect {
+        ContentRect { x, y, width, height }
+    }
+
+    /// Rows of `frame`, one entry per pixel: its blue channel.
+    fn blue_rows(frame: &[u8], width: usize) -> Vec<Vec<u8>> {
+        frame.chunks_exact(width * 4).map(|row| row.chunks_exact(4).map(|px| px[0]).collect()).collect()
+    }
+
+    #[test]
+    fn layouts_for_common_aspect_changes() {
+        let cases = [
+            // Ultrawide into 16:9: letterboxed, or cropped at the sides.
+            (FitMode::Fit, (3440, 1440), rect(0, 138, 1920, 804)),
+            (FitMode::Fill, (3440, 1440), rect(-330, 0, 2580, 1080)),
+            // 4:3 into 16:9: pillarboxed, or cropped at the top and bottom.
+            (FitMode::Fit, (1024, 768), rect(240, 0, 1440, 1080)),
+            (FitMode::Fill, (1024, 768), rect(0, -180, 1920, 1440)),
+            (FitMode::Stretch, (3440, 1440), rect(0, 0, 1920, 1080)),
+            (FitMode::Fit, (1280, 720), rect(0, 0, 1920, 1080)),
+            (FitMode::Fill, (1280, 720), rect(0, 0, 1920, 1080)),
+        ];
+        for (mode, (w, h), expected) in cases {
+            let content = fit(mode).content_rect(w, h, 1920, 1080);
+            assert_eq!(content, expected, "{mode:?} {w}x{h}");
+            let visible = content.visible(1920, 1080);
+            assert!(visible.width <= 1920 && visible.height <= 1080);
+        }
+        // Nothing to lay out: the whole frame.
+        assert_eq!(fit(FitMode::Fit).content_rect(0, 720, 1920, 1080), rect(0, 0, 1920, 1080));
+    }
+
+    #[test]
+    fn fit_paints_bars_around_the_picture() {
+        let white = [255u8; 4].repeat(8 * 2);
+        let mut out = vec![0; 8 * 8 * 4];
+        Scaler::new(fit(FitMode::Fit)).scale_into(&white, 8, 2, &mut out, 8, 8);
+        let rows = blue_rows(&out, 8);
+        for (y, row) in rows.iter().enumerate() {
+            let expected = if (3..5).contains(&y) { 255 } else { BAR[0] };
+            assert!(row.iter().all(|&b| b == expected), "row {y}: {row:?}");
+        }
+        assert!(out.chunks_exact(4).all(|px| px[1..] == [0, 255, 255] || px == [255; 4]));
+
+        // Pillarbox: a 1:1 picture in a 2:1 frame.
+        let mut out = vec![0; 8 * 4 * 4];
+        Scaler::new(fit(FitMode::Fit)).scale_into(&[255; 2 * 2 * 4], 2, 2, &mut out, 8, 4);
+        for row in blue_rows(&out, 8) {
+            assert_eq!(row, [0, 0, 255, 255, 255, 255, 0, 0]);
+        }
+    }
+
+    #[test]
+    fn fill_crops_the_centre() {
+        // Four columns with distinct blue values; a 2x2 output keeps the middle two.
+        let src: Vec<u8> = [10, 20, 30, 40].iter().flat_map(|&b| [b, 0, 0, 255]).collect::<Vec<_>>().repeat(2);
+        let options = fit(FitMode::Fill);
+        let mut out = vec![0; 2 * 2 * 4];
+        Scaler::new(options).scale_into(&src, 4, 2, &mut out, 2, 2);
+        assert_eq!(blue_rows(&out, 2), [[20, 30], [20, 30]]);
+        // The full picture would be 4 px wide, starting one pixel left of the frame.
+        assert_eq!(options.content_rect(4, 2, 2, 2), rect(-1, 0, 4, 2));
+
+        // Same on the other axis: four rows, the middle two survive.
+        let src: Vec<u8> = [10, 20, 30, 40].iter().flat_map(|&b| [b, 0, 0, 255].repeat(2)).collect();
+        Scaler::new(options).scale_into(&src, 2, 4, &mut out, 2, 2);
+        assert_eq!(blue_rows(&out, 2), [[20, 20], [30, 30]]);
+        assert_eq!(options.content_rect(2, 4, 2, 2), rect(0, -1, 2, 4));
+    }
+
+    #[test]
+    fn visible_clips_content_to_the_frame() {
+        // Already inside.
+        assert_eq!(rect(240, 0, 1440, 1080).visible(1920, 1080), rect(240, 0, 1440, 1080));
+        // Cropped by Fill: negative offset, larger than the frame.
+        assert_eq!(rect(-330, 0, 2580, 1080).visible(1920, 1080), rect(0, 0, 1920, 1080));
+        assert_eq!(rect(0, -180, 1920, 1440).visible(1920, 1080), rect(0, 0, 1920, 1080));
+        // Hanging off one corner.
+        assert_eq!(rect(-10, 1000, 100, 200).visible(1920, 1080), rect(0, 1000, 90, 80));
+        // Entirely outside.
+        assert_eq!(rect(2000, 0, 100, 100).visible(1920, 1080).width, 0);
+        assert_eq!(rect(0, -500, 100, 100).visible(1920, 1080).height, 0);
+    }
+
+    /// `cargo test --release -- --ignored --nocapture scaler_speed`.
+    #[test]
+    #[ignore = "benchmark; run in release mode"]
+    fn scaler_speed() {
+        let src = noise(3840 * 2160 * 4, 7);
+        for filter in FILTERS {
+            let mut scaler = Scaler::new(ScaleOptions::stretch(filter));
+            let mut dst = vec![0; 1920 * 1080 * 4];
+            scaler.scale_into(&src, 3840, 2160, &mut dst, 1920, 1080);
+            let runs = 20;
+            let started = Instant::now();
+            for _ in 0..runs {
+                scaler.scale_into(&src, 3840, 2160, &mut dst, 1920, 1080);
+            }
+            let ms = started.elapsed().as_secs_f64() * 1000.0 / runs as f64;
+            println!("{filter:?} 4K -> 1080p: {ms:.2} ms");
+        }
+    }
+}
@@ -1,171 +1,7 @@
-#![allow(dead_code)]
-
-use crate::error::RecorderError;
-use parking_lot::Mutex;
-use std::sync::Arc;
-use std::time::{Duration, Instant};
-use windows_capture::capture::{CaptureControl, Context, GraphicsCaptureApiHandler};
-use windows_capture::frame::Frame as WcFrame;
-use windows_capture::graphics_capture_api::InternalCaptureControl;
-use windows_capture::monitor::Monitor;
-use windows_capture::settings::{
-    ColorFormat, CursorCaptureSettings, DirtyRegionSettings, DrawBorderSettings,
-    MinimumUpdateIntervalSettings, SecondaryWindowSettings, Settings,
-};
-
-#[derive(Debug, Clone)]
-pub struct Frame {
-    pub data: Vec<u8>,
-    pub width: u32,
-    pub height: u32,
-    pub timestamp: u64,
-    pub frame_number: u64,
-}
-
-fn scale_bgra_nearest(src: &[u8], src_w: u32, src_h: u32, dst_w: u32, dst_h: u32) -> Vec<u8> {
-    if src_w == dst_w && src_h == dst_h {
-        return src.to_vec();
-    }
-
-    let mut out = vec![0u8; dst_w as usize * dst_h as usize * 4];
-    for y in 0..dst_h {
-        let sy = (y as u64 * src_h as u64 / dst_h as u64) as u32;
-        for x in 0..dst_w {
-            let sx = (x as u64 * src_w as u64 / dst_w as u64) as u32;
-
-            let s = ((sy as usize * src_w as usize + sx as usize) * 4) as usize;
-            let d = ((y as usize * dst_w as usize + x as usize) * 4) as usize;
-            out[d..d + 4].copy_from_slice(&src[s..s + 4]);
-        }
-    }
-    out
-}
-
-struct CaptureCallback {
-    latest: Arc<Mutex<Option<Vec<u8>>>>,
-    src_w: Arc<Mutex<u32>>,
-    src_h: Arc<Mutex<u32>>,
-    target_w: u32,
-    target_h: u32,
-}
-
-impl GraphicsCaptureApiHandler for CaptureCallback {
-    type Flags = (Arc<Mutex<Option<Vec<u8>>>>, Arc<Mutex<u32>>, Arc<Mutex<u32>>, u32, u32);
-    type Error = Box<dyn std::error::Error + Send + Sync>;
-
-    fn new(ctx: Context<Self::Flags>) -> Result<Self, Self::Error> {
-        let (latest, src_w, src_h, target_w, target_h) = ctx.flags;
-        Ok(Self { latest, src_w, src_h, target_w, target_h })
-    }
-
-    fn on_frame_arrived(
-        &mut self,
-        frame: &mut WcFrame,
-        _capture_control: InternalCaptureControl,
-    ) -> Result<(), Self::Error> {
-        let mut fb = frame.buffer()?;
-        let w = fb.width();
-        let h = fb.height();
-        let buf = fb.as_nopadding_buffer()?;
-        *self.src_w.lock() = w;
-        *self.src_h.lock() = h;
-
-        let scaled = scale_bgra_nearest(buf, w, h, self.target_w, self.target_h);
-        *self.latest.lock() = Some(scaled);
-        Ok(())
-    }
-
-    fn on_closed(&mut self) -> Result<(), Self::Error> {
-        Ok(())
-    }
-}
-
-pub struct ScreenCapturer {
-    target_w: u32,
-    target_h: u32,
-    started_at: Instant,
-    frame_number: u64,
-    latest: Arc<Mutex<Option<Vec<u8>>>>,
-    src_w: Arc<Mutex<u32>>,
-    src_h: Arc<Mutex<u32>>,
-    control: Option<CaptureControl<CaptureCallback, Box<dyn std::error::Error + Send + Sync>>>,
-}
-
-impl ScreenCapturer {
-    pub fn new(display_index: u32, width: u32, height: u32) -> Result<Self, RecorderError> {
-        if width == 0 || height == 0 {
-            return Err(RecorderError::invalid_settings("Invalid target resolution"));
-        }
-
-        let monitors = Monitor::enumerate().map_err(|e| RecorderError::device_not_found(e.to_string()))?;
-        let monitor = monitors
-            .get(display_index as usize)
-            .cloned()
-            .ok_or_else(|| RecorderError::device_not_found("Display"))?;
-
-        let latest = Arc::new(Mutex::new(None));
-        let src_w = Arc::new(Mutex::new(0u32));
-        let src_h = Arc::new(Mutex::new(0u32));
-
-        let settings = Settings::new(
-            monitor,
-            CursorCaptureSettings::Default,
-            DrawBorderSettings::Default,
-            SecondaryWindowSettings::Default,
-            MinimumUpdateIntervalSettings::Default,
-            DirtyRegionSettings::Default,
-            ColorFormat::Bgra8,
-            (latest.clone(), src_w.clone(), src_h.clone(), width, height),
-        );
-
-        let control = CaptureCallback::start_free_threaded(settings)
-            .map_err(|e| RecorderError::encoding_failed(format!("Screen capture start failed: {e:?}")))?;
-
-        Ok(Self {
-            target_w: width,
-            target_h: height,
-            started_at: Instant::now(),
-            frame_number: 0,
-            latest,
-            src_w,
-            src_h,
-            control: Some(control),
-        })
-    }
-
-    pub fn capture_frame(&mut self) -> Result<Frame, RecorderError> {
-        self.frame_number += 1;
-
-        // Wait briefly for first frame.
-        if self.latest.lock().is_none() {
-            let deadline = Instant::now() + Duration::from_secs(2);
-            while self.latest.lock().is_none() && Instant::now() < deadline {
-                std::thread::sleep(Duration::from_millis(10));
-            }
-        }
-
-        let data = self
-            .latest
-            .lock()
-            .clone()
-            .unwrap_or_else(|| vec![0u8; self.target_w as usize * self.target_h as usize * 4]);
-
-        Ok(Frame {
-            data,
-            width: self.target_w,
-            height: self.target_h,
-            timestamp: self.started_at.elapsed().as_millis() as u64,
-            frame_number: self.frame_number,
-        })
-    }
-
-    pub fn stop(&mut self) {
-        if let Some(control) = self.control.take() {
-            let _ = control.stop();
-        }
-    }
-
-    pub fn source_dimensions(&self) -> (u32, u32) {
-        (*self.src_w.lock(), *self.src_h.lock())
-    }
-}
+// Platform screen capture backends. Both expose the same `ScreenCapturer` API:
+// `new(display_index, region, width, height, system_cursor, scale, tone_map)`,
+// `for_window(...)`, `source_dimensions()` and the `FrameSource` trait.
+#[cfg(target_os = "linux")]
+pub use super::screen_capturer_x11::ScreenCapturer;
+#[cfg(windows)]
+pub use super::screen_capturer_wgc::ScreenCapturer;
@@ -0,0 +1,296 @@
+#![allow(dead_code)]
+
+use crate::commands::devices::enumerate_displays;
+use crate::error::RecorderError;
+use crate::recording::frame_pool::FrameData;
+use crate::recording::frame_source::{CaptureArea, ContentRect, Frame, FrameSource};
+use crate::recording::scaler::{ScaleOptions, Scaler};
+use crate::recording::tone_map::ToneMapper;
+use crate::state::app_state::CaptureRegion;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use windows_capture::capture::{CaptureControl, Context, GraphicsCaptureApiHandler};
+use windows_capture::frame::Frame as WcFrame;
+use windows_capture::graphics_capture_api::InternalCaptureControl;
+use windows_capture::monitor::Monitor;
+use windows_capture::settings::{
+    ColorFormat, CursorCaptureSettings, DirtyRegionSettings, DrawBorderSettings,
+    MinimumUpdateIntervalSettings, SecondaryWindowSettings, Settings, TryIntoCaptureItemWithType,
+};
+use windows_capture::window::Window;
+
+struct CaptureFlags {
+    latest: Arc<Mutex<Option<FrameData>>>,
+    src_w: Arc<Mutex<u32>>,
+    src_h: Arc<Mutex<u32>>,
+    target_w: u32,
+    target_h: u32,
+    region: Option<CaptureRegion>,
+    scale: ScaleOptions,
+    tone_map: Option<Arc<ToneMapper>>,
+}
+
+struct CaptureCallback {
+    latest: Arc<Mutex<Option<FrameData>>>,
+    src_w: Arc<Mutex<u32>>,
+    src_h: Arc<Mutex<u32>>,
+    target_w: u32,
+    target_h: u32,
+    region: Option<CaptureRegion>,
+    scaler: Scaler,
+    /// Set when capturing `Rgba16F`; frames are tone-mapped to BGRA before scaling.
+    tone_map: Option<Arc<ToneMapper>>,
+}
+
+impl GraphicsCaptureApiHandler for CaptureCallback {
+    type Flags = CaptureFlags;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn new(ctx: Context<Self::Flags>) -> Result<Self, Self::Error> {
+        let CaptureFlags { latest, src_w, src_h, target_w, target_h, region, scale, tone_map } = ctx.flags;
+        Ok(Self {
+            latest,
+            src_w,
+            src_h,
+            target_w,
+            target_h,
+            region,
+            scaler: Scaler::new(scale),
+            tone_map,
+        })
+    }
+
+    fn on_frame_arrived(
+        &mut self,
+        frame: &mut WcFrame,
+        _capture_control: InternalCaptureControl,
+    ) -> Result<(), Self::Error> {
+        let mut fb = match self.region {
+            // Crop on the GPU side before the CPU copy; clamp in case the display shrank.
+            Some(r) => {
+                let x1 = r.x.saturating_add(r.width).min(frame.width());
+                let y1 = r.y.saturating_add(r.height).min(frame.height());
+                frame.buffer_crop(r.x.min(x1), r.y.min(y1), x1, y1)?
+            }
+            None => frame.buffer()?,
+        };
+        let w = fb.width();
+        let h = fb.height();
+        let buf = fb.as_nopadding_buffer()?;
+        *self.src_w.lock() = w;
+        *self.src_h.lock() = h;
+
+        let scaled = match &self.tone_map {
+            Some(tone_map) => {
+                let sdr = tone_map.map(buf, w, h)?;
+                self.scaler.scale(&sdr, w, h, self.target_w, self.target_h)
+            }
+            None => self.scaler.scale(buf, w, h, self.target_w, self.target_h),
+        };
+        *self.latest.lock() = Some(scaled.into());
+        Ok(())
+    }
+
+    fn on_closed(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+pub struct ScreenCapturer {
+    target_w: u32,
+    target_h: u32,
+    started_at: Instant,
+    frame_number: u64,
+    latest: Arc<Mutex<Option<FrameData>>>,
+    src_w: Arc<Mutex<u32>>,
+    src_h: Arc<Mutex<u32>>,
+    /// Desktop rectangle of a display capture; window captures ask `window` instead.
+    area: Option<CaptureArea>,
+    window: Option<Window>,
+    scale: ScaleOptions,
+    control: Option<CaptureControl<CaptureCallback, Box<dyn std::error::Error + Send + Sync>>>,
+}
+
+impl ScreenCapturer {
+    /// Capture a monitor, optionally cropped to `region` (relative to the monitor's top-left).
+    /// `system_cursor` lets WGC draw the pointer; otherwise it is left out of the capture.
+    /// With `tone_map` the capture runs in `Rgba16F` and HDR content is mapped to SDR.
+    pub fn new(
+        display_index: u32,
+        region: Option<CaptureRegion>,
+        width: u32,
+        height: u32,
+        system_cursor: bool,
+        scale: ScaleOptions,
+        tone_map: Option<Arc<ToneMapper>>,
+    ) -> Result<Self, RecorderError> {
+        if width == 0 || height == 0 {
+            return Err(RecorderError::invalid_settings("Invalid target resolution"));
+        }
+
+        let monitors = Monitor::enumerate().map_err(|e| RecorderError::device_not_found(e.to_string()))?;
+        let monitor = monitors
+            .get(display_index as usize)
+            .cloned()
+            .ok_or_else(|| RecorderError::device_not_found("Display"))?;
+
+        let area = enumerate_displays()?
+            .into_iter()
+            .find(|d| d.index == display_index)
+            .map(|d| match region {
+                Some(r) => CaptureArea {
+                    x: d.x + r.x as i32,
+                    y: d.y + r.y as i32,
+                    width: r.width,
+                    height: r.height,
+                },
+                None => CaptureArea { x: d.x, y: d.y, width: d.width, height: d.height },
+            });
+
+        let mut capturer = Self::start(monitor, region, width, height, system_cursor, scale, tone_map)?;
+        capturer.area = area;
+        Ok(capturer)
+    }
+
+    /// Capture a single application window. WGC keeps following the window as it moves
+    /// or resizes; each frame is rescaled to the target size.
+    pub fn for_window(
+        window_id: u64,
+        width: u32,
+        height: u32,
+        system_cursor: bool,
+        scale: ScaleOptions,
+        tone_map: Option<Arc<ToneMapper>>,
+    ) -> Result<Self, RecorderError> {
+        if width == 0 || height == 0 {
+            return Err(RecorderError::invalid_settings("Invalid target resolution"));
+        }
+
+        let window = Window::from_raw_hwnd(window_id as usize as *mut std::ffi::c_void);
+        if !window.is_valid() {
+            return Err(RecorderError::device_not_found("Window"));
+        }
+
+        let mut capturer = Self::start(window, None, width, height, system_cursor, scale, tone_map)?;
+        capturer.window = Some(window);
+        Ok(capturer)
+    }
+
+    fn start<T: TryIntoCaptureItemWithType + Send + 'static>(
+        item: T,
+        region: Option<CaptureRegion>,
+        width: u32,
+        height: u32,
+        system_cursor: bool,
+        scale: ScaleOptions,
+        tone_map: Option<Arc<ToneMapper>>,
+    ) -> Result<Self, RecorderError> {
+        let latest = Arc::new(Mutex::new(None));
+        let src_w = Arc::new(Mutex::new(0u32));
+        let src_h = Arc::new(Mutex::new(0u32));
+
+        let settings = Settings::new(
+            item,
+            if system_cursor {
+                CursorCaptureSettings::Default
+            } else {
+                CursorCaptureSettings::WithoutCursor
+            },
+            DrawBorderSettings::Default,
+            SecondaryWindowSettings::Default,
+            MinimumUpdateIntervalSettings::Default,
+            DirtyRegionSettings::Default,
+            if tone_map.is_some() {
+                ColorFormat::Rgba16F
+            } else {
+                ColorFormat::Bgra8
+            },
+            CaptureFlags {
+                latest: latest.clone(),
+                src_w: src_w.clone(),
+                src_h: src_h.clone(),
+                target_w: width,
+                target_h: height,
+                region,
+                scale,
+                tone_map,
+            },
+        );
+
+        let control = CaptureCallback::start_free_threaded(settings)
+            .map_err(|e| RecorderError::encoding_failed(format!("Screen capture start failed: {e:?}")))?;
+
+        Ok(Self {
+            target_w: width,
+            target_h: height,
+            started_at: Instant::now(),
+            frame_number: 0,
+            latest,
+            src_w,
+            src_h,
+            area: None,
+            window: None,
+            scale,
+            control: Some(control),
+        })
+    }
+
+    pub fn source_dimensions(&self) -> (u32, u32) {
+        (*self.src_w.lock(), *self.src_h.lock())
+    }
+}
+
+impl FrameSource for ScreenCapturer {
+    fn capture_frame(&mut self) -> Result<Frame, RecorderError> {
+        self.frame_number += 1;
+
+        // Wait briefly for first frame.
+        if self.latest.lock().is_none() {
+            let deadline = Instant::now() + Duration::from_secs(2);
+            while self.latest.lock().is_none() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+
+        // Cloning only bumps the ref count; the callback swaps in a new buffer next frame.
+        let data = self.latest.lock().clone().unwrap_or_else(|| {
+            FrameData::from(vec![0u8; self.target_w as usize * self.target_h as usize * 4])
+        });
+
+        Ok(Frame {
+            data,
+            width: self.target_w,
+            height: self.target_h,
+            timestamp: self.started_at.elapsed().as_millis() as u64,
+            frame_number: self.frame_number,
+        })
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.target_w, self.target_h)
+    }
+
+    fn stop(&mut self) {
+        if let Some(control) = self.control.take() {
+            let _ = control.stop();
+        }
+    }
+
+    fn capture_area(&self) -> Option<CaptureArea> {
+        match &self.window {
+            Some(window) => window.rect().ok().map(|r| CaptureArea {
+                x: r.left,
+                y: r.top,
+                width: (r.right - r.left).max(0) as u32,
+                height: (r.bottom - r.top).max(0) as u32,
+            }),
+            None => self.area,
+        }
+    }
+
+    fn content_rect(&self) -> Option<ContentRect> {
+        let (src_w, src_h) = self.source_dimensions();
+        Some(self.scale.content_rect(src_w, src_h, self.target_w, self.target_h))
+    }
+}
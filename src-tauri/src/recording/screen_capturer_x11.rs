@@ -0,0 +1,1057 @@
+use crate::error::RecorderError;
+use crate::recording::frame_pool::{FrameBuffer, FramePool};
+use crate::recording::frame_source::{CaptureArea, ContentRect, Frame, FrameSource};
+use crate::recording::scaler::{ScaleOptions, Scaler};
+use crate::recording::tone_map::ToneMapper;
+use crate::state::app_state::CaptureRegion;
+use std::sync::Arc;
+use std::time::Instant;
+use x11rb::connection::{Connection, RequestConnection as _};
+use x11rb::protocol::composite::{self, ConnectionExt as _};
+use x11rb::protocol::randr::{self, ConnectionExt as _};
+use x11rb::protocol::shm::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ConnectionExt as _, Drawable, ImageFormat, ImageOrder, MapState, Pixmap, Window,
+    WindowClass,
+};
+use x11rb::rust_connection::RustConnection;
+
+fn x11_err(context: &str, e: impl std::fmt::Display) -> RecorderError {
+    RecorderError::capture_failed(format!("{context}: {e}"))
+}
+
+/// One capturable display on the X server.
+///
+/// With RandR this is a monitor (or an active CRTC on servers older than RandR 1.5),
+/// otherwise a whole X screen. Indices into `enumerate_outputs` are what
+/// `RecordingSettings::selected_display` refers to.
+#[derive(Debug, Clone)]
+pub(crate) struct X11Output {
+    pub name: String,
+    pub root: Window,
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+    pub primary: bool,
+}
+
+/// Connect to `display`, or to `$DISPLAY` when `None`.
+pub(crate) fn connect(display: Option<&str>) -> Result<(RustConnection, usize), RecorderError> {
+    x11rb::connect(display).map_err(|e| RecorderError::device_not_found(format!("X11 display ({e})")))
+}
+
+/// List displays in a stable order: X screens in server order, and within each screen
+/// the RandR monitors/CRTCs in the order the server reports them.
+pub(crate) fn enumerate_outputs(
+    conn: &RustConnection,
+    default_screen: usize,
+) -> Result<Vec<X11Output>, RecorderError> {
+    let mut outputs = Vec::new();
+    for (screen_num, screen) in conn.setup().roots.iter().enumerate() {
+        let randr = randr_outputs(conn, screen.root).unwrap_or_default();
+        if randr.is_empty() {
+            outputs.push(X11Output {
+                name: format!("Screen {screen_num}"),
+                root: screen.root,
+                x: 0,
+                y: 0,
+                width: screen.width_in_pixels,
+                height: screen.height_in_pixels,
+                primary: screen_num == default_screen,
+            });
+        } else {
+            outputs.extend(randr);
+        }
+    }
+
+    Ok(outputs)
+}
+
+fn randr_outputs(conn: &RustConnection, root: Window) -> Result<Vec<X11Output>, Box<dyn std::error::Error>> {
+    if conn.extension_information(randr::X11_EXTENSION_NAME)?.is_none() {
+        return Ok(vec![]);
+    }
+
+    let version = conn.randr_query_version(1, 5)?.reply()?;
+    if (version.major_version, version.minor_version) >= (1, 5) {
+        let monitors = conn.randr_get_monitors(root, true)?.reply()?.monitors;
+        let mut out = Vec::with_capacity(monitors.len());
+        for (i, m) in monitors.into_iter().enumerate() {
+            let name = conn
+                .get_atom_name(m.name)?
+                .reply()
+                .map(|r| String::from_utf8_lossy(&r.name).into_owned())
+                .unwrap_or_else(|_| format!("Monitor {}", i + 1));
+            out.push(X11Output {
+                name,
+                root,
+                x: m.x,
+                y: m.y,
+                width: m.width,
+                height: m.height,
+                primary: m.primary,
+            });
+        }
+        return Ok(out);
+    }
+
+    // RandR 1.2-1.4: one entry per active CRTC, named after its first output.
+    let resources = conn.randr_get_screen_resources_current(root)?.reply()?;
+    let primary_output = conn.randr_get_output_primary(root)?.reply()?.output;
+    let mut out = Vec::new();
+    for crtc in resources.crtcs {
+        let info = conn.randr_get_crtc_info(crtc, resources.config_timestamp)?.reply()?;
+        if info.mode == 0 || info.width == 0 || info.height == 0 {
+            continue;
+        }
+
+        let name = match info.outputs.first() {
+            Some(&output) => conn
+                .randr_get_output_info(output, resources.config_timestamp)?
+                .reply()
+                .map(|o| String::from_utf8_lossy(&o.name).into_owned())
+                .unwrap_or_else(|_| format!("CRTC {crtc}")),
+            None => format!("CRTC {crtc}"),
+        };
+        out.push(X11Output {
+            name,
+            root,
+            x: info.x,
+            y: info.y,
+            width: info.width,
+            height: info.height,
+            primary: info.outputs.contains(&primary_output),
+        });
+    }
+    Ok(out)
+}
+
+/// Where each output sits on the desktop. X screens each have their own coordinate space
+/// starting at 0,0, so they are laid out left to right in server order; outputs keep their
+/// position within their screen.
+pub(crate) fn desktop_positions(outputs: &[X11Output]) -> Vec<(i32, i32)> {
+    let mut positions = Vec::with_capacity(outputs.len());
+    let (mut root, mut screen_x, mut screen_right) = (None, 0i32, 0i32);
+    for o in outputs {
+        if root != Some(o.root) {
+            screen_x += screen_right;
+            screen_right = 0;
+            root = Some(o.root);
+        }
+        screen_right = screen_right.max(o.x as i32 + o.width as i32);
+        positions.push((screen_x + o.x as i32, o.y as i32));
+    }
+    positions
+}
+
+/// A MIT-SHM segment attached both to this process and to the X server.
+struct ShmSegment {
+    seg: shm::Seg,
+    addr: *mut u8,
+    size: usize,
+}
+
+// The mapping is process-wide and only touched through `&mut ScreenCapturer`.
+unsafe impl Send for ShmSegment {}
+
+impl ShmSegment {
+    fn new(conn: &RustConnection, size: usize) -> Result<Self, RecorderError> {
+        let id = unsafe { libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600) };
+        if id < 0 {
+            return Err(x11_err("shmget failed", std::io::Error::last_os_error()));
+        }
+
+        let addr = unsafe { libc::shmat(id, std::ptr::null(), 0) };
+        if addr as isize == -1 {
+            let err = std::io::Error::last_os_error();
+            unsafe { libc::shmctl(id, libc::IPC_RMID, std::ptr::null_mut()) };
+            return Err(x11_err("shmat failed", err));
+        }
+
+        let attached = conn
+            .generate_id()
+            .map_err(|e| x11_err("XID allocation failed", e))
+            .and_then(|seg| {
+                conn.shm_attach(seg, id as u32, false)
+                    .map_err(|e| x11_err("ShmAttach failed", e))?
+                    .check()
+                    .map_err(|e| x11_err("ShmAttach failed", e))?;
+                Ok(seg)
+            });
+
+        // Both sides are attached (or failed); let the kernel drop the segment once they detach.
+        unsafe { libc::shmctl(id, libc::IPC_RMID, std::ptr::null_mut()) };
+
+        match attached {
+            Ok(seg) => Ok(Self {
+                seg,
+                addr: addr as *mut u8,
+                size,
+            }),
+            Err(e) => {
+                unsafe { libc::shmdt(addr) };
+                Err(e)
+            }
+        }
+    }
+
+    fn as_slice(&self, len: usize) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.addr, len.min(self.size)) }
+    }
+
+    fn release(&mut self, conn: &RustConnection) {
+        if !self.addr.is_null() {
+            let _ = conn.shm_detach(self.seg);
+            let _ = conn.flush();
+            unsafe { libc::shmdt(self.addr as *const libc::c_void) };
+            self.addr = std::ptr::null_mut();
+        }
+    }
+}
+
+/// A top-level client window, as listed by `get_windows`.
+#[derive(Debug, Clone)]
+pub(crate) struct X11Window {
+    pub id: Window,
+    pub title: String,
+}
+
+fn intern(conn: &RustConnection, name: &[u8]) -> Result<Atom, RecorderError> {
+    Ok(conn
+        .intern_atom(false, name)
+        .map_err(|e| x11_err("InternAtom failed", e))?
+        .reply()
+        .map_err(|e| x11_err("InternAtom failed", e))?
+        .atom)
+}
+
+fn window_title(conn: &RustConnection, window: Window, net_wm_name: Atom) -> Option<String> {
+    for prop in [net_wm_name, AtomEnum::WM_NAME.into()] {
+        let reply = conn
+            .get_property(false, window, prop, AtomEnum::ANY, 0, 1024)
+            .ok()?
+            .reply()
+            .ok()?;
+        if !reply.value.is_empty() {
+            return Some(String::from_utf8_lossy(&reply.value).into_owned());
+        }
+    }
+    None
+}
+
+/// List titled top-level windows. Prefers the window manager's `_NET_CLIENT_LIST`; without
+/// a WM (bare Xvfb) the mapped children of each root are used instead.
+pub(crate) fn enumerate_windows(conn: &RustConnection) -> Result<Vec<X11Window>, RecorderError> {
+    let net_client_list = intern(conn, b"_NET_CLIENT_LIST")?;
+    let net_wm_name = intern(conn, b"_NET_WM_NAME")?;
+
+    let mut out = Vec::new();
+    for screen in &conn.setup().roots {
+        let clients: Vec<Window> = conn
+            .get_property(false, screen.root, net_client_list, AtomEnum::WINDOW, 0, u32::MAX)
+            .ok()
+            .and_then(|c| c.reply().ok())
+            .and_then(|r| r.value32().map(|v| v.collect()))
+            .unwrap_or_default();
+
+        let candidates = if clients.is_empty() {
+            let children = conn
+                .query_tree(screen.root)
+                .map_err(|e| x11_err("QueryTree failed", e))?
+                .reply()
+                .map_err(|e| x11_err("QueryTree failed", e))?
+                .children;
+            children
+                .into_iter()
+                .filter(|&w| {
+                    conn.get_window_attributes(w)
+                        .ok()
+                        .and_then(|c| c.reply().ok())
+                        .map(|a| a.map_state == MapState::VIEWABLE && a.class == WindowClass::INPUT_OUTPUT)
+                        .unwrap_or(false)
+                })
+                .collect()
+        } else {
+            clients
+        };
+
+        for id in candidates {
+            if let Some(title) = window_title(conn, id, net_wm_name).filter(|t| !t.trim().is_empty()) {
+                out.push(X11Window { id, title });
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+enum CaptureTarget {
+    Output(X11Output),
+    /// Client window, read from its own pixmap so whatever covers it isn't recorded. Its
+    /// position and size are re-queried every frame.
+    Window { id: Window, root: Window, pixmap: Pixmap },
+}
+
+/// What the next frame shows.
+struct SourceRect {
+    root: Window,
+    /// Top-left corner in root coordinates.
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+    /// Border around a window, which its pixmap includes and the rectangle doesn't.
+    border: u16,
+}
+
+/// Screen capture through the X server.
+///
+/// Uses `ShmGetImage` when MIT-SHM is available (local servers, Xvfb) and falls back to a
+/// plain `GetImage` round-trip otherwise. Frames come back as BGRA, scaled to the target size.
+pub struct ScreenCapturer {
+    conn: RustConnection,
+    target: CaptureTarget,
+    screen_w: u16,
+    screen_h: u16,
+    shm: Option<ShmSegment>,
+    target_w: u32,
+    target_h: u32,
+    scaler: Scaler,
+    /// Root-window rectangle behind the last frame.
+    src_x: i32,
+    src_y: i32,
+    src_w: u32,
+    src_h: u32,
+    started_at: Instant,
+    frame_number: u64,
+}
+
+impl ScreenCapturer {
+    /// Capture a display, optionally cropped to `region` (relative to the display's top-left).
+    /// The crop is applied to the `GetImage` rectangle, so only the region crosses the wire.
+    /// `GetImage` never includes the pointer, so `_system_cursor` has nothing to switch, and
+    /// X11 has no HDR, so `_tone_map` goes unused.
+    pub fn new(
+        display_index: u32,
+        region: Option<CaptureRegion>,
+        width: u32,
+        height: u32,
+        _system_cursor: bool,
+        scale: ScaleOptions,
+        _tone_map: Option<Arc<ToneMapper>>,
+    ) -> Result<Self, RecorderError> {
+        Self::open_output(connect(None)?, display_index, region, width, height, scale)
+    }
+
+    /// `new` on an existing connection and its default screen.
+    fn open_output(
+        (conn, default_screen): (RustConnection, usize),
+        display_index: u32,
+        region: Option<CaptureRegion>,
+        width: u32,
+        height: u32,
+        scale: ScaleOptions,
+    ) -> Result<Self, RecorderError> {
+        if width == 0 || height == 0 {
+            return Err(RecorderError::invalid_settings("Invalid target resolution"));
+        }
+
+        let mut output = enumerate_outputs(&conn, default_screen)?
+            .get(display_index as usize)
+            .cloned()
+            .ok_or_else(|| RecorderError::device_not_found("Display"))?;
+
+        if let Some(r) = region {
+            r.validate_within(output.width as u32, output.height as u32)?;
+            output.x += r.x as i16;
+            output.y += r.y as i16;
+            output.width = r.width as u16;
+            output.height = r.height as u16;
+        }
+
+        Self::start(conn, CaptureTarget::Output(output), width, height, scale)
+    }
+
+    /// Capture a single client window, following it as it moves or resizes. The window is read
+    /// from its own pixmap through the Composite extension, so overlapping windows and menus
+    /// aren't recorded and parts off screen are; servers without Composite are refused.
+    pub fn for_window(
+        window_id: u64,
+        width: u32,
+        height: u32,
+        _system_cursor: bool,
+        scale: ScaleOptions,
+        _tone_map: Option<Arc<ToneMapper>>,
+    ) -> Result<Self, RecorderError> {
+        Self::open_window(connect(None)?.0, window_id, width, height, scale)
+    }
+
+    /// `for_window` on an existing connection.
+    fn open_window(
+        conn: RustConnection,
+        window_id: u64,
+        width: u32,
+        height: u32,
+        scale: ScaleOptions,
+    ) -> Result<Self, RecorderError> {
+        if width == 0 || height == 0 {
+            return Err(RecorderError::invalid_settings("Invalid target resolution"));
+        }
+
+        let id = Window::try_from(window_id).map_err(|_| RecorderError::device_not_found("Window"))?;
+        let geometry = conn
+            .get_geometry(id)
+            .map_err(|e| x11_err("GetGeometry failed", e))?
+            .reply()
+            .map_err(|_| RecorderError::device_not_found("Window"))?;
+
+        let bpp = conn
+            .setup()
+            .pixmap_formats
+            .iter()
+            .find(|f| f.depth == geometry.depth)
+            .map(|f| f.bits_per_pixel);
+        if !matches!(geometry.depth, 24 | 32) || bpp != Some(32) {
+            return Err(RecorderError::device_not_found(format!(
+                "Window (unsupported X11 pixel format: depth {})",
+                geometry.depth
+            )));
+        }
+
+        let composite = conn.extension_information(composite::X11_EXTENSION_NAME).ok().flatten().is_some()
+            && conn
+                .composite_query_version(0, 4)
+                .ok()
+                .and_then(|c| c.reply().ok())
+                .is_some_and(|v| (v.major_version, v.minor_version) >= (0, 2));
+        if !composite {
+            return Err(RecorderError::invalid_settings(
+                "Window capture needs the X server's Composite extension, version 0.2 or later",
+            ));
+        }
+        // Keep the window's contents in an offscreen pixmap even where it is covered. The
+        // server drops the redirect when the connection closes.
+        conn.composite_redirect_window(id, composite::Redirect::AUTOMATIC)
+            .map_err(|e| x11_err("CompositeRedirectWindow failed", e))?
+            .check()
+            .map_err(|e| x11_err("CompositeRedirectWindow failed", e))?;
+        let pixmap = conn.generate_id().map_err(|e| x11_err("XID allocation failed", e))?;
+
+        let target = CaptureTarget::Window { id, root: geometry.root, pixmap };
+        Self::start(conn, target, width, height, scale)
+    }
+
+    fn start(
+        conn: RustConnection,
+        target: CaptureTarget,
+        width: u32,
+        height: u32,
+        scale: ScaleOptions,
+    ) -> Result<Self, RecorderError> {
+        let root = match &target {
+            CaptureTarget::Output(o) => o.root,
+            CaptureTarget::Window { root, .. } => *root,
+        };
+        check_pixel_format(&conn, root)?;
+
+        let (screen_w, screen_h) = conn
+            .setup()
+            .roots
+            .iter()
+            .find(|s| s.root == root)
+            .map(|s| (s.width_in_pixels, s.height_in_pixels))
+            .ok_or_else(|| RecorderError::device_not_found("Display"))?;
+
+        // Sized for the whole root window so any capture rectangle fits.
+        let shm = if conn
+            .extension_information(shm::X11_EXTENSION_NAME)
+            .ok()
+            .flatten()
+            .is_some()
+        {
+            let size = screen_w as usize * screen_h as usize * 4;
+            match ShmSegment::new(&conn, size) {
+                Ok(seg) => Some(seg),
+                Err(e) => {
+                    eprintln!("RecordFlow: MIT-SHM unavailable, falling back to GetImage: {e}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut capturer = Self {
+            conn,
+            target,
+            screen_w,
+            screen_h,
+            shm,
+            target_w: width,
+            target_h: height,
+            scaler: Scaler::new(scale),
+            src_x: 0,
+            src_y: 0,
+            src_w: 0,
+            src_h: 0,
+            started_at: Instant::now(),
+            frame_number: 0,
+        };
+        let rect = capturer.current_rect()?;
+        capturer.src_x = rect.x as i32;
+        capturer.src_y = rect.y as i32;
+        capturer.src_w = rect.width as u32;
+        capturer.src_h = rect.height as u32;
+        Ok(capturer)
+    }
+
+    pub fn source_dimensions(&self) -> (u32, u32) {
+        (self.src_w, self.src_h)
+    }
+
+    /// Root window and rectangle (in root coordinates) to capture right now.
+    fn current_rect(&self) -> Result<SourceRect, RecorderError> {
+        match &self.target {
+            CaptureTarget::Output(o) => Ok(SourceRect {
+                root: o.root,
+                x: o.x,
+                y: o.y,
+                width: o.width,
+                height: o.height,
+                border: 0,
+            }),
+            CaptureTarget::Window { id, root, .. } => {
+                let geometry = self
+                    .conn
+                    .get_geometry(*id)
+                    .map_err(|e| x11_err("GetGeometry failed", e))?
+                    .reply()
+                    .map_err(|_| RecorderError::device_not_found("Window (closed)"))?;
+                let origin = self
+                    .conn
+                    .translate_coordinates(*id, *root, 0, 0)
+                    .map_err(|e| x11_err("TranslateCoordinates failed", e))?
+                    .reply()
+                    .map_err(|_| RecorderError::device_not_found("Window (closed)"))?;
+                Ok(SourceRect {
+                    root: *root,
+                    x: origin.dst_x,
+                    y: origin.dst_y,
+                    width: geometry.width,
+                    height: geometry.height,
+                    border: geometry.border_width,
+                })
+            }
+        }
+    }
+
+    fn grab(&mut self) -> Result<FrameBuffer, RecorderError> {
+        let rect = self.current_rect()?;
+        self.src_x = rect.x as i32;
+        self.src_y = rect.y as i32;
+        self.src_w = rect.width as u32;
+        self.src_h = rect.height as u32;
+
+        let (w, h) = (rect.width, rect.height);
+        let src = match self.target {
+            CaptureTarget::Window { id, pixmap, .. } => self.grab_window(id, pixmap, &rect)?,
+            CaptureTarget::Output(_) => self.grab_root(&rect)?,
+        };
+
+        if (w as u32, h as u32) == (self.target_w, self.target_h) {
+            return Ok(src);
+        }
+        Ok(self.scaler.scale(&src, w as u32, h as u32, self.target_w, self.target_h))
+    }
+
+    /// Read the window's pixmap, named afresh every frame since resizing or remapping the
+    /// window replaces it. An unmapped (e.g. minimised) window has none and comes out black.
+    fn grab_window(&mut self, id: Window, pixmap: Pixmap, rect: &SourceRect) -> Result<FrameBuffer, RecorderError> {
+        let named = self
+            .conn
+            .composite_name_window_pixmap(id, pixmap)
+            .map_err(|e| x11_err("CompositeNameWindowPixmap failed", e))?
+            .check();
+        if named.is_err() {
+            let mut blank = FramePool::shared().take(rect.width as usize * rect.height as usize * 4);
+            blank.fill(0);
+            return Ok(blank);
+        }
+
+        let border = rect.border as i16;
+        let image = self.grab_rect(pixmap, border, border, rect.width, rect.height);
+        let _ = self.conn.free_pixmap(pixmap);
+        image
+    }
+
+    /// Read the rectangle off the root window, black where it lies outside the screen.
+    fn grab_root(&mut self, rect: &SourceRect) -> Result<FrameBuffer, RecorderError> {
+        let SourceRect { root, x, y, width: w, height: h, .. } = *rect;
+
+        // Clip to the root window; GetImage fails outright on out-of-bounds rectangles.
+        let x0 = (x as i32).max(0);
+        let y0 = (y as i32).max(0);
+        let x1 = (x as i32 + w as i32).min(self.screen_w as i32);
+        let y1 = (y as i32 + h as i32).min(self.screen_h as i32);
+
+        let full_len = w as usize * h as usize * 4;
+        Ok(if x1 <= x0 || y1 <= y0 {
+            let mut blank = FramePool::shared().take(full_len);
+            blank.fill(0);
+            blank
+        } else {
+            let (cw, ch) = ((x1 - x0) as u16, (y1 - y0) as u16);
+            let visible = self.grab_rect(root, x0 as i16, y0 as i16, cw, ch)?;
+            if (cw, ch) == (w, h) {
+                visible
+            } else {
+                let mut canvas = FramePool::shared().take(full_len);
+                canvas.fill(0);
+                let row = cw as usize * 4;
+                let dx = (x0 - x as i32) as usize;
+                let dy = (y0 - y as i32) as usize;
+                for r in 0..ch as usize {
+                    let d = ((dy + r) * w as usize + dx) * 4;
+                    canvas[d..d + row].copy_from_slice(&visible[r * row..(r + 1) * row]);
+                }
+                canvas
+            }
+        })
+    }
+
+    fn grab_rect(&mut self, drawable: Drawable, x: i16, y: i16, w: u16, h: u16) -> Result<FrameBuffer, RecorderError> {
+        let len = w as usize * h as usize * 4;
+
+        // The segment fits the screen; a window larger than that goes through GetImage.
+        if let Some(shm) = self.shm.as_ref().filter(|shm| len <= shm.size) {
+            let cookie = self
+                .conn
+                .shm_get_image(drawable, x, y, w, h, !0, ImageFormat::Z_PIXMAP.into(), shm.seg, 0)
+                .map_err(|e| x11_err("ShmGetImage failed", e))?;
+            match cookie.reply() {
+                Ok(_) => return Ok(FramePool::shared().copy_of(shm.as_slice(len))),
+                Err(e) => eprintln!("RecordFlow: ShmGetImage failed, falling back to GetImage: {e}"),
+            }
+
+            if let Some(mut shm) = self.shm.take() {
+                shm.release(&self.conn);
+            }
+        }
+
+        let reply = self
+            .conn
+            .get_image(ImageFormat::Z_PIXMAP, drawable, x, y, w, h, !0)
+            .map_err(|e| x11_err("GetImage failed", e))?
+            .reply()
+            .map_err(|e| x11_err("GetImage failed", e))?;
+        if reply.data.len() < len {
+            return Err(RecorderError::capture_failed(format!(
+                "GetImage returned {} bytes, expected {len}",
+                reply.data.len()
+            )));
+        }
+
+        Ok(FramePool::shared().copy_of(&reply.data[..len]))
+    }
+}
+
+/// ZPixmap data is only usable as-is when the root window is 24/32-bit TrueColor stored
+/// as little-endian 32 bpp, i.e. BGRX in memory.
+fn check_pixel_format(conn: &RustConnection, root: Window) -> Result<(), RecorderError> {
+    let setup = conn.setup();
+    let screen = setup
+        .roots
+        .iter()
+        .find(|s| s.root == root)
+        .ok_or_else(|| RecorderError::device_not_found("Display"))?;
+
+    let bpp = setup
+        .pixmap_formats
+        .iter()
+        .find(|f| f.depth == screen.root_depth)
+        .map(|f| f.bits_per_pixel)
+        .unwrap_or(0);
+
+    let visual = screen
+        .allowed_depths
+        .iter()
+        .flat_map(|d| d.visuals.iter())
+        .find(|v| v.visual_id == screen.root_visual);
+
+    let bgrx = visual
+        .map(|v| v.red_mask == 0xff0000 && v.green_mask == 0x00ff00 && v.blue_mask == 0x0000ff)
+        .unwrap_or(false);
+
+    if bpp != 32 || setup.image_byte_order != ImageOrder::LSB_FIRST || !bgrx {
+        return Err(RecorderError::device_not_found(format!(
+            "Display (unsupported X11 pixel format: depth {}, {bpp} bpp)",
+            screen.root_depth
+        )));
+    }
+    Ok(())
+}
+
+impl FrameSource for ScreenCapturer {
+    fn capture_frame(&mut self) -> Result<Frame, RecorderError> {
+        self.frame_number += 1;
+
+        let mut data = self.grab()?;
+        // The X server leaves the padding byte undefined.
+        for px in data.chunks_exact_mut(4) {
+            px[3] = 255;
+        }
+
+        Ok(Frame {
+            data: data.into(),
+            width: self.target_w,
+            height: self.target_h,
+            timestamp: self.started_at.elapsed().as_millis() as u64,
+            frame_number: self.frame_number,
+        })
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.target_w, self.target_h)
+    }
+
+    fn stop(&mut self) {
+        if let Some(mut shm) = self.shm.take() {
+            shm.release(&self.conn);
+        }
+    }
+
+    fn capture_area(&self) -> Option<CaptureArea> {
+        Some(CaptureArea {
+            x: self.src_x,
+            y: self.src_y,
+            width: self.src_w,
+            height: self.src_h,
+        })
+    }
+
+    fn content_rect(&self) -> Option<ContentRect> {
+        Some(self.scaler.content_rect(self.src_w, self.src_h, self.target_w, self.target_h))
+    }
+}
+
+impl Drop for ScreenCapturer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::app_state::ScaleFilter;
+    use parking_lot::{Mutex, MutexGuard};
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::OnceLock;
+    use x11rb::protocol::xproto::{ConfigureWindowAux, CreateWindowAux, PropMode};
+    use x11rb::wrapper::ConnectionExt as _;
+    use x11rb::COPY_DEPTH_FROM_PARENT;
+
+    /// An Xvfb with a 640x480 and a 320x240 screen, both black, shared by every test in this
+    /// module, and its display name. The guard serialises the tests, since they add windows and
+    /// monitors to it. The tests need Xvfb installed, so they only run on request:
+    /// `cargo test -- --ignored screen_capturer_x11`.
+    fn xvfb() -> (MutexGuard<'static, ()>, &'static str) {
+        static SERVER: OnceLock<(Mutex<()>, String, RustConnection, Child)> = OnceLock::new();
+        let (lock, display, ..) = SERVER.get_or_init(|| {
+            let mut child = Command::new("Xvfb")
+                .args(["-displayfd", "1", "-nolisten", "tcp", "-terminate", "-br"])
+                .args(["-screen", "0", "640x480x24", "-screen", "1", "320x240x24"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .expect("Xvfb not available");
+            let mut number = String::new();
+            BufReader::new(child.stdout.take().unwrap()).read_line(&mut number).unwrap();
+            let display = format!(":{}", number.trim());
+            // Held until the test binary exits; `-terminate` stops Xvfb once it's gone.
+            let (keepalive, _) = connect(Some(&display)).unwrap();
+            (Mutex::new(()), display, keepalive, child)
+        });
+        (lock.lock(), display)
+    }
+
+    fn scale() -> ScaleOptions {
+        ScaleOptions::stretch(ScaleFilter::Nearest)
+    }
+
+    /// `ScreenCapturer::new` against the test server.
+    fn capture_display(
+        display: &str,
+        index: u32,
+        region: Option<CaptureRegion>,
+        width: u32,
+        height: u32,
+    ) -> Result<ScreenCapturer, RecorderError> {
+        ScreenCapturer::open_output(connect(Some(display))?, index, region, width, height, scale())
+    }
+
+    /// Map a solid `rgb` window on screen `screen` and wait until the server has drawn it.
+    fn solid_window(conn: &RustConnection, screen: usize, rgb: u32, title: &str, rect: (i16, i16, u16, u16)) -> Window {
+        let root = conn.setup().roots[screen].root;
+        let id = conn.generate_id().unwrap();
+        let (x, y, w, h) = rect;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            id,
+            root,
+            x,
+            y,
+            w,
+            h,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            0,
+            &CreateWindowAux::new().background_pixel(rgb).override_redirect(1),
+        )
+        .unwrap();
+        conn.change_property8(PropMode::REPLACE, id, AtomEnum::WM_NAME, AtomEnum::STRING, title.as_bytes())
+            .unwrap();
+        conn.map_window(id).unwrap();
+        sync(conn);
+        id
+    }
+
+    /// Virtual monitors on the first screen, deleted again on drop so a failed assertion
+    /// doesn't leave the shared screen split for later tests.
+    struct Monitors<'a> {
+        conn: &'a RustConnection,
+        root: Window,
+        names: Vec<Atom>,
+    }
+
+    impl Monitors<'_> {
+        /// Add a monitor like `xrandr --setmonitor`.
+        fn add(&mut self, name: &[u8], x: i16, width: u16, height: u16) {
+            let atom = intern(self.conn, name).unwrap();
+            let monitor = randr::MonitorInfo {
+                name: atom,
+                primary: false,
+                automatic: false,
+                x,
+                y: 0,
+                width,
+                height,
+                width_in_millimeters: width as u32 / 4,
+                height_in_millimeters: height as u32 / 4,
+                outputs: vec![],
+            };
+            self.conn.randr_set_monitor(self.root, monitor).unwrap().check().unwrap();
+            self.names.push(atom);
+        }
+    }
+
+    impl Drop for Monitors<'_> {
+        fn drop(&mut self) {
+            for &atom in &self.names {
+                let _ = self.conn.randr_delete_monitor(self.root, atom).map(|cookie| cookie.check());
+            }
+        }
+    }
+
+    fn sync(conn: &RustConnection) {
+        conn.get_input_focus().unwrap().reply().unwrap();
+    }
+
+    fn assert_solid(frame: &Frame, rgb: u32) {
+        let bgra = [rgb as u8, (rgb >> 8) as u8, (rgb >> 16) as u8, 255];
+        assert_eq!(frame.data.len(), frame.width as usize * frame.height as usize * 4);
+        assert!(frame.data.chunks_exact(4).all(|px| px == bgra), "frame isn't solid #{rgb:06x}");
+    }
+
+    fn output(root: Window, x: i16, y: i16, width: u16, height: u16) -> X11Output {
+        X11Output { name: format!("{root}"), root, x, y, width, height, primary: false }
+    }
+
+    #[test]
+    fn screens_without_randr_sit_side_by_side() {
+        let screens = [output(1, 0, 0, 640, 480), output(2, 0, 0, 320, 240), output(3, 0, 0, 800, 600)];
+        assert_eq!(desktop_positions(&screens), [(0, 0), (640, 0), (960, 0)]);
+    }
+
+    #[test]
+    fn monitors_keep_their_place_within_a_screen() {
+        let outputs = [
+            output(1, 0, 0, 1920, 1080),
+            output(1, 1920, 200, 1280, 1024),
+            output(2, 0, 0, 800, 600),
+            output(2, 0, 600, 800, 600),
+        ];
+        assert_eq!(desktop_positions(&outputs), [(0, 0), (1920, 200), (3200, 0), (3200, 600)]);
+    }
+
+    #[test]
+    #[ignore = "needs Xvfb"]
+    fn captures_a_region_as_bgra() {
+        let (_guard, display) = xvfb();
+        let (conn, _) = connect(Some(display)).unwrap();
+        let window = solid_window(&conn, 0, 0xff8000, "region", (100, 50, 64, 32));
+
+        let region = CaptureRegion { x: 100, y: 50, width: 64, height: 32 };
+        let mut capturer = capture_display(display, 0, Some(region), 64, 32).unwrap();
+        let frame = capturer.capture_frame().unwrap();
+        assert_eq!((frame.width, frame.height, frame.frame_number), (64, 32, 1));
+        assert_solid(&frame, 0xff8000);
+        assert_eq!(capturer.capture_area(), Some(CaptureArea { x: 100, y: 50, width: 64, height: 32 }));
+
+        conn.destroy_window(window).unwrap();
+        sync(&conn);
+    }
+
+    #[test]
+    #[ignore = "needs Xvfb"]
+    fn get_image_fallback_matches_shm() {
+        let (_guard, display) = xvfb();
+        let (conn, _) = connect(Some(display)).unwrap();
+        let window = solid_window(&conn, 0, 0x3366cc, "fallback", (0, 0, 320, 240));
+
+        let mut capturer = capture_display(display, 0, None, 160, 120).unwrap();
+        assert!(capturer.shm.is_some(), "Xvfb should offer MIT-SHM");
+        let with_shm = capturer.capture_frame().unwrap();
+        capturer.stop();
+        let with_get_image = capturer.capture_frame().unwrap();
+
+        assert_eq!((with_shm.width, with_shm.height), (160, 120));
+        assert!(with_shm.data[..] == with_get_image.data[..]);
+        // The window covers the top-left quarter of the screen.
+        let px = |f: &Frame, x: usize, y: usize| f.data[(y * 160 + x) * 4..][..4].to_vec();
+        assert_eq!(px(&with_get_image, 10, 10), [0xcc, 0x66, 0x33, 255]);
+        assert_eq!(px(&with_get_image, 150, 110), [0, 0, 0, 255]);
+
+        conn.destroy_window(window).unwrap();
+        sync(&conn);
+    }
+
+    #[test]
+    #[ignore = "needs Xvfb"]
+    fn rejects_a_display_that_does_not_exist() {
+        let (_guard, display) = xvfb();
+        let err = capture_display(display, 99, None, 64, 64).err().unwrap();
+        assert_eq!(err.code, "DEVICE_NOT_FOUND");
+    }
+
+    #[test]
+    #[ignore = "needs Xvfb"]
+    fn enumerates_every_screen() {
+        let (_guard, display) = xvfb();
+        let (conn, default_screen) = connect(Some(display)).unwrap();
+        let outputs = enumerate_outputs(&conn, default_screen).unwrap();
+        let roots: Vec<Window> = conn.setup().roots.iter().map(|s| s.root).collect();
+
+        let sizes: Vec<_> = roots
+            .iter()
+            .map(|&root| {
+                let o = outputs.iter().find(|o| o.root == root).expect("screen without an output");
+                (o.width, o.height)
+            })
+            .collect();
+        assert_eq!(sizes, [(640, 480), (320, 240)]);
+        assert!(outputs.iter().filter(|o| o.primary).count() <= 1);
+    }
+
+    #[test]
+    #[ignore = "needs Xvfb"]
+    fn randr_monitors_keep_their_index() {
+        let (_guard, display) = xvfb();
+        let (conn, default_screen) = connect(Some(display)).unwrap();
+        let root = conn.setup().roots[0].root;
+        if conn.randr_query_version(1, 5).unwrap().reply().unwrap().minor_version < 5 {
+            eprintln!("RandR 1.5 not available, skipping");
+            return;
+        }
+
+        // Split the first screen into two virtual monitors.
+        let mut monitors = Monitors { conn: &conn, root, names: Vec::new() };
+        monitors.add(b"RF-LEFT", 0, 320, 480);
+        monitors.add(b"RF-RIGHT", 320, 320, 480);
+        let left = solid_window(&conn, 0, 0x00ff00, "left", (0, 0, 320, 480));
+        let right = solid_window(&conn, 0, 0x0000ff, "right", (320, 0, 320, 480));
+
+        let outputs = enumerate_outputs(&conn, default_screen).unwrap();
+        let displays = crate::commands::devices::display_list(outputs.clone());
+        assert_eq!(displays.len(), outputs.len());
+        for (name, rgb, x) in [("RF-LEFT", 0x00ff00, 0), ("RF-RIGHT", 0x0000ff, 320)] {
+            let index = outputs.iter().position(|o| o.name == name).expect("monitor not listed");
+            let info = &displays[index];
+            assert_eq!((info.index, info.x, info.y, info.width, info.height), (index as u32, x, 0, 320, 480));
+
+            let mut capturer = capture_display(display, index as u32, None, 32, 48).unwrap();
+            assert_eq!(capturer.capture_area(), Some(CaptureArea { x, y: 0, width: 320, height: 480 }));
+            assert_solid(&capturer.capture_frame().unwrap(), rgb);
+        }
+
+        drop(monitors);
+        conn.destroy_window(left).unwrap();
+        conn.destroy_window(right).unwrap();
+        sync(&conn);
+    }
+
+    #[test]
+    #[ignore = "needs Xvfb"]
+    fn lists_mapped_windows_with_titles() {
+        let (_guard, display) = xvfb();
+        let (conn, _) = connect(Some(display)).unwrap();
+        let titled = solid_window(&conn, 1, 0xffffff, "RecordFlow test client", (10, 10, 50, 50));
+        let untitled = solid_window(&conn, 1, 0xffffff, "", (70, 10, 50, 50));
+
+        let windows = enumerate_windows(&conn).unwrap();
+        let found = windows.iter().find(|w| w.id == titled).expect("client window not listed");
+        assert_eq!(found.title, "RecordFlow test client");
+        assert!(windows.iter().all(|w| w.id != untitled));
+
+        conn.unmap_window(titled).unwrap();
+        sync(&conn);
+        assert!(enumerate_windows(&conn).unwrap().iter().all(|w| w.id != titled));
+
+        conn.destroy_window(titled).unwrap();
+        conn.destroy_window(untitled).unwrap();
+        sync(&conn);
+    }
+
+    #[test]
+    #[ignore = "needs Xvfb"]
+    fn window_capture_follows_the_window() {
+        let (_guard, display) = xvfb();
+        let (conn, _) = connect(Some(display)).unwrap();
+        let window = solid_window(&conn, 0, 0xcc2200, "follow me", (40, 30, 100, 60));
+
+        let connection = connect(Some(display)).unwrap().0;
+        let mut capturer = ScreenCapturer::open_window(connection, window as u64, 100, 60, scale()).unwrap();
+        assert_solid(&capturer.capture_frame().unwrap(), 0xcc2200);
+        assert_eq!(capturer.capture_area(), Some(CaptureArea { x: 40, y: 30, width: 100, height: 60 }));
+
+        // A menu over the window isn't recorded.
+        let menu = solid_window(&conn, 0, 0xffffff, "menu", (60, 40, 50, 30));
+        assert_solid(&capturer.capture_frame().unwrap(), 0xcc2200);
+        conn.destroy_window(menu).unwrap();
+
+        // Nor is the border, though the window's pixmap includes it.
+        conn.configure_window(window, &ConfigureWindowAux::new().x(300).y(200).width(200).height(120).border_width(4))
+            .unwrap();
+        sync(&conn);
+        let frame = capturer.capture_frame().unwrap();
+        assert_eq!((frame.width, frame.height), (100, 60));
+        assert_solid(&frame, 0xcc2200);
+        assert_eq!(capturer.capture_area(), Some(CaptureArea { x: 300, y: 200, width: 200, height: 120 }));
+
+        // Partly off screen, the whole window is still there.
+        conn.configure_window(window, &ConfigureWindowAux::new().x(590).y(0).width(100).height(60)).unwrap();
+        sync(&conn);
+        assert_solid(&capturer.capture_frame().unwrap(), 0xcc2200);
+
+        // Minimised, it comes out black.
+        conn.unmap_window(window).unwrap();
+        sync(&conn);
+        assert_solid(&capturer.capture_frame().unwrap(), 0x000000);
+
+        conn.destroy_window(window).unwrap();
+        sync(&conn);
+        assert_eq!(capturer.capture_frame().unwrap_err().code, "DEVICE_NOT_FOUND");
+    }
+}
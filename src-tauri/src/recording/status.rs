@@ -1,3 +1,5 @@
+use crate::recording::frame_pool::FramePoolStats;
+use crate::recording::pipeline::StageStats;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -6,4 +8,12 @@ pub struct RecordingStatus {
     pub is_paused: bool,
     pub output_file: Option<String>,
     pub elapsed_seconds: u64,
+    /// Unchanged frames folded into the previous sample instead of being encoded.
+    pub skipped_frames: u64,
+    /// Per-stage throughput and latency of the current recording.
+    pub pipeline: Vec<StageStats>,
+    /// Frame buffer allocations vs. reuses since the recording started.
+    pub frame_pool: FramePoolStats,
+    /// Problems the recording worked around, e.g. a microphone the encoder can't record.
+    pub warnings: Vec<String>,
 }
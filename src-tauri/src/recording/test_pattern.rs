@@ -0,0 +1,226 @@
+use crate::error::RecorderError;
+use crate::recording::frame_pool::FramePool;
+use crate::recording::frame_source::{Frame, FrameSource};
+
+// 75% color bars, BGRA.
+const BARS: [[u8; 4]; 7] = [
+    [192, 192, 192, 255], // white
+    [0, 192, 192, 255],   // yellow
+    [192, 192, 0, 255],   // cyan
+    [0, 192, 0, 255],     // green
+    [192, 0, 192, 255],   // magenta
+    [0, 0, 192, 255],     // red
+    [192, 0, 0, 255],     // blue
+];
+
+// 3x5 glyphs for 0-9. Each byte is one row; bit 2 is the leftmost column.
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// Deterministic synthetic video source.
+///
+/// Renders color bars over a gray ramp, a bouncing box and the frame counter. Output only
+/// depends on the frame number, so two runs with the same settings produce identical frames.
+/// Timestamps are derived from the frame number and `fps` rather than the wall clock.
+pub struct TestPatternSource {
+    width: u32,
+    height: u32,
+    fps: u32,
+    frame_number: u64,
+    background: Vec<u8>,
+}
+
+impl TestPatternSource {
+    pub fn new(width: u32, height: u32, fps: u32) -> Result<Self, RecorderError> {
+        if width == 0 || height == 0 {
+            return Err(RecorderError::invalid_settings("Invalid target resolution"));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            fps: fps.max(1),
+            frame_number: 0,
+            background: render_background(width, height),
+        })
+    }
+
+    fn fill_rect(&self, data: &mut [u8], x: u32, y: u32, w: u32, h: u32, color: [u8; 4]) {
+        let x1 = (x + w).min(self.width);
+        let y1 = (y + h).min(self.height);
+        for yy in y.min(y1)..y1 {
+            let row = yy as usize * self.width as usize;
+            for xx in x.min(x1)..x1 {
+                let i = (row + xx as usize) * 4;
+                data[i..i + 4].copy_from_slice(&color);
+            }
+        }
+    }
+
+    fn draw_box(&self, data: &mut [u8]) {
+        let size = (self.height / 6).max(1).min(self.width);
+        let span_x = (self.width - size).max(1) as u64;
+        let span_y = (self.height - size).max(1) as u64;
+
+        // Cross the frame horizontally in ~2 seconds, vertically a bit slower.
+        let step_x = (span_x / (self.fps as u64 * 2)).max(1);
+        let step_y = (span_y / (self.fps as u64 * 3)).max(1);
+
+        let x = bounce(self.frame_number * step_x, span_x) as u32;
+        let y = bounce(self.frame_number * step_y, span_y) as u32;
+        self.fill_rect(data, x, y, size, size, [255, 255, 255, 255]);
+    }
+
+    fn draw_counter(&self, data: &mut [u8]) {
+        let digits = self.frame_number.to_string();
+        let scale = (self.height / 120).max(2);
+        let glyph_w = 3 * scale;
+        let gap = scale;
+        let pad = scale * 2;
+
+        let text_w = digits.len() as u32 * (glyph_w + gap) - gap;
+        let text_h = 5 * scale;
+        self.fill_rect(data, 0, 0, text_w + pad * 2, text_h + pad * 2, [0, 0, 0, 255]);
+
+        for (i, ch) in digits.bytes().enumerate() {
+            let glyph = &DIGITS[(ch - b'0') as usize];
+            let gx = pad + i as u32 * (glyph_w + gap);
+            for (row, bits) in glyph.iter().enumerate() {
+                for col in 0..3u32 {
+                    if bits & (0b100 >> col) != 0 {
+                        self.fill_rect(
+                            data,
+                            gx + col * scale,
+                            pad + row as u32 * scale,
+                            scale,
+                            scale,
+                            [255, 255, 255, 255],
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl FrameSource for TestPatternSource {
+    fn capture_frame(&mut self) -> Result<Frame, RecorderError> {
+        self.frame_number += 1;
+
+        let mut data = FramePool::shared().copy_of(&self.background);
+        self.draw_box(&mut data);
+        self.draw_counter(&mut data);
+
+        let timestamp = (self.frame_number - 1) * 1000 / self.fps as u64;
+
+        Ok(Frame {
+            data: data.into(),
+            width: self.width,
+            height: self.height,
+            timestamp,
+            frame_number: self.frame_number,
+        })
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn stop(&mut self) {}
+}
+
+/// Triangle wave over `0..=span`.
+fn bounce(pos: u64, span: u64) -> u64 {
+    let p = pos % (span * 2);
+    if p > span {
+        span * 2 - p
+    } else {
+        p
+    }
+}
+
+fn render_background(width: u32, height: u32) -> Vec<u8> {
+    let mut out = vec![0u8; width as usize * height as usize * 4];
+    let bars_h = height * 2 / 3;
+
+    for y in 0..height {
+        for x in 0..width {
+            let px = if y < bars_h {
+                BARS[(x as u64 * BARS.len() as u64 / width as u64) as usize]
+            } else {
+                let v = (x as u64 * 255 / width.saturating_sub(1).max(1) as u64) as u8;
+                [v, v, v, 255]
+            };
+            let i = (y as usize * width as usize + x as usize) * 4;
+            out[i..i + 4].copy_from_slice(&px);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::test_util::{sink_config, temp_path};
+    use crate::recording::video_sink::SinkRegistry;
+    use crate::state::app_state::{Container, RecordingSettings, VideoBackend};
+    use std::time::Duration;
+
+    #[test]
+    fn frames_are_deterministic() {
+        let mut a = TestPatternSource::new(160, 90, 30).unwrap();
+        let mut b = TestPatternSource::new(160, 90, 30).unwrap();
+        for _ in 0..90 {
+            let (fa, fb) = (a.capture_frame().unwrap(), b.capture_frame().unwrap());
+            assert_eq!(fa.frame_number, fb.frame_number);
+            assert_eq!(fa.timestamp, fb.timestamp);
+            assert!(fa.data[..] == fb.data[..], "frame {} differs", fa.frame_number);
+        }
+    }
+
+    #[test]
+    fn frames_change_and_timestamps_follow_fps() {
+        let mut source = TestPatternSource::new(160, 90, 30).unwrap();
+        let first = source.capture_frame().unwrap();
+        let second = source.capture_frame().unwrap();
+        assert_eq!((first.timestamp, second.timestamp), (0, 33));
+        assert_eq!(first.data.len(), 160 * 90 * 4);
+        assert!(first.data[..] != second.data[..]);
+    }
+
+    #[test]
+    fn records_into_a_sink() {
+        let path = temp_path("test_pattern.y4m");
+        let (w, h, frames) = (64u32, 48u32, 10u64);
+        let settings = RecordingSettings { video_backend: VideoBackend::Intermediate, ..Default::default() };
+        let config = sink_config(path.clone(), w, h, 30, Container::Y4m);
+
+        let (_, mut sink) = SinkRegistry::builtin().open(&settings, &config).unwrap();
+        let mut source = TestPatternSource::new(w, h, 30).unwrap();
+        for _ in 0..frames {
+            let frame = source.capture_frame().unwrap();
+            sink.write_video(&frame.data, Duration::from_millis(frame.timestamp)).unwrap();
+        }
+        sink.finalize().unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        let header = data.iter().position(|&b| b == b'\n').unwrap() + 1;
+        let frame_len = b"FRAME\n".len() + (w * h * 3 / 2) as usize;
+        assert_eq!(data.len(), header + frames as usize * frame_len);
+        assert_eq!(data.windows(6).filter(|w| w == b"FRAME\n").count(), frames as usize);
+
+        for p in [path.clone(), path.with_extension("timecode.json")] {
+            let _ = std::fs::remove_file(p);
+        }
+    }
+}
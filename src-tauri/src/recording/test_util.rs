@@ -0,0 +1,289 @@
+//! Helpers shared by the unit tests in this module.
+
+use crate::error::RecorderError;
+use crate::recording::color_convert::ColorSpace;
+use crate::recording::frame_source::{Frame, FrameSource};
+use crate::recording::mux::MuxOptions;
+use crate::recording::video_sink::SinkConfig;
+use crate::state::app_state::{Container, RecordingSettings};
+use std::path::PathBuf;
+
+fn be16(data: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes(data[at..at + 2].try_into().unwrap())
+}
+
+fn be32(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+fn be64(data: &[u8], at: usize) -> u64 {
+    u64::from_be_bytes(data[at..at + 8].try_into().unwrap())
+}
+
+/// Fresh path in the temp directory, unique to this process and `name`.
+pub fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("recordflow-test-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// A source whose frames are one BGRA color.
+pub struct SolidSource {
+    pub width: u32,
+    pub height: u32,
+    pub bgra: [u8; 4],
+    frame_number: u64,
+}
+
+impl SolidSource {
+    pub fn new(width: u32, height: u32, bgra: [u8; 4]) -> Self {
+        Self { width, height, bgra, frame_number: 0 }
+    }
+}
+
+impl FrameSource for SolidSource {
+    fn capture_frame(&mut self) -> Result<Frame, RecorderError> {
+        self.frame_number += 1;
+        Ok(Frame {
+            data: self.bgra.repeat(self.width as usize * self.height as usize).into(),
+            width: self.width,
+            height: self.height,
+            timestamp: 0,
+            frame_number: self.frame_number,
+        })
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn stop(&mut self) {}
+}
+
+/// Sink config with the default settings, no audio.
+pub fn sink_config(path: PathBuf, width: u32, height: u32, fps: u32, container: Container) -> SinkConfig {
+    let settings = RecordingSettings::default();
+    SinkConfig {
+        path,
+        width,
+        height,
+        fps,
+        bitrate_kbps: settings.bitrate.max(1),
+        keyframe_interval: fps,
+        color: ColorSpace::from_settings(&settings),
+        container,
+        mux: MuxOptions::from_settings(&settings),
+        av1: settings.av1.clone(),
+        gif: settings.gif.clone(),
+        audio: None,
+    }
+}
+
+/// One box of an MP4 file.
+pub struct Mp4Box<'a> {
+    pub kind: [u8; 4],
+    /// File offset of the box header.
+    pub start: usize,
+    /// File offset of `body`.
+    pub body_start: usize,
+    pub body: &'a [u8],
+}
+
+impl<'a> Mp4Box<'a> {
+    pub fn children(&self) -> Vec<Mp4Box<'a>> {
+        mp4_boxes(self.body, self.body_start)
+    }
+
+    /// The first child of type `kind`.
+    pub fn child(&self, kind: &[u8; 4]) -> Option<Mp4Box<'a>> {
+        self.children().into_iter().find(|b| &b.kind == kind)
+    }
+
+    /// The first box down `path` from here, e.g. `[b"mdia", b"minf", b"stbl"]`.
+    pub fn find(&self, path: &[&[u8; 4]]) -> Option<Mp4Box<'a>> {
+        let (first, rest) = path.split_first()?;
+        let child = self.child(first)?;
+        if rest.is_empty() {
+            Some(child)
+        } else {
+            child.find(rest)
+        }
+    }
+}
+
+/// The boxes laid out back to back in `data`, which sits `start` bytes into the file. Panics
+/// on a box that overruns `data` or on bytes left after the last one.
+pub fn mp4_boxes(data: &[u8], start: usize) -> Vec<Mp4Box<'_>> {
+    let mut boxes = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        assert!(pos + 8 <= data.len(), "{} stray bytes at {}", data.len() - pos, start + pos);
+        let kind: [u8; 4] = data[pos + 4..pos + 8].try_into().unwrap();
+        let (header, len) = match be32(data, pos) {
+            0 => (8, data.len() - pos),
+            1 => (16, be64(data, pos + 8) as usize),
+            n => (8, n as usize),
+        };
+        let name = String::from_utf8_lossy(&kind);
+        assert!(len >= header && pos + len <= data.len(), "{name} at {} overruns its parent", start + pos);
+        let body = &data[pos + header..pos + len];
+        boxes.push(Mp4Box { kind, start: start + pos, body_start: start + pos + header, body });
+        pos += len;
+    }
+    boxes
+}
+
+/// A track of an MP4 file, read back through its sample table.
+pub struct Mp4Track {
+    /// Sample entry type, e.g. `avc1`.
+    pub codec: [u8; 4],
+    /// Body of the configuration box in the sample entry (`avcC`, `av1C`, `esds`, `dfLa`).
+    pub config: Vec<u8>,
+    pub timescale: u32,
+    pub samples: Vec<Vec<u8>>,
+    /// File offset of each sample.
+    pub offsets: Vec<u64>,
+    pub durations: Vec<u32>,
+    pub sync: Vec<bool>,
+}
+
+/// The tracks of an MP4 file, regular or fragmented.
+pub fn mp4_tracks(file: &[u8]) -> Vec<Mp4Track> {
+    let top = mp4_boxes(file, 0);
+    let moov = top.iter().find(|b| &b.kind == b"moov").expect("no moov");
+    let mut tracks: Vec<_> =
+        moov.children().iter().filter(|b| &b.kind == b"trak").map(|trak| read_track(file, trak)).collect();
+    for moof in top.iter().filter(|b| &b.kind == b"moof") {
+        for traf in moof.children().iter().filter(|b| &b.kind == b"traf") {
+            read_fragment(file, moof, traf, &mut tracks);
+        }
+    }
+    tracks
+}
+
+/// Append the samples of one `traf` to its track. Only handles offsets relative to `moof`
+/// with every sample's duration, size and flags spelled out in `trun`.
+fn read_fragment(file: &[u8], moof: &Mp4Box, traf: &Mp4Box, tracks: &mut [Mp4Track]) {
+    let tfhd = traf.child(b"tfhd").expect("no tfhd").body;
+    assert_eq!(be32(tfhd, 0) & 0xff_ffff, 0x02_0000, "tfhd flags");
+    let track = &mut tracks[be32(tfhd, 4) as usize - 1];
+    let tfdt = traf.child(b"tfdt").expect("no tfdt").body;
+    let start = if tfdt[0] == 1 { be64(tfdt, 4) } else { be32(tfdt, 4) as u64 };
+    assert_eq!(start, track.durations.iter().map(|&d| d as u64).sum::<u64>(), "tfdt of a fragment");
+
+    for trun in traf.children().iter().filter(|b| &b.kind == b"trun") {
+        let body = trun.body;
+        let flags = be32(body, 0) & 0xff_ffff;
+        assert_eq!(flags & 0x0701, 0x0701, "trun without data offset, durations, sizes or flags");
+        let mut pos = 8;
+        let mut next = || {
+            pos += 4;
+            be32(body, pos - 4)
+        };
+        let mut offset = (moof.start as i64 + next() as i32 as i64) as u64;
+        for _ in 0..be32(body, 4) {
+            let (duration, size, sample_flags) = (next(), next(), next());
+            if flags & 0x0800 != 0 {
+                next();
+            }
+            track.samples.push(file[offset as usize..offset as usize + size as usize].to_vec());
+            track.offsets.push(offset);
+            track.durations.push(duration);
+            // sample_is_non_sync_sample
+            track.sync.push(sample_flags & 0x1_0000 == 0);
+            offset += size as u64;
+        }
+    }
+}
+
+fn read_track(file: &[u8], trak: &Mp4Box) -> Mp4Track {
+    let mdhd = trak.find(&[b"mdia", b"mdhd"]).expect("no mdhd").body;
+    let timescale = if mdhd[0] == 1 { be32(mdhd, 20) } else { be32(mdhd, 12) };
+    let stbl = trak.find(&[b"mdia", b"minf", b"stbl"]).expect("no stbl");
+    let table = |kind: &[u8; 4]| stbl.child(kind).map(|b| b.body);
+
+    // Full box header, entry count, then the first entry; its configuration box follows
+    // the visual or audio fields.
+    let stsd = table(b"stsd").expect("no stsd");
+    let entry = mp4_boxes(&stsd[8..], 0).remove(0);
+    let fields = if matches!(&entry.kind, b"mp4a" | b"fLaC") { 28 } else { 78 };
+    let config = mp4_boxes(&entry.body[fields..], 0).remove(0).body.to_vec();
+
+    let stsz = table(b"stsz").expect("no stsz");
+    let count = be32(stsz, 8) as usize;
+    let sizes: Vec<u32> = match be32(stsz, 4) {
+        0 => (0..count).map(|i| be32(stsz, 12 + 4 * i)).collect(),
+        size => vec![size; count],
+    };
+
+    let stts = table(b"stts").expect("no stts");
+    let durations: Vec<u32> = (0..be32(stts, 4) as usize)
+        .flat_map(|i| std::iter::repeat_n(be32(stts, 12 + 8 * i), be32(stts, 8 + 8 * i) as usize))
+        .collect();
+
+    let sync = match table(b"stss") {
+        Some(stss) => {
+            let numbers: Vec<u32> = (0..be32(stss, 4) as usize).map(|i| be32(stss, 8 + 4 * i)).collect();
+            (1..=count as u32).map(|n| numbers.contains(&n)).collect()
+        }
+        None => vec![true; count],
+    };
+
+    let chunks: Vec<u64> = match (table(b"stco"), table(b"co64")) {
+        (Some(stco), _) => (0..be32(stco, 4) as usize).map(|i| be32(stco, 8 + 4 * i) as u64).collect(),
+        (None, Some(co64)) => (0..be32(co64, 4) as usize).map(|i| be64(co64, 8 + 8 * i)).collect(),
+        (None, None) => panic!("no chunk offsets"),
+    };
+    let stsc = table(b"stsc").expect("no stsc");
+    // (first chunk, samples per chunk) runs.
+    let runs: Vec<(usize, usize)> = (0..be32(stsc, 4) as usize)
+        .map(|i| (be32(stsc, 8 + 12 * i) as usize, be32(stsc, 12 + 12 * i) as usize))
+        .collect();
+    let mut offsets = Vec::with_capacity(count);
+    for (chunk, &chunk_offset) in chunks.iter().enumerate() {
+        let per_chunk = runs.iter().rev().find(|&&(first, _)| first <= chunk + 1).expect("chunk before stsc").1;
+        let mut offset = chunk_offset;
+        for _ in 0..per_chunk {
+            offsets.push(offset);
+            offset += sizes[offsets.len() - 1] as u64;
+        }
+    }
+    assert_eq!(offsets.len(), count, "stsc and stco disagree with stsz");
+
+    let samples = offsets
+        .iter()
+        .zip(&sizes)
+        .map(|(&at, &len)| file[at as usize..at as usize + len as usize].to_vec())
+        .collect();
+    Mp4Track { codec: entry.kind, config, timescale, samples, offsets, durations, sync }
+}
+
+/// SPS and PPS from an `avcC` body.
+pub fn avc_parameter_sets(avcc: &[u8]) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+    let mut pos = 5;
+    let mut read = |count_mask: u8| {
+        let count = (avcc[pos] & count_mask) as usize;
+        pos += 1;
+        (0..count)
+            .map(|_| {
+                let len = be16(avcc, pos) as usize;
+                pos += 2 + len;
+                avcc[pos - len..pos].to_vec()
+            })
+            .collect::<Vec<_>>()
+    };
+    let sps = read(0x1f);
+    let pps = read(0xff);
+    (sps, pps)
+}
+
+/// Decode an MP4 FLAC track: its `dfLa` metadata (past the full box header) and frames
+/// make up a `.flac` stream again.
+pub fn decode_flac_track(track: &Mp4Track) -> Vec<i16> {
+    assert_eq!(&track.codec, b"fLaC");
+    let mut stream = b"fLaC".to_vec();
+    stream.extend_from_slice(&track.config[4..]);
+    track.samples.iter().for_each(|frame| stream.extend_from_slice(frame));
+    let mut reader = claxon::FlacReader::new(std::io::Cursor::new(stream)).unwrap();
+    reader.samples().map(|s| s.unwrap() as i16).collect()
+}
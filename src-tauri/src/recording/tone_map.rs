@@ -0,0 +1,230 @@
+use crate::error::RecorderError;
+use crate::recording::frame_pool::{FrameBuffer, FramePool};
+use crate::state::app_state::{HdrSettings, ToneMapCurve};
+
+/// scRGB, the format of `Rgba16F` desktop frames, puts 1.0 at 80 nits.
+const SCRGB_UNIT_NITS: f32 = 80.0;
+/// Non-negative finite half floats; 0x7C00 and up are infinities and NaNs.
+const HALF_ENTRIES: usize = 0x7C00;
+
+impl ToneMapCurve {
+    /// Map `x` (1.0 = SDR white) so that `peak` lands on 1.0.
+    fn map(self, x: f32, peak: f32) -> f32 {
+        let y = match self {
+            ToneMapCurve::Reinhard => x * (1.0 + x / (peak * peak)) / (1.0 + x),
+            // The 2.0 is Hable's usual exposure bias.
+            ToneMapCurve::Hable => hable(2.0 * x) / hable(2.0 * peak),
+            ToneMapCurve::Aces => aces(x) / aces(peak),
+        };
+        y.clamp(0.0, 1.0)
+    }
+}
+
+fn hable(x: f32) -> f32 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+    (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+}
+
+fn aces(x: f32) -> f32 {
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+/// Value of a non-negative, finite half float.
+fn half_to_f32(bits: u16) -> f32 {
+    let exponent = (bits >> 10) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        e => (1.0 + mantissa / 1024.0) * 2f32.powi(e - 15),
+    }
+}
+
+fn srgb_encode(linear: f32) -> f32 {
+    if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Turns linear scRGB `Rgba16F` frames into SDR BGRA.
+///
+/// Each channel goes through the curve on its own, as these curves were designed for, so
+/// very bright colors desaturate towards white rather than clipping to a flat hue. Negative
+/// (out-of-gamut) channels become zero. The curve and the sRGB encoding are baked into one
+/// table keyed by the raw half-float bits, which keeps a 4K frame to a few lookups per pixel.
+pub struct ToneMapper {
+    lut: Vec<u8>,
+}
+
+impl ToneMapper {
+    pub fn new(curve: ToneMapCurve, peak_nits: f32, sdr_white_nits: f32) -> Result<Self, RecorderError> {
+        if !(sdr_white_nits.is_finite() && sdr_white_nits > 0.0) {
+            return Err(RecorderError::invalid_settings("SDR white level must be above 0 nits"));
+        }
+        if !(peak_nits.is_finite() && (sdr_white_nits..=10_000.0).contains(&peak_nits)) {
+            return Err(RecorderError::invalid_settings(format!(
+                "HDR peak must be between the SDR white level ({sdr_white_nits} nits) and 10000 nits"
+            )));
+        }
+
+        let peak = peak_nits / sdr_white_nits;
+        let to_relative = SCRGB_UNIT_NITS / sdr_white_nits;
+        let lut = (0..HALF_ENTRIES as u16)
+            .map(|bits| {
+                let mapped = curve.map(half_to_f32(bits) * to_relative, peak);
+                (srgb_encode(mapped) * 255.0).round() as u8
+            })
+            .collect();
+
+        Ok(Self { lut })
+    }
+
+    pub fn from_settings(hdr: &HdrSettings) -> Result<Self, RecorderError> {
+        Self::new(hdr.curve, hdr.peak_nits, hdr.sdr_white_nits)
+    }
+
+    /// Tone-map into a buffer from the shared `FramePool`.
+    pub fn map(&self, rgba16f: &[u8], width: u32, height: u32) -> Result<FrameBuffer, RecorderError> {
+        let mut out = FramePool::shared().take(width as usize * height as usize * 4);
+        self.map_into(rgba16f, width, height, &mut out)?;
+        Ok(out)
+    }
+
+    /// Tone-map a tightly packed `Rgba16F` frame (little-endian halves) into `dst`, which
+    /// must hold exactly `width * height` BGRA pixels.
+    pub fn map_into(&self, rgba16f: &[u8], width: u32, height: u32, dst: &mut [u8]) -> Result<(), RecorderError> {
+        let pixels = width as usize * height as usize;
+        if rgba16f.len() != pixels * 8 || dst.len() != pixels * 4 {
+            return Err(RecorderError::invalid_settings(format!(
+                "HDR frame size mismatch: {} bytes into {} for {width}x{height}",
+                rgba16f.len(),
+                dst.len()
+            )));
+        }
+
+        let channel = |lo: u8, hi: u8| self.lut[Self::index(u16::from_le_bytes([lo, hi]))];
+        for (src, out) in rgba16f.chunks_exact(8).zip(dst.chunks_exact_mut(4)) {
+            out.copy_from_slice(&[
+                channel(src[4], src[5]),
+                channel(src[2], src[3]),
+                channel(src[0], src[1]),
+                255,
+            ]);
+        }
+        Ok(())
+    }
+
+    /// Table index of a half float: negatives become zero, infinities and NaN the largest
+    /// finite value.
+    #[inline(always)]
+    fn index(bits: u16) -> usize {
+        if bits & 0x8000 != 0 {
+            0
+        } else {
+            (bits as usize).min(HALF_ENTRIES - 1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZERO: u16 = 0x0000;
+    const NEG_ZERO: u16 = 0x8000;
+    const HALF: u16 = 0x3800;
+    const ONE: u16 = 0x3C00;
+    const FOUR: u16 = 0x4400;
+    /// 12.5, i.e. 1000 nits.
+    const PEAK: u16 = 0x4A40;
+    const TWICE_PEAK: u16 = 0x4E40;
+    const MAX: u16 = 0x7BFF;
+    const MINUS_ONE: u16 = 0xBC00;
+    const INF: u16 = 0x7C00;
+    const NEG_INF: u16 = 0xFC00;
+    const NAN: u16 = 0x7E00;
+
+    /// 1000 nit peak; scRGB 1.0 is SDR white.
+    fn mapper(curve: ToneMapCurve) -> ToneMapper {
+        ToneMapper::new(curve, 1000.0, SCRGB_UNIT_NITS).unwrap()
+    }
+
+    /// Map a grey pixel with every channel set to `bits`; returns the BGRA output.
+    fn map_grey(mapper: &ToneMapper, bits: u16) -> [u8; 4] {
+        let [lo, hi] = bits.to_le_bytes();
+        let src = [lo, hi, lo, hi, lo, hi, 0x00, 0x3C];
+        let mut out = [0u8; 4];
+        mapper.map_into(&src, 1, 1, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn decodes_half_floats() {
+        assert_eq!(half_to_f32(ZERO), 0.0);
+        assert_eq!(half_to_f32(ONE), 1.0);
+        assert_eq!(half_to_f32(PEAK), 12.5);
+        assert_eq!(half_to_f32(MAX), 65504.0);
+        assert_eq!(half_to_f32(0x0001), 2f32.powi(-24));
+    }
+
+    #[test]
+    fn known_values() {
+        // sRGB-encoded output for 0.5, 1.0 and 4.0 times SDR white.
+        for (curve, expected) in [
+            (ToneMapCurve::Reinhard, [156, 188, 234]),
+            (ToneMapCurve::Hable, [141, 176, 232]),
+            (ToneMapCurve::Aces, [205, 230, 250]),
+        ] {
+            let mapper = mapper(curve);
+            for (bits, want) in [HALF, ONE, FOUR].into_iter().zip(expected) {
+                let [b, g, r, a] = map_grey(&mapper, bits);
+                assert!((b, g, a) == (r, r, 255) && r.abs_diff(want) <= 1, "{curve:?} {bits:#06x} -> {r}");
+            }
+        }
+    }
+
+    #[test]
+    fn black_peak_and_beyond() {
+        for curve in [ToneMapCurve::Reinhard, ToneMapCurve::Hable, ToneMapCurve::Aces] {
+            let mapper = mapper(curve);
+            assert_eq!(map_grey(&mapper, ZERO), [0, 0, 0, 255], "{curve:?}");
+            assert_eq!(map_grey(&mapper, NEG_ZERO), [0, 0, 0, 255], "{curve:?}");
+            for bits in [PEAK, TWICE_PEAK, MAX] {
+                assert_eq!(map_grey(&mapper, bits), [255, 255, 255, 255], "{curve:?} {bits:#06x}");
+            }
+        }
+    }
+
+    #[test]
+    fn non_finite_and_negative_inputs() {
+        for curve in [ToneMapCurve::Reinhard, ToneMapCurve::Hable, ToneMapCurve::Aces] {
+            let mapper = mapper(curve);
+            assert_eq!(map_grey(&mapper, INF), [255, 255, 255, 255], "{curve:?}");
+            assert_eq!(map_grey(&mapper, NAN), [255, 255, 255, 255], "{curve:?}");
+            assert_eq!(map_grey(&mapper, NEG_INF), [0, 0, 0, 255], "{curve:?}");
+            assert_eq!(map_grey(&mapper, MINUS_ONE), [0, 0, 0, 255], "{curve:?}");
+        }
+    }
+
+    #[test]
+    fn curves_are_monotonic() {
+        for curve in [ToneMapCurve::Reinhard, ToneMapCurve::Hable, ToneMapCurve::Aces] {
+            let lut = mapper(curve).lut;
+            assert!(lut.windows(2).all(|w| w[0] <= w[1]), "{curve:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_bad_levels() {
+        assert!(ToneMapper::new(ToneMapCurve::Aces, 1000.0, 0.0).is_err());
+        assert!(ToneMapper::new(ToneMapCurve::Aces, 100.0, 200.0).is_err());
+        assert!(ToneMapper::new(ToneMapCurve::Aces, f32::NAN, 200.0).is_err());
+        assert!(ToneMapper::new(ToneMapCurve::Aces, 20_000.0, 200.0).is_err());
+    }
+}
@@ -1,3 +1,5 @@
 // Kept for backwards-compat with the previous module name. 
-// Implementation lives in video_encoder_mf.rs. 
+// Implementation lives in video_encoder_mf.rs; the worker opens encoders through
+// video_sink::SinkRegistry.
+#[cfg(windows)]
 pub use super::video_encoder_mf::VideoEncoder;
@@ -0,0 +1,463 @@
+use crate::error::RecorderError;
+use crate::recording::gif_palette::Palette;
+use crate::recording::scaler::{ScaleOptions, Scaler};
+use crate::recording::video_sink::{SinkCapabilities, SinkConfig, VideoSink};
+use crate::state::app_state::{GifPalette, GifSettings, ScaleFilter};
+use gif::{DisposalMethod, Encoder, Frame, Repeat};
+use std::borrow::Cow;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Browsers stretch GIF delays under 2/100 s to 1/10 s, so faster clips would play slower.
+const MAX_FPS: u32 = 50;
+
+/// Encodes tried before giving up on `max_size_kb` and keeping the smallest.
+const MAX_SHRINK_ATTEMPTS: usize = 5;
+
+/// Memory the stored frames may take before the clip is cut off: about 75 s at the default
+/// 800 px width, 16:9 and 10 fps.
+const MAX_STORED_BYTES: usize = 1 << 30;
+
+fn gif_err(e: gif::EncodingError) -> RecorderError {
+    RecorderError::encoding_failed(format!("GIF encode: {e}"))
+}
+
+/// GIF delays are in hundredths of a second.
+fn centis(d: Duration) -> u64 {
+    (d.as_millis() as u64 + 5) / 10
+}
+
+/// A frame kept for the GIF, at the stored size.
+struct Still {
+    bgra: Vec<u8>,
+    time: Duration,
+}
+
+/// Smallest `(x, y, width, height)` holding every pixel that differs between two frames;
+/// `None` if they are identical.
+fn changed_rect(prev: &[u8], next: &[u8], width: usize, height: usize) -> Option<(usize, usize, usize, usize)> {
+    let row = |y: usize| width * 4 * y..width * 4 * (y + 1);
+    let top = (0..height).find(|&y| prev[row(y)] != next[row(y)])?;
+    let bottom = (top..height).rev().find(|&y| prev[row(y)] != next[row(y)])?;
+
+    let (mut left, mut right) = (width, 0);
+    for y in top..=bottom {
+        let (p, n) = (&prev[row(y)], &next[row(y)]);
+        let differs = |x: &usize| p[x * 4..x * 4 + 4] != n[x * 4..x * 4 + 4];
+        if let Some(x) = (0..left).find(differs) {
+            left = x;
+        }
+        if let Some(x) = (right..width).rev().find(differs) {
+            right = x;
+        }
+    }
+    Some((left, top, right - left + 1, bottom - top + 1))
+}
+
+/// Animated GIF of the composited frames.
+///
+/// Frames are thinned out to `GifSettings::fps`, capped at `max_width` and kept in memory;
+/// the palettes need the whole clip, and `max_size_kb` can only be checked on the encoded
+/// file, so everything is encoded when the recording stops. Only the part of each frame
+/// that changed is stored, on top of the frame before it.
+///
+/// The clip ends where the kept frames reach `MAX_STORED_BYTES`. That and a missed
+/// `max_size_kb` come back through `take_warnings`.
+pub struct GifWriter {
+    path: PathBuf,
+    options: GifSettings,
+
+    width: u32,
+    height: u32,
+    /// Size frames are stored at.
+    stored_width: u32,
+    stored_height: u32,
+    fps: u32,
+    scaler: Scaler,
+
+    /// Latest frame at the input size and its slot on the `fps` grid, until a later slot
+    /// shows it is the one to keep.
+    pending: Option<(Vec<u8>, u64)>,
+    stills: Vec<Still>,
+    /// Cap on the memory `stills` take; frames past it are left out.
+    max_stored_bytes: usize,
+    /// Set once `max_stored_bytes` is reached.
+    full: bool,
+    warnings: Vec<String>,
+    /// Latest recording time the last frame is known to be on screen.
+    end: Duration,
+    finished: bool,
+}
+
+impl GifWriter {
+    pub fn new(config: &SinkConfig) -> Result<Self, RecorderError> {
+        let SinkConfig { width, height, fps, .. } = *config;
+        let options = config.gif.clone();
+        if width == 0 || height == 0 {
+            return Err(RecorderError::invalid_settings("GIF needs a non-zero frame size"));
+        }
+
+        let (stored_width, stored_height) = match options.max_width {
+            max if max > 0 && width > max => (max, (height as u64 * max as u64 / width as u64).max(1) as u32),
+            _ => (width, height),
+        };
+        if stored_width > u16::MAX as u32 || stored_height > u16::MAX as u32 {
+            return Err(RecorderError::invalid_settings(format!(
+                "GIF frames can't be larger than 65535x65535, got {stored_width}x{stored_height}"
+            )));
+        }
+        let fps = match options.fps {
+            0 => fps,
+            gif_fps => gif_fps.min(fps),
+        }
+        .clamp(1, MAX_FPS);
+
+        Ok(Self {
+            path: config.path.clone(),
+            options,
+            width,
+            height,
+            stored_width,
+            stored_height,
+            fps,
+            scaler: Scaler::new(ScaleOptions::stretch(ScaleFilter::Bicubic)),
+            pending: None,
+            stills: Vec::new(),
+            max_stored_bytes: MAX_STORED_BYTES,
+            full: false,
+            warnings: Vec::new(),
+            end: Duration::ZERO,
+            finished: false,
+        })
+    }
+
+    /// Slot on the frame grid that `elapsed` falls into.
+    fn frame_index(&self, elapsed: Duration) -> u64 {
+        (elapsed.as_nanos() * self.fps as u128 / 1_000_000_000) as u64
+    }
+
+    /// Keep the pending frame if something from a later slot replaces or holds it.
+    fn commit_before(&mut self, slot: u64) {
+        if self.pending.as_ref().is_some_and(|&(_, pending)| pending < slot) {
+            self.commit();
+        }
+    }
+
+    fn commit(&mut self) {
+        let Some((bgra, slot)) = self.pending.take() else {
+            return;
+        };
+        let time = Duration::from_nanos(slot * 1_000_000_000 / self.fps as u64);
+        let still_len = self.stored_width as usize * self.stored_height as usize * 4;
+        if (self.stills.len() + 1) * still_len > self.max_stored_bytes {
+            self.full = true;
+            // The last kept frame stays up until the cut.
+            self.end = time.saturating_sub(Duration::from_secs(1) / self.fps);
+            self.warnings.push(format!(
+                "The GIF stops at {:.0} s: longer clips at {}x{} and {} fps don't fit in memory",
+                time.as_secs_f64(),
+                self.stored_width,
+                self.stored_height,
+                self.fps
+            ));
+            return;
+        }
+        let bgra = if (self.stored_width, self.stored_height) == (self.width, self.height) {
+            bgra
+        } else {
+            let mut scaled = vec![0; self.stored_width as usize * self.stored_height as usize * 4];
+            self.scaler.scale_into(&bgra, self.width, self.height, &mut scaled, self.stored_width, self.stored_height);
+            scaled
+        };
+        self.stills.push(Still { bgra, time });
+    }
+
+    /// Encode the stills at `width` x `height`.
+    fn encode(&self, stills: &[Still], width: u32, height: u32) -> Result<Vec<u8>, RecorderError> {
+        let (w, h) = (width as usize, height as usize);
+        let mut global = (self.options.palette == GifPalette::Global)
+            .then(|| Palette::build(self.options.quantizer, stills.iter().map(|s| s.bgra.as_slice())));
+        let global_rgb = global.as_ref().map(Palette::rgb).unwrap_or_default();
+
+        let mut encoder = Encoder::new(Vec::new(), width as u16, height as u16, &global_rgb).map_err(gif_err)?;
+        encoder.set_repeat(Repeat::Infinite).map_err(gif_err)?;
+
+        let end = self.end.max(stills.last().map_or(Duration::ZERO, |s| s.time)) + Duration::from_secs(1) / self.fps;
+        let mut queued: Option<Frame<'static>> = None;
+        let mut crop = Vec::new();
+        for (i, still) in stills.iter().enumerate() {
+            let until = stills.get(i + 1).map_or(end, |next| next.time);
+            let delay = centis(until).saturating_sub(centis(still.time)).min(u16::MAX as u64) as u16;
+
+            let rect = match i.checked_sub(1) {
+                Some(prev) => changed_rect(&stills[prev].bgra, &still.bgra, w, h),
+                None => Some((0, 0, w, h)),
+            };
+            let Some((x, y, rw, rh)) = rect else {
+                // Nothing changed; the previous frame just stays up longer.
+                if let Some(frame) = queued.as_mut() {
+                    frame.delay = frame.delay.saturating_add(delay);
+                }
+                continue;
+            };
+
+            crop.clear();
+            for row in y..y + rh {
+                crop.extend_from_slice(&still.bgra[(row * w + x) * 4..(row * w + x + rw) * 4]);
+            }
+            let (indices, palette) = match global.as_mut() {
+                Some(palette) => (palette.map(&crop, rw, rh, self.options.dither), None),
+                None => {
+                    let mut palette = Palette::build(self.options.quantizer, std::iter::once(crop.as_slice()));
+                    (palette.map(&crop, rw, rh, self.options.dither), Some(palette.rgb()))
+                }
+            };
+
+            let frame = Frame {
+                delay,
+                dispose: DisposalMethod::Keep,
+                left: x as u16,
+                top: y as u16,
+                width: rw as u16,
+                height: rh as u16,
+                palette,
+                buffer: Cow::Owned(indices),
+                ..Frame::default()
+            };
+            if let Some(prev) = queued.replace(frame) {
+                encoder.write_frame(&prev).map_err(gif_err)?;
+            }
+        }
+        if let Some(last) = queued {
+            encoder.write_frame(&last).map_err(gif_err)?;
+        }
+        Ok(encoder.into_inner()?)
+    }
+
+    /// Encode at the stored size, then smaller until the file fits `max_size_kb`.
+    fn encode_within_limit(&mut self) -> Result<Vec<u8>, RecorderError> {
+        let limit = self.options.max_size_kb as usize * 1024;
+        let (mut width, mut height) = (self.stored_width, self.stored_height);
+        let mut bytes = self.encode(&self.stills, width, height)?;
+
+        let mut scaler = Scaler::new(ScaleOptions::stretch(ScaleFilter::Bicubic));
+        for _ in 1..MAX_SHRINK_ATTEMPTS {
+            if limit == 0 || bytes.len() <= limit || width <= 1 || height <= 1 {
+                break;
+            }
+            // Size goes roughly with the pixel count; aim a little under the limit.
+            let factor = (limit as f64 / bytes.len() as f64).sqrt().clamp(0.5, 0.9);
+            width = ((width as f64 * factor) as u32).max(1);
+            height = ((height as f64 * factor) as u32).max(1);
+
+            let smaller: Vec<Still> = self
+                .stills
+                .iter()
+                .map(|s| {
+                    let mut bgra = vec![0; width as usize * height as usize * 4];
+                    scaler.scale_into(&s.bgra, self.stored_width, self.stored_height, &mut bgra, width, height);
+                    Still { bgra, time: s.time }
+                })
+                .collect();
+            bytes = self.encode(&smaller, width, height)?;
+        }
+
+        if limit > 0 && bytes.len() > limit {
+            self.warnings.push(format!(
+                "The GIF is {} KiB at {width}x{height}, over the {} KiB target",
+                bytes.len() / 1024,
+                self.options.max_size_kb
+            ));
+        }
+        Ok(bytes)
+    }
+}
+
+impl VideoSink for GifWriter {
+    fn capabilities(&self) -> SinkCapabilities {
+        SinkCapabilities { audio: false, variable_frame_rate: true }
+    }
+
+    fn audio_samples_needed(&self, _elapsed: Duration) -> usize {
+        0
+    }
+
+    fn write_video(&mut self, bgra: &[u8], elapsed: Duration) -> Result<(), RecorderError> {
+        let expected = self.width as usize * self.height as usize * 4;
+        if bgra.len() != expected {
+            return Err(RecorderError::encoding_failed(format!(
+                "BGRA frame size mismatch: got {} bytes, expected {}",
+                bgra.len(),
+                expected
+            )));
+        }
+        if self.finished {
+            return Err(RecorderError::encoding_failed("GIF writer already finalized"));
+        }
+        if self.full {
+            return Ok(());
+        }
+
+        let slot = self.frame_index(elapsed);
+        self.commit_before(slot);
+        if self.full {
+            return Ok(());
+        }
+        // A later frame in the same slot replaces the pending one; reuse its buffer.
+        let mut buffer = self.pending.take().map(|(b, _)| b).unwrap_or_default();
+        buffer.clear();
+        buffer.extend_from_slice(bgra);
+        self.pending = Some((buffer, slot));
+        self.end = self.end.max(elapsed);
+        Ok(())
+    }
+
+    fn hold_video(&mut self, elapsed: Duration) {
+        if self.full {
+            return;
+        }
+        self.commit_before(self.frame_index(elapsed));
+        if !self.full {
+            self.end = self.end.max(elapsed);
+        }
+    }
+
+    fn write_audio(&mut self, _pcm_i16le: &[u8]) -> Result<(), RecorderError> {
+        Err(RecorderError::encoding_failed("GIF has no audio"))
+    }
+
+    fn finalize(&mut self) -> Result<(), RecorderError> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        self.commit();
+        if self.stills.is_empty() {
+            return Err(RecorderError::encoding_failed("No frames were recorded for the GIF"));
+        }
+
+        let bytes = self.encode_within_limit()?;
+        fs::write(&self.path, bytes)?;
+        self.stills = Vec::new();
+        Ok(())
+    }
+
+    fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+}
+
+impl Drop for GifWriter {
+    fn drop(&mut self) {
+        // Best-effort: nothing reaches the disk until the clip is encoded.
+        let _ = self.finalize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::frame_source::FrameSource;
+    use crate::recording::test_pattern::TestPatternSource;
+    use crate::recording::test_util::{sink_config, temp_path};
+    use crate::state::app_state::Container;
+
+    /// Feed `seconds` of the test pattern at 30 fps.
+    fn record(writer: &mut GifWriter, width: u32, height: u32, seconds: u32) {
+        let mut source = TestPatternSource::new(width, height, 30).unwrap();
+        for n in 0..seconds * 30 {
+            let frame = source.capture_frame().unwrap();
+            writer.write_video(&frame.data, Duration::from_secs(n as u64) / 30).unwrap();
+        }
+        writer.hold_video(Duration::from_secs(seconds as u64));
+    }
+
+    /// Frame count and total delay in hundredths of a second.
+    fn read_gif(path: &std::path::Path) -> (usize, u32) {
+        let mut decoder = gif::DecodeOptions::new().read_info(fs::File::open(path).unwrap()).unwrap();
+        let (mut frames, mut delay) = (0, 0);
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames += 1;
+            delay += frame.delay as u32;
+        }
+        (frames, delay)
+    }
+
+    #[test]
+    fn stops_at_the_memory_cap() {
+        let path = temp_path("capped.gif");
+        let (w, h) = (32u32, 24u32);
+        let mut writer = GifWriter::new(&sink_config(path.clone(), w, h, 30, Container::Gif)).unwrap();
+        // Room for 15 frames, 1.5 s at the default 10 fps.
+        writer.max_stored_bytes = 15 * (w * h * 4) as usize;
+        record(&mut writer, w, h, 3);
+        writer.finalize().unwrap();
+
+        let warnings = writer.take_warnings();
+        assert!(matches!(&warnings[..], [w] if w.starts_with("The GIF stops at 2 s")), "{warnings:?}");
+        assert!(writer.take_warnings().is_empty());
+        assert_eq!(writer.stills.len(), 0);
+        let (frames, delay) = read_gif(&path);
+        assert!(frames <= 15, "{frames} frames");
+        assert_eq!(delay, 150);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reports_a_missed_size_target() {
+        let path = temp_path("oversized.gif");
+        let (w, h) = (64u32, 48u32);
+        let mut config = sink_config(path.clone(), w, h, 30, Container::Gif);
+        config.gif.max_size_kb = 1;
+        let mut writer = GifWriter::new(&config).unwrap();
+        record(&mut writer, w, h, 2);
+        writer.finalize().unwrap();
+
+        let warnings = writer.take_warnings();
+        assert!(matches!(&warnings[..], [w] if w.ends_with("over the 1 KiB target")), "{warnings:?}");
+        let (_, delay) = read_gif(&path);
+        assert!((200..=210).contains(&delay), "{delay}");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn scales_to_max_width_with_a_palette_per_frame() {
+        let path = temp_path("per_frame.gif");
+        let (w, h) = (64u32, 48u32);
+        let mut config = sink_config(path.clone(), w, h, 30, Container::Gif);
+        config.gif.max_width = 32;
+        config.gif.palette = GifPalette::PerFrame;
+        config.gif.dither = true;
+        let mut writer = GifWriter::new(&config).unwrap();
+        record(&mut writer, w, h, 1);
+        writer.finalize().unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (32, 24));
+
+        // Scaled down, quantized and dithered, the first frame keeps the pattern's colors on
+        // average.
+        let original = TestPatternSource::new(w, h, 30).unwrap().capture_frame().unwrap();
+        let mean = |pixels: &[u8], channel: usize| {
+            pixels.chunks_exact(4).map(|px| px[channel] as f64).sum::<f64>() / (pixels.len() / 4) as f64
+        };
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert!(frame.left + frame.width <= 32 && frame.top + frame.height <= 24);
+            assert!(frame.palette.as_ref().is_some_and(|p| !p.is_empty() && p.len() <= 3 * 256));
+            if frames == 0 {
+                assert_eq!((frame.width, frame.height), (32, 24));
+                for (rgba, bgra) in [(0, 2), (1, 1), (2, 0)] {
+                    let (got, want) = (mean(&frame.buffer, rgba), mean(&original.data, bgra));
+                    assert!((got - want).abs() < 8.0, "channel {rgba}: {got:.1} against {want:.1}");
+                }
+            }
+            frames += 1;
+        }
+        assert!(frames > 1);
+        fs::remove_file(&path).unwrap();
+    }
+}
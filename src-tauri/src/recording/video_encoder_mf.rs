@@ -1,4 +1,7 @@
 use crate::error::RecorderError;
+use crate::recording::color_convert::{ColorConverter, ColorSpace, YuvLayout};
+use crate::recording::video_sink::{SinkCapabilities, SinkConfig, VideoSink};
+use crate::state::app_state::{ColorMatrix, ColorRange};
 use std::time::Duration;
 use windows::core::{HSTRING, PCWSTR};
 use windows::Win32::Media::MediaFoundation::*;
@@ -20,6 +23,33 @@ fn win_err(context: &str, e: windows::core::Error) -> RecorderError {
     RecorderError::encoding_failed(format!("{context}: {e}"))
 }
 
+/// Tag a video media type with `space`. The H.264 encoder carries it into the SPS VUI and
+/// the MPEG-4 sink into the `colr` box, so players don't have to guess.
+unsafe fn set_color_space(media_type: &IMFMediaType, space: ColorSpace, label: &str) -> Result<(), RecorderError> {
+    let (matrix, primaries) = match space.matrix {
+        ColorMatrix::Bt601 => (MFVideoTransferMatrix_BT601, MFVideoPrimaries_SMPTE170M),
+        ColorMatrix::Bt709 => (MFVideoTransferMatrix_BT709, MFVideoPrimaries_BT709),
+    };
+    let range = match space.range {
+        ColorRange::Limited => MFNominalRange_16_235,
+        ColorRange::Full => MFNominalRange_0_255,
+    };
+    media_type
+        .SetUINT32(&MF_MT_YUV_MATRIX, matrix.0 as u32)
+        .map_err(|e| win_err(&format!("SetUINT32({label}.yuv_matrix)"), e))?;
+    media_type
+        .SetUINT32(&MF_MT_VIDEO_PRIMARIES, primaries.0 as u32)
+        .map_err(|e| win_err(&format!("SetUINT32({label}.primaries)"), e))?;
+    // BT.601 uses the same transfer curve as BT.709.
+    media_type
+        .SetUINT32(&MF_MT_TRANSFER_FUNCTION, MFVideoTransFunc_709.0 as u32)
+        .map_err(|e| win_err(&format!("SetUINT32({label}.transfer)"), e))?;
+    media_type
+        .SetUINT32(&MF_MT_VIDEO_NOMINAL_RANGE, range.0 as u32)
+        .map_err(|e| win_err(&format!("SetUINT32({label}.nominal_range)"), e))?;
+    Ok(())
+}
+
 pub struct VideoEncoder {
     writer: IMFSinkWriter,
     video_stream: u32,
@@ -28,8 +58,13 @@ pub struct VideoEncoder {
     width: u32,
     height: u32,
     fps: u32,
+    /// Frames arrive as BGRA and are handed to Media Foundation as NV12.
+    converter: ColorConverter,
 
-    last_video_time_hns: Option<i64>,
+    /// Last video sample, held back until the next distinct frame tells us its duration.
+    pending_video: Option<(IMFSample, i64)>,
+    /// Time up to which the pending sample is known to stay on screen.
+    video_end_hns: i64,
 
     audio_sample_rate: Option<u32>,
     audio_channels: Option<u16>,
@@ -40,14 +75,15 @@ pub struct VideoEncoder {
 }
 
 impl VideoEncoder {
-    pub fn new(
-        output_path: &str,
-        width: u32,
-        height: u32,
-        fps: u32,
-        bitrate_kbps: u32,
-        audio_cfg: Option<(u32, u16)>,
-    ) -> Result<Self, RecorderError> {
+    pub fn new(config: &SinkConfig) -> Result<Self, RecorderError> {
+        let SinkConfig { width, height, fps, bitrate_kbps, keyframe_interval, color, audio: audio_cfg, .. } = *config;
+        if config.mux.fragment_duration.is_some() && config.mux.defragment {
+            // `Auto` moves on to a backend that honours it.
+            return Err(RecorderError::invalid_settings(
+                "Media Foundation can't defragment a fragmented MP4; turn off fragments or defragmenting",
+            ));
+        }
+
         unsafe {
             CoInitializeEx(None, COINIT_MULTITHREADED)
                 .ok()
@@ -59,7 +95,7 @@ impl VideoEncoder {
             .map(|(sr, ch)| (Some(sr.max(1)), Some(ch.max(1))))
             .unwrap_or((None, None));
 
-        let url = HSTRING::from(output_path);
+        let url = HSTRING::from(config.path.as_os_str());
         let url = PCWSTR(url.as_ptr());
 
         let mut attrs: Option<IMFAttributes> = None;
@@ -68,6 +104,18 @@ impl VideoEncoder {
         }
         let attrs = attrs.ok_or_else(|| RecorderError::encoding_failed("MFCreateAttributes returned null"))?;
         let _ = unsafe { attrs.SetUINT32(&MF_READWRITE_ENABLE_HARDWARE_TRANSFORMS, 1) };
+        if config.mux.fragment_duration.is_some() {
+            // The fragmented MP4 sink cuts a fragment at every keyframe, so `keyframe_interval`
+            // sets the fragment length. It can't defragment afterwards, hence the check above.
+            unsafe {
+                attrs
+                    .SetGUID(&MF_TRANSCODE_CONTAINERTYPE, &MFTranscodeContainerType_FMPEG4)
+                    .map_err(|e| win_err("SetGUID(container_type)", e))?;
+            }
+        } else if config.mux.faststart {
+            // Windows 10+; older sinks ignore it and keep `moov` at the end.
+            let _ = unsafe { attrs.SetUINT32(&MF_MPEG4SINK_MOOV_BEFORE_MDAT, 1) };
+        }
 
         let writer = unsafe {
             MFCreateSinkWriterFromURL(url, None, Some(&attrs))
@@ -86,6 +134,11 @@ impl VideoEncoder {
             video_out
                 .SetUINT32(&MF_MT_AVG_BITRATE, bitrate_kbps.saturating_mul(1000))
                 .map_err(|e| win_err("SetUINT32(video_out.bitrate)", e))?;
+            if keyframe_interval > 0 {
+                video_out
+                    .SetUINT32(&MF_MT_MAX_KEYFRAME_SPACING, keyframe_interval)
+                    .map_err(|e| win_err("SetUINT32(video_out.keyframe_spacing)", e))?;
+            }
             video_out
                 .SetUINT32(&MF_MT_INTERLACE_MODE, MFVideoInterlace_Progressive.0 as u32)
                 .map_err(|e| win_err("SetUINT32(video_out.interlace)", e))?;
@@ -98,18 +151,19 @@ impl VideoEncoder {
             video_out
                 .SetUINT64(&MF_MT_PIXEL_ASPECT_RATIO, pack_u32_pair(1, 1))
                 .map_err(|e| win_err("SetUINT64(video_out.par)", e))?;
+            set_color_space(&video_out, color, "video_out")?;
         }
 
         let video_stream = unsafe { writer.AddStream(&video_out).map_err(|e| win_err("AddStream(video)", e))? };
 
-        // Video input (ARGB32 ~= BGRA in memory)
+        // Video input (NV12, converted by us so the matrix and range match the tags)
         let video_in = unsafe { MFCreateMediaType().map_err(|e| win_err("MFCreateMediaType(video_in)", e))? };
         unsafe {
             video_in
                 .SetGUID(&MF_MT_MAJOR_TYPE, &MFMediaType_Video)
                 .map_err(|e| win_err("SetGUID(video_in.major)", e))?;
             video_in
-                .SetGUID(&MF_MT_SUBTYPE, &MFVideoFormat_ARGB32)
+                .SetGUID(&MF_MT_SUBTYPE, &MFVideoFormat_NV12)
                 .map_err(|e| win_err("SetGUID(video_in.subtype)", e))?;
             video_in
                 .SetUINT32(&MF_MT_INTERLACE_MODE, MFVideoInterlace_Progressive.0 as u32)
@@ -124,8 +178,9 @@ impl VideoEncoder {
                 .SetUINT64(&MF_MT_PIXEL_ASPECT_RATIO, pack_u32_pair(1, 1))
                 .map_err(|e| win_err("SetUINT64(video_in.par)", e))?;
             video_in
-                .SetUINT32(&MF_MT_DEFAULT_STRIDE, width.saturating_mul(4))
+                .SetUINT32(&MF_MT_DEFAULT_STRIDE, width)
                 .map_err(|e| win_err("SetUINT32(video_in.stride)", e))?;
+            set_color_space(&video_in, color, "video_in")?;
 
             writer
                 .SetInputMediaType(video_stream, &video_in, None)
@@ -209,7 +264,9 @@ impl VideoEncoder {
             width,
             height,
             fps: fps.max(1),
-            last_video_time_hns: None,
+            converter: ColorConverter::new(color),
+            pending_video: None,
+            video_end_hns: 0,
             audio_sample_rate,
             audio_channels,
             audio_written_frames: 0,
@@ -218,28 +275,24 @@ impl VideoEncoder {
         })
     }
 
-    pub fn audio_samples_needed_for_elapsed(&self, elapsed: Duration) -> Option<usize> {
-        let sr = self.audio_sample_rate? as u128;
-        let ch = self.audio_channels? as u128;
-
-        let desired_frames = (elapsed.as_nanos() * sr / 1_000_000_000) as u64;
-        let to_write = desired_frames.saturating_sub(self.audio_written_frames);
-        Some((to_write as usize).saturating_mul(ch as usize))
+    fn frame_period_hns(&self) -> i64 {
+        (HNS_PER_SEC / self.fps as i64).max(1)
     }
 
-    pub fn encode_frame(&mut self, bgra: &[u8], elapsed: Duration) -> Result<(), RecorderError> {
-        self.encode_frame_internal(bgra, elapsed)?;
-        Ok(())
-    }
+    /// Write the held-back sample, lasting until `end_hns`.
+    fn flush_pending_video(&mut self, end_hns: i64) -> Result<(), RecorderError> {
+        let Some((sample, time_hns)) = self.pending_video.take() else {
+            return Ok(());
+        };
 
-    pub fn encode_frame_with_audio(
-        &mut self,
-        bgra: &[u8],
-        elapsed: Duration,
-        audio_pcm_i16le: &[u8],
-    ) -> Result<(), RecorderError> {
-        self.encode_frame_internal(bgra, elapsed)?;
-        self.encode_audio_internal(audio_pcm_i16le)?;
+        unsafe {
+            sample
+                .SetSampleDuration((end_hns - time_hns).max(1))
+                .map_err(|e| win_err("IMFSample::SetSampleDuration(video)", e))?;
+            self.writer
+                .WriteSample(self.video_stream, &sample)
+                .map_err(|e| win_err("WriteSample(video)", e))?;
+        }
         Ok(())
     }
 
@@ -257,14 +310,11 @@ impl VideoEncoder {
         }
 
         let time_hns = duration_to_hns(elapsed);
-        let duration_hns = match self.last_video_time_hns {
-            Some(prev) if time_hns > prev => (time_hns - prev).max(1),
-            _ => (HNS_PER_SEC / self.fps as i64).max(1),
-        };
-        self.last_video_time_hns = Some(time_hns);
+        self.flush_pending_video(time_hns)?;
 
+        let nv12_len = YuvLayout::Nv12.frame_len(self.width, self.height);
         let buffer = unsafe {
-            MFCreateMemoryBuffer(bgra.len() as u32).map_err(|e| win_err("MFCreateMemoryBuffer(video)", e))?
+            MFCreateMemoryBuffer(nv12_len as u32).map_err(|e| win_err("MFCreateMemoryBuffer(video)", e))?
         };
 
         unsafe {
@@ -278,12 +328,19 @@ impl VideoEncoder {
                     Some(&mut cur_len as *mut u32),
                 )
                 .map_err(|e| win_err("IMFMediaBuffer::Lock(video)", e))?;
-            std::ptr::copy_nonoverlapping(bgra.as_ptr(), ptr, bgra.len());
+            let converted = self.converter.convert_into(
+                bgra,
+                self.width,
+                self.height,
+                YuvLayout::Nv12,
+                std::slice::from_raw_parts_mut(ptr, nv12_len),
+            );
             buffer
                 .Unlock()
                 .map_err(|e| win_err("IMFMediaBuffer::Unlock(video)", e))?;
+            converted?;
             buffer
-                .SetCurrentLength(bgra.len() as u32)
+                .SetCurrentLength(nv12_len as u32)
                 .map_err(|e| win_err("IMFMediaBuffer::SetCurrentLength(video)", e))?;
 
             let sample = MFCreateSample().map_err(|e| win_err("MFCreateSample(video)", e))?;
@@ -293,14 +350,10 @@ impl VideoEncoder {
             sample
                 .SetSampleTime(time_hns)
                 .map_err(|e| win_err("IMFSample::SetSampleTime(video)", e))?;
-            sample
-                .SetSampleDuration(duration_hns)
-                .map_err(|e| win_err("IMFSample::SetSampleDuration(video)", e))?;
 
-            self.writer
-                .WriteSample(self.video_stream, &sample)
-                .map_err(|e| win_err("WriteSample(video)", e))?;
+            self.pending_video = Some((sample, time_hns));
         }
+        self.video_end_hns = time_hns.saturating_add(self.frame_period_hns());
 
         Ok(())
     }
@@ -382,8 +435,40 @@ impl VideoEncoder {
 
         Ok(())
     }
+}
+
+impl VideoSink for VideoEncoder {
+    fn capabilities(&self) -> SinkCapabilities {
+        SinkCapabilities { audio: true, variable_frame_rate: true }
+    }
+
+    fn audio_samples_needed(&self, elapsed: Duration) -> usize {
+        let (Some(sr), Some(ch)) = (self.audio_sample_rate, self.audio_channels) else {
+            return 0;
+        };
+
+        let desired_frames = (elapsed.as_nanos() * sr as u128 / 1_000_000_000) as u64;
+        let to_write = desired_frames.saturating_sub(self.audio_written_frames);
+        (to_write as usize).saturating_mul(ch as usize)
+    }
+
+    fn write_video(&mut self, bgra: &[u8], elapsed: Duration) -> Result<(), RecorderError> {
+        self.encode_frame_internal(bgra, elapsed)
+    }
+
+    /// Stretch the previous sample instead of encoding an identical picture. This is what
+    /// makes the output variable frame rate.
+    fn hold_video(&mut self, elapsed: Duration) {
+        let end = duration_to_hns(elapsed).saturating_add(self.frame_period_hns());
+        self.video_end_hns = self.video_end_hns.max(end);
+    }
+
+    fn write_audio(&mut self, pcm_i16le: &[u8]) -> Result<(), RecorderError> {
+        self.encode_audio_internal(pcm_i16le)
+    }
 
-    pub fn finalize(&mut self) -> Result<(), RecorderError> {
+    fn finalize(&mut self) -> Result<(), RecorderError> {
+        self.flush_pending_video(self.video_end_hns)?;
         unsafe {
             self.writer.Finalize().map_err(|e| win_err("Finalize", e))?;
         }
@@ -0,0 +1,458 @@
+use crate::error::RecorderError;
+use crate::recording::audio_encoder_flac::FlacEncoder;
+use crate::recording::color_convert::{ColorConverter, ColorSpace, YuvLayout};
+use crate::recording::mux::{open_muxer, AudioCodec, Muxer, Sample, TrackConfig, VideoCodec};
+use crate::recording::video_sink::{SinkCapabilities, SinkConfig, VideoSink};
+#[cfg(not(feature = "openh264-source"))]
+use crate::utils::paths::get_app_data_dir;
+use openh264::encoder::{
+    BitRate, EncodedBitStream, Encoder, EncoderConfig, FrameRate, FrameType, IntraFramePeriod, RateControlMode,
+    UsageType,
+};
+use openh264::formats::YUVSlices;
+use openh264::OpenH264API;
+use std::time::Duration;
+
+/// Video track ticks per second; 90 kHz divides every common frame rate.
+const TIMESCALE: u32 = 90_000;
+
+const NAL_SPS: u8 = 7;
+const NAL_PPS: u8 = 8;
+
+fn to_ticks(d: Duration) -> u64 {
+    (d.as_nanos() * TIMESCALE as u128 / 1_000_000_000) as u64
+}
+
+fn h264_err(context: &str, e: impl std::fmt::Display) -> RecorderError {
+    RecorderError::encoding_failed(format!("{context}: {e}"))
+}
+
+/// File name of Cisco's OpenH264 2.6.0 build for this platform, the release `openh264` 0.8
+/// is written against and checks the hash of.
+#[cfg(not(feature = "openh264-source"))]
+fn cisco_library() -> Option<&'static str> {
+    let name = match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("windows", "x86_64") => "openh264-2.6.0-win64.dll",
+        ("windows", "x86") => "openh264-2.6.0-win32.dll",
+        ("linux", "x86_64") => "libopenh264-2.6.0-linux64.7.so",
+        ("linux", "x86") => "libopenh264-2.6.0-linux32.7.so",
+        ("linux", "aarch64") => "libopenh264-2.6.0-linux-arm64.7.so",
+        ("linux", "arm") => "libopenh264-2.6.0-linux-arm.7.so",
+        ("macos", "aarch64") => "libopenh264-2.6.0-mac-arm64.dylib",
+        ("macos", "x86_64") => "libopenh264-2.6.0-mac-x64.dylib",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// The OpenH264 library to encode with: Cisco's binary from the app data directory or next
+/// to the executable, wherever the installer downloaded it to. With the `openh264-source`
+/// feature, the copy compiled into this build.
+pub fn load_api() -> Result<OpenH264API, RecorderError> {
+    #[cfg(feature = "openh264-source")]
+    return Ok(OpenH264API::from_source());
+
+    #[cfg(not(feature = "openh264-source"))]
+    {
+        let (os, arch) = (std::env::consts::OS, std::env::consts::ARCH);
+        let name = cisco_library()
+            .ok_or_else(|| RecorderError::encoding_failed(format!("Cisco doesn't ship OpenH264 for {os}-{arch}")))?;
+        let exe_dir = std::env::current_exe().ok().and_then(|exe| exe.parent().map(|dir| dir.to_path_buf()));
+        let dirs: Vec<_> = [get_app_data_dir().ok(), exe_dir].into_iter().flatten().collect();
+        if let Some(path) = dirs.iter().map(|dir| dir.join(name)).find(|path| path.is_file()) {
+            return OpenH264API::from_blob_path(&path)
+                .map_err(|e| h264_err(&format!("Can't load {}", path.display()), e));
+        }
+        let searched: Vec<_> = dirs.iter().map(|dir| dir.display().to_string()).collect();
+        Err(RecorderError::encoding_failed(format!(
+            "OpenH264 needs Cisco's {name} (http://ciscobinary.openh264.org/{name}.bz2, unpacked) in {}",
+            searched.join(" or ")
+        )))
+    }
+}
+
+/// Drop the Annex-B start code (`00 00 01` or `00 00 00 01`) in front of a NAL unit.
+fn strip_start_code(nal: &[u8]) -> &[u8] {
+    let zeros = nal.iter().take_while(|&&b| b == 0).count();
+    match nal.get(zeros) {
+        Some(1) if zeros >= 2 => &nal[zeros + 1..],
+        _ => nal,
+    }
+}
+
+/// One encoded picture as length-prefixed NAL units, with the parameter sets taken out for
+/// the track header.
+struct AccessUnit {
+    data: Vec<u8>,
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+}
+
+impl AccessUnit {
+    fn from_annex_b(bitstream: &EncodedBitStream) -> Self {
+        let mut unit = Self { data: Vec::new(), sps: None, pps: None };
+        for l in 0..bitstream.num_layers() {
+            let Some(layer) = bitstream.layer(l) else { continue };
+            for n in 0..layer.nal_count() {
+                let Some(nal) = layer.nal_unit(n).map(strip_start_code) else { continue };
+                match nal.first().map(|b| b & 0x1f) {
+                    Some(NAL_SPS) => unit.sps = Some(nal.to_vec()),
+                    Some(NAL_PPS) => unit.pps = Some(nal.to_vec()),
+                    Some(_) => {
+                        unit.data.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+                        unit.data.extend_from_slice(nal);
+                    }
+                    None => {}
+                }
+            }
+        }
+        unit
+    }
+}
+
+/// An encoded picture, held back until the next one tells us how long it lasts.
+struct PendingSample {
+    time: u64,
+    is_sync: bool,
+    data: Vec<u8>,
+}
+
+/// A FLAC frame as a sample; every one of them decodes on its own.
+fn audio_sample(frame: &[u8], samples_per_channel: u32) -> Sample<'_> {
+    Sample { data: frame, duration: samples_per_channel, composition_offset: 0, is_sync: true }
+}
+
+/// The microphone track: lossless FLAC, since there is no AAC encoder on this path.
+struct AudioTrack {
+    encoder: FlacEncoder,
+    sample_rate: u32,
+    channels: u16,
+    /// Added together with the video track.
+    track_id: Option<u32>,
+    /// Frames encoded before the tracks exist, with their samples per channel.
+    queued: Vec<(Vec<u8>, u32)>,
+    /// Samples per channel taken so far.
+    received: u64,
+}
+
+/// Software H.264 through Cisco's OpenH264, written to MP4 or Matroska.
+///
+/// Meant for machines where Media Foundation or its hardware encoder is missing, including
+/// Linux. The microphone goes in as FLAC, which MP4 and Matroska players both handle.
+/// Fails to open until Cisco's library is in place, see `load_api`.
+pub struct OpenH264Encoder {
+    encoder: Encoder,
+    /// `None` once finalized.
+    muxer: Option<Box<dyn Muxer>>,
+    /// The track is added with the first IDR, whose SPS/PPS it needs.
+    track_id: Option<u32>,
+
+    width: u32,
+    height: u32,
+    fps: u32,
+    color: ColorSpace,
+    converter: ColorConverter,
+    i420: Vec<u8>,
+
+    pending: Option<PendingSample>,
+    /// Time up to which the pending sample is known to stay on screen.
+    end: u64,
+
+    /// `None` without a microphone.
+    audio: Option<AudioTrack>,
+}
+
+impl OpenH264Encoder {
+    pub fn new(config: &SinkConfig) -> Result<Self, RecorderError> {
+        let SinkConfig { width, height, fps, bitrate_kbps, keyframe_interval, color, .. } = *config;
+        if width == 0 || height == 0 || !width.is_multiple_of(2) || !height.is_multiple_of(2) {
+            return Err(RecorderError::invalid_settings(format!(
+                "OpenH264 needs an even, non-zero frame size, got {width}x{height}"
+            )));
+        }
+        let fps = fps.max(1);
+
+        let mut encoder_config = EncoderConfig::new()
+            .usage_type(UsageType::ScreenContentRealTime)
+            .rate_control_mode(RateControlMode::Bitrate)
+            .bitrate(BitRate::from_bps(bitrate_kbps.saturating_mul(1000)))
+            .max_frame_rate(FrameRate::from_hz(fps as f32));
+        if keyframe_interval > 0 {
+            encoder_config = encoder_config.intra_frame_period(IntraFramePeriod::from_num_frames(keyframe_interval));
+        }
+        let encoder = Encoder::with_api_config(load_api()?, encoder_config)
+            .map_err(|e| h264_err("OpenH264 init failed", e))?;
+
+        let audio = match config.audio {
+            Some((sample_rate, channels)) => Some(AudioTrack {
+                encoder: FlacEncoder::new(sample_rate, channels)?,
+                sample_rate,
+                channels,
+                track_id: None,
+                queued: Vec::new(),
+                received: 0,
+            }),
+            None => None,
+        };
+        let muxer = open_muxer(&config.path, config.container, config.mux)?;
+
+        Ok(Self {
+            encoder,
+            muxer: Some(muxer),
+            track_id: None,
+            width,
+            height,
+            fps,
+            color,
+            converter: ColorConverter::new(color),
+            i420: vec![0; YuvLayout::I420.frame_len(width, height)],
+            pending: None,
+            end: 0,
+            audio,
+        })
+    }
+
+    fn frame_period(&self) -> u64 {
+        (TIMESCALE / self.fps).max(1) as u64
+    }
+
+    fn muxer(&mut self) -> Result<&mut Box<dyn Muxer>, RecorderError> {
+        self.muxer
+            .as_mut()
+            .ok_or_else(|| RecorderError::encoding_failed("OpenH264 encoder already finalized"))
+    }
+
+    /// Add the video track, which needs the parameter sets of the first IDR, and the audio
+    /// track with whatever audio came before it.
+    fn add_tracks(&mut self, sps: Option<Vec<u8>>, pps: Option<Vec<u8>>) -> Result<(), RecorderError> {
+        let (Some(seq_param_set), Some(pic_param_set)) = (sps, pps) else {
+            return Err(RecorderError::encoding_failed("OpenH264 started without SPS/PPS"));
+        };
+        let track = TrackConfig::Video {
+            codec: VideoCodec::Avc { sps: seq_param_set, pps: pic_param_set },
+            width: self.width,
+            height: self.height,
+            timescale: TIMESCALE,
+            // OpenH264 leaves the colour description out of the SPS.
+            color: Some(self.color),
+        };
+        self.track_id = Some(self.muxer()?.add_track(track)?);
+
+        let Some(mut audio) = self.audio.take() else {
+            return Ok(());
+        };
+        let track = TrackConfig::Audio {
+            codec: AudioCodec::Flac { stream_info: audio.encoder.stream_info().to_vec() },
+            sample_rate: audio.sample_rate,
+            channels: audio.channels,
+        };
+        let result = self.muxer().and_then(|muxer| {
+            let track_id = muxer.add_track(track)?;
+            audio.track_id = Some(track_id);
+            for (frame, len) in audio.queued.drain(..) {
+                muxer.write_sample(track_id, audio_sample(&frame, len))?;
+            }
+            Ok(())
+        });
+        self.audio = Some(audio);
+        result
+    }
+
+    /// Write the audio frames ready so far, down to a partial one with `flush`.
+    fn write_audio_frames(&mut self, flush: bool) -> Result<(), RecorderError> {
+        let Some(audio) = self.audio.as_mut() else {
+            return Ok(());
+        };
+        while let Some((frame, len)) = audio.encoder.next_frame(flush) {
+            match (audio.track_id, self.muxer.as_mut()) {
+                (Some(track_id), Some(muxer)) => muxer.write_sample(track_id, audio_sample(&frame, len))?,
+                _ => audio.queued.push((frame, len)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the held-back sample, lasting until `end`.
+    fn flush_pending(&mut self, end: u64) -> Result<(), RecorderError> {
+        let (Some(sample), Some(track_id)) = (self.pending.take(), self.track_id) else {
+            return Ok(());
+        };
+
+        let duration = end.saturating_sub(sample.time).clamp(1, u32::MAX as u64) as u32;
+        self.muxer()?.write_sample(
+            track_id,
+            Sample { data: &sample.data, duration, composition_offset: 0, is_sync: sample.is_sync },
+        )
+    }
+}
+
+impl VideoSink for OpenH264Encoder {
+    fn capabilities(&self) -> SinkCapabilities {
+        SinkCapabilities { audio: true, variable_frame_rate: true }
+    }
+
+    fn audio_samples_needed(&self, elapsed: Duration) -> usize {
+        let Some(audio) = &self.audio else {
+            return 0;
+        };
+        let desired = (elapsed.as_nanos() * audio.sample_rate as u128 / 1_000_000_000) as u64;
+        (desired.saturating_sub(audio.received) as usize).saturating_mul(audio.channels as usize)
+    }
+
+    fn write_video(&mut self, bgra: &[u8], elapsed: Duration) -> Result<(), RecorderError> {
+        let expected = self.width as usize * self.height as usize * 4;
+        if bgra.len() != expected {
+            return Err(RecorderError::encoding_failed(format!(
+                "BGRA frame size mismatch: got {} bytes, expected {}",
+                bgra.len(),
+                expected
+            )));
+        }
+
+        self.converter
+            .convert_into(bgra, self.width, self.height, YuvLayout::I420, &mut self.i420)?;
+        let (frame_type, unit) = {
+            let (w, h) = (self.width as usize, self.height as usize);
+            let (y, chroma) = self.i420.split_at(w * h);
+            let (u, v) = chroma.split_at(w * h / 4);
+            let yuv = YUVSlices::new((y, u, v), (w, h), (w, w / 2, w / 2));
+            let bitstream = self.encoder.encode(&yuv).map_err(|e| h264_err("OpenH264 encode", e))?;
+            (bitstream.frame_type(), AccessUnit::from_annex_b(&bitstream))
+        };
+        if matches!(frame_type, FrameType::Skip | FrameType::Invalid) {
+            // Rate control dropped the picture; the previous one stays up instead.
+            self.hold_video(elapsed);
+            return Ok(());
+        }
+        let is_sync = matches!(frame_type, FrameType::IDR);
+        if self.track_id.is_none() {
+            self.add_tracks(unit.sps, unit.pps)?;
+        }
+        let data = unit.data;
+
+        // Sample times have to increase even if two frames land on the same tick.
+        let time = match &self.pending {
+            Some(prev) => to_ticks(elapsed).max(prev.time + 1),
+            None => to_ticks(elapsed),
+        };
+        self.flush_pending(time)?;
+        self.pending = Some(PendingSample { time, is_sync, data });
+        self.end = time + self.frame_period();
+        Ok(())
+    }
+
+    fn hold_video(&mut self, elapsed: Duration) {
+        self.end = self.end.max(to_ticks(elapsed) + self.frame_period());
+    }
+
+    fn write_audio(&mut self, pcm_i16le: &[u8]) -> Result<(), RecorderError> {
+        let Some(audio) = self.audio.as_mut() else {
+            return Err(RecorderError::encoding_failed("OpenH264 sink was opened without audio"));
+        };
+        let pcm: Vec<i16> = pcm_i16le.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        audio.encoder.push(&pcm);
+        audio.received += (pcm.len() / audio.channels as usize) as u64;
+        self.write_audio_frames(false)
+    }
+
+    fn finalize(&mut self) -> Result<(), RecorderError> {
+        self.flush_pending(self.end)?;
+        if self.muxer.is_some() {
+            self.write_audio_frames(true)?;
+        }
+        if let Some(muxer) = self.muxer.take() {
+            muxer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for OpenH264Encoder {
+    fn drop(&mut self) {
+        // Best-effort: keep whatever was encoded playable.
+        let _ = self.finalize();
+    }
+}
+
+// Needs the library compiled in, since Cisco's binary isn't around in CI:
+// `cargo test --features openh264-source openh264`.
+#[cfg(all(test, feature = "openh264-source"))]
+mod tests {
+    use super::*;
+    use crate::recording::frame_source::FrameSource;
+    use crate::recording::mux::MuxOptions;
+    use crate::recording::test_pattern::TestPatternSource;
+    use crate::recording::test_util::{avc_parameter_sets, decode_flac_track, mp4_tracks, sink_config, temp_path};
+    use crate::state::app_state::Container;
+    use openh264::decoder::{Decoder, DecoderConfig};
+    use openh264::formats::YUVSource;
+
+    fn put_annex_b(nal: &[u8], out: &mut Vec<u8>) {
+        out.extend_from_slice(&[0, 0, 0, 1]);
+        out.extend_from_slice(nal);
+    }
+
+    #[test]
+    fn encoded_frames_decode() {
+        let api = load_api().unwrap();
+        let path = temp_path("openh264.mp4");
+        let (w, h, frames) = (160u32, 96u32, 45);
+        let mut config = sink_config(path.clone(), w, h, 30, Container::Mp4);
+        config.mux = MuxOptions { faststart: false, fragment_duration: None, defragment: false };
+        config.audio = Some((48_000, 2));
+
+        let mut sink = OpenH264Encoder::new(&config).unwrap();
+        let mut source = TestPatternSource::new(w, h, 30).unwrap();
+        let mut originals = Vec::new();
+        let mut pcm = Vec::new();
+        for _ in 0..frames {
+            let frame = source.capture_frame().unwrap();
+            let elapsed = Duration::from_millis(frame.timestamp);
+            // A ramp, so every sample differs from its neighbours.
+            let needed = sink.audio_samples_needed(elapsed);
+            let chunk: Vec<i16> = (pcm.len()..pcm.len() + needed).map(|i| (i * 31 % 4000) as i16 - 2000).collect();
+            sink.write_audio(&chunk.iter().flat_map(|s| s.to_le_bytes()).collect::<Vec<_>>()).unwrap();
+            pcm.extend(chunk);
+            sink.write_video(&frame.data, elapsed).unwrap();
+            originals.push(frame.data.to_vec());
+        }
+        sink.finalize().unwrap();
+
+        let tracks = mp4_tracks(&std::fs::read(&path).unwrap());
+        let _ = std::fs::remove_file(&path);
+        let [track, audio] = &tracks[..] else { panic!("expected two tracks, got {}", tracks.len()) };
+        assert_eq!(&track.codec, b"avc1");
+        assert_eq!(audio.timescale, 48_000);
+        assert!(!pcm.is_empty() && decode_flac_track(audio) == pcm, "audio decodes differently");
+        assert_eq!(track.samples.len(), frames);
+        assert!(track.sync[0] && track.sync[30], "keyframes every second");
+
+        let (sps, pps) = avc_parameter_sets(&track.config);
+        let mut decoder = Decoder::with_api_config(api, DecoderConfig::new()).unwrap();
+        let converter = ColorConverter::new(config.color);
+        let (w, h) = (w as usize, h as usize);
+        for (i, (sample, original)) in track.samples.iter().zip(&originals).enumerate() {
+            let mut packet = Vec::new();
+            if i == 0 {
+                sps.iter().chain(&pps).for_each(|nal| put_annex_b(nal, &mut packet));
+            }
+            let mut rest = &sample[..];
+            while let Some((len, tail)) = rest.split_first_chunk::<4>() {
+                let (nal, tail) = tail.split_at(u32::from_be_bytes(*len) as usize);
+                put_annex_b(nal, &mut packet);
+                rest = tail;
+            }
+
+            let picture = decoder.decode(&packet).unwrap().unwrap_or_else(|| panic!("frame {i} didn't decode"));
+            assert_eq!(picture.dimensions(), (w, h));
+            let expected = converter.convert(original, w as u32, h as u32, YuvLayout::I420).unwrap();
+            let stride = picture.strides().0;
+            let error: u64 = (0..h)
+                .flat_map(|y| (0..w).map(move |x| (y, x)))
+                .map(|(y, x)| picture.y()[y * stride + x].abs_diff(expected[y * w + x]) as u64)
+                .sum();
+            let mean = error as f64 / (w * h) as f64;
+            assert!(mean < 3.0, "frame {i}: mean luma error {mean:.2}");
+        }
+    }
+}
@@ -0,0 +1,352 @@
+use crate::error::RecorderError;
+use crate::recording::color_convert::{ColorConverter, ColorSpace, YuvLayout};
+use crate::recording::mux::{open_muxer, Muxer, Sample, TrackConfig, VideoCodec};
+use crate::recording::video_sink::{SinkCapabilities, SinkConfig, VideoSink};
+use crate::state::app_state::ColorMatrix;
+use rav1e::prelude::{
+    ColorDescription, ColorPrimaries, Config, Context, EncoderConfig, EncoderStatus, FrameType, MatrixCoefficients,
+    Packet, PixelRange, Rational, TransferCharacteristics,
+};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Video track ticks per second; 90 kHz divides every common frame rate.
+const TIMESCALE: u32 = 90_000;
+
+const MAX_SPEED: u8 = 10;
+
+/// rav1e opens every packet with this temporal delimiter OBU, which MP4 and Matroska
+/// samples leave out.
+const TEMPORAL_DELIMITER: [u8; 2] = [0x12, 0x00];
+
+fn to_ticks(d: Duration) -> u64 {
+    (d.as_nanos() * TIMESCALE as u128 / 1_000_000_000) as u64
+}
+
+fn av1_err(context: &str, e: impl std::fmt::Display) -> RecorderError {
+    RecorderError::encoding_failed(format!("{context}: {e}"))
+}
+
+fn color_description(color: ColorSpace) -> ColorDescription {
+    match color.matrix {
+        ColorMatrix::Bt601 => ColorDescription {
+            color_primaries: ColorPrimaries::BT601,
+            transfer_characteristics: TransferCharacteristics::BT601,
+            matrix_coefficients: MatrixCoefficients::BT601,
+        },
+        ColorMatrix::Bt709 => ColorDescription {
+            color_primaries: ColorPrimaries::BT709,
+            transfer_characteristics: TransferCharacteristics::BT709,
+            matrix_coefficients: MatrixCoefficients::BT709,
+        },
+    }
+}
+
+/// An encoded temporal unit, held back until the next one tells us how long it lasts.
+struct PendingSample {
+    time: u64,
+    is_sync: bool,
+    data: Vec<u8>,
+}
+
+/// Software AV1 through rav1e, written to MP4, MKV or WebM.
+///
+/// Runs on the CPU only, so it works on machines without any hardware encoder. rav1e keeps
+/// a lookahead of frames, so packets come out some frames after their input; their times
+/// are kept in `queued` until then, keyed by input frame number. Video only, like OpenH264.
+pub struct Rav1eEncoder {
+    ctx: Context<u8>,
+    /// `None` once finalized.
+    muxer: Option<Box<dyn Muxer>>,
+    track_id: u32,
+
+    width: u32,
+    height: u32,
+    fps: u32,
+    converter: ColorConverter,
+    i420: Vec<u8>,
+
+    /// Times of frames sent to rav1e whose packets haven't come out yet, by input frame number.
+    queued: BTreeMap<u64, u64>,
+    /// Input frame number of the next frame sent to rav1e.
+    next_frameno: u64,
+    pending: Option<PendingSample>,
+    /// Time up to which the last frame is known to stay on screen.
+    end: u64,
+}
+
+impl Rav1eEncoder {
+    pub fn new(config: &SinkConfig) -> Result<Self, RecorderError> {
+        let SinkConfig { width, height, fps, bitrate_kbps, keyframe_interval, color, .. } = *config;
+        if width == 0 || height == 0 {
+            return Err(RecorderError::invalid_settings("rav1e needs a non-zero frame size"));
+        }
+        if config.av1.speed > MAX_SPEED {
+            return Err(RecorderError::invalid_settings(format!(
+                "AV1 speed must be 0-{MAX_SPEED}, got {}",
+                config.av1.speed
+            )));
+        }
+        let fps = fps.max(1);
+
+        let mut enc = EncoderConfig::with_speed_preset(config.av1.speed);
+        enc.width = width as usize;
+        enc.height = height as usize;
+        enc.time_base = Rational::new(1, fps as u64);
+        enc.pixel_range = if color.full_range() { PixelRange::Full } else { PixelRange::Limited };
+        enc.color_description = Some(color_description(color));
+        if keyframe_interval > 0 {
+            enc.max_key_frame_interval = keyframe_interval as u64;
+            enc.min_key_frame_interval = enc.min_key_frame_interval.min(keyframe_interval as u64);
+        }
+        if config.av1.quantizer > 0 {
+            enc.quantizer = config.av1.quantizer as usize;
+        } else {
+            enc.bitrate = bitrate_kbps.saturating_mul(1000).min(i32::MAX as u32) as i32;
+        }
+        let ctx: Context<u8> = Config::new()
+            .with_encoder_config(enc)
+            .new_context()
+            .map_err(|e| av1_err("rav1e init failed", e))?;
+
+        let mut muxer = open_muxer(&config.path, config.container, config.mux)?;
+        let track_id = muxer.add_track(TrackConfig::Video {
+            codec: VideoCodec::Av1 { config: ctx.container_sequence_header() },
+            width,
+            height,
+            timescale: TIMESCALE,
+            color: Some(color),
+        })?;
+
+        Ok(Self {
+            ctx,
+            muxer: Some(muxer),
+            track_id,
+            width,
+            height,
+            fps,
+            converter: ColorConverter::new(color),
+            i420: vec![0; YuvLayout::I420.frame_len(width, height)],
+            queued: BTreeMap::new(),
+            next_frameno: 0,
+            pending: None,
+            end: 0,
+        })
+    }
+
+    fn frame_period(&self) -> u64 {
+        (TIMESCALE / self.fps).max(1) as u64
+    }
+
+    fn muxer(&mut self) -> Result<&mut Box<dyn Muxer>, RecorderError> {
+        self.muxer
+            .as_mut()
+            .ok_or_else(|| RecorderError::encoding_failed("rav1e encoder already finalized"))
+    }
+
+    /// Write the held-back sample, lasting until `end`.
+    fn flush_pending(&mut self, end: u64) -> Result<(), RecorderError> {
+        let Some(sample) = self.pending.take() else {
+            return Ok(());
+        };
+
+        let duration = end.saturating_sub(sample.time).clamp(1, u32::MAX as u64) as u32;
+        let track_id = self.track_id;
+        self.muxer()?.write_sample(
+            track_id,
+            Sample { data: &sample.data, duration, composition_offset: 0, is_sync: sample.is_sync },
+        )
+    }
+
+    fn write_packet(&mut self, packet: Packet<u8>) -> Result<(), RecorderError> {
+        let Some(time) = self.queued.remove(&packet.input_frameno) else {
+            return Err(RecorderError::encoding_failed(format!(
+                "rav1e returned a packet for unknown frame {}",
+                packet.input_frameno
+            )));
+        };
+        self.flush_pending(time)?;
+
+        let mut data = packet.data;
+        if data.starts_with(&TEMPORAL_DELIMITER) {
+            data.drain(..TEMPORAL_DELIMITER.len());
+        }
+        self.pending = Some(PendingSample { time, is_sync: packet.frame_type == FrameType::KEY, data });
+        Ok(())
+    }
+
+    /// Pass on every packet rav1e has ready.
+    fn drain(&mut self) -> Result<(), RecorderError> {
+        loop {
+            match self.ctx.receive_packet() {
+                Ok(packet) => self.write_packet(packet)?,
+                Err(EncoderStatus::Encoded) => {}
+                Err(EncoderStatus::NeedMoreData | EncoderStatus::LimitReached) => return Ok(()),
+                Err(e) => return Err(av1_err("rav1e encode", e)),
+            }
+        }
+    }
+}
+
+impl VideoSink for Rav1eEncoder {
+    fn capabilities(&self) -> SinkCapabilities {
+        SinkCapabilities { audio: false, variable_frame_rate: true }
+    }
+
+    fn audio_samples_needed(&self, _elapsed: Duration) -> usize {
+        0
+    }
+
+    fn write_video(&mut self, bgra: &[u8], elapsed: Duration) -> Result<(), RecorderError> {
+        let expected = self.width as usize * self.height as usize * 4;
+        if bgra.len() != expected {
+            return Err(RecorderError::encoding_failed(format!(
+                "BGRA frame size mismatch: got {} bytes, expected {}",
+                bgra.len(),
+                expected
+            )));
+        }
+
+        self.converter
+            .convert_into(bgra, self.width, self.height, YuvLayout::I420, &mut self.i420)?;
+        let mut frame = self.ctx.new_frame();
+        let (w, h) = (self.width as usize, self.height as usize);
+        let (cw, ch) = (w.div_ceil(2), h.div_ceil(2));
+        let (y, chroma) = self.i420.split_at(w * h);
+        let (u, v) = chroma.split_at(cw * ch);
+        frame.planes[0].copy_from_raw_u8(y, w, 1);
+        frame.planes[1].copy_from_raw_u8(u, cw, 1);
+        frame.planes[2].copy_from_raw_u8(v, cw, 1);
+
+        // Sample times have to increase even if two frames land on the same tick.
+        let last = self.queued.values().next_back().or(self.pending.as_ref().map(|p| &p.time)).copied();
+        let time = match last {
+            Some(last) => to_ticks(elapsed).max(last + 1),
+            None => to_ticks(elapsed),
+        };
+        self.ctx
+            .send_frame(Arc::new(frame))
+            .map_err(|e| av1_err("rav1e send_frame", e))?;
+        self.queued.insert(self.next_frameno, time);
+        self.next_frameno += 1;
+        self.end = time + self.frame_period();
+        self.drain()
+    }
+
+    fn hold_video(&mut self, elapsed: Duration) {
+        self.end = self.end.max(to_ticks(elapsed) + self.frame_period());
+    }
+
+    fn write_audio(&mut self, _pcm_i16le: &[u8]) -> Result<(), RecorderError> {
+        Err(RecorderError::encoding_failed("The rav1e backend records video only"))
+    }
+
+    fn finalize(&mut self) -> Result<(), RecorderError> {
+        if self.muxer.is_none() {
+            return Ok(());
+        }
+        // Encode whatever is still in the lookahead.
+        self.ctx.flush();
+        self.drain()?;
+        self.flush_pending(self.end)?;
+        if let Some(muxer) = self.muxer.take() {
+            muxer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Rav1eEncoder {
+    fn drop(&mut self) {
+        // Best-effort: keep whatever was encoded playable.
+        let _ = self.finalize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::frame_source::FrameSource;
+    use crate::recording::mux::MuxOptions;
+    use crate::recording::test_pattern::TestPatternSource;
+    use crate::recording::test_util::{mp4_tracks, sink_config, temp_path};
+    use crate::state::app_state::Container;
+
+    /// OBU header of a sequence header, which rav1e repeats in front of every keyframe.
+    const OBU_SEQUENCE_HEADER: u8 = 0x0a;
+
+    #[test]
+    fn packets_keep_their_frame_times() {
+        let path = temp_path("rav1e.mp4");
+        let (w, h, fps) = (32u32, 32u32, 30u32);
+        let mut config = sink_config(path.clone(), w, h, fps, Container::Mp4);
+        config.mux = MuxOptions { faststart: false, fragment_duration: None, defragment: false };
+        config.keyframe_interval = 15;
+        config.av1.speed = MAX_SPEED;
+
+        // Every tenth frame is a duplicate the pipeline holds instead of encoding.
+        let mut sink = Rav1eEncoder::new(&config).unwrap();
+        let mut source = TestPatternSource::new(w, h, fps).unwrap();
+        let mut times = Vec::new();
+        for n in 0..40u32 {
+            let frame = source.capture_frame().unwrap();
+            let elapsed = Duration::from_secs(n as u64) / fps;
+            if n % 10 == 5 {
+                sink.hold_video(elapsed);
+            } else {
+                sink.write_video(&frame.data, elapsed).unwrap();
+                times.push(to_ticks(elapsed));
+            }
+        }
+        sink.finalize().unwrap();
+
+        let tracks = mp4_tracks(&std::fs::read(&path).unwrap());
+        let _ = std::fs::remove_file(&path);
+        let [track] = &tracks[..] else { panic!("expected one track, got {}", tracks.len()) };
+        assert_eq!(&track.codec, b"av01");
+        assert_eq!(track.timescale, TIMESCALE);
+        assert_eq!(track.samples.len(), times.len());
+
+        let period = (TIMESCALE / fps) as u64;
+        let expected: Vec<u32> =
+            times.windows(2).map(|t| (t[1] - t[0]) as u32).chain([period as u32]).collect();
+        assert_eq!(track.durations, expected);
+
+        assert!(track.sync[0]);
+        let keyframes = track.sync.iter().filter(|&&sync| sync).count();
+        assert!((times.len() / 15..=times.len() / 2).contains(&keyframes), "{keyframes} keyframes");
+        for (i, (sample, &sync)) in track.samples.iter().zip(&track.sync).enumerate() {
+            assert!(!sample.starts_with(&TEMPORAL_DELIMITER), "sample {i} keeps its temporal delimiter");
+            assert_eq!(sample[0] == OBU_SEQUENCE_HEADER, sync, "sample {i}");
+        }
+    }
+
+    #[test]
+    fn held_frames_stretch_the_previous_sample() {
+        let path = temp_path("rav1e-held.mp4");
+        let (w, h, fps) = (32u32, 32u32, 30u32);
+        let mut config = sink_config(path.clone(), w, h, fps, Container::Mp4);
+        config.mux = MuxOptions { faststart: false, fragment_duration: None, defragment: false };
+        config.av1.speed = MAX_SPEED;
+
+        // A static screen for frames 1-5 and again from frame 7 until the end.
+        let mut sink = Rav1eEncoder::new(&config).unwrap();
+        let mut source = TestPatternSource::new(w, h, fps).unwrap();
+        for n in 0..10u32 {
+            let frame = source.capture_frame().unwrap();
+            let elapsed = Duration::from_secs(n as u64) / fps;
+            if matches!(n, 0 | 6) {
+                sink.write_video(&frame.data, elapsed).unwrap();
+            } else {
+                sink.hold_video(elapsed);
+            }
+        }
+        sink.finalize().unwrap();
+
+        let tracks = mp4_tracks(&std::fs::read(&path).unwrap());
+        let _ = std::fs::remove_file(&path);
+        let period = TIMESCALE / fps;
+        assert_eq!(tracks[0].durations, [6 * period, 4 * period]);
+    }
+}
@@ -0,0 +1,388 @@
+use crate::error::RecorderError;
+use crate::recording::color_convert::{ColorConverter, ColorSpace, YuvLayout};
+use crate::recording::video_sink::{SinkCapabilities, SinkConfig, VideoSink};
+use crate::state::app_state::{ColorMatrix, ColorRange};
+use chrono::{DateTime, Local, TimeDelta, Timelike};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+fn wav_err(context: &str, e: hound::Error) -> RecorderError {
+    RecorderError::encoding_failed(format!("{context}: {e}"))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// Time of day as `HH:MM:SS:FF` at `fps`.
+fn timecode(at: &DateTime<Local>, fps: u32) -> String {
+    let frame = at.nanosecond().min(999_999_999) as u64 * fps as u64 / 1_000_000_000;
+    format!("{:02}:{:02}:{:02}:{:02}", at.hour(), at.minute(), at.second(), frame)
+}
+
+/// Written next to the media as `<name>.timecode.json`. Both files start at recording time
+/// zero: video frame `n` is shown from `n / fps`, audio sample `k` plays at `k / sample_rate`.
+#[derive(Serialize)]
+struct TimecodeManifest {
+    /// Wall-clock time of recording time zero.
+    started_at: String,
+    /// `started_at` as a timecode, to give both clips the same start in an editor.
+    start_timecode: String,
+    video: VideoManifest,
+    audio: Option<AudioManifest>,
+}
+
+#[derive(Serialize)]
+struct VideoManifest {
+    file: String,
+    width: u32,
+    height: u32,
+    fps: u32,
+    color_matrix: ColorMatrix,
+    color_range: ColorRange,
+    frames: u64,
+    /// Frames repeated because capture fell behind or the screen didn't change.
+    duplicated_frames: u64,
+    /// Captured frames that landed in an already written frame slot.
+    dropped_frames: u64,
+    duration_secs: f64,
+}
+
+#[derive(Serialize)]
+struct AudioManifest {
+    file: String,
+    sample_rate: u32,
+    channels: u16,
+    bits_per_sample: u16,
+    /// Per channel.
+    samples: u64,
+    duration_secs: f64,
+}
+
+struct WavOutput {
+    path: PathBuf,
+    writer: WavWriter<BufWriter<File>>,
+    sample_rate: u32,
+    channels: u16,
+    /// Per channel.
+    written: u64,
+}
+
+/// Editing intermediate: uncompressed 4:2:0 Y4M at a constant frame rate, the microphone as
+/// 16-bit WAV beside it, and a timecode manifest tying the two together.
+///
+/// Y4M has no timestamps, so frames are laid on a fixed `fps` grid by their recording time:
+/// gaps are filled by repeating the previous frame and a second frame for an already written
+/// slot is dropped. That keeps frame `n` at `n / fps` in any editor. Expect about
+/// `width * height * 1.5 * fps` bytes per second of video.
+pub struct Y4mWavWriter {
+    /// `None` once finalized.
+    video: Option<BufWriter<File>>,
+    video_path: PathBuf,
+    manifest_path: PathBuf,
+    audio: Option<WavOutput>,
+
+    width: u32,
+    height: u32,
+    fps: u32,
+    color: ColorSpace,
+    converter: ColorConverter,
+    /// Last frame, kept around for repeating.
+    i420: Vec<u8>,
+
+    frames: u64,
+    unique_frames: u64,
+    dropped_frames: u64,
+    /// Latest recording time the last frame is known to be on screen.
+    end: Duration,
+    /// Wall-clock time of recording time zero, worked out at the first write: the sink is
+    /// opened before capture starts.
+    started_at: Option<DateTime<Local>>,
+}
+
+impl Y4mWavWriter {
+    pub fn new(config: &SinkConfig) -> Result<Self, RecorderError> {
+        let SinkConfig { width, height, fps, color, .. } = *config;
+        if width == 0 || height == 0 {
+            return Err(RecorderError::invalid_settings("Y4M needs a non-zero frame size"));
+        }
+        let fps = fps.max(1);
+
+        let mut video = BufWriter::new(File::create(&config.path)?);
+        let range = if color.full_range() { "FULL" } else { "LIMITED" };
+        let matrix = match color.matrix {
+            ColorMatrix::Bt601 => "BT601",
+            ColorMatrix::Bt709 => "BT709",
+        };
+        writeln!(
+            video,
+            "YUV4MPEG2 W{width} H{height} F{fps}:1 Ip A1:1 C420jpeg XCOLORRANGE={range} XCOLORMATRIX={matrix}"
+        )?;
+
+        let audio = match config.audio {
+            Some((sample_rate, channels)) => {
+                let path = config.path.with_extension("wav");
+                let spec = WavSpec { channels, sample_rate, bits_per_sample: 16, sample_format: SampleFormat::Int };
+                let writer = WavWriter::create(&path, spec).map_err(|e| wav_err("WAV create failed", e))?;
+                Some(WavOutput { path, writer, sample_rate, channels, written: 0 })
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            video: Some(video),
+            video_path: config.path.clone(),
+            manifest_path: config.path.with_extension("timecode.json"),
+            audio,
+            width,
+            height,
+            fps,
+            color,
+            converter: ColorConverter::new(color),
+            i420: vec![0; YuvLayout::I420.frame_len(width, height)],
+            frames: 0,
+            unique_frames: 0,
+            dropped_frames: 0,
+            end: Duration::ZERO,
+            started_at: None,
+        })
+    }
+
+    /// Note when recording time zero was from a write `elapsed` into the recording.
+    fn stamp_start(&mut self, elapsed: Duration) {
+        if self.started_at.is_none() {
+            self.started_at = Some(Local::now() - TimeDelta::from_std(elapsed).unwrap_or_default());
+        }
+    }
+
+    /// Slot on the frame grid that `elapsed` falls into.
+    fn frame_index(&self, elapsed: Duration) -> u64 {
+        (elapsed.as_nanos() * self.fps as u128 / 1_000_000_000) as u64
+    }
+
+    /// Write the current frame into every slot before `slot`.
+    fn fill_until(&mut self, slot: u64) -> Result<(), RecorderError> {
+        let video = self
+            .video
+            .as_mut()
+            .ok_or_else(|| RecorderError::encoding_failed("Y4M writer already finalized"))?;
+        while self.frames < slot {
+            video.write_all(b"FRAME\n")?;
+            video.write_all(&self.i420)?;
+            self.frames += 1;
+        }
+        Ok(())
+    }
+
+    fn write_manifest(&self) -> Result<(), RecorderError> {
+        let started_at = self.started_at.unwrap_or_else(Local::now);
+        let manifest = TimecodeManifest {
+            started_at: started_at.to_rfc3339(),
+            start_timecode: timecode(&started_at, self.fps),
+            video: VideoManifest {
+                file: file_name(&self.video_path),
+                width: self.width,
+                height: self.height,
+                fps: self.fps,
+                color_matrix: self.color.matrix,
+                color_range: self.color.range,
+                frames: self.frames,
+                duplicated_frames: self.frames.saturating_sub(self.unique_frames),
+                dropped_frames: self.dropped_frames,
+                duration_secs: self.frames as f64 / self.fps as f64,
+            },
+            audio: self.audio.as_ref().map(|a| AudioManifest {
+                file: file_name(&a.path),
+                sample_rate: a.sample_rate,
+                channels: a.channels,
+                bits_per_sample: 16,
+                samples: a.written,
+                duration_secs: a.written as f64 / a.sample_rate as f64,
+            }),
+        };
+        fs::write(&self.manifest_path, serde_json::to_vec_pretty(&manifest)?)?;
+        Ok(())
+    }
+}
+
+impl VideoSink for Y4mWavWriter {
+    fn capabilities(&self) -> SinkCapabilities {
+        SinkCapabilities { audio: true, variable_frame_rate: true }
+    }
+
+    fn audio_samples_needed(&self, elapsed: Duration) -> usize {
+        let Some(audio) = &self.audio else {
+            return 0;
+        };
+
+        let desired = (elapsed.as_nanos() * audio.sample_rate as u128 / 1_000_000_000) as u64;
+        (desired.saturating_sub(audio.written) as usize).saturating_mul(audio.channels as usize)
+    }
+
+    fn write_video(&mut self, bgra: &[u8], elapsed: Duration) -> Result<(), RecorderError> {
+        let expected = self.width as usize * self.height as usize * 4;
+        if bgra.len() != expected {
+            return Err(RecorderError::encoding_failed(format!(
+                "BGRA frame size mismatch: got {} bytes, expected {}",
+                bgra.len(),
+                expected
+            )));
+        }
+
+        self.stamp_start(elapsed);
+        let slot = self.frame_index(elapsed);
+        if slot < self.frames {
+            self.dropped_frames += 1;
+            return Ok(());
+        }
+        // The previous frame stays up until this one; before the first there is nothing to
+        // repeat, so the first frame also covers any slots ahead of it.
+        if self.frames > 0 {
+            self.fill_until(slot)?;
+        }
+        self.converter
+            .convert_into(bgra, self.width, self.height, YuvLayout::I420, &mut self.i420)?;
+        self.unique_frames += 1;
+        self.end = self.end.max(elapsed);
+        self.fill_until(slot + 1)
+    }
+
+    fn hold_video(&mut self, elapsed: Duration) {
+        self.end = self.end.max(elapsed);
+    }
+
+    fn write_audio(&mut self, pcm_i16le: &[u8]) -> Result<(), RecorderError> {
+        let Some(audio) = self.audio.as_mut() else {
+            return Err(RecorderError::encoding_failed("Y4M writer was opened without audio"));
+        };
+
+        for sample in pcm_i16le.chunks_exact(2) {
+            audio
+                .writer
+                .write_sample(i16::from_le_bytes([sample[0], sample[1]]))
+                .map_err(|e| wav_err("WAV write failed", e))?;
+        }
+        audio.written += (pcm_i16le.len() / 2 / audio.channels.max(1) as usize) as u64;
+        // The manager catches audio up to the frame it is about to write.
+        let elapsed = Duration::from_nanos(audio.written * 1_000_000_000 / audio.sample_rate as u64);
+        self.stamp_start(elapsed);
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<(), RecorderError> {
+        if self.video.is_none() {
+            return Ok(());
+        }
+        if self.frames > 0 {
+            let last = self.frame_index(self.end);
+            self.fill_until(last + 1)?;
+        }
+        if let Some(mut video) = self.video.take() {
+            video.flush()?;
+        }
+        if let Some(audio) = self.audio.as_mut() {
+            audio.writer.flush().map_err(|e| wav_err("WAV finalize failed", e))?;
+        }
+        self.write_manifest()
+    }
+}
+
+impl Drop for Y4mWavWriter {
+    fn drop(&mut self) {
+        // Best-effort: leave the WAV header and manifest describing what was written.
+        let _ = self.finalize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::frame_source::FrameSource;
+    use crate::recording::replay_source::ReplaySource;
+    use crate::recording::scaler::ScaleOptions;
+    use crate::recording::test_util::{sink_config, temp_path};
+    use crate::state::app_state::{Container, ReplaySettings, ScaleFilter};
+
+    #[test]
+    fn clip_wav_and_manifest_agree() {
+        let path = temp_path("intermediate.y4m");
+        let (w, h, fps) = (32u32, 16u32, 30u32);
+        let mut config = sink_config(path.clone(), w, h, fps, Container::Y4m);
+        config.audio = Some((48_000, 2));
+        // Not what a reader assumes without the tag.
+        config.color = ColorSpace { matrix: ColorMatrix::Bt709, range: ColorRange::Limited };
+
+        // One second: frames 10-14 unchanged and held, a second frame in slot 20 dropped. It
+        // starts a while after the sink is opened, like capture does.
+        let opened = Local::now();
+        let mut sink = Y4mWavWriter::new(&config).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        let red = [0u8, 0, 255, 255].repeat((w * h) as usize);
+        let feed_audio = |sink: &mut Y4mWavWriter, elapsed| {
+            let pcm = vec![0u8; sink.audio_samples_needed(elapsed) * 2];
+            sink.write_audio(&pcm).unwrap();
+        };
+        for n in 0..30u32 {
+            // Mid-slot, clear of rounding at the slot edges.
+            let elapsed = Duration::from_secs_f64((n as f64 + 0.5) / fps as f64);
+            feed_audio(&mut sink, elapsed);
+            if (10..15).contains(&n) {
+                sink.hold_video(elapsed);
+                continue;
+            }
+            sink.write_video(&red, elapsed).unwrap();
+            if n == 20 {
+                sink.write_video(&red, elapsed + Duration::from_millis(5)).unwrap();
+            }
+        }
+        feed_audio(&mut sink, Duration::from_secs(1));
+        sink.finalize().unwrap();
+        drop(sink);
+
+        let video = fs::read(&path).unwrap();
+        let header_len = video.iter().position(|&b| b == b'\n').unwrap() + 1;
+        assert_eq!(
+            std::str::from_utf8(&video[..header_len]).unwrap(),
+            "YUV4MPEG2 W32 H16 F30:1 Ip A1:1 C420jpeg XCOLORRANGE=LIMITED XCOLORMATRIX=BT709\n"
+        );
+        let frame_len = b"FRAME\n".len() + YuvLayout::I420.frame_len(w, h);
+        assert_eq!(video.len(), header_len + 30 * frame_len);
+
+        let wav_path = path.with_extension("wav");
+        let wav = hound::WavReader::open(&wav_path).unwrap();
+        assert_eq!((wav.spec().sample_rate, wav.spec().channels), (48_000, 2));
+        assert_eq!(wav.duration(), 48_000);
+        drop(wav);
+
+        let manifest_path = path.with_extension("timecode.json");
+        let manifest: serde_json::Value = serde_json::from_slice(&fs::read(&manifest_path).unwrap()).unwrap();
+        let (video_info, audio_info) = (&manifest["video"], &manifest["audio"]);
+        assert_eq!(video_info["file"], file_name(&path));
+        assert_eq!(video_info["frames"], 30);
+        assert_eq!(video_info["duplicated_frames"], 5);
+        assert_eq!(video_info["dropped_frames"], 1);
+        assert_eq!(video_info["color_matrix"], "bt709");
+        assert_eq!(video_info["duration_secs"], 1.0);
+        assert_eq!(audio_info["samples"], 48_000);
+        assert_eq!(audio_info["duration_secs"], 1.0);
+        // Time zero is the first write less its recording time (17 ms in), not the opening.
+        let started_at = DateTime::parse_from_rfc3339(manifest["started_at"].as_str().unwrap()).unwrap();
+        let delay = (started_at.with_timezone(&Local) - opened).num_milliseconds();
+        assert!((70..1000).contains(&delay), "started {delay} ms after the sink opened");
+
+        // The replay reader decodes the frames back with the tagged matrix.
+        let settings = ReplaySettings { path: path.to_string_lossy().into_owned(), ..Default::default() };
+        let mut replay = ReplaySource::new(&settings, None, ScaleOptions::stretch(ScaleFilter::Nearest)).unwrap();
+        let frame = replay.capture_frame().unwrap();
+        let [b, g, r, _] = frame.data[..4] else { unreachable!() };
+        assert!(b <= 2 && g <= 2 && r >= 253, "red came back as {:?}", [b, g, r]);
+
+        for file in [path, wav_path, manifest_path] {
+            fs::remove_file(file).unwrap();
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use crate::error::RecorderError;
+use crate::recording::color_convert::ColorSpace;
+use crate::recording::mux::MuxOptions;
+use crate::recording::video_encoder_openh264::OpenH264Encoder;
+use crate::recording::video_encoder_gif::GifWriter;
+use crate::recording::video_encoder_rav1e::Rav1eEncoder;
+use crate::recording::video_encoder_y4m::Y4mWavWriter;
+use crate::state::app_state::{Av1Settings, Container, GifSettings, RecordingSettings, VideoBackend};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// What a sink can do with the streams it is given, so the worker can adapt before the
+/// first frame instead of failing halfway through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SinkCapabilities {
+    /// Accepts `write_audio`; without it the microphone is left off.
+    pub audio: bool,
+    /// Honours `hold_video`; without it duplicate frames are written out again.
+    pub variable_frame_rate: bool,
+}
+
+/// Everything a backend needs to open its output.
+#[derive(Debug, Clone)]
+pub struct SinkConfig {
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub bitrate_kbps: u32,
+    /// Frames between keyframes; 0 leaves it to the encoder.
+    pub keyframe_interval: u32,
+    pub color: ColorSpace,
+    pub container: Container,
+    /// MP4 layout: faststart, fragments.
+    pub mux: MuxOptions,
+    /// Only read by the rav1e backend.
+    pub av1: Av1Settings,
+    /// Only read by the GIF backend.
+    pub gif: GifSettings,
+    /// Sample rate and channel count of the microphone, if one is recorded.
+    pub audio: Option<(u32, u16)>,
+}
+
+/// Destination for the encode stage: takes BGRA frames and interleaved 16-bit PCM stamped
+/// with recording time, and owns encoding and muxing from there.
+pub trait VideoSink {
+    fn capabilities(&self) -> SinkCapabilities;
+
+    /// Interleaved samples to write so audio catches up with `elapsed`.
+    fn audio_samples_needed(&self, elapsed: Duration) -> usize;
+
+    /// Write one `width * height` BGRA frame shown from `elapsed` on.
+    fn write_video(&mut self, bgra: &[u8], elapsed: Duration) -> Result<(), RecorderError>;
+
+    /// The previous frame is still on screen at `elapsed`. Only called when the sink
+    /// reports `variable_frame_rate`.
+    fn hold_video(&mut self, _elapsed: Duration) {}
+
+    /// Append little-endian 16-bit PCM in the layout given by `SinkConfig::audio`.
+    fn write_audio(&mut self, pcm_i16le: &[u8]) -> Result<(), RecorderError>;
+
+    /// Flush everything and close the file. Nothing may be written afterwards.
+    fn finalize(&mut self) -> Result<(), RecorderError>;
+
+    /// Problems worked around since the last call that the user should hear about, e.g. a
+    /// file that came out over its size target.
+    fn take_warnings(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+type OpenSink = fn(&SinkConfig) -> Result<Box<dyn VideoSink>, RecorderError>;
+
+/// One encoder the registry can open.
+#[derive(Clone, Copy)]
+pub struct SinkBackend {
+    pub id: VideoBackend,
+    pub name: &'static str,
+    /// Output containers the backend can write.
+    pub containers: &'static [Container],
+    /// Records the microphone. While it is on, `Auto` skips backends that don't.
+    pub audio: bool,
+    pub open: OpenSink,
+}
+
+/// Backends known to this build, in order of preference for `VideoBackend::Auto`.
+#[derive(Clone)]
+pub struct SinkRegistry {
+    backends: Vec<SinkBackend>,
+}
+
+impl SinkRegistry {
+    pub fn new() -> Self {
+        Self { backends: Vec::new() }
+    }
+
+    /// Registry with every backend compiled into this build.
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        #[cfg(windows)]
+        registry.register(SinkBackend {
+            id: VideoBackend::MediaFoundation,
+            name: "Media Foundation",
+            containers: &[Container::Mp4],
+            audio: true,
+            open: |config| Ok(Box::new(crate::recording::video_encoder_mf::VideoEncoder::new(config)?)),
+        });
+        registry.register(SinkBackend {
+            id: VideoBackend::OpenH264,
+            name: "OpenH264",
+            containers: &[Container::Mp4, Container::Mkv],
+            audio: true,
+            open: |config| Ok(Box::new(OpenH264Encoder::new(config)?)),
+        });
+        registry.register(SinkBackend {
+            id: VideoBackend::Rav1e,
+            name: "rav1e",
+            containers: &[Container::Mp4, Container::Mkv, Container::Webm],
+            audio: false,
+            open: |config| Ok(Box::new(Rav1eEncoder::new(config)?)),
+        });
+        registry.register(SinkBackend {
+            id: VideoBackend::Intermediate,
+            name: "Y4M + WAV",
+            containers: &[Container::Y4m],
+            audio: true,
+            open: |config| Ok(Box::new(Y4mWavWriter::new(config)?)),
+        });
+        registry.register(SinkBackend {
+            id: VideoBackend::Gif,
+            name: "GIF",
+            containers: &[Container::Gif],
+            audio: false,
+            open: |config| Ok(Box::new(GifWriter::new(config)?)),
+        });
+        registry
+    }
+
+    /// Add a backend; a later registration for the same id replaces the earlier one.
+    pub fn register(&mut self, backend: SinkBackend) {
+        match self.backends.iter_mut().find(|b| b.id == backend.id) {
+            Some(existing) => *existing = backend,
+            None => self.backends.push(backend),
+        }
+    }
+
+    /// Open the backend `settings.video_backend` asks for. `Auto` tries each one that can
+    /// write `config.container` in turn, so a machine whose hardware encoder is missing still
+    /// records. It never falls back to a video-only backend while `config.audio` is set:
+    /// that would drop the microphone without the user asking for it.
+    pub fn open(
+        &self,
+        settings: &RecordingSettings,
+        config: &SinkConfig,
+    ) -> Result<(&SinkBackend, Box<dyn VideoSink>), RecorderError> {
+        if settings.video_backend != VideoBackend::Auto {
+            let backend = self.backends.iter().find(|b| b.id == settings.video_backend).ok_or_else(|| {
+                RecorderError::invalid_settings(format!(
+                    "Video backend {:?} is not available on this platform",
+                    settings.video_backend
+                ))
+            })?;
+            if !backend.containers.contains(&config.container) {
+                return Err(RecorderError::invalid_settings(format!(
+                    "{} can't write {} files",
+                    backend.name,
+                    config.container.extension().to_uppercase()
+                )));
+            }
+            return Ok((backend, (backend.open)(config)?));
+        }
+
+        let mut last_error = None;
+        let mut video_only = Vec::new();
+        for backend in self.backends.iter().filter(|b| b.containers.contains(&config.container)) {
+            if config.audio.is_some() && !backend.audio {
+                video_only.push(backend.name);
+                continue;
+            }
+            match (backend.open)(config) {
+                Ok(sink) => return Ok((backend, sink)),
+                Err(e) => {
+                    eprintln!("RecordFlow: {} encoder unavailable, trying the next one: {e}", backend.name);
+                    last_error = Some(e);
+                }
+            }
+        }
+        if !video_only.is_empty() {
+            let reason = last_error.map(|e| format!(" ({})", e.details.unwrap_or(e.message))).unwrap_or_default();
+            return Err(RecorderError::invalid_settings(format!(
+                "No encoder on this machine can record {} with the microphone{reason}. Turn the microphone \
+                 off, or choose {} as the video backend to record without sound",
+                config.container.extension().to_uppercase(),
+                video_only.join(" or ")
+            )));
+        }
+        Err(last_error.unwrap_or_else(|| {
+            RecorderError::encoding_failed(format!(
+                "No video encoder for {} is available on this platform",
+                config.container.extension().to_uppercase()
+            ))
+        }))
+    }
+}
+
+impl Default for SinkRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::test_util::{sink_config, temp_path};
+
+    /// An MP4 registry whose audio-capable encoder is missing, like Media Foundation on a
+    /// machine without it.
+    fn registry() -> SinkRegistry {
+        let mut registry = SinkRegistry::new();
+        registry.register(SinkBackend {
+            id: VideoBackend::MediaFoundation,
+            name: "With audio",
+            containers: &[Container::Mp4],
+            audio: true,
+            open: |_| Err(RecorderError::encoding_failed("no hardware encoder")),
+        });
+        registry.register(SinkBackend {
+            id: VideoBackend::OpenH264,
+            name: "Video only",
+            containers: &[Container::Mp4],
+            audio: false,
+            open: |_| Err(RecorderError::encoding_failed("video only opened")),
+        });
+        registry
+    }
+
+    fn open_error(config: &SinkConfig) -> RecorderError {
+        match registry().open(&RecordingSettings::default(), config) {
+            Ok((backend, _)) => panic!("{} opened", backend.name),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn auto_falls_back_without_a_microphone() {
+        let config = sink_config(temp_path("fallback.mp4"), 64, 48, 30, Container::Mp4);
+        assert_eq!(open_error(&config).details.as_deref(), Some("video only opened"));
+    }
+
+    #[test]
+    fn auto_keeps_the_microphone() {
+        let config = SinkConfig {
+            audio: Some((48_000, 2)),
+            ..sink_config(temp_path("fallback.mp4"), 64, 48, 30, Container::Mp4)
+        };
+        let e = open_error(&config);
+        assert_eq!(e.code, "INVALID_SETTINGS");
+        let details = e.details.unwrap();
+        assert!(details.contains("no hardware encoder") && details.contains("Video only"), "{details}");
+    }
+}
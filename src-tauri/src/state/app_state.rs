@@ -4,12 +4,474 @@ use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Output size of the recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Resolution {
     #[serde(rename = "720p")]
     P720,
     #[serde(rename = "1080p")]
     P1080,
+    #[serde(rename = "1440p")]
+    P1440,
+    #[serde(rename = "2160p")]
+    P2160,
+    /// Whatever the capture source delivers, shrunk to fit the encoder limits if needed.
+    #[serde(rename = "native")]
+    Native,
+    #[serde(rename = "custom")]
+    Custom { width: u32, height: u32 },
+}
+
+impl Resolution {
+    /// Smallest frame edge the encoders accept.
+    pub const MIN_DIMENSION: u32 = 16;
+    /// Largest frame the H.264 encoders accept (level 5.1/5.2).
+    pub const MAX_WIDTH: u32 = 4096;
+    pub const MAX_HEIGHT: u32 = 2304;
+
+    /// Size in pixels. `native` is only called for `Resolution::Native` and yields the
+    /// source size, which is rounded down to even dimensions and scaled down to the encoder
+    /// limits if it exceeds them.
+    pub fn dimensions(
+        &self,
+        native: impl FnOnce() -> Result<(u32, u32), RecorderError>,
+    ) -> Result<(u32, u32), RecorderError> {
+        let (width, height) = match *self {
+            Resolution::P720 => (1280, 720),
+            Resolution::P1080 => (1920, 1080),
+            Resolution::P1440 => (2560, 1440),
+            Resolution::P2160 => (3840, 2160),
+            Resolution::Native => {
+                let (width, height) = native()?;
+                Self::fit_encoder_limits(width, height)
+            }
+            Resolution::Custom { width, height } => (width, height),
+        };
+        Self::validate_dimensions(width, height)?;
+        Ok((width, height))
+    }
+
+    /// Check the settings without resolving `Native`.
+    pub fn validate(&self) -> Result<(), RecorderError> {
+        match *self {
+            Resolution::Custom { width, height } => Self::validate_dimensions(width, height),
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_dimensions(width: u32, height: u32) -> Result<(), RecorderError> {
+        if !width.is_multiple_of(2) || !height.is_multiple_of(2) {
+            return Err(RecorderError::invalid_settings(format!(
+                "Resolution {width}x{height} must have even dimensions"
+            )));
+        }
+        if width < Self::MIN_DIMENSION || height < Self::MIN_DIMENSION {
+            return Err(RecorderError::invalid_settings(format!(
+                "Resolution {width}x{height} is below the {min}x{min} minimum",
+                min = Self::MIN_DIMENSION
+            )));
+        }
+        if width > Self::MAX_WIDTH || height > Self::MAX_HEIGHT {
+            return Err(RecorderError::invalid_settings(format!(
+                "Resolution {width}x{height} exceeds the encoder limit of {}x{}",
+                Self::MAX_WIDTH,
+                Self::MAX_HEIGHT
+            )));
+        }
+        Ok(())
+    }
+
+    /// Largest even size with the aspect ratio of `width` x `height` that the encoders take.
+    fn fit_encoder_limits(width: u32, height: u32) -> (u32, u32) {
+        let (width, height) = (width.max(1) as u64, height.max(1) as u64);
+        let (max_w, max_h) = (Self::MAX_WIDTH as u64, Self::MAX_HEIGHT as u64);
+        let (w, h) = if width > max_w || height > max_h {
+            if width * max_h > height * max_w {
+                (max_w, height * max_w / width)
+            } else {
+                (width * max_h / height, max_h)
+            }
+        } else {
+            (width, height)
+        };
+        let even = |v: u64| ((v as u32) & !1).max(Self::MIN_DIMENSION);
+        (even(w), even(h))
+    }
+}
+
+/// YUV matrix frames are converted with before encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorMatrix {
+    /// SD matrix; some older players assume it regardless of tagging.
+    Bt601,
+    Bt709,
+}
+
+/// Value range of the encoded YUV.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorRange {
+    /// 16-235 luma, 16-240 chroma; what players expect unless told otherwise.
+    Limited,
+    /// 0-255.
+    Full,
+}
+
+/// File format of the recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Container {
+    Mp4,
+    /// Matroska; any codec, and a cut-off file stays playable up to the last cluster.
+    Mkv,
+    /// The Matroska subset browsers play; AV1/VP9 and Opus only.
+    Webm,
+    /// Uncompressed Y4M video with the microphone as WAV and a timecode manifest beside it,
+    /// for editing. Written by `VideoBackend::Intermediate` only.
+    Y4m,
+    /// Animated GIF without audio, for pasting short clips into issue trackers. Written by
+    /// `VideoBackend::Gif` only.
+    Gif,
+}
+
+impl Container {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Mkv => "mkv",
+            Container::Webm => "webm",
+            Container::Y4m => "y4m",
+            Container::Gif => "gif",
+        }
+    }
+}
+
+/// Which encoder writes the recording. The recording manager's sink registry maps these to
+/// implementations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VideoBackend {
+    /// The first backend available on this platform.
+    Auto,
+    /// Windows Media Foundation H.264 + AAC in MP4.
+    MediaFoundation,
+    /// Software H.264 through OpenH264, video only. Runs anywhere, no GPU needed.
+    #[serde(rename = "openh264")]
+    OpenH264,
+    /// Software AV1 through rav1e, video only. Much smaller files than H.264 at the same
+    /// quality, but costs far more CPU.
+    Rav1e,
+    /// Y4M + WAV editing intermediate; see `Container::Y4m`.
+    Intermediate,
+    /// Animated GIF; see `GifSettings`.
+    Gif,
+}
+
+/// Tuning for the rav1e AV1 backend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Av1Settings {
+    /// rav1e speed preset, 0 (smallest file) to 10 (fastest). Live recording needs the top
+    /// presets unless the machine has cores to spare.
+    pub speed: u8,
+    /// Constant quantizer, 1 (best) to 255; 0 targets `RecordingSettings::bitrate` instead.
+    pub quantizer: u8,
+}
+
+impl Default for Av1Settings {
+    fn default() -> Self {
+        Self { speed: 10, quantizer: 0 }
+    }
+}
+
+/// Whether GIF frames share one color table or each gets its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GifPalette {
+    /// One table for the whole clip: smallest files, but colors that show up briefly suffer.
+    Global,
+    /// A table per frame: better colors, up to 768 extra bytes a frame.
+    PerFrame,
+}
+
+/// How GIF palettes are picked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GifQuantizer {
+    /// Fast; good for UI screenshots with flat colors.
+    MedianCut,
+    /// Slower; better on photos and gradients.
+    #[serde(rename = "neuquant")]
+    NeuQuant,
+}
+
+/// Tuning for the GIF backend. The clip is kept in memory until the recording stops, so
+/// this is meant for short recordings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GifSettings {
+    pub palette: GifPalette,
+    pub quantizer: GifQuantizer,
+    /// Floyd-Steinberg dithering: smoother gradients, but larger files.
+    pub dither: bool,
+    /// Frame rate of the GIF, capped at the recording's; 0 keeps the recording's.
+    pub fps: u32,
+    /// Downscale wider recordings to this width; 0 keeps the size.
+    pub max_width: u32,
+    /// Shrink the frames until the file fits in this many KiB; 0 for no limit.
+    pub max_size_kb: u32,
+}
+
+impl Default for GifSettings {
+    fn default() -> Self {
+        Self {
+            palette: GifPalette::Global,
+            quantizer: GifQuantizer::MedianCut,
+            dither: false,
+            fps: 10,
+            max_width: 800,
+            max_size_kb: 0,
+        }
+    }
+}
+
+/// File format of an audio-only recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioFormat {
+    /// Uncompressed 16-bit PCM.
+    Wav,
+    /// Lossless, roughly half the size of WAV.
+    Flac,
+    /// AAC-LC in MPEG-4 audio. Encoded by Media Foundation, so Windows only.
+    M4a,
+}
+
+impl AudioFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "wav",
+            AudioFormat::Flac => "flac",
+            AudioFormat::M4a => "m4a",
+        }
+    }
+}
+
+/// Where screen frames come from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureSource {
+    /// Live capture of `selected_display`.
+    Display,
+    /// Every monitor, stitched together using the display offsets.
+    AllDisplays,
+    /// Live capture of the application window in `selected_window`.
+    Window,
+    /// Synthetic color bars; needs no display or GPU.
+    TestPattern,
+    /// Frames read back from the clip in `RecordingSettings::replay`.
+    Replay,
+}
+
+/// A recorded clip fed through the pipeline in place of a live device.
+///
+/// The format is picked from `path`: a directory is read as a PNG sequence, a `.y4m` file as
+/// YUV4MPEG2, anything else as raw BGRA frames of `width` x `height`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplaySettings {
+    pub path: String,
+    /// Frame size of a raw BGRA file; ignored for Y4M and PNG.
+    pub width: u32,
+    pub height: u32,
+    /// Frame rate of PNG and raw BGRA clips; Y4M carries its own.
+    pub fps: u32,
+    /// Start over at the end instead of holding the last frame.
+    pub looping: bool,
+    /// Pace playback against the wall clock instead of handing out one frame per request.
+    pub realtime: bool,
+}
+
+impl Default for ReplaySettings {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            width: 0,
+            height: 0,
+            fps: 30,
+            looping: true,
+            realtime: true,
+        }
+    }
+}
+
+/// Sub-rectangle of the selected display, in that display's pixel coordinates
+/// (0,0 is the display's top-left corner, not the virtual desktop's).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CaptureRegion {
+    /// Check the region is non-empty and fits inside a `display_width` x `display_height` display.
+    pub fn validate_within(&self, display_width: u32, display_height: u32) -> Result<(), RecorderError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(RecorderError::invalid_settings("Capture region must not be empty"));
+        }
+
+        let fits_x = self.x.checked_add(self.width).is_some_and(|r| r <= display_width);
+        let fits_y = self.y.checked_add(self.height).is_some_and(|b| b <= display_height);
+        if !fits_x || !fits_y {
+            return Err(RecorderError::invalid_settings(format!(
+                "Capture region {}x{}+{}+{} is outside the {}x{} display",
+                self.width, self.height, self.x, self.y, display_width, display_height
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// How the mouse pointer ends up in the recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CursorMode {
+    /// Whatever the platform capture API includes (WGC draws it, X11 never does).
+    System,
+    /// Captured without the pointer; `FrameCompositor` draws it with click highlights.
+    Overlay,
+    /// No pointer at all.
+    Hidden,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CursorSettings {
+    pub mode: CursorMode,
+    /// Size of the drawn pointer relative to its 12x19 px default. Overlay mode only.
+    pub scale: f32,
+    /// Animate a ring under the pointer on every click.
+    pub click_rings: bool,
+    /// `#RRGGBB` ring colors.
+    pub left_click_color: String,
+    pub right_click_color: String,
+    /// Stop drawing the pointer after `idle_timeout_ms` without movement.
+    pub hide_when_idle: bool,
+    pub idle_timeout_ms: u32,
+}
+
+impl Default for CursorSettings {
+    fn default() -> Self {
+        Self {
+            mode: CursorMode::System,
+            scale: 1.0,
+            click_rings: true,
+            left_click_color: "#FFC107".to_string(),
+            right_click_color: "#2196F3".to_string(),
+            hide_when_idle: false,
+            idle_timeout_ms: 3000,
+        }
+    }
+}
+
+/// Resampling filter for every resize: capture to output size and the camera overlay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScaleFilter {
+    /// Fastest; blocky, and drops detail such as thin text when shrinking.
+    Nearest,
+    Bilinear,
+    /// Catmull-Rom; sharper than bilinear for slightly more work.
+    Bicubic,
+    /// Lanczos-3; sharpest, slowest.
+    Lanczos,
+}
+
+/// How a source with a different aspect ratio than the output is made to fit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FitMode {
+    /// Scale each axis independently; distorts mismatched sources.
+    Stretch,
+    /// Keep the aspect ratio and pad with bars in `bar_color`.
+    Fit,
+    /// Keep the aspect ratio and crop whatever overflows the output.
+    Fill,
+}
+
+/// What a full queue between two pipeline stages does with a new item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueuePolicy {
+    /// Wait for room, slowing the producing stage down.
+    Block,
+    /// Discard the oldest queued item to make room.
+    DropOldest,
+    /// Discard the new item.
+    DropNewest,
+}
+
+/// Queues between the capture, composite and encode threads.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PipelineSettings {
+    /// Frames each queue holds before its policy kicks in.
+    pub queue_depth: u32,
+    /// Capture -> composite. Dropping keeps capture on its clock when later stages fall behind.
+    pub capture_queue: QueuePolicy,
+    /// Composite -> encode.
+    pub encode_queue: QueuePolicy,
+}
+
+impl Default for PipelineSettings {
+    fn default() -> Self {
+        Self {
+            queue_depth: 4,
+            capture_queue: QueuePolicy::DropOldest,
+            encode_queue: QueuePolicy::Block,
+        }
+    }
+}
+
+/// Curve that squeezes HDR highlights into SDR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMapCurve {
+    /// Extended Reinhard. With `peak_nits` equal to `sdr_white_nits` it leaves SDR content
+    /// untouched and only clips highlights.
+    Reinhard,
+    /// Hable's filmic curve; soft shoulder, slightly lifted shadows.
+    Hable,
+    /// Narkowicz's fit of the ACES filmic curve; the most contrast.
+    Aces,
+}
+
+/// HDR desktop capture. Only WGC delivers float frames; other backends ignore this.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HdrSettings {
+    /// Capture `Rgba16F` and tone-map it to SDR before compositing. Meant for HDR displays;
+    /// on SDR ones the desktop tops out at 80 nits and comes out dim.
+    pub enabled: bool,
+    pub curve: ToneMapCurve,
+    /// Brightness mapped to full white; anything brighter clips.
+    pub peak_nits: f32,
+    /// Brightness of SDR white on the HDR desktop (Windows' "SDR content brightness").
+    pub sdr_white_nits: f32,
+}
+
+impl Default for HdrSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            curve: ToneMapCurve::Reinhard,
+            peak_nits: 1000.0,
+            sdr_white_nits: 200.0,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,13 +495,45 @@ pub enum CameraSize {
 #[serde(default)]
 pub struct RecordingSettings {
     pub screen_enabled: bool,
+    pub capture_source: CaptureSource,
     pub resolution: Resolution,
+    /// Used when the captured picture doesn't match `resolution`, and for the camera overlay.
+    pub scale_filter: ScaleFilter,
+    pub fit_mode: FitMode,
+    /// `#RRGGBB` color of the letterbox/pillarbox bars in `FitMode::Fit`.
+    pub bar_color: String,
     pub fps: u32,
+    /// Skip frames identical to the previous one and stretch its duration instead; `fps`
+    /// then becomes an upper bound.
+    pub variable_frame_rate: bool,
     pub bitrate: u32,
+    /// Longest stretch between keyframes; 0 leaves it to the encoder.
+    pub keyframe_interval_secs: u32,
+    pub color_matrix: ColorMatrix,
+    pub color_range: ColorRange,
+    pub video_backend: VideoBackend,
+    pub container: Container,
+    pub av1: Av1Settings,
+    pub gif: GifSettings,
+    /// Write the MP4 index before the media, for playback straight from a web server.
+    pub faststart: bool,
+    /// Write fragmented MP4 with a fragment about this often, so a crash only loses the last
+    /// few seconds; 0 writes a regular MP4 indexed when the recording stops.
+    pub fragment_interval_secs: u32,
+    /// After a clean stop, turn the fragmented file into a regular one.
+    pub defragment_on_stop: bool,
     pub selected_display: u32,
+    /// Only used with `CaptureSource::Display`; `None` records the whole display.
+    pub capture_region: Option<CaptureRegion>,
     pub selected_window: Option<String>,
+    /// Clip used by `CaptureSource::Replay`.
+    pub replay: Option<ReplaySettings>,
+    pub cursor: CursorSettings,
+    pub hdr: HdrSettings,
     pub selected_camera: Option<String>,
     pub camera_enabled: bool,
+    /// When set, the camera overlay is read from this clip instead of `selected_camera`.
+    pub camera_replay: Option<ReplaySettings>,
     pub camera_position: CameraPosition,
     pub camera_size: CameraSize,
     pub microphone_device: String,
@@ -48,19 +542,42 @@ pub struct RecordingSettings {
     pub system_audio_device: String,
     pub system_audio_enabled: bool,
     pub system_audio_volume: f32,
+    /// Output format when `screen_enabled` is off and only audio is recorded.
+    pub audio_format: AudioFormat,
+    pub pipeline: PipelineSettings,
 }
 
 impl Default for RecordingSettings {
     fn default() -> Self {
         Self {
             screen_enabled: true,
+            capture_source: CaptureSource::Display,
             resolution: Resolution::P1080,
+            scale_filter: ScaleFilter::Nearest,
+            fit_mode: FitMode::Stretch,
+            bar_color: "#000000".to_string(),
             fps: 30,
+            variable_frame_rate: false,
             bitrate: 5000,
+            keyframe_interval_secs: 2,
+            color_matrix: ColorMatrix::Bt709,
+            color_range: ColorRange::Limited,
+            video_backend: VideoBackend::Auto,
+            container: Container::Mp4,
+            av1: Av1Settings::default(),
+            gif: GifSettings::default(),
+            faststart: true,
+            fragment_interval_secs: 2,
+            defragment_on_stop: false,
             selected_display: 0,
+            capture_region: None,
             selected_window: None,
+            replay: None,
+            cursor: CursorSettings::default(),
+            hdr: HdrSettings::default(),
             selected_camera: None,
             camera_enabled: false,
+            camera_replay: None,
             camera_position: CameraPosition::BottomRight,
             camera_size: CameraSize::Medium,
             microphone_device: String::new(),
@@ -69,6 +586,8 @@ impl Default for RecordingSettings {
             system_audio_device: String::new(),
             system_audio_enabled: false,
             system_audio_volume: 0.6,
+            audio_format: AudioFormat::Wav,
+            pipeline: PipelineSettings::default(),
         }
     }
 }
@@ -83,6 +602,12 @@ pub struct AppState {
     pub history: Arc<Mutex<Vec<TimerSession>>>,
 }
 
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AppState {
     pub fn new() -> Self {
         Self {
@@ -175,3 +700,99 @@ impl AppState {
         self.settings.lock().clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(x: u32, y: u32, width: u32, height: u32) -> CaptureRegion {
+        CaptureRegion { x, y, width, height }
+    }
+
+    #[test]
+    fn capture_region_must_not_be_empty() {
+        for empty in [region(0, 0, 0, 100), region(10, 10, 100, 0), region(0, 0, 0, 0)] {
+            let err = empty.validate_within(1920, 1080).unwrap_err();
+            assert_eq!(err.code, "INVALID_SETTINGS");
+            assert_eq!(err.details.as_deref(), Some("Capture region must not be empty"));
+        }
+    }
+
+    #[test]
+    fn capture_region_may_touch_the_display_edge() {
+        assert!(region(0, 0, 1920, 1080).validate_within(1920, 1080).is_ok());
+        assert!(region(1820, 980, 100, 100).validate_within(1920, 1080).is_ok());
+        assert!(region(1821, 980, 100, 100).validate_within(1920, 1080).is_err());
+        assert!(region(1820, 981, 100, 100).validate_within(1920, 1080).is_err());
+        assert!(region(1920, 0, 1, 1).validate_within(1920, 1080).is_err());
+    }
+
+    #[test]
+    fn capture_region_overflow_is_rejected() {
+        let huge = region(u32::MAX - 10, 0, 20, 10);
+        assert_eq!(huge.validate_within(1920, 1080).unwrap_err().code, "INVALID_SETTINGS");
+        let huge = region(0, u32::MAX, 10, u32::MAX);
+        assert_eq!(huge.validate_within(u32::MAX, u32::MAX).unwrap_err().code, "INVALID_SETTINGS");
+    }
+
+    #[test]
+    fn legacy_resolutions_still_deserialize() {
+        let parse = |json: &str| serde_json::from_str::<Resolution>(json).unwrap();
+        assert_eq!(parse(r#""720p""#), Resolution::P720);
+        assert_eq!(parse(r#""1080p""#), Resolution::P1080);
+
+        // Configs saved before the other sizes existed.
+        let settings: RecordingSettings = serde_json::from_str(r#"{"resolution": "720p", "fps": 30}"#).unwrap();
+        assert_eq!(settings.resolution, Resolution::P720);
+    }
+
+    #[test]
+    fn custom_resolution_json_shape() {
+        let custom = Resolution::Custom { width: 1280, height: 800 };
+        let json = serde_json::to_value(custom).unwrap();
+        assert_eq!(json, serde_json::json!({ "custom": { "width": 1280, "height": 800 } }));
+        assert_eq!(serde_json::from_value::<Resolution>(json).unwrap(), custom);
+        assert_eq!(serde_json::to_value(Resolution::Native).unwrap(), "native");
+    }
+
+    #[test]
+    fn custom_resolution_limits() {
+        let check = |width, height| Resolution::Custom { width, height }.validate();
+        assert!(check(1280, 800).is_ok());
+        assert!(check(16, 16).is_ok());
+        assert!(check(4096, 2304).is_ok());
+
+        for (width, height) in [(1281, 800), (1280, 801), (14, 100), (100, 14), (0, 0), (4098, 2304), (4096, 2306)] {
+            let err = check(width, height).unwrap_err();
+            assert_eq!(err.code, "INVALID_SETTINGS", "{width}x{height}");
+        }
+        let details = |width, height| check(width, height).unwrap_err().details.unwrap();
+        assert!(details(1281, 800).contains("even"));
+        assert!(details(14, 100).contains("minimum"));
+        assert!(details(4098, 2304).contains("encoder limit"));
+
+        // Presets are always valid; Custom is checked again when resolved.
+        assert!(Resolution::P2160.validate().is_ok());
+        let native_unused = || -> Result<(u32, u32), RecorderError> { unreachable!() };
+        assert!(Resolution::Custom { width: 15, height: 16 }.dimensions(native_unused).is_err());
+    }
+
+    #[test]
+    fn native_resolution_fits_the_encoder_limits() {
+        let native = |width, height| Resolution::Native.dimensions(|| Ok((width, height))).unwrap();
+        // Super ultrawide: width-bound.
+        assert_eq!(native(5120, 1440), (4096, 1152));
+        // 8K: both edges hit the limit at once.
+        assert_eq!(native(7680, 4320), (4096, 2304));
+        // Inside the limits: only rounded down to even.
+        assert_eq!(native(1921, 1081), (1920, 1080));
+        assert_eq!(native(3840, 2160), (3840, 2160));
+        // Portrait, height-bound.
+        assert_eq!(native(1440, 2560), (1296, 2304));
+        // Never below the minimum.
+        assert_eq!(native(8, 8), (16, 16));
+
+        let err = Resolution::Native.dimensions(|| Err(RecorderError::device_not_found("Display"))).unwrap_err();
+        assert_eq!(err.code, "DEVICE_NOT_FOUND");
+    }
+}
@@ -1,3 +1,4 @@
+use crate::state::app_state::CaptureRegion;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,4 +16,10 @@ pub struct TimerSession {
     pub duration_seconds: u64,
     pub status: SessionStatus,
     pub output_file: Option<String>,
+    /// Display region that was recorded, if the session used one.
+    #[serde(default)]
+    pub capture_region: Option<CaptureRegion>,
+    /// Duplicate frames the session skipped with variable frame rate on.
+    #[serde(default)]
+    pub skipped_frames: u64,
 }
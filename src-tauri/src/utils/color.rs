@@ -0,0 +1,31 @@
+use crate::error::RecorderError;
+
+/// Parse `#RRGGBB` (the `#` is optional) into BGR order.
+pub fn parse_hex_color(value: &str) -> Result<[u8; 3], RecorderError> {
+    let hex = value.trim().trim_start_matches('#');
+    let invalid = || RecorderError::invalid_settings(format!("Invalid color '{value}', expected #RRGGBB"));
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+    Ok([channel(4)?, channel(2)?, channel(0)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rgb_into_bgr() {
+        assert_eq!(parse_hex_color("#ff8000").unwrap(), [0x00, 0x80, 0xff]);
+        assert_eq!(parse_hex_color(" 0A0b0C ").unwrap(), [0x0c, 0x0b, 0x0a]);
+    }
+
+    #[test]
+    fn rejects_malformed_colors() {
+        for value in ["", "#fff", "#ff80001", "#gg8000", "#ff80\u{e9}"] {
+            assert!(parse_hex_color(value).is_err(), "{value:?}");
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Allocation benchmark for the frame pool. Lives in its own test binary because it swaps
+//! in a counting global allocator.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::time::Instant;
+use tauri_02_lib::recording::color_convert::{ColorConverter, ColorSpace, YuvLayout};
+use tauri_02_lib::recording::frame_dedup::DuplicateFrameDetector;
+use tauri_02_lib::recording::frame_pool::FramePool;
+use tauri_02_lib::recording::frame_source::FrameSource;
+use tauri_02_lib::recording::pipeline::LatestSlot;
+use tauri_02_lib::recording::scaler::{ScaleOptions, Scaler};
+use tauri_02_lib::recording::test_pattern::TestPatternSource;
+use tauri_02_lib::state::app_state::{RecordingSettings, ScaleFilter};
+
+/// Counts heap allocations made by the current thread.
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATED: Cell<(u64, u64)> = const { Cell::new((0, 0)) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATED.try_with(|a| a.set((a.get().0 + 1, a.get().1 + layout.size() as u64)));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn allocated() -> (u64, u64) {
+    ALLOCATED.with(Cell::get)
+}
+
+/// Steady-state recording of the synthetic source (capture, downscale, hand-off through
+/// the camera-style slot, dedup and color conversion) must not allocate frame memory once
+/// the pool is warm. Run with
+/// `cargo test --release --test frame_pool_alloc -- --ignored --nocapture`.
+#[test]
+#[ignore = "allocation benchmark; run in release mode"]
+fn steady_state_recording_does_not_allocate() {
+    const WARM_UP: usize = 30;
+    const FRAMES: usize = 600;
+    let mut source = TestPatternSource::new(1920, 1080, 60).unwrap();
+    let mut scaler = Scaler::new(ScaleOptions::stretch(ScaleFilter::Bilinear));
+    let slot = LatestSlot::default();
+    let mut dedup = DuplicateFrameDetector::new();
+    let converter = ColorConverter::new(ColorSpace::from_settings(&RecordingSettings::default()));
+
+    let mut record = |n: usize| {
+        for _ in 0..n {
+            let mut frame = source.capture_frame().unwrap();
+            frame.data = scaler.scale(&frame.data, 1920, 1080, 1280, 720).into();
+            (frame.width, frame.height) = (1280, 720);
+            slot.publish(frame);
+            let frame = slot.latest().unwrap();
+            std::hint::black_box(dedup.is_duplicate(&frame));
+            std::hint::black_box(converter.convert(&frame.data, 1280, 720, YuvLayout::Nv12).unwrap());
+        }
+    };
+
+    record(WARM_UP);
+    let (pool_before, heap_before) = (FramePool::shared().stats(), allocated());
+    let started = Instant::now();
+    record(FRAMES);
+    let secs = started.elapsed().as_secs_f64();
+    let pool = FramePool::shared().stats().since(&pool_before);
+    let (count, bytes) = (allocated().0 - heap_before.0, allocated().1 - heap_before.1);
+
+    println!(
+        "{FRAMES} frames: {} pool allocations, {count} heap allocations, {:.1} KB/s allocated",
+        pool.allocations,
+        bytes as f64 / secs / 1024.0
+    );
+    assert_eq!(pool.allocations, 0);
+    // Only bookkeeping (`Arc`s, the counter text) is left; nothing frame-sized.
+    assert!(bytes / (FRAMES as u64) < 1024, "{bytes} bytes allocated over {FRAMES} frames");
+}
@@ -1,40 +1,48 @@
-import type { Resolution } from "../types";
+import type { Resolution, ResolutionPreset } from "../types";
 
 type Props = {
   selectedResolution: Resolution;
   onResolutionChange: (resolution: Resolution) => void;
 };
 
-const dims: Record<Resolution, string> = {
+const presets: ResolutionPreset[] = ["720p", "1080p", "1440p", "2160p", "native"];
+
+const dims: Record<ResolutionPreset, string> = {
   "720p": "1280x720",
   "1080p": "1920x1080",
+  "1440p": "2560x1440",
+  "2160p": "3840x2160",
+  native: "Source size",
 };
 
+const presetLabel = (preset: ResolutionPreset) => (preset === "native" ? "Native" : preset);
+
+const describe = (resolution: Resolution) =>
+  typeof resolution === "string"
+    ? dims[resolution]
+    : `${resolution.custom.width}x${resolution.custom.height}`;
+
 export function VideoSettings({ selectedResolution, onResolutionChange }: Props) {
   return (
     <section className="rf-card">
       <div className="rf-card-title">Video</div>
 
       <div className="rf-row">
-        <button
-          className={selectedResolution === "720p" ? "rf-chip rf-chip-on" : "rf-chip"}
-          onClick={() => onResolutionChange("720p")}
-          type="button"
-        >
-          720p
-        </button>
-        <button
-          className={selectedResolution === "1080p" ? "rf-chip rf-chip-on" : "rf-chip"}
-          onClick={() => onResolutionChange("1080p")}
-          type="button"
-        >
-          1080p
-        </button>
+        {presets.map((preset) => (
+          <button
+            key={preset}
+            className={selectedResolution === preset ? "rf-chip rf-chip-on" : "rf-chip"}
+            onClick={() => onResolutionChange(preset)}
+            type="button"
+          >
+            {presetLabel(preset)}
+          </button>
+        ))}
       </div>
 
       <div className="rf-kv">
         <div className="rf-k">Resolution</div>
-        <div className="rf-v">{dims[selectedResolution]}</div>
+        <div className="rf-v">{describe(selectedResolution)}</div>
       </div>
       <div className="rf-kv">
         <div className="rf-k">FPS</div>
@@ -20,6 +20,10 @@ const defaultStatus: RecordingStatus = {
   is_paused: false,
   output_file: null,
   elapsed_seconds: 0,
+  skipped_frames: 0,
+  pipeline: [],
+  frame_pool: { allocations: 0, allocated_bytes: 0, reuses: 0 },
+  warnings: [],
 };
 
 export function useRecording(): UseRecordingResult {
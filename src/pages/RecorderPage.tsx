@@ -178,6 +178,11 @@ export function RecorderPage() {
               elapsedSeconds={recording.status.elapsed_seconds}
               isRecording={recording.status.is_recording}
             />
+            {recording.status.warnings.map((warning) => (
+              <div key={warning} className="rf-warning">
+                {warning}
+              </div>
+            ))}
           </section>
 
           <RecordingControls
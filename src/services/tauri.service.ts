@@ -7,6 +7,7 @@ import type {
   RecordingInfo,
   RecordingSettings,
   RecordingStatus,
+  WindowInfo,
 } from "../types";
 
 // Helper to check if running in Tauri
@@ -15,13 +16,56 @@ export const isTauriApp = () => !!(window as any).__TAURI_INTERNALS__;
 // Mock state for browser development
 let mockSettings: RecordingSettings = {
   screen_enabled: true,
+  capture_source: "display",
   resolution: "1080p",
+  scale_filter: "nearest",
+  fit_mode: "stretch",
+  bar_color: "#000000",
   fps: 30,
+  variable_frame_rate: false,
   bitrate: 5000,
+  keyframe_interval_secs: 2,
+  color_matrix: "bt709",
+  color_range: "limited",
+  video_backend: "auto",
+  container: "mp4",
+  av1: {
+    speed: 10,
+    quantizer: 0,
+  },
+  gif: {
+    palette: "global",
+    quantizer: "median_cut",
+    dither: false,
+    fps: 10,
+    max_width: 800,
+    max_size_kb: 0,
+  },
+  faststart: true,
+  fragment_interval_secs: 2,
+  defragment_on_stop: false,
   selected_display: 0,
+  capture_region: null,
   selected_window: null,
+  replay: null,
+  cursor: {
+    mode: "system",
+    scale: 1,
+    click_rings: true,
+    left_click_color: "#FFC107",
+    right_click_color: "#2196F3",
+    hide_when_idle: false,
+    idle_timeout_ms: 3000,
+  },
+  hdr: {
+    enabled: false,
+    curve: "reinhard",
+    peak_nits: 1000,
+    sdr_white_nits: 200,
+  },
   selected_camera: null,
   camera_enabled: false,
+  camera_replay: null,
   camera_position: "BottomRight",
   camera_size: "Medium",
   microphone_device: "Default",
@@ -30,6 +74,12 @@ let mockSettings: RecordingSettings = {
   system_audio_device: "Default",
   system_audio_enabled: false,
   system_audio_volume: 0.6,
+  audio_format: "wav",
+  pipeline: {
+    queue_depth: 4,
+    capture_queue: "drop_oldest",
+    encode_queue: "block",
+  },
 };
 
 let mockStatus: RecordingStatus = {
@@ -37,6 +87,10 @@ let mockStatus: RecordingStatus = {
   is_paused: false,
   output_file: null,
   elapsed_seconds: 0,
+  skipped_frames: 0,
+  pipeline: [],
+  frame_pool: { allocations: 0, allocated_bytes: 0, reuses: 0 },
+  warnings: [],
 };
 
 let mockHistory: TimerSession[] = [];
@@ -55,7 +109,7 @@ async function invoke<T>(cmd: string, args?: any): Promise<T> {
     if (cmd === "get_recording_status") return mockStatus as unknown as T;
     
     if (cmd === "start_recording") {
-      mockStatus = { ...mockStatus, is_recording: true, elapsed_seconds: 0 };
+      mockStatus = { ...mockStatus, is_recording: true, elapsed_seconds: 0, skipped_frames: 0 };
       if (mockTimerId) window.clearInterval(mockTimerId);
       mockTimerId = window.setInterval(() => {
         if (mockStatus.is_recording && !mockStatus.is_paused) {
@@ -77,6 +131,8 @@ async function invoke<T>(cmd: string, args?: any): Promise<T> {
           duration_seconds: mockStatus.elapsed_seconds,
           status: "completed",
           output_file: "mock_output.mp4",
+          capture_region: mockSettings.capture_region,
+          skipped_frames: mockStatus.skipped_frames,
         },
         ...mockHistory,
       ];
@@ -95,6 +151,7 @@ async function invoke<T>(cmd: string, args?: any): Promise<T> {
     }
 
     if (cmd === "get_displays") return [{ index: 0, name: "Main Monitor", width: 1920, height: 1080, x: 0, y: 0, is_primary: true }] as unknown as T;
+    if (cmd === "get_windows") return [{ window_id: 1, title: "Untitled - Notepad" }] as unknown as T;
     if (cmd === "get_cameras") return [{ index: 0, name: "FaceTime HD Camera", width: 1280, height: 720 }] as unknown as T;
     if (cmd === "get_audio_inputs") return [{ index: 0, name: "Internal Mic", channels: 2, sample_rate: 48000, is_input: true }] as unknown as T;
     if (cmd === "get_system_audio_devices") return [{ index: 0, name: "Speakers", channels: 2, sample_rate: 48000, is_input: false }] as unknown as T;
@@ -131,6 +188,7 @@ export const tauriService = {
 
   // Devices
   getDisplays: () => invoke<DisplayInfo[]>("get_displays"),
+  getWindows: () => invoke<WindowInfo[]>("get_windows"),
   getCameras: () => invoke<CameraInfo[]>("get_cameras"),
   getAudioInputs: () => invoke<AudioDeviceInfo[]>("get_audio_inputs"),
   getSystemAudioDevices: () => invoke<AudioDeviceInfo[]>("get_system_audio_devices"),
@@ -8,6 +8,11 @@ export interface DisplayInfo {
   is_primary: boolean;
 }
 
+export interface WindowInfo {
+  window_id: number;
+  title: string;
+}
+
 export interface CameraInfo {
   index: number;
   name: string;
@@ -23,11 +28,38 @@ export interface AudioDeviceInfo {
   is_input: boolean;
 }
 
+export interface StageStats {
+  stage: string;
+  frames: number;
+  dropped: number;
+  queued: number;
+  avg_ms: number;
+  max_ms: number;
+  last_ms: number;
+}
+
+export interface FramePoolStats {
+  allocations: number;
+  allocated_bytes: number;
+  reuses: number;
+}
+
 export interface RecordingStatus {
   is_recording: boolean;
   is_paused: boolean;
   output_file: string | null;
   elapsed_seconds: number;
+  skipped_frames: number;
+  pipeline: StageStats[];
+  frame_pool: FramePoolStats;
+  warnings: string[];
+}
+
+export interface CaptureRegion {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
 }
 
 export type TimerSessionStatus = "completed" | "failed";
@@ -39,6 +71,8 @@ export interface TimerSession {
   duration_seconds: number;
   status: TimerSessionStatus;
   output_file: string | null;
+  capture_region: CaptureRegion | null;
+  skipped_frames: number;
 }
 
 export interface RecordingInfo {
@@ -49,19 +83,112 @@ export interface RecordingInfo {
   created_at: string;
 }
 
-export type Resolution = "720p" | "1080p";
+export type CaptureSource = "display" | "all_displays" | "window" | "test_pattern" | "replay";
+
+export interface ReplaySettings {
+  path: string;
+  width: number;
+  height: number;
+  fps: number;
+  looping: boolean;
+  realtime: boolean;
+}
+
+export type CursorMode = "system" | "overlay" | "hidden";
+
+export interface CursorSettings {
+  mode: CursorMode;
+  scale: number;
+  click_rings: boolean;
+  left_click_color: string;
+  right_click_color: string;
+  hide_when_idle: boolean;
+  idle_timeout_ms: number;
+}
+
+export type ScaleFilter = "nearest" | "bilinear" | "bicubic" | "lanczos";
+
+export type FitMode = "stretch" | "fit" | "fill";
+
+export type ColorMatrix = "bt601" | "bt709";
+
+export type ColorRange = "limited" | "full";
+
+export type Container = "mp4" | "mkv" | "webm" | "y4m" | "gif";
+
+export type VideoBackend = "auto" | "media_foundation" | "openh264" | "rav1e" | "intermediate" | "gif";
+
+export interface Av1Settings {
+  speed: number;
+  quantizer: number;
+}
+
+export type GifPalette = "global" | "per_frame";
+
+export type GifQuantizer = "median_cut" | "neuquant";
+
+export interface GifSettings {
+  palette: GifPalette;
+  quantizer: GifQuantizer;
+  dither: boolean;
+  fps: number;
+  max_width: number;
+  max_size_kb: number;
+}
+
+export type AudioFormat = "wav" | "flac" | "m4a";
+
+export type ToneMapCurve = "reinhard" | "hable" | "aces";
+
+export interface HdrSettings {
+  enabled: boolean;
+  curve: ToneMapCurve;
+  peak_nits: number;
+  sdr_white_nits: number;
+}
+
+export type QueuePolicy = "block" | "drop_oldest" | "drop_newest";
+
+export interface PipelineSettings {
+  queue_depth: number;
+  capture_queue: QueuePolicy;
+  encode_queue: QueuePolicy;
+}
+
+export type ResolutionPreset = "720p" | "1080p" | "1440p" | "2160p" | "native";
+export type Resolution = ResolutionPreset | { custom: { width: number; height: number } };
 export type CameraPosition = "TopLeft" | "TopRight" | "BottomLeft" | "BottomRight";
 export type CameraSize = "Small" | "Medium" | "Large";
 
 export interface RecordingSettings {
   screen_enabled: boolean;
+  capture_source: CaptureSource;
   resolution: Resolution;
+  scale_filter: ScaleFilter;
+  fit_mode: FitMode;
+  bar_color: string;
   fps: number;
+  variable_frame_rate: boolean;
   bitrate: number;
+  keyframe_interval_secs: number;
+  color_matrix: ColorMatrix;
+  color_range: ColorRange;
+  video_backend: VideoBackend;
+  container: Container;
+  av1: Av1Settings;
+  gif: GifSettings;
+  faststart: boolean;
+  fragment_interval_secs: number;
+  defragment_on_stop: boolean;
   selected_display: number;
+  capture_region: CaptureRegion | null;
   selected_window: string | null;
+  replay: ReplaySettings | null;
+  cursor: CursorSettings;
+  hdr: HdrSettings;
   selected_camera: string | null;
   camera_enabled: boolean;
+  camera_replay: ReplaySettings | null;
   camera_position: CameraPosition;
   camera_size: CameraSize;
   microphone_device: string;
@@ -70,4 +197,6 @@ export interface RecordingSettings {
   system_audio_device: string;
   system_audio_enabled: boolean;
   system_audio_volume: number;
+  audio_format: AudioFormat;
+  pipeline: PipelineSettings;
 }